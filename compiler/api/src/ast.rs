@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+
+use zaitun_bootstrap::ast::{ASTNode, AST};
+
+use crate::diagnostics::Span;
+
+/// Read-only view of a parsed file. The underlying tree is internal to the
+/// compiler; tools only see the items exposed through this type.
+pub struct Ast {
+    file: PathBuf,
+    inner: AST,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Function,
+    Variable,
+    Struct,
+    Enum,
+    Interface,
+    Module,
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: String,
+    pub type_name: String,
+}
+
+/// A top-level or module-level declaration
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub kind: ItemKind,
+    pub name: String,
+    pub is_public: bool,
+    pub doc_comment: Option<String>,
+    pub params: Vec<Param>,
+    pub return_type: Option<String>,
+    pub span: Span,
+    pub children: Vec<Item>,
+}
+
+impl Ast {
+    pub(crate) fn from_internal(file: &Path, inner: AST) -> Self {
+        Ast {
+            file: file.to_path_buf(),
+            inner,
+        }
+    }
+
+    pub(crate) fn internal(&self) -> &AST {
+        &self.inner
+    }
+
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Render as versioned JSON (the `--emit ast-json` format)
+    pub fn to_json(&self) -> Result<String, zaitun_bootstrap::ast_json::AstJsonError> {
+        zaitun_bootstrap::ast_json::to_string(&self.file, &self.inner)
+    }
+
+    /// Declarations in source order
+    pub fn items(&self) -> Vec<Item> {
+        collect_items(&self.inner.nodes)
+    }
+}
+
+fn collect_items(nodes: &[ASTNode]) -> Vec<Item> {
+    nodes.iter().filter_map(item_from_node).collect()
+}
+
+fn item_from_node(node: &ASTNode) -> Option<Item> {
+    let item = match node {
+        ASTNode::FunctionDecl(f) => Item {
+            kind: ItemKind::Function,
+            name: f.name.clone(),
+            is_public: f.is_public,
+            doc_comment: f.doc_comment.clone(),
+            params: f.params.iter()
+                .map(|p| Param { name: p.name.clone(), type_name: p.type_name.clone() })
+                .collect(),
+            return_type: Some(f.return_type.to_string()),
+            span: Span::from_internal(&f.span.start, &f.span.end),
+            children: Vec::new(),
+        },
+        ASTNode::VariableDecl(v) => Item {
+            kind: ItemKind::Variable,
+            name: v.name.clone(),
+            is_public: v.is_public,
+            doc_comment: None,
+            params: Vec::new(),
+            return_type: None,
+            span: Span::from_internal(&v.span.start, &v.span.end),
+            children: Vec::new(),
+        },
+        ASTNode::StructDecl(s) => declaration(ItemKind::Struct, &s.name, s.is_public, &s.doc_comment, &s.span),
+        ASTNode::EnumDecl(e) => declaration(ItemKind::Enum, &e.name, e.is_public, &e.doc_comment, &e.span),
+        ASTNode::InterfaceDecl(i) => declaration(ItemKind::Interface, &i.name, i.is_public, &i.doc_comment, &i.span),
+        ASTNode::Module(m) => Item {
+            children: collect_items(&m.body),
+            ..declaration(ItemKind::Module, &m.name, true, &m.doc_comment, &m.span)
+        },
+        _ => return None,
+    };
+
+    Some(item)
+}
+
+fn declaration(
+    kind: ItemKind,
+    name: &str,
+    is_public: bool,
+    doc_comment: &Option<String>,
+    span: &zaitun_bootstrap::error_handling::Span,
+) -> Item {
+    Item {
+        kind,
+        name: name.to_string(),
+        is_public,
+        doc_comment: doc_comment.clone(),
+        params: Vec::new(),
+        return_type: None,
+        span: Span::from_internal(&span.start, &span.end),
+        children: Vec::new(),
+    }
+}
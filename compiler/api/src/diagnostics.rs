@@ -0,0 +1,199 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use zaitun_bootstrap::error_handling::{CompileError, ExpansionInfo, SourceLocation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// 1-based line and column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub file: PathBuf,
+    pub start: LineCol,
+    pub end: LineCol,
+}
+
+impl Span {
+    pub fn point(file: &Path, line: usize, column: usize) -> Self {
+        let position = LineCol { line, column };
+        Span {
+            file: file.to_path_buf(),
+            start: position,
+            end: position,
+        }
+    }
+
+    pub(crate) fn from_internal(start: &SourceLocation, end: &SourceLocation) -> Self {
+        Span {
+            file: start.file.clone(),
+            start: LineCol { line: start.line, column: start.column },
+            end: LineCol { line: end.line, column: end.column },
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.start.line, self.start.column)
+    }
+}
+
+/// One level of macro expansion a diagnostic occurred in
+#[derive(Debug, Clone, PartialEq)]
+pub struct MacroFrame {
+    pub macro_name: String,
+    pub call_site: Span,
+    pub def_site: Option<Span>,
+}
+
+impl MacroFrame {
+    fn from_internal(info: &ExpansionInfo) -> Self {
+        MacroFrame {
+            macro_name: info.macro_name.clone(),
+            call_site: Span::from_internal(&info.call_site.start, &info.call_site.end),
+            def_site: info.def_site.as_ref().map(|s| Span::from_internal(&s.start, &s.end)),
+        }
+    }
+}
+
+/// A single compiler message
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+    /// Replacement text for `span`, e.g. the closest known name for a typo
+    pub suggestion: Option<String>,
+    /// Other edits resolving the diagnostic, as `(title, replacement)`
+    pub fixes: Vec<(String, String)>,
+    /// Macro expansions the span lies in, innermost first
+    pub macro_backtrace: Vec<MacroFrame>,
+}
+
+impl Diagnostic {
+    pub fn error(message: &str) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.to_string(),
+            span: None,
+            notes: Vec::new(),
+            help: None,
+            suggestion: None,
+            fixes: Vec::new(),
+            macro_backtrace: Vec::new(),
+        }
+    }
+
+    pub fn warning(message: &str) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(message)
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub(crate) fn from_internal(error: &CompileError) -> Self {
+        Diagnostic {
+            severity: if error.is_warning() { Severity::Warning } else { Severity::Error },
+            message: format!("{}: {}", error.kind, error.message),
+            span: error.span.as_ref().map(|s| Span::from_internal(&s.start, &s.end)),
+            notes: error.notes.clone(),
+            help: error.help.clone(),
+            suggestion: error.suggestion.clone(),
+            fixes: error
+                .fixes
+                .iter()
+                .map(|fix| (fix.title.clone(), fix.replacement.clone()))
+                .collect(),
+            macro_backtrace: error.expansion.iter().map(MacroFrame::from_internal).collect(),
+        }
+    }
+
+    /// Secondary locations for editors (LSP `relatedInformation`). Collapsed
+    /// by default to the outermost invocation, the code the user wrote;
+    /// `full` lists every invocation and definition site.
+    pub fn related_locations(&self, full: bool) -> Vec<(Span, String)> {
+        let frames = if full {
+            &self.macro_backtrace[..]
+        } else {
+            &self.macro_backtrace[self.macro_backtrace.len().saturating_sub(1)..]
+        };
+
+        let mut related = Vec::new();
+        for frame in frames {
+            related.push((frame.call_site.clone(), format!("in this expansion of macro `{}`", frame.macro_name)));
+            if let Some(def_site) = &frame.def_site {
+                related.push((def_site.clone(), format!("macro `{}` defined here", frame.macro_name)));
+            }
+        }
+        related
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+
+        write!(f, "{}: {}", label, self.message)?;
+        if let Some(span) = &self.span {
+            write!(f, " at {}", span)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Diagnostics collected for one compilation request
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Diagnostics { items: Vec::new() }
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.items.push(diagnostic);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.items.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.items.iter().filter(|d| d.severity == Severity::Error).count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.items.iter().filter(|d| d.severity == Severity::Warning).count()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.items.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
@@ -0,0 +1,103 @@
+//! Stable compiler API for tool authors.
+//!
+//! Formatters, linters and CI bots should depend on this crate instead of
+//! the bootstrap compiler's internal modules. Everything exported here
+//! follows semver: internal representations may change between releases,
+//! but these functions and view types only change in a major version bump.
+
+mod ast;
+mod diagnostics;
+mod symbols;
+
+pub use ast::{Ast, Item, ItemKind, Param};
+pub use diagnostics::{Diagnostic, Diagnostics, LineCol, MacroFrame, Severity, Span};
+pub use symbols::{Symbol, SymbolIndex, SymbolKind};
+
+/// Reader for the `--emit ast-json` format, usable without a parsed `Ast`
+pub mod ast_json {
+    pub use zaitun_bootstrap::ast_json::{
+        from_str, AstDocument, AstJsonError, ExprJson, NodeJson, ParamJson, SpanJson, AST_JSON_VERSION,
+    };
+}
+
+use std::path::Path;
+
+use zaitun_bootstrap::lexer::{Lexer, TokenType};
+use zaitun_bootstrap::parser::Parser;
+use zaitun_bootstrap::typecheck::TypeChecker;
+
+/// Version of this API surface, bumped independently of the compiler
+pub const API_VERSION: &str = "0.1.0";
+
+/// Result of parsing a single source file
+pub struct ParseResult {
+    pub ast: Option<Ast>,
+    pub diagnostics: Diagnostics,
+}
+
+/// Result of parsing and type checking a single source file
+pub struct CheckResult {
+    pub ast: Option<Ast>,
+    pub symbols: SymbolIndex,
+    pub diagnostics: Diagnostics,
+}
+
+/// Parse source text. `file_name` is only used for spans in diagnostics.
+pub fn parse_str(file_name: &str, source: &str) -> ParseResult {
+    let mut diagnostics = Diagnostics::new();
+    let file = Path::new(file_name);
+
+    // Lexical errors are reported up front so tools get them even when
+    // the parser gives up on the file
+    let tokens = Lexer::new(source.to_string()).scan_tokens();
+    for token in tokens.iter().filter(|t| t.token_type == TokenType::Error) {
+        diagnostics.push(Diagnostic::error(&token.lexeme).with_span(Span::point(
+            file,
+            token.line,
+            token.column,
+        )));
+    }
+
+    let ast = if diagnostics.has_errors() {
+        None
+    } else {
+        let mut parser = Parser::new(Lexer::new(source.to_string())).with_file(file);
+        let ast = parser.parse();
+        for error in parser.errors() {
+            diagnostics.push(Diagnostic::from_internal(error));
+        }
+        Some(Ast::from_internal(file, ast))
+    };
+
+    ParseResult { ast, diagnostics }
+}
+
+/// Parse and type check source text
+pub fn check_str(file_name: &str, source: &str) -> CheckResult {
+    let ParseResult { ast, mut diagnostics } = parse_str(file_name, source);
+
+    let symbols = match &ast {
+        Some(ast) => {
+            let mut checker = TypeChecker::new();
+            for error in checker.check(ast.internal()) {
+                diagnostics.push(Diagnostic::from_internal(&error));
+            }
+            SymbolIndex::from_ast(ast)
+        }
+        None => SymbolIndex::new(),
+    };
+
+    CheckResult { ast, symbols, diagnostics }
+}
+
+/// Read and parse a file from disk
+pub fn parse_file(path: &Path) -> std::io::Result<ParseResult> {
+    let source = std::fs::read_to_string(path)?;
+    Ok(parse_str(&path.to_string_lossy(), &source))
+}
+
+/// Read, parse and type check a file from disk
+pub fn check_file(path: &Path) -> std::io::Result<CheckResult> {
+    let source = std::fs::read_to_string(path)?;
+    Ok(check_str(&path.to_string_lossy(), &source))
+}
@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+
+use crate::ast::{Ast, Item, ItemKind};
+use crate::diagnostics::Span;
+
+pub type SymbolKind = ItemKind;
+
+/// A named declaration that can be looked up by its qualified path
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub path: String,
+    pub kind: SymbolKind,
+    pub is_public: bool,
+    pub span: Span,
+    pub doc_comment: Option<String>,
+}
+
+/// Declarations of a checked file, keyed by qualified path (`module::name`)
+#[derive(Debug, Clone, Default)]
+pub struct SymbolIndex {
+    symbols: BTreeMap<String, Symbol>,
+}
+
+impl SymbolIndex {
+    pub fn new() -> Self {
+        SymbolIndex { symbols: BTreeMap::new() }
+    }
+
+    pub(crate) fn from_ast(ast: &Ast) -> Self {
+        let mut index = SymbolIndex::new();
+        for item in ast.items() {
+            index.add_item("", &item);
+        }
+        index
+    }
+
+    fn add_item(&mut self, prefix: &str, item: &Item) {
+        let path = if prefix.is_empty() {
+            item.name.clone()
+        } else {
+            format!("{}::{}", prefix, item.name)
+        };
+
+        for child in &item.children {
+            self.add_item(&path, child);
+        }
+
+        self.symbols.insert(path.clone(), Symbol {
+            path,
+            kind: item.kind,
+            is_public: item.is_public,
+            span: item.span.clone(),
+            doc_comment: item.doc_comment.clone(),
+        });
+    }
+
+    /// Look up a symbol by qualified path
+    pub fn lookup(&self, path: &str) -> Option<&Symbol> {
+        self.symbols.get(path)
+    }
+
+    /// All symbols whose last path segment is `name`
+    pub fn find_by_name<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Symbol> + 'a {
+        self.symbols.values()
+            .filter(move |s| s.path.rsplit("::").next() == Some(name))
+    }
+
+    /// The innermost symbol whose span contains the given 1-based position
+    pub fn symbol_at(&self, line: usize, column: usize) -> Option<&Symbol> {
+        self.symbols.values()
+            .filter(|s| {
+                let start = (s.span.start.line, s.span.start.column);
+                let end = (s.span.end.line, s.span.end.column);
+                start <= (line, column) && (line, column) <= end
+            })
+            .max_by_key(|s| (s.span.start.line, s.span.start.column))
+    }
+
+    /// Iterate symbols in path order
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
@@ -1,13 +1,39 @@
 mod ast;
 mod parser;
 mod codegen;
+mod daemon;
+mod driver;
+mod edition;
+mod memory_stats;
+mod version_info;
 
 use std::env;
 use std::fs;
+use std::path::Path;
 use std::process::Command;
 
+use driver::{CompilerDriver, CompilerOptions, EmitKind};
+
+/// Counts allocations for `--memory-stats`
+#[global_allocator]
+static ALLOCATOR: memory_stats::CountingAllocator = memory_stats::CountingAllocator;
+
 fn main() {
     let args: Vec<String> = env::args().collect();
+    
+    match args.get(1).map(String::as_str) {
+        Some("daemon") => std::process::exit(daemon::run_server(&args[2..])),
+        Some(command @ ("build" | "check")) => {
+            std::process::exit(daemon::run_client(command, &args[2..]))
+        }
+        Some("migrate") => std::process::exit(edition::run_migrate(&args[2..])),
+        Some("--emit") => std::process::exit(run_emit(&args[2..])),
+        Some(option) if option.starts_with("--print=") => {
+            std::process::exit(version_info::run_print(&option["--print=".len()..]))
+        }
+        _ => {}
+    }
+    
     let input = fs::read_to_string(&args[1]).expect("Read error");
     
     let ast = parser::parse(&input);
@@ -39,4 +65,41 @@ fn process_file(path: &str) -> Result<(), Box<dyn std::error::Error>> {
         .status()
         .expect("LLVM failed");
     Ok(())
-}
\ No newline at end of file
+}
+
+/// `zaitun --emit KINDS FILE...`: compile in-process and write only the
+/// requested artifacts, e.g. `--emit ast-json` for the AST as JSON
+fn run_emit(args: &[String]) -> i32 {
+    let (kinds, files) = match args.split_first() {
+        Some((kinds, files)) if !files.is_empty() => (kinds, files),
+        _ => {
+            eprintln!("usage: zaitun --emit KINDS FILE...");
+            return 2;
+        }
+    };
+    let emit = match EmitKind::parse_list(kinds) {
+        Ok(emit) => emit,
+        Err(kind) => {
+            eprintln!("Unknown --emit kind: {}", kind);
+            return 2;
+        }
+    };
+    
+    let mut driver = CompilerDriver::new();
+    for file in files {
+        if let Err(e) = driver.add_source_file(Path::new(file)) {
+            eprintln!("Failed to read {}: {}", file, e);
+            return 1;
+        }
+    }
+    driver.set_options(CompilerOptions {
+        emit,
+        ..CompilerOptions::default()
+    });
+    
+    let success = driver.compile().is_ok();
+    for message in driver.diagnostic_messages() {
+        eprintln!("{}", message);
+    }
+    if success { 0 } else { 1 }
+}
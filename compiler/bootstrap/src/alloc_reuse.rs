@@ -0,0 +1,401 @@
+//! Reuse of short-lived allocations in loops.
+//!
+//! A loop that builds a temporary string or array on every iteration, only
+//! to print, compare or measure it, allocates once per iteration and leaves
+//! the collector to clean up. When escape analysis shows the allocation
+//! cannot outlive its iteration, it is replaced by an operation that
+//! refills one buffer allocated before the loop. The runtime's reuse
+//! operations fall back to a fresh allocation when the buffer is too small
+//! or still shared, so the rewrite never changes behaviour, only how often
+//! the allocator is called.
+//!
+//! Allocations of the same kind whose lifetimes within an iteration do not
+//! overlap share one buffer, e.g. two temporaries built one after the
+//! other in the loop body.
+
+use crate::error_handling::Span;
+use crate::ssa::{BlockId, Inst, SsaFunction, Terminator, ValueId};
+use crate::vectorize::{Remark, RemarkKind};
+use std::collections::{HashMap, HashSet};
+
+/// An allocating runtime operation and the operation that refills a
+/// buffer instead
+struct Reusable {
+    alloc: &'static str,
+    /// Allocates the empty buffer before the loop
+    empty: &'static str,
+    /// Takes the buffer first, then the allocation's operands if
+    /// `keeps_operands`, and returns the buffer
+    reuse: &'static str,
+    keeps_operands: bool,
+}
+
+const REUSABLE: &[Reusable] = &[
+    Reusable {
+        alloc: "string.concat",
+        empty: "string.new",
+        reuse: "string.concat_into",
+        keeps_operands: true,
+    },
+    Reusable {
+        alloc: "array.new",
+        empty: "array.new",
+        reuse: "array.clear",
+        keeps_operands: false,
+    },
+];
+
+/// What a use does with a pointer to the allocation
+enum Use {
+    /// Reads or mutates it in place
+    Read,
+    /// The result may point to the same memory
+    Alias,
+    /// It may be kept beyond the iteration
+    Escape(String),
+}
+
+fn classify(inst: &Inst, position: usize) -> Use {
+    match inst {
+        Inst::Binary(..) | Inst::Neg(_) | Inst::Not(_) | Inst::Const(_) | Inst::Param(_) => Use::Read,
+        Inst::Phi(_) => Use::Escape("it is carried to another iteration or out of the loop".to_string()),
+        Inst::Call(callee, _) => Use::Escape(format!("it is passed to `{}`", callee)),
+        Inst::Opaque(op, _) => match (op.as_str(), position) {
+            ("string.len" | "string.eq" | "string.hash" | "string.contains" | "print", _) => Use::Read,
+            ("array.len" | "array.get" | "array.set" | "array.push" | "array.pop", 0) => Use::Read,
+            // Concatenating with an empty string returns the other operand
+            ("string.concat" | "string.concat_into" | "string.slice", _) => Use::Alias,
+            ("array.clear", 0) => Use::Alias,
+            (op, _) => Use::Escape(format!("it is stored by `{}`", op)),
+        },
+    }
+}
+
+/// Where a value is used: by an instruction, at an operand position, or by
+/// a block's terminator
+#[derive(Clone, Copy)]
+enum User {
+    Inst(ValueId, usize),
+    Terminator,
+}
+
+struct Loop {
+    header: BlockId,
+    body: HashSet<BlockId>,
+}
+
+/// Natural loops, one per header, from back edges to a block dominating
+/// their source
+fn loops(function: &SsaFunction) -> Vec<Loop> {
+    let predecessors = function.predecessors();
+    let count = function.blocks.len();
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let mut reachable = vec![false; count];
+    let mut pending = vec![0];
+    while let Some(block) = pending.pop() {
+        if reachable[block] {
+            continue;
+        }
+        reachable[block] = true;
+        pending.extend(function.blocks[block].terminator.successors());
+    }
+
+    // Iterative dominator sets; small enough for per-function use
+    let all: HashSet<BlockId> = (0..count).filter(|&block| reachable[block]).collect();
+    let mut dominators: Vec<HashSet<BlockId>> = (0..count)
+        .map(|block| if block == 0 { HashSet::from([0]) } else { all.clone() })
+        .collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in 1..count {
+            if !reachable[block] {
+                continue;
+            }
+            let mut dominated: Option<HashSet<BlockId>> = None;
+            for &predecessor in predecessors[block].iter().filter(|&&p| reachable[p]) {
+                dominated = Some(match dominated {
+                    None => dominators[predecessor].clone(),
+                    Some(set) => set.intersection(&dominators[predecessor]).copied().collect(),
+                });
+            }
+            let mut dominated = dominated.unwrap_or_default();
+            dominated.insert(block);
+            if dominated != dominators[block] {
+                dominators[block] = dominated;
+                changed = true;
+            }
+        }
+    }
+
+    let mut loops: Vec<Loop> = Vec::new();
+    for latch in (0..count).filter(|&block| reachable[block]) {
+        for header in function.blocks[latch].terminator.successors() {
+            if !dominators[latch].contains(&header) {
+                continue;
+            }
+            let mut body = HashSet::from([header]);
+            let mut pending = vec![latch];
+            while let Some(block) = pending.pop() {
+                if body.insert(block) {
+                    pending.extend(predecessors[block].iter().copied().filter(|&p| reachable[p]));
+                }
+            }
+            match loops.iter_mut().find(|loop_| loop_.header == header) {
+                Some(loop_) => loop_.body.extend(body),
+                None => loops.push(Loop { header, body }),
+            }
+        }
+    }
+    loops
+}
+
+/// An allocation that can be served from a buffer
+struct Site {
+    value: ValueId,
+    block: BlockId,
+    position: usize,
+    kind: usize,
+    header: BlockId,
+    /// Last use of the allocation or its aliases, if all are in `block`
+    last_use: Option<usize>,
+}
+
+/// A buffer allocated before a loop
+struct Buffer {
+    value: ValueId,
+    /// Block and position after which no allocation served from the
+    /// buffer is used any more; `None` if one is used in several blocks
+    free_after: Option<(BlockId, usize)>,
+}
+
+/// Result of the pass on one function
+#[derive(Debug, Default)]
+pub struct ReuseResult {
+    /// Allocations now served from a buffer
+    pub reused: usize,
+    /// Buffers allocated before loops
+    pub buffers: usize,
+    pub remarks: Vec<Remark>,
+}
+
+/// Rewrite the non-escaping allocations in `function`'s loops to reuse
+/// buffers. Remarks are produced for allocations in blocks with a source
+/// span.
+pub fn run(function: &mut SsaFunction) -> ReuseResult {
+    let mut result = ReuseResult::default();
+    let loops = loops(function);
+    if loops.is_empty() {
+        return result;
+    }
+
+    let mut users: HashMap<ValueId, Vec<(BlockId, User)>> = HashMap::new();
+    for (block, data) in function.blocks.iter().enumerate() {
+        for &value in &data.insts {
+            for (position, operand) in function.values[value].operands().into_iter().enumerate() {
+                users.entry(operand).or_default().push((block, User::Inst(value, position)));
+            }
+        }
+        for operand in data.terminator.operands() {
+            users.entry(operand).or_default().push((block, User::Terminator));
+        }
+    }
+
+    let mut sites = Vec::new();
+    for (block, data) in function.blocks.iter().enumerate() {
+        // The innermost loop holding the block
+        let Some(loop_) = loops
+            .iter()
+            .filter(|loop_| loop_.body.contains(&block))
+            .min_by_key(|loop_| loop_.body.len())
+        else {
+            continue;
+        };
+
+        for (position, &value) in data.insts.iter().enumerate() {
+            let Inst::Opaque(op, _) = &function.values[value] else {
+                continue;
+            };
+            let Some(kind) = REUSABLE.iter().position(|reusable| reusable.alloc == op.as_str()) else {
+                continue;
+            };
+            match escapes(function, &users, value, block, loop_) {
+                Ok(last_use) => sites.push(Site {
+                    value,
+                    block,
+                    position,
+                    kind,
+                    header: loop_.header,
+                    last_use,
+                }),
+                Err(reason) => {
+                    if let Some(span) = &data.span {
+                        result.remarks.push(remark(
+                            function,
+                            span,
+                            RemarkKind::Missed,
+                            format!("allocation by `{}` not reused: {}", op, reason),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut preheaders: HashMap<BlockId, Option<BlockId>> = HashMap::new();
+    let mut buffers: HashMap<(BlockId, usize), Vec<Buffer>> = HashMap::new();
+    for site in sites {
+        let reusable = &REUSABLE[site.kind];
+        let preheader = *preheaders.entry(site.header).or_insert_with(|| {
+            let loop_ = loops.iter().find(|loop_| loop_.header == site.header).expect("site's loop");
+            preheader(function, loop_)
+        });
+        let span = function.blocks[site.block].span.clone();
+        let Some(preheader) = preheader else {
+            if let Some(span) = &span {
+                result.remarks.push(remark(
+                    function,
+                    span,
+                    RemarkKind::Missed,
+                    format!("allocation by `{}` not reused: the loop has no single entry edge", reusable.alloc),
+                ));
+            }
+            continue;
+        };
+
+        let candidates = buffers.entry((site.header, site.kind)).or_default();
+        let free_after = site.last_use.map(|position| (site.block, position));
+        let shared = candidates.iter_mut().find(|buffer| {
+            matches!(buffer.free_after, Some((block, position)) if block == site.block && position < site.position)
+        });
+        let (buffer, merged) = match shared {
+            Some(buffer) => {
+                buffer.free_after = free_after;
+                (buffer.value, true)
+            }
+            None => {
+                let value = function.push(preheader, Inst::Opaque(reusable.empty.to_string(), Vec::new()));
+                candidates.push(Buffer { value, free_after });
+                result.buffers += 1;
+                (value, false)
+            }
+        };
+
+        let mut operands = vec![buffer];
+        if reusable.keeps_operands {
+            operands.extend(function.values[site.value].operands());
+        }
+        function.values[site.value] = Inst::Opaque(reusable.reuse.to_string(), operands);
+        result.reused += 1;
+
+        if let Some(span) = &span {
+            let message = if merged {
+                format!("allocation by `{}` shares a buffer with an earlier one in the loop", reusable.alloc)
+            } else {
+                format!("allocation by `{}` reuses a buffer allocated before the loop", reusable.alloc)
+            };
+            result.remarks.push(remark(function, span, RemarkKind::Passed, message));
+        }
+    }
+
+    result
+}
+
+/// Why `value`, allocated in `block`, may outlive the current iteration of
+/// `loop_`. Otherwise the position of its last use, if it and its aliases
+/// are only used in `block`.
+fn escapes(
+    function: &SsaFunction,
+    users: &HashMap<ValueId, Vec<(BlockId, User)>>,
+    value: ValueId,
+    block: BlockId,
+    loop_: &Loop,
+) -> Result<Option<usize>, String> {
+    let mut last_use = Some(0);
+    let mut seen = HashSet::from([value]);
+    let mut pending = vec![value];
+    while let Some(alias) = pending.pop() {
+        for &(user_block, user) in users.get(&alias).map(Vec::as_slice).unwrap_or_default() {
+            if !loop_.body.contains(&user_block) {
+                return Err("it is used after the loop".to_string());
+            }
+            let position = match user {
+                User::Terminator => match function.blocks[user_block].terminator {
+                    Terminator::Return(_) => return Err("it is returned".to_string()),
+                    _ => function.blocks[user_block].insts.len(),
+                },
+                User::Inst(user_value, operand) => {
+                    match classify(&function.values[user_value], operand) {
+                        Use::Read => {}
+                        Use::Alias => {
+                            if seen.insert(user_value) {
+                                pending.push(user_value);
+                            }
+                        }
+                        Use::Escape(reason) => return Err(reason),
+                    }
+                    function.blocks[user_block]
+                        .insts
+                        .iter()
+                        .position(|&inst| inst == user_value)
+                        .expect("user is in its block")
+                }
+            };
+            last_use = match last_use {
+                Some(last) if user_block == block => Some(last.max(position)),
+                _ => None,
+            };
+        }
+    }
+    Ok(last_use)
+}
+
+/// The block control enters `loop_` from, created if the only entry edge
+/// comes from a branch. `None` unless the loop has exactly one entry edge.
+fn preheader(function: &mut SsaFunction, loop_: &Loop) -> Option<BlockId> {
+    let header = loop_.header;
+    let outside: Vec<BlockId> = function.predecessors()[header]
+        .iter()
+        .copied()
+        .filter(|block| !loop_.body.contains(block))
+        .collect();
+    let [entry] = outside[..] else {
+        return None;
+    };
+    if function.blocks[entry].terminator == Terminator::Jump(header) {
+        return Some(entry);
+    }
+
+    let preheader = function.add_block(None);
+    function.blocks[preheader].terminator = Terminator::Jump(header);
+    if let Terminator::Branch { then_block, else_block, .. } = &mut function.blocks[entry].terminator {
+        for target in [then_block, else_block] {
+            if *target == header {
+                *target = preheader;
+            }
+        }
+    }
+    for &value in &function.blocks[header].insts {
+        if let Inst::Phi(inputs) = &mut function.values[value] {
+            for (predecessor, _) in inputs.iter_mut() {
+                if *predecessor == entry {
+                    *predecessor = preheader;
+                }
+            }
+        }
+    }
+    Some(preheader)
+}
+
+fn remark(function: &SsaFunction, span: &Span, kind: RemarkKind, message: String) -> Remark {
+    Remark {
+        pass: "alloc-reuse",
+        kind,
+        function: function.name.clone(),
+        span: span.clone(),
+        message,
+    }
+}
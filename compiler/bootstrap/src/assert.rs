@@ -0,0 +1,319 @@
+//! The `assert(condition)` and `debug_assert(condition)` builtins, both
+//! taking an optional message as second argument.
+//!
+//! A failed assertion reports the condition, the values of its
+//! subexpressions and where it is, e.g.
+//!
+//! ```text
+//! assertion failed: `total + 1 == expected`
+//!   --> src/main.zt:12:5
+//!   total + 1 = 4
+//!   total = 3
+//!   expected = 5
+//! ```
+//!
+//! Compiled code and the VM both hand the failure to the runtime's
+//! `assertion_failed`, which formats and raises it, so the report is the
+//! same whichever runs the program. `debug_assert` is removed before either
+//! sees the program from `-O2` on, unless `--keep-debug-asserts` is given;
+//! the condition is then not evaluated at all.
+
+use crate::error_handling::Span;
+use crate::types::{Type, TypeChecker, TypeError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssertKind {
+    Assert,
+    DebugAssert,
+}
+
+impl AssertKind {
+    /// The builtin called `name`, if it is one
+    pub fn from_name(name: &str) -> Option<AssertKind> {
+        match name {
+            "assert" => Some(AssertKind::Assert),
+            "debug_assert" => Some(AssertKind::DebugAssert),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AssertKind::Assert => "assert",
+            AssertKind::DebugAssert => "debug_assert",
+        }
+    }
+
+    /// Whether assertions of this kind are kept at this optimization level
+    pub fn is_enabled(self, optimization_level: u8, keep_debug_asserts: bool) -> bool {
+        match self {
+            AssertKind::Assert => true,
+            AssertKind::DebugAssert => optimization_level < 2 || keep_debug_asserts,
+        }
+    }
+
+    /// The `kind` field of the runtime's `AssertInfo`
+    fn tag(self) -> u8 {
+        match self {
+            AssertKind::Assert => 0,
+            AssertKind::DebugAssert => 1,
+        }
+    }
+}
+
+/// Type of an assertion call: a `bool` condition, optionally followed by a
+/// `string` message
+pub fn check_args(types: &TypeChecker, arg_types: &[Type]) -> Result<Type, TypeError> {
+    match arg_types {
+        [condition] => types.check_assignment(&Type::Bool, condition)?,
+        [condition, message] => {
+            types.check_assignment(&Type::Bool, condition)?;
+            types.check_assignment(&Type::String, message)?;
+        }
+        [] => return Err(TypeError::WrongNumberOfArguments(1, 0)),
+        _ => return Err(TypeError::WrongNumberOfArguments(2, arg_types.len())),
+    }
+    Ok(Type::Void)
+}
+
+/// The asserted condition, as far as the failure report needs it
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssertExpr {
+    Literal(String),
+    /// A variable, call, field access or index
+    Value { text: String, type_: Type },
+    Unary { op: String, operand: Box<AssertExpr> },
+    Binary {
+        op: String,
+        left: Box<AssertExpr>,
+        right: Box<AssertExpr>,
+        type_: Type,
+    },
+}
+
+impl AssertExpr {
+    /// Source form, parenthesized only where precedence requires it
+    pub fn text(&self) -> String {
+        match self {
+            AssertExpr::Literal(text) | AssertExpr::Value { text, .. } => text.clone(),
+            AssertExpr::Unary { op, operand } => match **operand {
+                AssertExpr::Binary { .. } => format!("{}({})", op, operand.text()),
+                _ => format!("{}{}", op, operand.text()),
+            },
+            AssertExpr::Binary { op, left, right, .. } => {
+                let precedence = precedence(op);
+                // Operators are left-associative, so an equal-precedence
+                // right operand needs parentheses too
+                let left = left.operand_text(|inner| inner < precedence);
+                let right = right.operand_text(|inner| inner <= precedence);
+                format!("{} {} {}", left, op, right)
+            }
+        }
+    }
+
+    fn operand_text(&self, needs_parens: impl Fn(u8) -> bool) -> String {
+        match self {
+            AssertExpr::Binary { op, .. } if needs_parens(precedence(op)) => format!("({})", self.text()),
+            _ => self.text(),
+        }
+    }
+
+    fn type_(&self) -> Option<&Type> {
+        match self {
+            AssertExpr::Value { type_, .. } | AssertExpr::Binary { type_, .. } => Some(type_),
+            AssertExpr::Literal(_) | AssertExpr::Unary { .. } => None,
+        }
+    }
+
+    /// Subexpressions whose values a failure shows, left to right and
+    /// without repeats. Literals show nothing new, and neither does the
+    /// whole condition. Right operands of `&&` and `||` are left out: they
+    /// are not evaluated when the left one decides the result.
+    pub fn captured(&self) -> Vec<(String, Type)> {
+        let mut captured = Vec::new();
+        self.capture_operands(&mut captured);
+        captured
+    }
+
+    fn capture(&self, captured: &mut Vec<(String, Type)>) {
+        if let Some(type_) = self.type_().filter(|type_| ValueTag::of(type_).is_some()) {
+            let text = self.text();
+            if !captured.iter().any(|(seen, _)| *seen == text) {
+                captured.push((text, type_.clone()));
+            }
+        }
+        self.capture_operands(captured);
+    }
+
+    fn capture_operands(&self, captured: &mut Vec<(String, Type)>) {
+        match self {
+            AssertExpr::Literal(_) | AssertExpr::Value { .. } => {}
+            AssertExpr::Unary { operand, .. } => operand.capture(captured),
+            AssertExpr::Binary { op, left, right, .. } => {
+                left.capture(captured);
+                if op != "&&" && op != "||" {
+                    right.capture(captured);
+                }
+            }
+        }
+    }
+}
+
+fn precedence(op: &str) -> u8 {
+    match op {
+        "||" => 1,
+        "&&" => 2,
+        "==" | "!=" => 3,
+        "<" | "<=" | ">" | ">=" => 4,
+        "+" | "-" => 5,
+        _ => 6,
+    }
+}
+
+/// How a captured value is passed to the runtime; the `tag` field of its
+/// `AssertValue`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueTag {
+    Int = 0,
+    Float = 1,
+    Bool = 2,
+    String = 3,
+}
+
+impl ValueTag {
+    /// Only these types have a value the report can show
+    fn of(type_: &Type) -> Option<ValueTag> {
+        match type_ {
+            Type::Int => Some(ValueTag::Int),
+            Type::Float => Some(ValueTag::Float),
+            Type::Bool => Some(ValueTag::Bool),
+            Type::String => Some(ValueTag::String),
+            _ => None,
+        }
+    }
+}
+
+/// Declarations a module with assertions needs, once per module
+pub const LLVM_DECLARATIONS: &str = "\
+%zaitun.assert_info = type { i8, ptr, ptr, i32, i32, ptr, i32 }
+%zaitun.assert_value = type { i8, i64 }
+declare void @zaitun_assert_failed(ptr, ptr, ptr) cold noreturn
+";
+
+/// IR for one assertion
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertIr {
+    /// Constant report data, at module level
+    pub globals: String,
+    pub code: String,
+}
+
+/// One `assert` or `debug_assert` call
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assertion {
+    pub kind: AssertKind,
+    pub condition: AssertExpr,
+    pub span: Span,
+}
+
+impl Assertion {
+    /// LLVM IR checking `condition`, an `i8` bool. `values` are the
+    /// operands computing `self.condition.captured()`, in that order, and
+    /// `message` the message string if one was given. Failure calls into
+    /// the runtime; success continues after the code, which ends with the
+    /// label `{prefix}.ok`.
+    pub fn llvm_ir(&self, condition: &str, values: &[String], message: Option<&str>, prefix: &str) -> AssertIr {
+        let captured = self.condition.captured();
+        assert_eq!(captured.len(), values.len(), "one operand per captured value");
+
+        let mut globals = String::new();
+        globals.push_str(&c_string(&format!("{}.expr", prefix), &self.condition.text()));
+        let file = self.span.start.file.to_string_lossy();
+        globals.push_str(&c_string(&format!("{}.file", prefix), &file));
+        let mut names = Vec::new();
+        for (index, (text, _)) in captured.iter().enumerate() {
+            let name = format!("{}.name{}", prefix, index);
+            globals.push_str(&c_string(&name, text));
+            names.push(format!("ptr @{}", name));
+        }
+        let names_ptr = if names.is_empty() {
+            "null".to_string()
+        } else {
+            globals.push_str(&format!(
+                "@{}.names = private unnamed_addr constant [{} x ptr] [{}]\n",
+                prefix,
+                names.len(),
+                names.join(", ")
+            ));
+            format!("@{}.names", prefix)
+        };
+        globals.push_str(&format!(
+            "@{p}.info = private unnamed_addr constant %zaitun.assert_info {{ i8 {}, ptr @{p}.expr, ptr @{p}.file, i32 {}, i32 {}, ptr {}, i32 {} }}\n",
+            self.kind.tag(),
+            self.span.start.line,
+            self.span.start.column,
+            names_ptr,
+            names.len(),
+            p = prefix,
+        ));
+
+        let mut code = format!(
+            "  %{p}.cond = icmp ne i8 {}, 0\n  br i1 %{p}.cond, label %{p}.ok, label %{p}.fail\n{p}.fail:\n",
+            condition,
+            p = prefix,
+        );
+        let values_ptr = if captured.is_empty() {
+            "null".to_string()
+        } else {
+            code.push_str(&format!("  %{}.values = alloca [{} x %zaitun.assert_value]\n", prefix, captured.len()));
+            for (index, ((_, type_), value)) in captured.iter().zip(values).enumerate() {
+                let tag = ValueTag::of(type_).expect("captured values have a tag");
+                let slot = format!("%{}.value{}", prefix, index);
+                code.push_str(&format!(
+                    "  {slot} = getelementptr [{n} x %zaitun.assert_value], ptr %{p}.values, i64 0, i64 {index}\n\
+                     \x20 store i8 {tag}, ptr {slot}\n",
+                    n = captured.len(),
+                    p = prefix,
+                    tag = tag as u8,
+                ));
+                let bits = match tag {
+                    ValueTag::Int => value.clone(),
+                    ValueTag::Float | ValueTag::Bool | ValueTag::String => {
+                        let bits = format!("%{}.bits{}", prefix, index);
+                        code.push_str(&match tag {
+                            ValueTag::Float => format!("  {} = bitcast double {} to i64\n", bits, value),
+                            ValueTag::Bool => format!("  {} = zext i8 {} to i64\n", bits, value),
+                            _ => format!("  {} = ptrtoint ptr {} to i64\n", bits, value),
+                        });
+                        bits
+                    }
+                };
+                code.push_str(&format!(
+                    "  {slot}.bits = getelementptr %zaitun.assert_value, ptr {slot}, i64 0, i32 1\n\
+                     \x20 store i64 {bits}, ptr {slot}.bits\n",
+                ));
+            }
+            format!("%{}.values", prefix)
+        };
+        code.push_str(&format!(
+            "  call void @zaitun_assert_failed(ptr @{}.info, ptr {}, ptr {})\n  unreachable\n{}.ok:\n",
+            prefix,
+            values_ptr,
+            message.unwrap_or("null"),
+            prefix,
+        ));
+
+        AssertIr { globals, code }
+    }
+}
+
+/// A NUL-terminated string constant named `@{name}`
+fn c_string(name: &str, value: &str) -> String {
+    let escaped: String = value.bytes().map(|byte| format!("\\{:02X}", byte)).collect();
+    format!(
+        "@{} = private unnamed_addr constant [{} x i8] c\"{}\\00\"\n",
+        name,
+        value.len() + 1,
+        escaped
+    )
+}
@@ -0,0 +1,238 @@
+//! Syntax tree built by `parser`.
+//!
+//! Declarations keep their doc comments and spans so `docgen`, the stable
+//! API and `--emit ast-json` can report them. Expressions used as
+//! statements are stored as `BinaryExpr` or `Literal` when they are one, so
+//! AST-level folding can see them without unwrapping an `Expression`.
+
+use crate::error_handling::Span;
+use crate::numeric::NumericLiteral;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct AST {
+    pub nodes: Vec<ASTNode>,
+}
+
+impl AST {
+    pub fn new(nodes: Vec<ASTNode>) -> Self {
+        AST { nodes }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ASTNode {
+    FunctionDecl(FunctionDecl),
+    VariableDecl(VariableDecl),
+    StructDecl(StructDecl),
+    EnumDecl(EnumDecl),
+    InterfaceDecl(InterfaceDecl),
+    Module(Module),
+    MacroDefinition(MacroDefinition),
+    MacroInvocation(MacroInvocation),
+    Loop {
+        body: Vec<ASTNode>,
+    },
+    For {
+        init: Box<ASTNode>,
+        condition: Box<ASTNode>,
+        update: Box<ASTNode>,
+        body: Vec<ASTNode>,
+    },
+    BinaryExpr(BinaryExpr),
+    Literal(Literal),
+    /// Any other expression used as a statement
+    Expression(Expr),
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionDecl {
+    pub name: String,
+    pub is_public: bool,
+    pub doc_comment: Option<String>,
+    pub params: Vec<Param>,
+    /// As written; `void` when the signature has no `->`
+    pub return_type: String,
+    pub body: Vec<ASTNode>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: String,
+    pub type_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct VariableDecl {
+    pub name: String,
+    pub is_public: bool,
+    pub type_name: Option<String>,
+    pub value: Option<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructDecl {
+    pub name: String,
+    pub is_public: bool,
+    pub doc_comment: Option<String>,
+    pub fields: Vec<Field>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub type_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumDecl {
+    pub name: String,
+    pub is_public: bool,
+    pub doc_comment: Option<String>,
+    pub variants: Vec<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct InterfaceDecl {
+    pub name: String,
+    pub is_public: bool,
+    pub is_sealed: bool,
+    pub doc_comment: Option<String>,
+    pub methods: Vec<MethodSignature>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct MethodSignature {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub return_type: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub name: String,
+    pub doc_comment: Option<String>,
+    pub body: Vec<ASTNode>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct MacroDefinition {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<ASTNode>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct MacroInvocation {
+    pub name: String,
+    pub args: Vec<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Literal(Literal),
+    Identifier(String, Span),
+    Binary(Box<BinaryExpr>),
+    /// Callee, arguments, and the span up to the closing parenthesis
+    FunctionCall(Box<Expr>, Vec<Expr>, Span),
+    Assignment(Box<Expr>, Box<Expr>),
+    ThreadSpawn(Box<Expr>, Span),
+    AtomicAccess(Box<Expr>, Span),
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Literal(literal) => literal.span.clone(),
+            Expr::Binary(binary) => binary.span.clone(),
+            Expr::Assignment(target, value) => Span {
+                start: target.span().start,
+                end: value.span().end,
+            },
+            Expr::Identifier(_, span)
+            | Expr::FunctionCall(_, _, span)
+            | Expr::ThreadSpawn(_, span)
+            | Expr::AtomicAccess(_, span) => span.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BinaryExpr {
+    pub op: BinOp,
+    pub left: Expr,
+    pub right: Expr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl BinOp {
+    /// Whether the result is `bool` rather than the operand type
+    pub fn is_comparison(self) -> bool {
+        !matches!(self, BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div)
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Literal {
+    pub value: LiteralValue,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiteralValue {
+    Number(NumericLiteral),
+    /// Contents without the quotes
+    String(String),
+    Bool(bool),
+}
+
+impl fmt::Display for LiteralValue {
+    /// Source form, so a literal prints as it was written
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiteralValue::Number(number) => write!(f, "{}", number.text),
+            LiteralValue::String(text) => write!(f, "\"{}\"", text),
+            LiteralValue::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
@@ -0,0 +1,449 @@
+//! Stable JSON rendering of the parsed AST (`--emit ast-json`).
+//!
+//! The schema is versioned independently of the compiler's internal AST so
+//! codemod tools and the fuzzer's minimizer can consume it without linking
+//! the compiler. Adding optional fields is a minor change; renaming or
+//! removing fields bumps `AST_JSON_VERSION`.
+
+use crate::ast::*;
+use crate::error_handling::Span;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const AST_JSON_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AstDocument {
+    pub version: u32,
+    pub file: String,
+    pub nodes: Vec<NodeJson>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpanJson {
+    pub file: String,
+    /// `[line, column]`, both 1-based
+    pub start: [usize; 2],
+    pub end: [usize; 2],
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParamJson {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NodeJson {
+    Function {
+        name: String,
+        public: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        doc: Option<String>,
+        params: Vec<ParamJson>,
+        return_type: String,
+        span: SpanJson,
+    },
+    Variable {
+        name: String,
+        public: bool,
+        span: SpanJson,
+    },
+    Struct {
+        name: String,
+        public: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        doc: Option<String>,
+        span: SpanJson,
+    },
+    Enum {
+        name: String,
+        public: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        doc: Option<String>,
+        span: SpanJson,
+    },
+    Interface {
+        name: String,
+        public: bool,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        sealed: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        doc: Option<String>,
+        span: SpanJson,
+    },
+    Module {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        doc: Option<String>,
+        body: Vec<NodeJson>,
+        span: SpanJson,
+    },
+    MacroInvocation {
+        name: String,
+        span: SpanJson,
+    },
+    MacroDefinition {
+        name: String,
+        span: SpanJson,
+    },
+    Loop {
+        body: Vec<NodeJson>,
+        /// Covers the body; the parser does not record the position of the
+        /// `loop` keyword, so an empty loop has no span
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        span: Option<SpanJson>,
+    },
+    For {
+        init: Box<NodeJson>,
+        condition: Box<NodeJson>,
+        update: Box<NodeJson>,
+        body: Vec<NodeJson>,
+        /// From the start of `init` to the end of the body
+        span: SpanJson,
+    },
+    Binary {
+        op: String,
+        left: ExprJson,
+        right: ExprJson,
+        span: SpanJson,
+    },
+    Literal {
+        value: String,
+        span: SpanJson,
+    },
+    /// Any other expression used as a statement
+    Expression {
+        expr: ExprJson,
+    },
+}
+
+/// Operand of a statement-level node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExprJson {
+    Literal {
+        value: String,
+        span: SpanJson,
+    },
+    Identifier {
+        name: String,
+        span: SpanJson,
+    },
+    Binary {
+        op: String,
+        left: Box<ExprJson>,
+        right: Box<ExprJson>,
+        span: SpanJson,
+    },
+    Call {
+        callee: Box<ExprJson>,
+        args: Vec<ExprJson>,
+        span: SpanJson,
+    },
+    Assign {
+        target: Box<ExprJson>,
+        value: Box<ExprJson>,
+        span: SpanJson,
+    },
+    ThreadSpawn {
+        span: SpanJson,
+    },
+    AtomicAccess {
+        span: SpanJson,
+    },
+}
+
+impl NodeJson {
+    pub fn span(&self) -> Option<&SpanJson> {
+        match self {
+            NodeJson::Loop { span, .. } => span.as_ref(),
+            NodeJson::Function { span, .. }
+            | NodeJson::Variable { span, .. }
+            | NodeJson::Struct { span, .. }
+            | NodeJson::Enum { span, .. }
+            | NodeJson::Interface { span, .. }
+            | NodeJson::Module { span, .. }
+            | NodeJson::MacroInvocation { span, .. }
+            | NodeJson::MacroDefinition { span, .. }
+            | NodeJson::For { span, .. }
+            | NodeJson::Binary { span, .. }
+            | NodeJson::Literal { span, .. } => Some(span),
+            NodeJson::Expression { expr } => Some(expr.span()),
+        }
+    }
+}
+
+impl ExprJson {
+    pub fn span(&self) -> &SpanJson {
+        match self {
+            ExprJson::Literal { span, .. }
+            | ExprJson::Identifier { span, .. }
+            | ExprJson::Binary { span, .. }
+            | ExprJson::Call { span, .. }
+            | ExprJson::Assign { span, .. }
+            | ExprJson::ThreadSpawn { span }
+            | ExprJson::AtomicAccess { span } => span,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AstJsonError {
+    Malformed(String),
+    UnsupportedVersion(u32),
+    /// The AST has a shape the schema cannot represent
+    Unserializable(String),
+}
+
+impl std::fmt::Display for AstJsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AstJsonError::Malformed(msg) => write!(f, "Malformed AST JSON: {}", msg),
+            AstJsonError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported AST JSON version {} (this compiler reads version {})",
+                version, AST_JSON_VERSION
+            ),
+            AstJsonError::Unserializable(msg) => write!(f, "Cannot render AST as JSON: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AstJsonError {}
+
+/// Convert a parsed file into its JSON document
+pub fn to_document(file: &Path, ast: &AST) -> Result<AstDocument, AstJsonError> {
+    Ok(AstDocument {
+        version: AST_JSON_VERSION,
+        file: file.to_string_lossy().to_string(),
+        nodes: nodes_to_json(&ast.nodes)?,
+    })
+}
+
+/// Render a parsed file as pretty-printed JSON
+pub fn to_string(file: &Path, ast: &AST) -> Result<String, AstJsonError> {
+    serde_json::to_string_pretty(&to_document(file, ast)?)
+        .map_err(|e| AstJsonError::Unserializable(e.to_string()))
+}
+
+/// Read a document produced by `to_string`. Documents from any other
+/// schema version are rejected: newer ones may use nodes this reader does
+/// not know, and older ones were written against a different schema.
+pub fn from_str(json: &str) -> Result<AstDocument, AstJsonError> {
+    // Check the version before the nodes so a document from another
+    // version reports that rather than a confusing shape error
+    let header: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| AstJsonError::Malformed(e.to_string()))?;
+    let version = header.get("version")
+        .and_then(serde_json::Value::as_u64)
+        .ok_or_else(|| AstJsonError::Malformed("missing `version`".to_string()))?;
+    if version != AST_JSON_VERSION as u64 {
+        return Err(AstJsonError::UnsupportedVersion(version.min(u32::MAX as u64) as u32));
+    }
+
+    let document: AstDocument = serde_json::from_value(header)
+        .map_err(|e| AstJsonError::Malformed(e.to_string()))?;
+
+    Ok(document)
+}
+
+fn nodes_to_json(nodes: &[ASTNode]) -> Result<Vec<NodeJson>, AstJsonError> {
+    nodes.iter().map(node_to_json).collect()
+}
+
+fn node_to_json(node: &ASTNode) -> Result<NodeJson, AstJsonError> {
+    let node = match node {
+        ASTNode::FunctionDecl(f) => NodeJson::Function {
+            name: f.name.clone(),
+            public: f.is_public,
+            doc: f.doc_comment.clone(),
+            params: f.params.iter()
+                .map(|p| ParamJson { name: p.name.clone(), type_name: p.type_name.clone() })
+                .collect(),
+            return_type: f.return_type.to_string(),
+            span: span_to_json(&f.span),
+        },
+        ASTNode::VariableDecl(v) => NodeJson::Variable {
+            name: v.name.clone(),
+            public: v.is_public,
+            span: span_to_json(&v.span),
+        },
+        ASTNode::StructDecl(s) => NodeJson::Struct {
+            name: s.name.clone(),
+            public: s.is_public,
+            doc: s.doc_comment.clone(),
+            span: span_to_json(&s.span),
+        },
+        ASTNode::EnumDecl(e) => NodeJson::Enum {
+            name: e.name.clone(),
+            public: e.is_public,
+            doc: e.doc_comment.clone(),
+            span: span_to_json(&e.span),
+        },
+        ASTNode::InterfaceDecl(i) => NodeJson::Interface {
+            name: i.name.clone(),
+            public: i.is_public,
+            sealed: i.is_sealed,
+            doc: i.doc_comment.clone(),
+            span: span_to_json(&i.span),
+        },
+        ASTNode::Module(m) => NodeJson::Module {
+            name: m.name.clone(),
+            doc: m.doc_comment.clone(),
+            body: nodes_to_json(&m.body)?,
+            span: span_to_json(&m.span),
+        },
+        ASTNode::MacroInvocation(invocation) => NodeJson::MacroInvocation {
+            name: invocation.name.clone(),
+            span: span_to_json(&invocation.span),
+        },
+        ASTNode::MacroDefinition(definition) => NodeJson::MacroDefinition {
+            name: definition.name.clone(),
+            span: span_to_json(&definition.span),
+        },
+        ASTNode::Loop { body } => {
+            let body = nodes_to_json(body)?;
+            let span = body.iter().find_map(NodeJson::span)
+                .zip(body.iter().rev().find_map(NodeJson::span))
+                .map(|(first, last)| covering_span(first, last));
+            NodeJson::Loop { body, span }
+        }
+        ASTNode::For { init, condition, update, body } => {
+            let init = node_to_json(init)?;
+            let body = nodes_to_json(body)?;
+            // The span starts at the initializer, so one without a span
+            // leaves nothing to anchor the loop to
+            let start = init.span().ok_or_else(|| {
+                AstJsonError::Unserializable("`for` initializer has no span".to_string())
+            })?;
+            let end = body.iter().rev().find_map(NodeJson::span).unwrap_or(start);
+            let span = covering_span(start, end);
+            NodeJson::For {
+                init: Box::new(init),
+                condition: Box::new(node_to_json(condition)?),
+                update: Box::new(node_to_json(update)?),
+                body,
+                span,
+            }
+        }
+        ASTNode::BinaryExpr(binary) => NodeJson::Binary {
+            op: binary.op.to_string(),
+            left: expr_to_json(&binary.left),
+            right: expr_to_json(&binary.right),
+            span: span_to_json(&binary.span),
+        },
+        ASTNode::Literal(literal) => NodeJson::Literal {
+            value: literal.value.to_string(),
+            span: span_to_json(&literal.span),
+        },
+        ASTNode::Expression(expr) => NodeJson::Expression {
+            expr: expr_to_json(expr),
+        },
+    };
+    Ok(node)
+}
+
+fn expr_to_json(expr: &Expr) -> ExprJson {
+    let span = span_to_json(&expr.span());
+    match expr {
+        Expr::Literal(literal) => ExprJson::Literal {
+            value: literal.value.to_string(),
+            span,
+        },
+        Expr::Identifier(name, _) => ExprJson::Identifier {
+            name: name.clone(),
+            span,
+        },
+        Expr::Binary(binary) => ExprJson::Binary {
+            op: binary.op.to_string(),
+            left: Box::new(expr_to_json(&binary.left)),
+            right: Box::new(expr_to_json(&binary.right)),
+            span,
+        },
+        Expr::FunctionCall(callee, args, _) => ExprJson::Call {
+            callee: Box::new(expr_to_json(callee)),
+            args: args.iter().map(expr_to_json).collect(),
+            span,
+        },
+        Expr::Assignment(target, value) => ExprJson::Assign {
+            target: Box::new(expr_to_json(target)),
+            value: Box::new(expr_to_json(value)),
+            span,
+        },
+        Expr::ThreadSpawn(..) => ExprJson::ThreadSpawn { span },
+        Expr::AtomicAccess(..) => ExprJson::AtomicAccess { span },
+    }
+}
+
+/// Span from the start of `first` to the end of `last`
+fn covering_span(first: &SpanJson, last: &SpanJson) -> SpanJson {
+    SpanJson {
+        file: first.file.clone(),
+        start: first.start,
+        end: last.end,
+    }
+}
+
+fn span_to_json(span: &Span) -> SpanJson {
+    SpanJson {
+        file: span.start.file.to_string_lossy().to_string(),
+        start: [span.start.line, span.start.column],
+        end: [span.end.line, span.end.column],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> AST {
+        let mut parser = Parser::new(Lexer::new(source.to_string())).with_file(Path::new("main.zt"));
+        let ast = parser.parse();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        ast
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ast = parse(
+            "/// Entry point\n\
+             pub fn main() {\n\
+                 let total: int = 0;\n\
+                 for let i = 0; i < 10; i = i + 1 {\n\
+                     total = total + i;\n\
+                 }\n\
+                 print(total);\n\
+             }\n\
+             struct Point { x: int, y: int }\n",
+        );
+        let json = to_string(Path::new("main.zt"), &ast).unwrap();
+        let document = from_str(&json).unwrap();
+        assert_eq!(document, to_document(Path::new("main.zt"), &ast).unwrap());
+        assert_eq!(document.version, AST_JSON_VERSION);
+        assert!(matches!(
+            &document.nodes[0],
+            NodeJson::Function { name, public: true, doc: Some(doc), .. } if name == "main" && doc == "Entry point"
+        ));
+    }
+
+    #[test]
+    fn test_other_version_rejected() {
+        let json = to_string(Path::new("main.zt"), &parse("fn main() {}")).unwrap();
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        for version in [AST_JSON_VERSION - 1, AST_JSON_VERSION + 1] {
+            value["version"] = version.into();
+            match from_str(&value.to_string()) {
+                Err(AstJsonError::UnsupportedVersion(found)) => assert_eq!(found, version),
+                other => panic!("version {} was not rejected: {:?}", version, other),
+            }
+        }
+
+        value.as_object_mut().unwrap().remove("version");
+        assert!(matches!(from_str(&value.to_string()), Err(AstJsonError::Malformed(_))));
+    }
+}
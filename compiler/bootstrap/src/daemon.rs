@@ -0,0 +1,581 @@
+//! `zaitun daemon`: a long-lived compiler process that keeps parsed and
+//! analyzed modules in a `ModuleCache` and serves `build`/`check`
+//! requests from the CLI over a local socket.
+//!
+//! Messages are JSON, each sent as one frame: a little-endian `u32` length
+//! followed by the payload (the same framing as `std::ipc`). One daemon
+//! serves one workspace; its socket name is derived from the workspace
+//! root. The CLI starts a daemon on first use and falls back to compiling
+//! in-process if one cannot be reached.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::driver::{
+    CompilerDriver, CompilerOptions, EmitKind, ModuleCache, DEFAULT_ERROR_LIMIT, STDIN_FILE_NAME, STDOUT_PATH,
+};
+use crate::edition::Edition;
+use crate::error_handling::MacroBacktrace;
+use crate::numeric::{LiteralPolicy, NumericType};
+use crate::optimize::SizeLevel;
+use crate::suggest;
+
+/// Bumped when the request/response format changes
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// A daemon with no requests for this long exits
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+const SPAWN_WAIT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Command {
+    Build,
+    Check,
+    Status,
+    Shutdown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub protocol: u32,
+    /// A daemon from a different compiler build must not serve requests
+    pub compiler_version: String,
+    pub command: Command,
+    pub sources: Vec<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub optimization_level: u8,
+    #[serde(default)]
+    pub size_level: SizeLevel,
+    #[serde(default)]
+    pub lto: bool,
+    #[serde(default)]
+    pub macro_backtrace: bool,
+    #[serde(default = "default_error_limit")]
+    pub error_limit: usize,
+    #[serde(default)]
+    pub literal_policy: LiteralPolicy,
+    #[serde(default)]
+    pub remarks: bool,
+    #[serde(default)]
+    pub keep_debug_asserts: bool,
+    #[serde(default)]
+    pub incremental: Option<PathBuf>,
+    #[serde(default)]
+    pub edition: Edition,
+    #[serde(default)]
+    pub memory_stats: bool,
+    /// Source read from the client's standard input (`build -`)
+    #[serde(default)]
+    pub stdin_source: Option<String>,
+    /// `--emit` kinds as given on the command line; empty means the
+    /// command's default artifacts
+    #[serde(default)]
+    pub emit: Vec<String>,
+}
+
+fn default_error_limit() -> usize {
+    DEFAULT_ERROR_LIMIT
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    pub success: bool,
+    pub diagnostics: Vec<String>,
+    pub elapsed_ms: u64,
+    /// Modules served from the cache over the daemon's lifetime
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub cached_modules: usize,
+}
+
+#[derive(Debug)]
+pub enum DaemonError {
+    Io(std::io::Error),
+    Protocol(String),
+    /// The daemon was built from a different compiler version
+    VersionMismatch { daemon: String, client: String },
+    Unsupported,
+}
+
+impl std::fmt::Display for DaemonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DaemonError::Io(e) => write!(f, "Daemon I/O error: {}", e),
+            DaemonError::Protocol(msg) => write!(f, "Daemon protocol error: {}", msg),
+            DaemonError::VersionMismatch { daemon, client } => {
+                write!(f, "Daemon is version {} but the CLI is {}", daemon, client)
+            }
+            DaemonError::Unsupported => write!(f, "Compiler daemon is not supported on this platform"),
+        }
+    }
+}
+
+impl std::error::Error for DaemonError {}
+
+impl From<std::io::Error> for DaemonError {
+    fn from(error: std::io::Error) -> Self {
+        DaemonError::Io(error)
+    }
+}
+
+pub fn compiler_version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+/// Socket path for the daemon serving `workspace`
+pub fn socket_path(workspace: &Path) -> PathBuf {
+    let root = workspace.canonicalize().unwrap_or_else(|_| workspace.to_path_buf());
+    let mut hasher = DefaultHasher::new();
+    root.hash(&mut hasher);
+
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    dir.join(format!("zaitun-daemon-{:016x}.sock", hasher.finish()))
+}
+
+fn write_message<W: Write, T: Serialize>(writer: &mut W, message: &T) -> Result<(), DaemonError> {
+    let payload = serde_json::to_vec(message).map_err(|e| DaemonError::Protocol(e.to_string()))?;
+    writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+    writer.write_all(&payload)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_message<R: Read, T: for<'de> Deserialize<'de>>(reader: &mut R) -> Result<T, DaemonError> {
+    let mut header = [0u8; 4];
+    reader.read_exact(&mut header)?;
+    let len = u32::from_le_bytes(header) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(DaemonError::Protocol(format!("Message of {} bytes is too large", len)));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    serde_json::from_slice(&payload).map_err(|e| DaemonError::Protocol(e.to_string()))
+}
+
+/// Daemon state: the warm cache plus bookkeeping
+pub struct Daemon {
+    socket: PathBuf,
+    cache: ModuleCache,
+    idle_timeout: Duration,
+}
+
+impl Daemon {
+    pub fn new(socket: PathBuf) -> Self {
+        Daemon {
+            socket,
+            cache: ModuleCache::new(),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+        }
+    }
+
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Answer one request. Returns `None` for `Shutdown`.
+    pub fn handle(&mut self, request: &Request) -> Option<Response> {
+        let start = Instant::now();
+
+        let (success, diagnostics) = match request.command {
+            Command::Shutdown => return None,
+            Command::Status => (true, Vec::new()),
+            Command::Build | Command::Check => self.compile(request),
+        };
+
+        Some(Response {
+            success,
+            diagnostics,
+            elapsed_ms: start.elapsed().as_millis() as u64,
+            cache_hits: self.cache.hits,
+            cache_misses: self.cache.misses,
+            cached_modules: self.cache.len(),
+        })
+    }
+
+    fn compile(&mut self, request: &Request) -> (bool, Vec<String>) {
+        let mut driver = CompilerDriver::new();
+        for source in &request.sources {
+            if let Err(e) = driver.add_source_file(source) {
+                return (false, vec![e.to_string()]);
+            }
+        }
+        if let Some(text) = &request.stdin_source {
+            driver.add_source_text(Path::new(STDIN_FILE_NAME), text.clone());
+        }
+        if let Some(output) = &request.output {
+            driver.set_output_file(output);
+        }
+
+        let mut options = CompilerOptions {
+            optimization_level: request.optimization_level,
+            size_level: request.size_level,
+            lto: request.lto,
+            ..CompilerOptions::default()
+        };
+        if request.macro_backtrace {
+            options.macro_backtrace = MacroBacktrace::Full;
+        }
+        options.error_limit = request.error_limit;
+        options.literal_policy = request.literal_policy;
+        options.remarks = request.remarks;
+        options.keep_debug_asserts = request.keep_debug_asserts;
+        options.incremental = request.incremental.clone();
+        options.edition = request.edition;
+        options.memory_stats = request.memory_stats;
+        // `check` stops after analysis; emitting only an interface is the
+        // cheapest artifact that still runs the type checker
+        if request.command == Command::Check {
+            options.emit = vec![EmitKind::Interface];
+        }
+        if !request.emit.is_empty() {
+            options.emit = request.emit.iter().filter_map(|kind| EmitKind::parse(kind)).collect();
+        }
+        driver.set_options(options);
+
+        let success = driver.compile_cached(&mut self.cache).is_ok();
+        (success, driver.diagnostic_messages())
+    }
+
+    #[cfg(unix)]
+    pub fn run(&mut self) -> Result<(), DaemonError> {
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        if self.socket.exists() {
+            if UnixStream::connect(&self.socket).is_ok() {
+                return Err(DaemonError::Io(std::io::Error::new(
+                    std::io::ErrorKind::AddrInUse,
+                    "A daemon is already running for this workspace",
+                )));
+            }
+            std::fs::remove_file(&self.socket)?;
+        }
+
+        let listener = UnixListener::bind(&self.socket)?;
+        listener.set_nonblocking(true)?;
+        let mut last_request = Instant::now();
+
+        let result = loop {
+            let mut stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    if last_request.elapsed() >= self.idle_timeout {
+                        break Ok(());
+                    }
+                    std::thread::sleep(Duration::from_millis(50));
+                    continue;
+                }
+                Err(e) => break Err(DaemonError::Io(e)),
+            };
+            last_request = Instant::now();
+            stream.set_nonblocking(false)?;
+
+            let request: Request = match read_message(&mut stream) {
+                Ok(request) => request,
+                // A client that disconnects mid-request is not fatal
+                Err(_) => continue,
+            };
+
+            if request.protocol != PROTOCOL_VERSION || request.compiler_version != compiler_version() {
+                let _ = write_message(&mut stream, &mismatch_response());
+                break Ok(());
+            }
+
+            match self.handle(&request) {
+                Some(response) => {
+                    let _ = write_message(&mut stream, &response);
+                }
+                None => break Ok(()),
+            }
+        };
+
+        let _ = std::fs::remove_file(&self.socket);
+        result
+    }
+
+    #[cfg(not(unix))]
+    pub fn run(&mut self) -> Result<(), DaemonError> {
+        Err(DaemonError::Unsupported)
+    }
+}
+
+/// Sent instead of a result when the client's version differs; the daemon
+/// exits afterwards so the client can start a matching one
+fn mismatch_response() -> Response {
+    Response {
+        success: false,
+        diagnostics: vec![format!("version-mismatch: daemon {}", compiler_version())],
+        elapsed_ms: 0,
+        cache_hits: 0,
+        cache_misses: 0,
+        cached_modules: 0,
+    }
+}
+
+/// Send `request` to the workspace daemon, starting one if none is running
+#[cfg(unix)]
+pub fn send(workspace: &Path, request: &Request) -> Result<Response, DaemonError> {
+    use std::os::unix::net::UnixStream;
+
+    let socket = socket_path(workspace);
+    let mut stream = match UnixStream::connect(&socket) {
+        Ok(stream) => stream,
+        Err(_) => {
+            spawn_daemon(workspace)?;
+            wait_for_socket(&socket)?
+        }
+    };
+
+    write_message(&mut stream, request)?;
+    let response: Response = read_message(&mut stream)?;
+
+    let mismatch = response.diagnostics.first()
+        .and_then(|d| d.strip_prefix("version-mismatch: daemon "))
+        .map(str::to_string);
+    if let Some(daemon) = mismatch {
+        return Err(DaemonError::VersionMismatch {
+            daemon,
+            client: request.compiler_version.clone(),
+        });
+    }
+
+    Ok(response)
+}
+
+#[cfg(not(unix))]
+pub fn send(_workspace: &Path, _request: &Request) -> Result<Response, DaemonError> {
+    Err(DaemonError::Unsupported)
+}
+
+#[cfg(unix)]
+fn wait_for_socket(socket: &Path) -> Result<std::os::unix::net::UnixStream, DaemonError> {
+    let deadline = Instant::now() + SPAWN_WAIT;
+    loop {
+        match std::os::unix::net::UnixStream::connect(socket) {
+            Ok(stream) => return Ok(stream),
+            Err(e) if Instant::now() >= deadline => return Err(DaemonError::Io(e)),
+            Err(_) => std::thread::sleep(Duration::from_millis(20)),
+        }
+    }
+}
+
+/// Start `zaitun daemon` for `workspace` as a detached background process
+fn spawn_daemon(workspace: &Path) -> Result<(), DaemonError> {
+    use std::process::{Command as Process, Stdio};
+
+    let exe = std::env::current_exe()?;
+    Process::new(exe)
+        .arg("daemon")
+        .arg("--workspace")
+        .arg(workspace)
+        .current_dir(workspace)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+const SERVER_OPTIONS: &[&str] = &["--workspace", "--idle-timeout"];
+const CLIENT_OPTIONS: &[&str] = &["--no-daemon", "--macro-backtrace", "--error-limit", "--emit", "--lto", "--default-int", "--remarks", "--keep-debug-asserts", "--incremental", "--edition", "--memory-stats"];
+
+fn report_unknown_option(option: &str, known: &[&str]) {
+    eprintln!("Unknown option: {}", option);
+    if let Some(help) = suggest::did_you_mean(option, known.iter().copied()) {
+        eprintln!("help: {}", help);
+    }
+}
+
+/// Entry point for `zaitun daemon [--workspace DIR] [--idle-timeout SECS]`
+pub fn run_server(args: &[String]) -> i32 {
+    let mut workspace = PathBuf::from(".");
+    let mut idle_timeout = DEFAULT_IDLE_TIMEOUT;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--workspace" => {
+                if let Some(dir) = args.next() {
+                    workspace = PathBuf::from(dir);
+                }
+            }
+            "--idle-timeout" => {
+                if let Some(secs) = args.next().and_then(|s| s.parse().ok()) {
+                    idle_timeout = Duration::from_secs(secs);
+                }
+            }
+            other => {
+                report_unknown_option(other, SERVER_OPTIONS);
+                return 2;
+            }
+        }
+    }
+
+    let mut daemon = Daemon::new(socket_path(&workspace)).with_idle_timeout(idle_timeout);
+    match daemon.run() {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// Entry point for `zaitun build|check [--no-daemon] [--macro-backtrace] [--error-limit N]
+/// [--emit KINDS] [--lto] [--default-int TYPE] [--remarks] [--keep-debug-asserts] [--incremental DIR]
+/// [--edition YEAR] [--memory-stats]
+/// [-O<n>|-Os|-Oz] [-o OUT] FILES...`.
+/// A file of `-` reads source from stdin, named `<stdin>` in diagnostics;
+/// `-o -` writes artifacts to stdout.
+/// Goes through the workspace daemon unless `--no-daemon` or
+/// `--memory-stats` is given, output goes to stdout, or the daemon cannot
+/// be reached.
+pub fn run_client(command: &str, args: &[String]) -> i32 {
+    let command = if command == "check" { Command::Check } else { Command::Build };
+    let mut use_daemon = std::env::var_os("ZAITUN_NO_DAEMON").is_none();
+    let mut request = Request {
+        protocol: PROTOCOL_VERSION,
+        compiler_version: compiler_version(),
+        command,
+        sources: Vec::new(),
+        output: None,
+        optimization_level: 0,
+        size_level: SizeLevel::Speed,
+        lto: false,
+        macro_backtrace: false,
+        error_limit: DEFAULT_ERROR_LIMIT,
+        literal_policy: LiteralPolicy::default(),
+        remarks: false,
+        keep_debug_asserts: false,
+        incremental: None,
+        edition: Edition::default(),
+        memory_stats: false,
+        stdin_source: None,
+        emit: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-daemon" => use_daemon = false,
+            "--macro-backtrace" => request.macro_backtrace = true,
+            "--lto" => request.lto = true,
+            "--remarks" => request.remarks = true,
+            "--keep-debug-asserts" => request.keep_debug_asserts = true,
+            "--memory-stats" => request.memory_stats = true,
+            "--incremental" => match args.next() {
+                // The daemon may run in another directory
+                Some(dir) => request.incremental = Some(std::env::current_dir().unwrap_or_default().join(dir)),
+                None => {
+                    eprintln!("--incremental expects a directory");
+                    return 2;
+                }
+            },
+            "--edition" => match args.next().and_then(|year| Edition::parse(year)) {
+                Some(edition) => request.edition = edition,
+                None => {
+                    let years: Vec<&str> = Edition::ALL.iter().map(|edition| edition.as_str()).collect();
+                    eprintln!("--edition expects one of {}", years.join(", "));
+                    return 2;
+                }
+            },
+            "--error-limit" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(limit) => request.error_limit = limit,
+                None => {
+                    eprintln!("--error-limit expects a number (0 for no limit)");
+                    return 2;
+                }
+            },
+            "--default-int" => match args.next().and_then(|name| NumericType::parse(name)) {
+                Some(type_) if !type_.is_float() => request.literal_policy.default_int = type_,
+                _ => {
+                    eprintln!("--default-int expects an integer type such as i32 or i64");
+                    return 2;
+                }
+            },
+            "--emit" => {
+                let kinds = args.next().map(String::as_str).unwrap_or("");
+                if let Err(kind) = EmitKind::parse_list(kinds) {
+                    eprintln!("Unknown --emit kind: {}", kind);
+                    return 2;
+                }
+                request.emit.extend(kinds.split(',').map(str::to_string));
+            }
+            "-" => {
+                let mut text = String::new();
+                if let Err(e) = std::io::stdin().read_to_string(&mut text) {
+                    eprintln!("Failed to read standard input: {}", e);
+                    return 1;
+                }
+                request.stdin_source = Some(text);
+            }
+            "-o" => request.output = args.next().map(PathBuf::from),
+            level if level.starts_with("-O") => {
+                let (level, size) = SizeLevel::from_flag(&level[2..]).unwrap_or((2, SizeLevel::Speed));
+                request.optimization_level = level;
+                request.size_level = size;
+            }
+            option if option.starts_with("--") => {
+                report_unknown_option(option, CLIENT_OPTIONS);
+                return 2;
+            }
+            source => {
+                let path = PathBuf::from(source);
+                // The daemon may run in another directory
+                request.sources.push(path.canonicalize().unwrap_or(path));
+            }
+        }
+    }
+    let to_stdout = request.output.as_deref() == Some(Path::new(STDOUT_PATH));
+    if let Some(output) = &request.output {
+        if output.is_relative() && !to_stdout {
+            request.output = std::env::current_dir().ok().map(|dir| dir.join(output));
+        }
+    }
+    // Artifacts for `-o -` must reach this process's stdout, which a
+    // daemon cannot write to
+    if to_stdout {
+        use_daemon = false;
+    }
+    // The daemon's peak RSS covers every build it has served, not this one
+    if request.memory_stats {
+        use_daemon = false;
+    }
+
+    let workspace = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+
+    let response = if use_daemon {
+        match send(&workspace, &request) {
+            Ok(response) => Some(response),
+            Err(DaemonError::VersionMismatch { .. }) => {
+                // The stale daemon has exited; the next attempt starts ours
+                send(&workspace, &request).ok()
+            }
+            Err(_) => None,
+        }
+    } else {
+        None
+    };
+
+    let response = response.unwrap_or_else(|| {
+        let mut local = Daemon::new(PathBuf::new());
+        local.handle(&request).expect("build and check always respond")
+    });
+
+    for diagnostic in &response.diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+
+    if response.success { 0 } else { 1 }
+}
@@ -0,0 +1,145 @@
+//! Devirtualization of method calls.
+//!
+//! A call through an interface or a class that may be subclassed goes
+//! through the vtable. It can become a direct call, which the inliner can
+//! then see through, when every class the receiver could be resolves the
+//! method to the same implementation. The receiver's class is known either
+//! from flow analysis, e.g. the value was just constructed, or because its
+//! static type is `sealed`: a sealed type can only be extended in its own
+//! module and subclasses of a sealed class are sealed too, so every class
+//! that could be the receiver is known, also to dependent modules, which
+//! get the whole hierarchy through the interface file.
+
+use crate::types::{Type, TypeChecker};
+use std::collections::{BTreeSet, HashMap};
+
+/// How a method call is emitted
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dispatch {
+    /// Call `Class.method` directly
+    Direct { class: String, method: String },
+    /// Call through the vtable
+    Virtual,
+}
+
+impl Dispatch {
+    pub fn symbol(&self) -> Option<String> {
+        match self {
+            Dispatch::Direct { class, method } => Some(format!("{}.{}", class, method)),
+            Dispatch::Virtual => None,
+        }
+    }
+}
+
+/// Concrete classes of local variables, as far as flow analysis knows them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConcreteTypes {
+    classes: HashMap<String, String>,
+}
+
+impl ConcreteTypes {
+    pub fn new() -> Self {
+        ConcreteTypes::default()
+    }
+
+    /// `variable = Class(...)`
+    pub fn construct(&mut self, variable: &str, class: &str) {
+        self.classes.insert(variable.to_string(), class.to_string());
+    }
+
+    /// Any other assignment, e.g. from a call or a parameter
+    pub fn forget(&mut self, variable: &str) {
+        self.classes.remove(variable);
+    }
+
+    /// `variable = other`
+    pub fn copy(&mut self, variable: &str, other: &str) {
+        match self.classes.get(other).cloned() {
+            Some(class) => self.construct(variable, &class),
+            None => self.forget(variable),
+        }
+    }
+
+    /// Facts holding where two paths join: a class both agree on
+    pub fn join(&mut self, other: &ConcreteTypes) {
+        self.classes
+            .retain(|variable, class| other.classes.get(variable) == Some(class));
+    }
+
+    pub fn class_of(&self, variable: &str) -> Option<&str> {
+        self.classes.get(variable).map(String::as_str)
+    }
+}
+
+/// Dispatch for `receiver.method(...)`. `known_class` is the receiver's
+/// class if flow analysis proved it.
+pub fn resolve(types: &TypeChecker, receiver: &Type, method: &str, known_class: Option<&str>) -> Dispatch {
+    let candidates = match (known_class, receiver) {
+        (Some(class), _) => vec![class.to_string()],
+        (None, Type::Class(name)) | (None, Type::Interface(name)) => match possible_classes(types, receiver, name) {
+            Some(classes) => classes,
+            None => return Dispatch::Virtual,
+        },
+        _ => return Dispatch::Virtual,
+    };
+
+    let mut owners = BTreeSet::new();
+    for class in &candidates {
+        match implementation_of(types, class, method) {
+            Some(owner) => {
+                owners.insert(owner);
+            }
+            // No body to call; leave the error or the abstract call alone
+            None => return Dispatch::Virtual,
+        }
+    }
+
+    match owners.len() {
+        1 => Dispatch::Direct {
+            class: owners.into_iter().next().expect("one owner").to_string(),
+            method: method.to_string(),
+        },
+        _ => Dispatch::Virtual,
+    }
+}
+
+/// Every class a value of the sealed type `name` can be, or `None` when
+/// the type is open to extension
+fn possible_classes(types: &TypeChecker, receiver: &Type, name: &str) -> Option<Vec<String>> {
+    if !types.is_sealed(name) {
+        return None;
+    }
+
+    let mut pending: Vec<String> = match receiver {
+        Type::Interface(_) => types.implementers(name).to_vec(),
+        _ => vec![name.to_string()],
+    };
+    let mut classes = Vec::new();
+    while let Some(class) = pending.pop() {
+        // An implementer of a sealed interface may itself be open, and
+        // then has subclasses no module can list
+        if !types.is_sealed(&class) {
+            return None;
+        }
+        pending.extend(types.subclasses(&class).iter().cloned());
+        classes.push(class);
+    }
+    Some(classes)
+}
+
+/// The class whose body `class.method` runs: the nearest class up the
+/// parent chain that defines the method
+fn implementation_of<'a>(types: &'a TypeChecker, class: &'a str, method: &str) -> Option<&'a str> {
+    let mut current = Some(class);
+    while let Some(class) = current {
+        let defines = types
+            .members_of(class)
+            .iter()
+            .any(|(name, type_)| name == method && matches!(type_, Type::Function(_, _)));
+        if defines {
+            return Some(class);
+        }
+        current = types.parent_class(class);
+    }
+    None
+}
@@ -8,6 +8,16 @@ pub enum DocFormat {
     PlainText,
 }
 
+impl DocFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            DocFormat::HTML => "html",
+            DocFormat::Markdown => "md",
+            DocFormat::PlainText => "txt",
+        }
+    }
+}
+
 pub struct DocGenerator {
     ast: AST,
     output_format: DocFormat,
@@ -40,7 +50,7 @@ impl DocGenerator {
     }
     
     fn generate_module_doc(&self, module: &Module) -> Result<(), std::io::Error> {
-        let path = Path::new(&self.output_dir).join(format!("{}.md", module.name));
+        let path = Path::new(&self.output_dir).join(format!("{}.{}", module.name, self.output_format.extension()));
         let mut content = String::new();
         
         content.push_str(&format!("# Module {}\n\n", module.name));
@@ -96,8 +106,52 @@ impl DocGenerator {
         content.push_str("```\n\n");
     }
     
+    fn document_struct(&self, content: &mut String, struct_decl: &StructDecl) {
+        content.push_str(&format!("### `struct {}`\n\n", struct_decl.name));
+        
+        if let Some(doc) = &struct_decl.doc_comment {
+            content.push_str(&format!("{}\n\n", doc));
+        }
+        
+        for field in &struct_decl.fields {
+            content.push_str(&format!("- `{}: {}`\n", field.name, field.type_name));
+        }
+        content.push('\n');
+    }
+    
+    fn document_enum(&self, content: &mut String, enum_decl: &EnumDecl) {
+        content.push_str(&format!("### `enum {}`\n\n", enum_decl.name));
+        
+        if let Some(doc) = &enum_decl.doc_comment {
+            content.push_str(&format!("{}\n\n", doc));
+        }
+        
+        for variant in &enum_decl.variants {
+            content.push_str(&format!("- `{}`\n", variant));
+        }
+        content.push('\n');
+    }
+    
+    fn document_interface(&self, content: &mut String, interface: &InterfaceDecl) {
+        let keyword = if interface.is_sealed { "sealed interface" } else { "interface" };
+        content.push_str(&format!("### `{} {}`\n\n", keyword, interface.name));
+        
+        if let Some(doc) = &interface.doc_comment {
+            content.push_str(&format!("{}\n\n", doc));
+        }
+        
+        for method in &interface.methods {
+            let params: Vec<String> = method.params
+                .iter()
+                .map(|param| format!("{}: {}", param.name, param.type_name))
+                .collect();
+            content.push_str(&format!("- `fn {}({}) -> {}`\n", method.name, params.join(", "), method.return_type));
+        }
+        content.push('\n');
+    }
+    
     fn generate_index(&self) -> Result<(), std::io::Error> {
-        let path = Path::new(&self.output_dir).join("index.md");
+        let path = Path::new(&self.output_dir).join(format!("index.{}", self.output_format.extension()));
         let mut content = String::new();
         
         content.push_str("# SafeLang API Documentation\n\n");
@@ -105,7 +159,7 @@ impl DocGenerator {
         
         for node in &self.ast.nodes {
             if let ASTNode::Module(module) = node {
-                content.push_str(&format!("- [{0}]({0}.md)\n", module.name));
+                content.push_str(&format!("- [{0}]({0}.{1})\n", module.name, self.output_format.extension()));
             }
         }
         
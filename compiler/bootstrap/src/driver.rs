@@ -1,23 +1,72 @@
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use crate::checksum::Fingerprint;
+use crate::edition::Edition;
+use crate::error_handling::{MacroBacktrace, SourceMap};
+use crate::lto;
+use crate::numeric::LiteralPolicy;
+use crate::optimize::SizeLevel;
+use crate::interface::{ModuleInterface, INTERFACE_EXTENSION};
+use crate::kv_store::KvStore;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::memory_stats::MemoryReport;
+use crate::reproducible::PathRemapper;
+use crate::alloc_reuse;
+use crate::ssa::SsaFunction;
+use crate::vectorize::{self, CountedLoop, Remark, VectorPlan, VectorTarget};
+
+/// File name recorded in spans for source read from standard input
+pub const STDIN_FILE_NAME: &str = "<stdin>";
+
+/// Output path meaning "write to standard output"
+pub const STDOUT_PATH: &str = "-";
+
+/// Store in the `--incremental` directory holding build fingerprints
+pub const FINGERPRINT_STORE: &str = "fingerprints.kv";
 
 pub struct CompilerDriver {
     source_files: Vec<PathBuf>,
+    /// Sources supplied as text rather than read from disk, keyed by the
+    /// name used for them in spans
+    in_memory_sources: HashMap<PathBuf, String>,
+    /// Modules parsed with another edition than `options.edition`
+    module_editions: HashMap<PathBuf, Edition>,
     output_file: PathBuf,
     include_paths: Vec<PathBuf>,
+    interface_paths: Vec<PathBuf>,
     options: CompilerOptions,
     diagnostics: Vec<CompileError>,
+    /// Optimizer decisions, reported with `--remarks`
+    remarks: Vec<Remark>,
+    /// Allocations per pass of the last compile, reported with
+    /// `--memory-stats`
+    memory: MemoryReport,
+    source_map: SourceMap,
+}
+
+impl Default for CompilerDriver {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CompilerDriver {
     pub fn new() -> Self {
         CompilerDriver {
             source_files: Vec::new(),
+            in_memory_sources: HashMap::new(),
+            module_editions: HashMap::new(),
             output_file: PathBuf::from("a.out"),
             include_paths: Vec::new(),
+            interface_paths: Vec::new(),
             options: CompilerOptions::default(),
             diagnostics: Vec::new(),
+            remarks: Vec::new(),
+            memory: MemoryReport::new(),
+            source_map: SourceMap::new(),
         }
     }
     
@@ -33,6 +82,38 @@ impl CompilerDriver {
         }
     }
     
+    /// Compile `text` as if it were a file called `name`. The name only
+    /// appears in diagnostics and debug info; nothing is read from disk.
+    pub fn add_source_text(&mut self, name: &Path, text: String) {
+        if !self.in_memory_sources.contains_key(name) {
+            self.source_files.push(name.to_path_buf());
+        }
+        self.in_memory_sources.insert(name.to_path_buf(), text);
+    }
+    
+    /// Read the whole of standard input as a source named `<stdin>`
+    pub fn add_stdin_source(&mut self) -> Result<(), std::io::Error> {
+        let mut text = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut text)?;
+        self.add_source_text(Path::new(STDIN_FILE_NAME), text);
+        Ok(())
+    }
+    
+    /// Parse the module at `path` with `edition` rather than the edition
+    /// in the options, for builds mixing packages of different editions
+    pub fn set_module_edition(&mut self, path: &Path, edition: Edition) {
+        self.module_editions.insert(path.to_path_buf(), edition);
+    }
+    
+    fn edition_of(&self, path: &Path) -> Edition {
+        self.module_editions.get(path).copied().unwrap_or(self.options.edition)
+    }
+    
+    /// Whether artifacts go to standard output (`-o -`)
+    pub fn writes_to_stdout(&self) -> bool {
+        self.output_file == Path::new(STDOUT_PATH)
+    }
+    
     pub fn set_output_file(&mut self, path: &Path) {
         self.output_file = path.to_path_buf();
     }
@@ -49,18 +130,163 @@ impl CompilerDriver {
         }
     }
     
+    /// Add a directory searched for `.zi` interface files of dependencies
+    pub fn add_interface_path(&mut self, path: &Path) -> Result<(), std::io::Error> {
+        if path.is_dir() {
+            self.interface_paths.push(path.to_path_buf());
+            Ok(())
+        } else {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Interface directory not found: {}", path.display()),
+            ))
+        }
+    }
+    
+    /// Find and load the interface for an imported module, if one was emitted
+    pub fn load_interface(&self, module_name: &str) -> Result<Option<ModuleInterface>, CompileError> {
+        let file_name = format!("{}.{}", module_name, INTERFACE_EXTENSION);
+        
+        for dir in &self.interface_paths {
+            let path = dir.join(&file_name);
+            if path.is_file() {
+                return ModuleInterface::read_from(&path)
+                    .and_then(|interface| interface.ensure_current_compiler().map(|_| Some(interface)))
+                    .map_err(|e| CompileError::new(
+                        ErrorKind::IO,
+                        &format!("Failed to load interface {}: {}", path.display(), e),
+                        None,
+                    ));
+            }
+        }
+        
+        Ok(None)
+    }
+    
+    /// Sources loaded by the last compile. Cloning shares the loaded text,
+    /// so the daemon and editor integrations can render snippets without
+    /// reading files again.
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+    
     pub fn set_options(&mut self, options: CompilerOptions) {
         self.options = options;
     }
     
     pub fn compile(&mut self) -> Result<(), CompileError> {
+        self.compile_cached(&mut ModuleCache::new())
+    }
+    
+    /// Compile, reusing parsed and analyzed modules from `cache` for files
+    /// whose contents have not changed. Used by the daemon to keep results
+    /// warm between requests.
+    ///
+    /// With `--incremental DIR` the build is skipped entirely when its
+    /// output exists and its fingerprint matches the last successful build
+    /// of that output. Warnings of the skipped build are not repeated.
+    pub fn compile_cached(&mut self, cache: &mut ModuleCache) -> Result<(), CompileError> {
+        let Some(dir) = self.options.incremental.clone() else {
+            return self.compile_modules(cache);
+        };
+        
+        // A broken store only costs the skip, never the build
+        let mut store = KvStore::open(&dir.join(FINGERPRINT_STORE)).ok();
+        let key = self.output_file.to_string_lossy().into_owned();
+        let fingerprint = self.build_fingerprint();
+        let up_to_date = match (&store, &fingerprint) {
+            (Some(store), Some(fingerprint)) => {
+                !self.writes_to_stdout()
+                    && self.output_file.exists()
+                    && store.get(key.as_bytes()) == Some(&fingerprint[..])
+            }
+            _ => false,
+        };
+        if up_to_date {
+            self.diagnostics.clear();
+            return Ok(());
+        }
+        
+        let result = self.compile_modules(cache);
+        if let (Ok(()), Some(store), Some(fingerprint)) = (&result, &mut store, &fingerprint) {
+            if self.diagnostics.is_empty() {
+                let _ = store.put(key.as_bytes(), fingerprint);
+            }
+        }
+        result
+    }
+    
+    /// Fingerprint of everything the output depends on: the compiler, the
+    /// options and each source's path and contents. `None` when a source
+    /// cannot be read, which the build itself then reports.
+    fn build_fingerprint(&self) -> Option<[u8; 8]> {
+        // Unlike `DefaultHasher`, the same in every build of the compiler
+        // reading the stored value
+        let mut fingerprint = Fingerprint::new();
+        fingerprint.str_field(env!("CARGO_PKG_VERSION"));
+        // Neither changes what is built
+        let options = CompilerOptions { incremental: None, memory_stats: false, ..self.options.clone() };
+        fingerprint.str_field(&format!("{:?}", options));
+        for source_file in &self.source_files {
+            fingerprint
+                .str_field(&source_file.to_string_lossy())
+                .str_field(self.edition_of(source_file).as_str());
+            match self.in_memory_sources.get(source_file) {
+                Some(text) => fingerprint.str_field(text),
+                None => fingerprint.field(&fs::read(source_file).ok()?),
+            };
+        }
+        Some(fingerprint.finish().to_le_bytes())
+    }
+    
+    fn compile_modules(&mut self, cache: &mut ModuleCache) -> Result<(), CompileError> {
+        self.memory = MemoryReport::new();
+        let result = self.run_passes(cache);
+        self.memory.finish();
+        result
+    }
+    
+    fn run_passes(&mut self, cache: &mut ModuleCache) -> Result<(), CompileError> {
         self.diagnostics.clear();
+        self.remarks.clear();
         
-        // 1. Parse all source files
-        let mut asts = HashMap::new();
+        // 1. Parse all source files. Keyed in path order so module order,
+        // and everything generated from it, does not depend on hashing.
+        self.memory.begin_pass("parse");
+        let mut asts = BTreeMap::new();
+        let mut module_hashes = BTreeMap::new();
         for source_file in &self.source_files {
-            match self.parse_file(source_file) {
+            let read = match self.in_memory_sources.get(source_file) {
+                Some(text) => Ok(text.clone()),
+                None => fs::read_to_string(source_file),
+            };
+            let content = match read {
+                Ok(content) => content,
+                Err(e) => {
+                    self.diagnostics.push(CompileError::new(
+                        ErrorKind::IO,
+                        &format!("Failed to read file: {}", e),
+                        None,
+                    ));
+                    continue;
+                }
+            };
+            // The same text means something else in another edition
+            let edition = self.edition_of(source_file);
+            let hash = content_hash(&content, edition);
+            module_hashes.insert(source_file.clone(), hash);
+            self.source_map.add_source(source_file.clone(), content.clone());
+            
+            if let Some(ast) = cache.parsed(source_file, hash) {
+                cache.hits += 1;
+                asts.insert(source_file.clone(), ast);
+                continue;
+            }
+            
+            cache.misses += 1;
+            match self.parse_source(source_file, &content, edition) {
                 Ok(ast) => {
+                    cache.store_parsed(source_file, hash, ast.clone());
                     asts.insert(source_file.clone(), ast);
                 }
                 Err(error) => {
@@ -69,15 +295,53 @@ impl CompilerDriver {
             }
         }
         
+        // Analysis of one module depends on the others, so cached modules
+        // are only reused for exactly the same set of sources: adding or
+        // removing a module changes what names resolve to as much as
+        // editing one
+        let inputs = AnalysisInputs {
+            modules: module_hashes.into_iter().collect(),
+            literal_policy: self.options.literal_policy,
+            optimization_level: self.options.optimization_level,
+            keep_debug_asserts: self.options.keep_debug_asserts,
+        };
+        if cache.analyzed_with.as_ref() != Some(&inputs) {
+            cache.analyzed.clear();
+            cache.analyzed_with = Some(inputs);
+        }
+        
         if !self.diagnostics.is_empty() && self.options.fail_on_error {
             return Err(self.diagnostics[0].clone());
         }
         
+        if self.options.emit.contains(&EmitKind::AstJson) {
+            for (file, ast) in &asts {
+                if let Err(error) = self.emit_ast_json(file, ast) {
+                    self.diagnostics.push(error);
+                }
+            }
+            
+            // Emitting only the AST stops after parsing
+            if self.options.emit.iter().all(|kind| *kind == EmitKind::AstJson) {
+                return match self.diagnostics.first() {
+                    Some(error) if self.options.fail_on_error => Err(error.clone()),
+                    _ => Ok(()),
+                };
+            }
+        }
+        
         // 2. Semantic analysis
+        self.memory.begin_pass("analyze");
         let mut program = Program::new();
         for (file, ast) in &asts {
+            if let Some(module) = cache.analyzed.get(file) {
+                program.add_module(module.clone());
+                continue;
+            }
+            
             match self.analyze(file, ast) {
                 Ok(module) => {
+                    cache.analyzed.insert(file.clone(), module.clone());
                     program.add_module(module);
                 }
                 Err(error) => {
@@ -90,19 +354,62 @@ impl CompilerDriver {
             return Err(self.diagnostics[0].clone());
         }
         
+        if self.options.emit.contains(&EmitKind::Interface) {
+            for module in &program.modules {
+                if let Err(error) = self.emit_interface(module) {
+                    self.diagnostics.push(error);
+                }
+            }
+        }
+        
         // 3. Optimization (if enabled)
         if self.options.optimization_level > 0 {
-            self.optimize(&mut program);
+            self.memory.begin_pass("optimize");
+            let mut remarks = Vec::new();
+            self.optimize(&mut program, &mut remarks);
+            self.remarks = remarks;
+        }
+        
+        // Per-module IR for a later `--lto` link, e.g. when this package is
+        // built as a dependency
+        if self.options.emit.contains(&EmitKind::LtoIr) {
+            for module in &program.modules {
+                if let Err(error) = self.emit_lto_ir(module) {
+                    self.diagnostics.push(error);
+                }
+            }
+            
+            if self.options.emit.iter().all(|kind| matches!(kind, EmitKind::LtoIr | EmitKind::Interface)) {
+                return match self.diagnostics.first() {
+                    Some(error) if self.options.fail_on_error => Err(error.clone()),
+                    _ => Ok(()),
+                };
+            }
+        }
+        
+        // 3b. Whole-program optimization: merge this program's IR with
+        // dependency IR and rerun inlining and dead-code elimination
+        // before code generation lowers the linked program
+        if self.options.lto {
+            self.memory.begin_pass("lto");
+            if let Err(error) = self.link_time_optimize(&program) {
+                self.diagnostics.push(error.clone());
+                if self.options.fail_on_error {
+                    return Err(error);
+                }
+            }
         }
         
         // 4. Code generation
+        self.memory.begin_pass("codegen");
         match self.generate_code(&program) {
             Ok(ir) => {
                 // 5. Output generation
+                self.memory.begin_pass("output");
                 match self.output_generation(&ir) {
                     Ok(_) => Ok(()),
                     Err(error) => {
-                        self.diagnostics.push(error);
+                        self.diagnostics.push(error.clone());
                         if self.options.fail_on_error {
                             Err(error)
                         } else {
@@ -112,7 +419,7 @@ impl CompilerDriver {
                 }
             }
             Err(error) => {
-                self.diagnostics.push(error);
+                self.diagnostics.push(error.clone());
                 if self.options.fail_on_error {
                     Err(error)
                 } else {
@@ -122,38 +429,154 @@ impl CompilerDriver {
         }
     }
     
-    fn parse_file(&self, file: &Path) -> Result<AST, CompileError> {
-        // Read file content
-        let content = fs::read_to_string(file)
+    fn parse_source(&self, file: &Path, content: &str, edition: Edition) -> Result<ParsedModule, CompileError> {
+        let mut parser = Parser::new(Lexer::with_edition(content.to_string(), edition)).with_file(file);
+        let tree = parser.parse();
+        if let Some(error) = parser.errors().first() {
+            let span = error.span.as_ref().map(|span| Span {
+                file: file.to_path_buf(),
+                start_line: span.start.line,
+                start_column: span.start.column,
+                end_line: span.end.line,
+                end_column: span.end.column,
+            });
+            return Err(CompileError::new(ErrorKind::Parse, &error.message, span));
+        }
+        Ok(ParsedModule { edition, tree })
+    }
+    
+    fn emit_ast_json(&self, file: &Path, ast: &ParsedModule) -> Result<(), CompileError> {
+        let json = crate::ast_json::to_string(&self.options.path_remapper.remap(file), &ast.tree)
+            .map_err(|e| CompileError::new(ErrorKind::CodeGen, &e.to_string(), None))?;
+        if self.writes_to_stdout() {
+            return write_stdout(json.as_bytes());
+        }
+        
+        let output = if self.source_files.len() == 1 {
+            self.output_file.with_extension("ast.json")
+        } else {
+            file.with_extension("ast.json")
+        };
+        
+        fs::write(&output, json)
+            .map_err(|e| CompileError::new(
+                ErrorKind::IO,
+                &format!("Failed to write {}: {}", output.display(), e),
+                None,
+            ))
+    }
+    
+    fn emit_interface(&self, module: &Module) -> Result<(), CompileError> {
+        if self.writes_to_stdout() {
+            return write_stdout(&module.interface.encode());
+        }
+        
+        let dir = self.output_file.parent().unwrap_or(Path::new("."));
+        let path = dir.join(format!("{}.{}", module.name, INTERFACE_EXTENSION));
+        
+        module.interface.write_to(&path)
             .map_err(|e| CompileError::new(
                 ErrorKind::IO,
-                &format!("Failed to read file: {}", e),
+                &format!("Failed to write interface {}: {}", path.display(), e),
                 None,
-            ))?;
+            ))
+    }
+    
+    fn emit_lto_ir(&self, module: &Module) -> Result<(), CompileError> {
+        let dir = self.output_file.parent().unwrap_or(Path::new("."));
+        let path = dir.join(format!("{}.{}", module.name, lto::LTO_IR_EXTENSION));
         
-        // Parse file content
+        self.lower_module_ir(module).write_to(&path)
+            .map_err(|e| CompileError::new(
+                ErrorKind::IO,
+                &format!("Failed to write LTO IR {}: {}", path.display(), e),
+                None,
+            ))
+    }
+    
+    fn lower_module_ir(&self, module: &Module) -> lto::ModuleIr {
+        // Lower each function to link-time IR
         // ... implementation details ...
-        Ok(AST {})
+        lto::ModuleIr::new(&module.name)
     }
     
-    fn analyze(&self, file: &Path, ast: &AST) -> Result<Module, CompileError> {
-        // Perform semantic analysis
+    /// Link this program's IR with `.zir` files found on the interface
+    /// path, where the package manager places cached dependency IR
+    fn link_time_optimize(&self, program: &Program) -> Result<lto::LinkedProgram, CompileError> {
+        let lto_error = |e: lto::LtoError| CompileError::new(ErrorKind::IO, &format!("LTO failed: {}", e), None);
+        
+        let mut modules: Vec<lto::ModuleIr> = program.modules
+            .iter()
+            .map(|module| self.lower_module_ir(module))
+            .collect();
+        for dir in &self.interface_paths {
+            modules.extend(lto::read_dir(dir).map_err(lto_error)?);
+        }
+        
+        let mut options = lto::LtoOptions::default();
+        // Size presets never inline
+        if self.options.size_level != SizeLevel::Speed {
+            options.inline_threshold = 0;
+        }
+        
+        lto::link(modules, &options).map_err(lto_error)
+    }
+    
+    fn analyze(&self, _file: &Path, ast: &ParsedModule) -> Result<Module, CompileError> {
+        // Perform semantic analysis; unsuffixed literals are typed with
+        // `options.literal_policy`, and `static` items are checked and
+        // ordered by `statics::StaticChecker`. `assert` and `debug_assert`
+        // calls are checked with `assert::check_args`, and those whose
+        // `AssertKind::is_enabled` is false for `optimization_level` and
+        // `keep_debug_asserts` are dropped here, so codegen and the VM see
+        // the same program
         // ... implementation details ...
-        Ok(Module::new("module"))
+        let mut module = Module::new("module");
+        // Recorded so dependents know which edition the module was written in
+        module.interface.edition = ast.edition;
+        Ok(module)
     }
     
-    fn optimize(&self, program: &mut Program) {
-        // Apply optimizations based on optimization level
+    /// Optimize `program`, recording each decision worth a remark in
+    /// `remarks`
+    fn optimize(&self, program: &mut Program, remarks: &mut Vec<Remark>) {
+        // Apply the `Optimizer::for_level(optimization_level, size_level)`
+        // pipeline to each module, then `optimize_function` to each
+        // function once lowered to SSA, reporting its `unreachable_code`
+        // warnings
         // ... implementation details ...
+        
+        let target = VectorTarget::for_triple(&self.options.target_triple);
+        for module in &mut program.modules {
+            for function in &mut module.functions {
+                remarks.extend(alloc_reuse::run(function).remarks);
+            }
+            
+            module.vector_plans.clear();
+            for (index, counted_loop) in module.loops.iter().enumerate() {
+                let (plan, remark) = vectorize::vectorize(counted_loop, target);
+                remarks.push(remark);
+                if let Some(plan) = plan {
+                    module.vector_plans.push((index, plan));
+                }
+            }
+        }
     }
     
-    fn generate_code(&self, program: &Program) -> Result<IR, CompileError> {
-        // Generate intermediate representation
+    fn generate_code(&self, _program: &Program) -> Result<IR, CompileError> {
+        // Generate intermediate representation; statics come from
+        // `InitPlan::llvm_globals`, `match` on integer or string literals
+        // is lowered through `switch_lower`, and assertions the analysis
+        // kept through `Assertion::llvm_ir`
         // ... implementation details ...
         Ok(IR {})
     }
     
-    fn output_generation(&self, ir: &IR) -> Result<(), CompileError> {
+    fn output_generation(&self, _ir: &IR) -> Result<(), CompileError> {
+        // Source paths in debug info go through `path_remapper`, and object
+        // headers that carry a timestamp use `reproducible::artifact_timestamp()`
+        // instead of the current time. With `-o -` the artifact is written
+        // through `write_stdout` instead of to a file.
         // Generate output file
         // ... implementation details ...
         Ok(())
@@ -162,35 +585,259 @@ impl CompilerDriver {
     pub fn get_diagnostics(&self) -> &[CompileError] {
         &self.diagnostics
     }
+    
+    pub fn remarks(&self) -> &[Remark] {
+        &self.remarks
+    }
+    
+    pub fn memory_report(&self) -> &MemoryReport {
+        &self.memory
+    }
+    
+    /// Diagnostics rendered as `file:line:column: message`, cut off after
+    /// `error_limit` with a summary of how many were left out, followed by
+    /// remarks if `--remarks` asked for them and the memory report if
+    /// `--memory-stats` did
+    pub fn diagnostic_messages(&self) -> Vec<String> {
+        let limit = match self.options.error_limit {
+            0 => self.diagnostics.len(),
+            limit => limit.min(self.diagnostics.len()),
+        };
+        
+        let mut messages: Vec<String> = self.diagnostics[..limit]
+            .iter()
+            .map(|error| error.to_string())
+            .collect();
+        
+        let hidden = self.diagnostics.len() - limit;
+        if hidden > 0 {
+            messages.push(format!(
+                "... and {} more error{} (use --error-limit 0 to show all)",
+                hidden,
+                if hidden == 1 { "" } else { "s" },
+            ));
+        }
+        
+        if self.options.remarks {
+            messages.extend(self.remarks.iter().map(|remark| remark.to_string()));
+        }
+        
+        if self.options.memory_stats {
+            messages.push(self.memory.to_string());
+        }
+        
+        messages
+    }
+}
+
+/// Parsed and analyzed modules kept between compilations, keyed by path
+/// and validated by a hash of the file contents
+pub struct ModuleCache {
+    parsed: HashMap<PathBuf, (u64, ParsedModule)>,
+    analyzed: HashMap<PathBuf, Module>,
+    /// What `analyzed` was computed from
+    analyzed_with: Option<AnalysisInputs>,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Default for ModuleCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ModuleCache {
+    pub fn new() -> Self {
+        ModuleCache {
+            parsed: HashMap::new(),
+            analyzed: HashMap::new(),
+            analyzed_with: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+    
+    fn parsed(&self, file: &Path, hash: u64) -> Option<ParsedModule> {
+        match self.parsed.get(file) {
+            Some((cached_hash, ast)) if *cached_hash == hash => Some(ast.clone()),
+            _ => None,
+        }
+    }
+    
+    fn store_parsed(&mut self, file: &Path, hash: u64, ast: ParsedModule) {
+        self.parsed.insert(file.to_path_buf(), (hash, ast));
+    }
+    
+    pub fn len(&self) -> usize {
+        self.parsed.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.parsed.is_empty()
+    }
+    
+    pub fn clear(&mut self) {
+        self.parsed.clear();
+        self.analyzed.clear();
+        self.analyzed_with = None;
+    }
+}
+
+/// Everything analysis results depend on, so cached ones are only reused
+/// when all of it is the same
+#[derive(Debug, Clone, PartialEq)]
+struct AnalysisInputs {
+    /// Path and content hash of every module, in path order
+    modules: Vec<(PathBuf, u64)>,
+    /// Literal typing changes the types analysis infers
+    literal_policy: LiteralPolicy,
+    /// Analysis drops the assertions `AssertKind::is_enabled` turns off
+    /// for these
+    optimization_level: u8,
+    keep_debug_asserts: bool,
+}
+
+/// Write an artifact to standard output for `-o -`
+fn write_stdout(bytes: &[u8]) -> Result<(), CompileError> {
+    use std::io::Write;
+    
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(bytes)
+        .and_then(|_| stdout.flush())
+        .map_err(|e| CompileError::new(
+            ErrorKind::IO,
+            &format!("Failed to write to standard output: {}", e),
+            None,
+        ))
+}
+
+fn content_hash(content: &str, edition: Edition) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    content.hash(&mut hasher);
+    edition.hash(&mut hasher);
+    hasher.finish()
 }
 
 #[derive(Debug, Clone)]
 pub struct CompilerOptions {
     pub optimization_level: u8,
+    /// `-Os`/`-Oz` select size presets on top of `optimization_level`
+    pub size_level: SizeLevel,
     pub debug_info: bool,
     pub fail_on_error: bool,
     pub emit_warnings: bool,
     pub target_triple: String,
+    pub emit: Vec<EmitKind>,
+    /// `--remap-path-prefix` mappings applied to paths recorded in artifacts
+    pub path_remapper: PathRemapper,
+    /// `--macro-backtrace`: show every expansion level in diagnostics
+    /// instead of only the outermost invocation
+    pub macro_backtrace: MacroBacktrace,
+    /// `--error-limit N`: most errors to print, 0 for no limit
+    pub error_limit: usize,
+    /// `--lto`: optimize across module and dependency boundaries at link time
+    pub lto: bool,
+    /// `--default-int TYPE`: type of unsuffixed integer literals
+    pub literal_policy: LiteralPolicy,
+    /// `--remarks`: report optimizer decisions, e.g. why a loop was or
+    /// was not vectorized
+    pub remarks: bool,
+    /// `--keep-debug-asserts`: keep `debug_assert` from `-O2` on, where it
+    /// is otherwise removed
+    pub keep_debug_asserts: bool,
+    /// `--incremental DIR`: skip builds whose fingerprint in `DIR` shows
+    /// nothing changed since the output was written
+    pub incremental: Option<PathBuf>,
+    /// `--edition YEAR`: edition modules are parsed with, unless set per
+    /// module with `CompilerDriver::set_module_edition`
+    pub edition: Edition,
+    /// `--memory-stats`: report peak RSS and each pass's allocations
+    pub memory_stats: bool,
+}
+
+/// Artifacts requested with `--emit`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmitKind {
+    /// Native object/executable output
+    Binary,
+    /// Textual LLVM IR
+    LlvmIr,
+    /// Versioned JSON rendering of the parsed AST, see `ast_json`
+    AstJson,
+    /// Exported signatures as a binary `.zi` interface file
+    Interface,
+    /// Serialized per-module IR (`.zir`) for a later `--lto` link
+    LtoIr,
 }
 
+impl EmitKind {
+    pub fn parse(value: &str) -> Option<EmitKind> {
+        match value {
+            "bin" | "binary" => Some(EmitKind::Binary),
+            "llvm-ir" => Some(EmitKind::LlvmIr),
+            "ast-json" => Some(EmitKind::AstJson),
+            "interface" | "zi" => Some(EmitKind::Interface),
+            "lto-ir" | "zir" => Some(EmitKind::LtoIr),
+            _ => None,
+        }
+    }
+    
+    /// Comma-separated `--emit` value such as `ast-json,interface`; the
+    /// error is the first kind not recognized
+    pub fn parse_list(value: &str) -> Result<Vec<EmitKind>, String> {
+        value
+            .split(',')
+            .map(|kind| EmitKind::parse(kind).ok_or_else(|| kind.to_string()))
+            .collect()
+    }
+}
+
+/// Enough to fix a batch of real mistakes without scrolling past the
+/// cascade a single broken brace can cause
+pub const DEFAULT_ERROR_LIMIT: usize = 50;
+
 impl Default for CompilerOptions {
     fn default() -> Self {
         CompilerOptions {
             optimization_level: 0,
+            size_level: SizeLevel::Speed,
             debug_info: false,
             fail_on_error: true,
             emit_warnings: true,
             target_triple: String::from("x86_64-unknown-linux-gnu"),
+            emit: vec![EmitKind::Binary],
+            path_remapper: PathRemapper::new(),
+            macro_backtrace: MacroBacktrace::Collapsed,
+            error_limit: DEFAULT_ERROR_LIMIT,
+            lto: false,
+            literal_policy: LiteralPolicy::default(),
+            remarks: false,
+            keep_debug_asserts: false,
+            incremental: None,
+            edition: Edition::default(),
+            memory_stats: false,
         }
     }
 }
 
-struct AST {
-    // AST structure
+#[derive(Clone)]
+struct ParsedModule {
+    /// Edition the module was parsed with
+    edition: Edition,
+    tree: crate::ast::AST,
 }
 
+#[derive(Clone)]
 struct Module {
     name: String,
+    interface: ModuleInterface,
+    /// Functions lowered to SSA for the optimizer
+    functions: Vec<SsaFunction>,
+    /// Counted loops lowered for `vectorize`
+    loops: Vec<CountedLoop>,
+    /// How to emit each vectorized loop, by index into `loops`
+    vector_plans: Vec<(usize, VectorPlan)>,
     // Module structure
 }
 
@@ -198,6 +845,10 @@ impl Module {
     fn new(name: &str) -> Self {
         Module {
             name: name.to_string(),
+            interface: ModuleInterface::new(name),
+            functions: Vec::new(),
+            loops: Vec::new(),
+            vector_plans: Vec::new(),
         }
     }
 }
@@ -223,12 +874,28 @@ struct IR {
 }
 
 #[derive(Debug, Clone)]
-struct CompileError {
+pub struct CompileError {
     kind: ErrorKind,
     message: String,
     span: Option<Span>,
 }
 
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.span {
+            Some(span) => write!(
+                f,
+                "{}:{}:{}: {}",
+                span.file.display(),
+                span.start_line,
+                span.start_column,
+                self.message
+            ),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
 impl CompileError {
     fn new(kind: ErrorKind, message: &str, span: Option<Span>) -> Self {
         CompileError {
@@ -237,10 +904,18 @@ impl CompileError {
             span,
         }
     }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn span(&self) -> Option<&Span> {
+        self.span.as_ref()
+    }
 }
 
 #[derive(Debug, Clone)]
-enum ErrorKind {
+pub enum ErrorKind {
     IO,
     Parse,
     Type,
@@ -249,10 +924,115 @@ enum ErrorKind {
 }
 
 #[derive(Debug, Clone)]
-struct Span {
-    file: PathBuf,
-    start_line: usize,
-    start_column: usize,
-    end_line: usize,
-    end_column: usize,
-}
\ No newline at end of file
+pub struct Span {
+    pub file: PathBuf,
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error_handling::{SourceLocation, Span as SourceSpan};
+    use crate::types::Type;
+    use crate::vectorize::{ArrayInfo, LoopExpr, LoopStmt, RemarkKind};
+
+    /// `for i in 0..n { out[i] = a[i] * 2.0 }`
+    fn scale_loop(no_vectorize: bool) -> CountedLoop {
+        let location = |line| SourceLocation {
+            file: PathBuf::from("scale.zt"),
+            line,
+            column: 5,
+        };
+        let array = |name: &str| ArrayInfo {
+            name: name.to_string(),
+            element: Type::Float,
+            unique: true,
+        };
+        CountedLoop {
+            function: "scale".to_string(),
+            span: SourceSpan {
+                start: location(2),
+                end: location(4),
+            },
+            no_vectorize,
+            counter: "i".to_string(),
+            step: 1,
+            arrays: vec![array("a"), array("out")],
+            body: vec![LoopStmt::Store {
+                array: "out".to_string(),
+                offset: 0,
+                value: LoopExpr::Binary(
+                    "*".to_string(),
+                    Box::new(LoopExpr::Load { array: "a".to_string(), offset: 0 }),
+                    Box::new(LoopExpr::Float(2.0)),
+                ),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_optimize_records_remarks() {
+        let mut driver = CompilerDriver::new();
+        driver.set_options(CompilerOptions {
+            optimization_level: 2,
+            remarks: true,
+            ..CompilerOptions::default()
+        });
+
+        let mut module = Module::new("scale");
+        module.loops.push(scale_loop(false));
+        module.loops.push(scale_loop(true));
+        let mut program = Program::new();
+        program.add_module(module);
+
+        let mut remarks = Vec::new();
+        driver.optimize(&mut program, &mut remarks);
+        let kinds: Vec<&RemarkKind> = remarks.iter().map(|remark| &remark.kind).collect();
+        assert_eq!(kinds, [&RemarkKind::Passed, &RemarkKind::Missed]);
+        assert!(remarks.iter().all(|remark| remark.pass == "vectorize" && remark.function == "scale"));
+        assert_eq!(program.modules[0].vector_plans.len(), 1);
+
+        driver.remarks = remarks;
+        let messages = driver.diagnostic_messages();
+        assert!(messages.iter().any(|message| message.contains("loop vectorized")));
+        assert!(messages.iter().any(|message| message.contains("`#[no_vectorize]`")));
+    }
+
+    /// Compile `source` as `main.zt` with `cache` and report whether the
+    /// analyzed module came from the cache. A cached module is marked so a
+    /// reanalyzed one can be told apart.
+    fn analysis_reused(cache: &mut ModuleCache, source: &str, options: CompilerOptions, edition: Edition) -> bool {
+        let file = Path::new("main.zt");
+        let mut driver = CompilerDriver::new();
+        driver.set_options(options);
+        driver.add_source_text(file, source.to_string());
+        driver.set_module_edition(file, edition);
+        driver.compile_cached(cache).unwrap();
+
+        let module = cache.analyzed.get_mut(file).expect("module was analyzed");
+        let reused = module.name == "cached";
+        module.name = "cached".to_string();
+        reused
+    }
+
+    #[test]
+    fn test_analysis_cache_inputs() {
+        let source = "fn main() { let x = 1; }";
+        let options = CompilerOptions::default;
+        let mut cache = ModuleCache::new();
+
+        assert!(!analysis_reused(&mut cache, source, options(), Edition::E2024));
+        assert!(analysis_reused(&mut cache, source, options(), Edition::E2024));
+
+        assert!(!analysis_reused(&mut cache, source, options(), Edition::E2026));
+        assert!(analysis_reused(&mut cache, source, options(), Edition::E2026));
+
+        let keep_asserts = || CompilerOptions { keep_debug_asserts: true, ..options() };
+        assert!(!analysis_reused(&mut cache, source, keep_asserts(), Edition::E2026));
+        assert!(analysis_reused(&mut cache, source, keep_asserts(), Edition::E2026));
+
+        assert!(!analysis_reused(&mut cache, "fn main() { let x = 2; }", keep_asserts(), Edition::E2026));
+    }
+}
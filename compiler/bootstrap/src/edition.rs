@@ -0,0 +1,215 @@
+//! Editions: opt-in language changes that would break existing code, such
+//! as new keywords.
+//!
+//! A package picks its edition with `edition = "2026"` in its manifest,
+//! which the package manager passes on as `--edition`, and every module is
+//! parsed with the edition of the package it belongs to. Modules of
+//! different editions work together: interfaces record each module's
+//! edition and its exported names as plain identifiers, so a `yield`
+//! exported by a 2024 module is `r#yield` to a 2026 one.
+//!
+//! Moving to a newer edition is helped by migration lints, which flag code
+//! whose meaning changes in the next edition and carry a fix for it.
+//! `zaitun migrate --edition YEAR FILES...` applies the fixes.
+
+use std::cmp::Reverse;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error_handling::{CompileError, ErrorKind, SourceLocation, Span};
+use crate::lexer::{Lexer, TokenType};
+
+/// Lint name of the migration diagnostics
+pub const MIGRATION_LINT: &str = "edition_keywords";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+pub enum Edition {
+    /// The first edition, and the one code without an `edition` gets
+    #[default]
+    #[serde(rename = "2024")]
+    E2024,
+    /// `async`, `await`, `yield` and `sealed` become keywords
+    #[serde(rename = "2026")]
+    E2026,
+}
+
+impl Edition {
+    /// Oldest first
+    pub const ALL: &'static [Edition] = &[Edition::E2024, Edition::E2026];
+
+    pub const LATEST: Edition = Edition::E2026;
+
+    pub fn parse(year: &str) -> Option<Edition> {
+        Edition::ALL.iter().copied().find(|edition| edition.as_str() == year)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Edition::E2024 => "2024",
+            Edition::E2026 => "2026",
+        }
+    }
+
+    /// The edition before this one, which migrating to this one starts from
+    pub fn previous(self) -> Option<Edition> {
+        let index = Edition::ALL.iter().position(|edition| *edition == self)?;
+        index.checked_sub(1).map(|index| Edition::ALL[index])
+    }
+
+    /// Tag in interface files
+    pub(crate) fn tag(self) -> u8 {
+        match self {
+            Edition::E2024 => 0,
+            Edition::E2026 => 1,
+        }
+    }
+
+    pub(crate) fn from_tag(tag: u8) -> Option<Edition> {
+        Edition::ALL.iter().copied().find(|edition| edition.tag() == tag)
+    }
+}
+
+impl fmt::Display for Edition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Diagnostics for code in `source` written for edition `from` that
+/// changes meaning in `to`: identifiers that are keywords in `to`. Each
+/// fix turns one into a raw identifier.
+pub fn migration_lints(file: &Path, source: &str, from: Edition, to: Edition) -> Vec<CompileError> {
+    let old = Lexer::with_edition(source.to_string(), from).scan_tokens();
+    let new = Lexer::with_edition(source.to_string(), to).scan_tokens();
+
+    // Editions only change how words lex, never how many tokens there are
+    old.iter()
+        .zip(&new)
+        .filter(|(old, new)| old.token_type == TokenType::Identifier && new.token_type != TokenType::Identifier)
+        .map(|(token, _)| {
+            let location = |column| SourceLocation {
+                file: file.to_path_buf(),
+                line: token.line,
+                column,
+            };
+            CompileError::new(
+                ErrorKind::Lint(MIGRATION_LINT.to_string()),
+                &format!("`{}` is a keyword in edition {}", token.lexeme, to),
+            )
+            .with_span(Span {
+                start: location(token.column),
+                end: location(token.column + token.lexeme.chars().count()),
+            })
+            .with_help(&format!("rename it, or write `r#{}` to keep the name", token.lexeme))
+            .with_fix("Use a raw identifier", &format!("r#{}", token.lexeme))
+        })
+        .collect()
+}
+
+/// Apply the first fix of each diagnostic to `source`. Diagnostics without
+/// a fix or a single-line span are left for the user.
+pub fn apply_fixes(source: &str, diagnostics: &[CompileError]) -> String {
+    let mut edits: Vec<(usize, usize, &str)> = diagnostics
+        .iter()
+        .filter_map(|diagnostic| {
+            let span = diagnostic.span.as_ref()?;
+            let fix = diagnostic.fixes.first()?;
+            if span.start.line != span.end.line {
+                return None;
+            }
+            let start = byte_offset(source, span.start.line, span.start.column)?;
+            let end = byte_offset(source, span.end.line, span.end.column)?;
+            Some((start, end, fix.replacement.as_str()))
+        })
+        .collect();
+
+    // From the end, so earlier offsets stay valid
+    edits.sort_by_key(|edit| Reverse(edit.0));
+    edits.dedup_by_key(|edit| edit.0);
+    let mut fixed = source.to_string();
+    for (start, end, replacement) in edits {
+        fixed.replace_range(start..end, replacement);
+    }
+    fixed
+}
+
+/// Byte offset of a 1-based line and column, columns counting characters
+/// as the lexer does
+fn byte_offset(source: &str, line: usize, column: usize) -> Option<usize> {
+    let line_start: usize = source.split_inclusive('\n').take(line.checked_sub(1)?).map(str::len).sum();
+    let text = source[line_start..].split('\n').next()?;
+    let within = match text.char_indices().nth(column.checked_sub(1)?) {
+        Some((offset, _)) => offset,
+        // Just past the last character
+        None if text.chars().count() == column - 1 => text.len(),
+        None => return None,
+    };
+    Some(line_start + within)
+}
+
+/// Entry point for `zaitun migrate [--edition YEAR] [--check] FILES...`:
+/// rewrite the files for `YEAR` (the latest edition by default), or with
+/// `--check` only report what would change
+pub fn run_migrate(args: &[String]) -> i32 {
+    let mut to = Edition::LATEST;
+    let mut check = false;
+    let mut files = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--edition" => match args.next().and_then(|year| Edition::parse(year)) {
+                Some(edition) => to = edition,
+                None => {
+                    let years: Vec<&str> = Edition::ALL.iter().map(|edition| edition.as_str()).collect();
+                    eprintln!("--edition expects one of {}", years.join(", "));
+                    return 2;
+                }
+            },
+            file => files.push(PathBuf::from(file)),
+        }
+    }
+
+    let Some(from) = to.previous() else {
+        eprintln!("Edition {} is the first edition; there is nothing to migrate from", to);
+        return 0;
+    };
+
+    let mut remaining = 0;
+    for file in &files {
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", file.display(), e);
+                return 1;
+            }
+        };
+        let lints = migration_lints(file, &source, from, to);
+        if lints.is_empty() {
+            continue;
+        }
+
+        if check {
+            for lint in &lints {
+                eprintln!("{}", lint.format_with_source(&source));
+            }
+            remaining += lints.len();
+        } else {
+            if let Err(e) = fs::write(file, apply_fixes(&source, &lints)) {
+                eprintln!("Failed to write {}: {}", file.display(), e);
+                return 1;
+            }
+            println!("{}: applied {} fix(es)", file.display(), lints.len());
+        }
+    }
+
+    if check {
+        return if remaining == 0 { 0 } else { 1 };
+    }
+    println!("Now set `edition = \"{}\"` in the package manifest", to);
+    0
+}
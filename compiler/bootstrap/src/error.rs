@@ -69,9 +69,9 @@ impl CompileError {
             
             // Extract the relevant line from source code
             if let Some(line) = source_code.lines().nth(span.start.line - 1) {
-                result.push_str(&format!("   |\n"));
+                result.push_str("   |\n");
                 result.push_str(&format!("{:3} | {}\n", span.start.line, line));
-                result.push_str(&format!("   | "));
+                result.push_str("   | ");
                 
                 // Add caret pointing to the error
                 for _ in 0..span.start.column - 1 {
@@ -114,24 +114,3 @@ impl fmt::Display for CompileError {
 }
 
 impl std::error::Error for CompileError {}
-use thiserror::Error;
-use crate::span::Span;
-
-#[derive(Error, Debug)]
-pub enum CompileError {
-    #[error("Memory safety violation: {0}")]
-    MemorySafety(String, Span),
-    
-    #[error("Type mismatch: expected {expected}, found {found}")]
-    TypeMismatch {
-        expected: String,
-        found: String,
-        span: Span,
-    },
-    
-    #[error("Thread safety violation: {0}")]
-    ThreadSafety(String, Span),
-
-    #[error("Join error: {0}")]
-    JoinError(String, Span),
-}
\ No newline at end of file
@@ -1,5 +1,7 @@
+use std::collections::HashMap;
 use std::fmt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 /// Represents a location in source code
 #[derive(Debug, Clone, PartialEq)]
@@ -39,6 +41,8 @@ pub enum ErrorKind {
     Safety,
     IO,
     Internal,
+    /// Warning from the named lint, e.g. `unused_must_use`
+    Lint(String),
 }
 
 impl fmt::Display for ErrorKind {
@@ -52,6 +56,7 @@ impl fmt::Display for ErrorKind {
             ErrorKind::Safety => write!(f, "Safety error"),
             ErrorKind::IO => write!(f, "I/O error"),
             ErrorKind::Internal => write!(f, "Internal compiler error"),
+            ErrorKind::Lint(name) => write!(f, "Warning ({})", name),
         }
     }
 }
@@ -64,6 +69,30 @@ pub struct CompileError {
     pub span: Option<Span>,
     pub notes: Vec<String>,
     pub help: Option<String>,
+    /// Replacement for the text at `span`, offered by editors as a quick fix
+    pub suggestion: Option<String>,
+    /// Further quick fixes when there is more than one way to resolve it
+    pub fixes: Vec<QuickFix>,
+    /// Macro expansions the error occurred in, innermost first
+    pub expansion: Vec<ExpansionInfo>,
+}
+
+/// An edit that resolves a diagnostic, offered by editors as a code action
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuickFix {
+    pub title: String,
+    /// Replaces the text at the diagnostic's span
+    pub replacement: String,
+}
+
+/// How much of a macro expansion chain to show
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MacroBacktrace {
+    /// Only the outermost invocation, i.e. the code the user wrote
+    #[default]
+    Collapsed,
+    /// Every expansion, as requested with `--macro-backtrace`
+    Full,
 }
 
 impl CompileError {
@@ -74,9 +103,16 @@ impl CompileError {
             span: None,
             notes: Vec::new(),
             help: None,
+            suggestion: None,
+            fixes: Vec::new(),
+            expansion: Vec::new(),
         }
     }
     
+    pub fn is_warning(&self) -> bool {
+        matches!(self.kind, ErrorKind::Lint(_))
+    }
+    
     pub fn with_span(mut self, span: Span) -> Self {
         self.span = Some(span);
         self
@@ -92,8 +128,65 @@ impl CompileError {
         self
     }
     
+    /// Suggest replacing the text at the span with `replacement`, shown as
+    /// "did you mean" help
+    pub fn with_suggestion(mut self, replacement: &str) -> Self {
+        self.help = Some(crate::suggest::help_text(replacement));
+        self.suggestion = Some(replacement.to_string());
+        self
+    }
+    
+    /// Offer replacing the text at the span with `replacement`
+    pub fn with_fix(mut self, title: &str, replacement: &str) -> Self {
+        self.fixes.push(QuickFix {
+            title: title.to_string(),
+            replacement: replacement.to_string(),
+        });
+        self
+    }
+    
+    /// Record that the error occurred inside an expansion of `info`. Call
+    /// once per level while unwinding, innermost first.
+    pub fn with_expansion(mut self, info: ExpansionInfo) -> Self {
+        self.expansion.push(info);
+        self
+    }
+    
+    /// Format using files from `source_map`, following the span out of
+    /// any macro expansions back to the code the user wrote
+    pub fn format_with_map(&self, source_map: &SourceMap, backtrace: MacroBacktrace) -> String {
+        let mut result = format!("{}: {}\n", self.kind, self.message);
+        
+        if let Some(span) = &self.span {
+            result.push_str(&source_map.render_snippet(span, ""));
+            
+            // Spans into registered expansion files carry their own chain
+            let mut frames = source_map.expansion_backtrace(span);
+            if frames.is_empty() {
+                frames = self.expansion.iter().collect();
+            }
+            push_backtrace(&mut result, &frames, backtrace, |span| source_map.render_snippet(span, ""));
+        }
+        
+        for note in &self.notes {
+            result.push_str(&format!("note: {}\n", note));
+        }
+        
+        if let Some(help) = &self.help {
+            result.push_str(&format!("help: {}\n", help));
+        }
+        
+        result
+    }
+    
     /// Format the error with source code context
     pub fn format_with_source(&self, source_code: &str) -> String {
+        self.format_with_source_and_backtrace(source_code, MacroBacktrace::Collapsed)
+    }
+    
+    /// Format the error with source code context and, for errors inside
+    /// macro expansions, the chain of invocation and definition sites
+    pub fn format_with_source_and_backtrace(&self, source_code: &str, backtrace: MacroBacktrace) -> String {
         let mut result = format!("{}: {}\n", self.kind, self.message);
         
         if let Some(span) = &self.span {
@@ -124,6 +217,9 @@ impl CompileError {
             }
         }
         
+        let frames: Vec<&ExpansionInfo> = self.expansion.iter().collect();
+        push_backtrace(&mut result, &frames, backtrace, |span| format!("  --> {}\n", span.start));
+        
         // Add notes
         for note in &self.notes {
             result.push_str(&format!("note: {}\n", note));
@@ -152,11 +248,44 @@ impl fmt::Display for CompileError {
 
 impl std::error::Error for CompileError {}
 
+/// Append "in expansion of" notes for `frames` (innermost first), each with
+/// the invocation site and, where known, the macro definition
+fn push_backtrace(
+    result: &mut String,
+    frames: &[&ExpansionInfo],
+    backtrace: MacroBacktrace,
+    render: impl Fn(&Span) -> String,
+) {
+    let shown = match backtrace {
+        MacroBacktrace::Full => frames,
+        MacroBacktrace::Collapsed => &frames[frames.len().saturating_sub(1)..],
+    };
+    
+    for frame in shown {
+        result.push_str(&format!("note: in this expansion of macro `{}`\n", frame.macro_name));
+        result.push_str(&render(&frame.call_site));
+        if let Some(def_site) = &frame.def_site {
+            result.push_str(&format!("note: macro `{}` defined here\n", frame.macro_name));
+            result.push_str(&render(def_site));
+        }
+    }
+    
+    let hidden = frames.len() - shown.len();
+    if hidden > 0 {
+        result.push_str(&format!(
+            "note: {} nested macro expansion{} hidden; use --macro-backtrace to show all\n",
+            hidden,
+            if hidden == 1 { "" } else { "s" },
+        ));
+    }
+}
+
 /// Collection of errors and warnings
 #[derive(Debug, Default)]
 pub struct Diagnostics {
     errors: Vec<CompileError>,
     warnings: Vec<CompileError>,
+    macro_backtrace: MacroBacktrace,
 }
 
 impl Diagnostics {
@@ -164,9 +293,14 @@ impl Diagnostics {
         Diagnostics {
             errors: Vec::new(),
             warnings: Vec::new(),
+            macro_backtrace: MacroBacktrace::Collapsed,
         }
     }
     
+    pub fn set_macro_backtrace(&mut self, backtrace: MacroBacktrace) {
+        self.macro_backtrace = backtrace;
+    }
+    
     pub fn add_error(&mut self, error: CompileError) {
         self.errors.push(error);
     }
@@ -191,28 +325,12 @@ impl Diagnostics {
         let mut result = String::new();
         
         for error in &self.errors {
-            if let Some(span) = &error.span {
-                if let Some(source) = source_map.get_source(&span.start.file) {
-                    result.push_str(&error.format_with_source(source));
-                } else {
-                    result.push_str(&format!("{}\n", error));
-                }
-            } else {
-                result.push_str(&format!("{}\n", error));
-            }
+            result.push_str(&error.format_with_map(source_map, self.macro_backtrace));
             result.push('\n');
         }
         
         for warning in &self.warnings {
-            if let Some(span) = &warning.span {
-                if let Some(source) = source_map.get_source(&span.start.file) {
-                    result.push_str(&warning.format_with_source(source));
-                } else {
-                    result.push_str(&format!("{}\n", warning));
-                }
-            } else {
-                result.push_str(&format!("{}\n", warning));
-            }
+            result.push_str(&warning.format_with_map(source_map, self.macro_backtrace));
             result.push('\n');
         }
         
@@ -220,24 +338,356 @@ impl Diagnostics {
     }
 }
 
-/// Manages source code files
-#[derive(Debug, Default)]
+/// Stable identifier of a file in a `SourceMap`. Re-adding a path keeps
+/// its id, so ids held by diagnostics and caches stay valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(pub u32);
+
+/// Where a file's text came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileOrigin {
+    /// Read from disk or supplied by an editor
+    Source,
+    /// Textually included from another file at `included_at`
+    Include { included_at: Span },
+    /// Produced by expanding a macro
+    Expansion(ExpansionInfo),
+}
+
+/// Record of one macro expansion, linking generated code back to the
+/// code that produced it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpansionInfo {
+    pub macro_name: String,
+    /// The invocation `name!(...)`
+    pub call_site: Span,
+    /// The `macro name { ... }` definition, if known
+    pub def_site: Option<Span>,
+}
+
+/// A loaded file with precomputed line starts. Shared via `Arc` so the
+/// driver, diagnostics and the LSP can hold the same text without copying.
+#[derive(Debug)]
+pub struct SourceFile {
+    pub id: FileId,
+    pub path: PathBuf,
+    pub source: Arc<str>,
+    pub origin: FileOrigin,
+    /// Byte offset of the start of each line
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    fn new(id: FileId, path: PathBuf, source: Arc<str>, origin: FileOrigin) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        SourceFile {
+            id,
+            path,
+            source,
+            origin,
+            line_starts,
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Text of 1-based line `line`, without its line terminator
+    pub fn line(&self, line: usize) -> Option<&str> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self.line_starts.get(line).copied().unwrap_or(self.source.len());
+        Some(self.source[start..end].trim_end_matches(['\n', '\r']))
+    }
+
+    /// 1-based line and column of a byte offset
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        };
+        (line + 1, offset - self.line_starts[line] + 1)
+    }
+
+    /// Byte offset of a 1-based line and column
+    pub fn offset(&self, line: usize, column: usize) -> Option<usize> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        Some((start + column.saturating_sub(1)).min(self.source.len()))
+    }
+
+    pub fn location(&self, offset: usize) -> SourceLocation {
+        let (line, column) = self.line_col(offset);
+        SourceLocation {
+            file: self.path.clone(),
+            line,
+            column,
+        }
+    }
+}
+
+/// A span with a message, for diagnostics pointing at several places
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Label {
+    pub fn new(span: Span, message: &str) -> Self {
+        Label {
+            span,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Manages source code files.
+///
+/// Cloning is cheap: files are reference counted, so a clone handed to
+/// the LSP or a worker thread shares the loaded text with the driver.
+#[derive(Debug, Default, Clone)]
 pub struct SourceMap {
-    sources: std::collections::HashMap<PathBuf, String>,
+    files: Vec<Arc<SourceFile>>,
+    by_path: HashMap<PathBuf, FileId>,
+    expansion_count: usize,
 }
 
 impl SourceMap {
     pub fn new() -> Self {
         SourceMap {
-            sources: std::collections::HashMap::new(),
+            files: Vec::new(),
+            by_path: HashMap::new(),
+            expansion_count: 0,
         }
     }
     
-    pub fn add_source(&mut self, path: PathBuf, source: String) {
-        self.sources.insert(path, source);
+    fn insert(&mut self, path: PathBuf, source: Arc<str>, origin: FileOrigin) -> FileId {
+        if let Some(&id) = self.by_path.get(&path) {
+            self.files[id.0 as usize] = Arc::new(SourceFile::new(id, path, source, origin));
+            return id;
+        }
+        
+        let id = FileId(self.files.len() as u32);
+        self.by_path.insert(path.clone(), id);
+        self.files.push(Arc::new(SourceFile::new(id, path, source, origin)));
+        id
+    }
+    
+    /// Add or replace a file, returning its id
+    pub fn add_source(&mut self, path: PathBuf, source: String) -> FileId {
+        self.insert(path, source.into(), FileOrigin::Source)
     }
     
-    pub fn get_source(&self, path: &PathBuf) -> Option<&str> {
-        self.sources.get(path).map(|s| s.as_str())
+    /// Add a file that was included from `included_at`
+    pub fn add_include(&mut self, path: PathBuf, source: String, included_at: Span) -> FileId {
+        self.insert(path, source.into(), FileOrigin::Include { included_at })
     }
-}
\ No newline at end of file
+    
+    /// Add the text produced by a macro expansion as a virtual file, so
+    /// spans in generated code can be rendered and traced back to the
+    /// invocation
+    pub fn add_expansion(&mut self, info: ExpansionInfo, expanded: String) -> FileId {
+        self.expansion_count += 1;
+        let path = PathBuf::from(format!("<expansion #{} of {}!>", self.expansion_count, info.macro_name));
+        self.insert(path, expanded.into(), FileOrigin::Expansion(info))
+    }
+    
+    pub fn get_source(&self, path: &Path) -> Option<&str> {
+        self.file_by_path(path).map(|file| &*file.source)
+    }
+    
+    pub fn file_id(&self, path: &Path) -> Option<FileId> {
+        self.by_path.get(path).copied()
+    }
+    
+    /// Shared handle to a file
+    pub fn file(&self, id: FileId) -> Option<Arc<SourceFile>> {
+        self.files.get(id.0 as usize).cloned()
+    }
+    
+    fn file_by_path(&self, path: &Path) -> Option<&SourceFile> {
+        let id = self.by_path.get(path)?;
+        self.files.get(id.0 as usize).map(|file| file.as_ref())
+    }
+    
+    pub fn files(&self) -> impl Iterator<Item = &Arc<SourceFile>> {
+        self.files.iter()
+    }
+    
+    /// Macro expansions enclosing `span`, innermost first
+    pub fn expansion_backtrace(&self, span: &Span) -> Vec<&ExpansionInfo> {
+        let mut frames = Vec::new();
+        let mut path = span.start.file.clone();
+        
+        // Bounded by the number of files, in case of a malformed cycle
+        for _ in 0..self.files.len() {
+            match self.file_by_path(&path).map(|file| &file.origin) {
+                Some(FileOrigin::Expansion(info)) => {
+                    frames.push(info);
+                    path = info.call_site.start.file.clone();
+                }
+                _ => break,
+            }
+        }
+        
+        frames
+    }
+    
+    /// Render the lines covered by `span` with a caret underline and
+    /// optional label, in the same layout as `CompileError::format_with_source`
+    pub fn render_snippet(&self, span: &Span, label: &str) -> String {
+        let mut result = format!("  --> {}\n", span.start);
+        
+        let file = match self.file_by_path(&span.start.file) {
+            Some(file) => file,
+            None => return result,
+        };
+        
+        let last_line = if span.end.file == span.start.file {
+            span.end.line.max(span.start.line)
+        } else {
+            span.start.line
+        };
+        let gutter = last_line.to_string().len().max(3);
+        
+        result.push_str(&format!("{:gutter$} |\n", ""));
+        for line_number in span.start.line..=last_line {
+            let line = match file.line(line_number) {
+                Some(line) => line,
+                None => break,
+            };
+            result.push_str(&format!("{:>gutter$} | {}\n", line_number, line));
+            
+            let start = if line_number == span.start.line { span.start.column } else { 1 };
+            let end = if line_number == last_line && span.end.line == last_line {
+                span.end.column
+            } else {
+                line.len() + 1
+            };
+            let width = end.saturating_sub(start).max(1);
+            
+            result.push_str(&format!("{:gutter$} | {}{}", "", " ".repeat(start.saturating_sub(1)), "^".repeat(width)));
+            if line_number == last_line && !label.is_empty() {
+                result.push(' ');
+                result.push_str(label);
+            }
+            result.push('\n');
+        }
+        
+        result
+    }
+    
+    /// Render several labelled spans, possibly in different files: grouped
+    /// by file in first-mention order, each file's labels in line order
+    pub fn render_labels(&self, labels: &[Label]) -> String {
+        let mut order: Vec<&PathBuf> = Vec::new();
+        for label in labels {
+            if !order.contains(&&label.span.start.file) {
+                order.push(&label.span.start.file);
+            }
+        }
+        
+        let mut result = String::new();
+        for path in order {
+            let mut in_file: Vec<&Label> = labels.iter()
+                .filter(|label| &label.span.start.file == path)
+                .collect();
+            in_file.sort_by_key(|label| (label.span.start.line, label.span.start.column));
+            
+            for label in in_file {
+                result.push_str(&self.render_snippet(&label.span, &label.message));
+            }
+        }
+        
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(file: &str, start: (usize, usize), end: (usize, usize)) -> Span {
+        let location = |(line, column)| SourceLocation {
+            file: PathBuf::from(file),
+            line,
+            column,
+        };
+        Span {
+            start: location(start),
+            end: location(end),
+        }
+    }
+
+    #[test]
+    fn test_source_file_line_table() {
+        let mut map = SourceMap::new();
+        let id = map.add_source(PathBuf::from("a.zt"), "let a = 1;\r\nlet b = 2;\n\nend".to_string());
+        let file = map.file(id).unwrap();
+
+        assert_eq!(file.line_count(), 4);
+        assert_eq!(file.line(1), Some("let a = 1;"));
+        assert_eq!(file.line(3), Some(""));
+        assert_eq!(file.line(4), Some("end"));
+        assert_eq!(file.line(5), None);
+        assert_eq!(file.line(0), None);
+
+        assert_eq!(file.line_col(0), (1, 1));
+        assert_eq!(file.line_col(16), (2, 5));
+        assert_eq!(file.line_col(1000), (4, 4));
+        assert_eq!(file.offset(2, 5), Some(16));
+
+        // Re-adding a path keeps its id and replaces the text; handles to
+        // the old text stay valid
+        let id_again = map.add_source(PathBuf::from("a.zt"), "changed".to_string());
+        assert_eq!(id_again, id);
+        assert_eq!(map.get_source(&PathBuf::from("a.zt")), Some("changed"));
+        assert_eq!(file.line(1), Some("let a = 1;"));
+    }
+
+    #[test]
+    fn test_render_labels_across_files() {
+        let mut map = SourceMap::new();
+        map.add_source(PathBuf::from("main.zt"), "use lib::f;\nlet x = f(1);\n".to_string());
+        map.add_source(PathBuf::from("lib.zt"), "pub fn f(a: str) {\n}\n".to_string());
+
+        let rendered = map.render_labels(&[
+            Label::new(span("main.zt", (2, 11), (2, 12)), "expected `str`"),
+            Label::new(span("lib.zt", (1, 10), (1, 16)), "parameter declared here"),
+            Label::new(span("main.zt", (1, 5), (1, 11)), "imported here"),
+        ]);
+
+        assert_eq!(
+            rendered,
+            "  --> main.zt:1:5\n    |\n  1 | use lib::f;\n    |     ^^^^^^ imported here\n\
+             \x20 --> main.zt:2:11\n    |\n  2 | let x = f(1);\n    |           ^ expected `str`\n\
+             \x20 --> lib.zt:1:10\n    |\n  1 | pub fn f(a: str) {\n    |          ^^^^^^ parameter declared here\n"
+        );
+    }
+
+    #[test]
+    fn test_snippet_in_macro_expansion() {
+        let mut map = SourceMap::new();
+        map.add_source(PathBuf::from("main.zt"), "twice!(x)\n".to_string());
+        let info = ExpansionInfo {
+            macro_name: "twice".to_string(),
+            call_site: span("main.zt", (1, 1), (1, 10)),
+            def_site: None,
+        };
+        let id = map.add_expansion(info.clone(), "x + x".to_string());
+        let expanded = map.file(id).unwrap().path.to_string_lossy().to_string();
+
+        let error_span = span(&expanded, (1, 3), (1, 4));
+        assert_eq!(map.expansion_backtrace(&error_span), vec![&info]);
+
+        let error = CompileError::new(ErrorKind::Type, "mismatched types").with_span(error_span);
+        let rendered = error.format_with_map(&map, MacroBacktrace::Collapsed);
+        assert!(rendered.contains("1 | x + x\n    |   ^\n"));
+        assert!(rendered.contains("note: in this expansion of macro `twice`\n  --> main.zt:1:1\n"));
+        assert!(rendered.contains("1 | twice!(x)\n    | ^^^^^^^^^\n"));
+    }
+}
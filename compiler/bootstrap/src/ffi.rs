@@ -1,5 +1,14 @@
-use crate::ast::*;
-use crate::error::CompileError;
+use crate::error_handling::{CompileError, ErrorKind, Span};
+use crate::layout::LayoutCx;
+use crate::types::Type;
+
+/// A call into a foreign function
+pub struct ForeignCall {
+    pub name: String,
+    /// Whether the binding declares the function safe to call
+    pub is_safe: bool,
+    pub span: Span,
+}
 
 pub struct FFIChecker {
     allowed_unsafe: bool,
@@ -14,15 +23,32 @@ impl FFIChecker {
         let mut errors = Vec::new();
         
         if !self.allowed_unsafe && !call.is_safe {
-            errors.push(CompileError::new(
-                "Unsafe foreign call not allowed in this context",
-                call.span,
-            ));
+            errors.push(
+                CompileError::new(ErrorKind::Safety, "Unsafe foreign call not allowed in this context")
+                    .with_span(call.span.clone()),
+            );
         }
         
-        // Check parameter types for FFI compatibility
+        // Check parameter types for FFI compatibility with
+        // `check_signature`
         // ... implementation details ...
         
         errors
     }
+    
+    /// Parameters and the result of a foreign function must have C
+    /// layouts; structs need `#[repr(C)]` or `#[repr(packed)]`
+    pub fn check_signature(
+        &self,
+        layouts: &LayoutCx,
+        params: &[Type],
+        return_type: &Type,
+        span: &Span,
+    ) -> Vec<CompileError> {
+        params
+            .iter()
+            .chain(std::iter::once(return_type))
+            .filter_map(|type_| layouts.check_ffi_type(type_, span).err())
+            .collect()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,159 @@
+//! Functions and bound methods used as values.
+//!
+//! `let f = parse` takes a reference to a named function and
+//! `let f = list.push` binds a method to its receiver. Both have an
+//! ordinary `Type::Function`; for a bound method the receiver is captured
+//! and is not one of the parameters, so `list.push` has type `(Int) -> Void`
+//! and can be passed wherever such a function is expected.
+//!
+//! At runtime every function value is a code pointer and an environment
+//! pointer, `{ ptr, ptr }`. A function reference captures nothing: its
+//! environment is null and the code pointer is the function itself, called
+//! directly. A bound method points at a thunk that takes the environment
+//! first, recovers the receiver from it and makes the call
+//! `receiver.method(...)` would have made.
+
+use crate::types::Type;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FunctionRef {
+    /// A named function, e.g. `parse` or `json::parse`
+    Direct { name: String, type_: Type },
+    /// `receiver.method`, with the receiver captured when the value is made
+    Bound { receiver: Type, method: String, type_: Type },
+}
+
+impl FunctionRef {
+    /// The value's type; a bound method's receiver is not a parameter
+    pub fn type_(&self) -> &Type {
+        match self {
+            FunctionRef::Direct { type_, .. } | FunctionRef::Bound { type_, .. } => type_,
+        }
+    }
+
+    /// Type of the captured state, `None` for a plain function pointer
+    pub fn captures(&self) -> Option<&Type> {
+        match self {
+            FunctionRef::Direct { .. } => None,
+            FunctionRef::Bound { receiver, .. } => Some(receiver),
+        }
+    }
+
+    /// Symbol of the function the value calls in the end
+    pub fn target_symbol(&self) -> String {
+        match self {
+            FunctionRef::Direct { name, .. } => symbol(name),
+            FunctionRef::Bound { receiver, method, .. } => format!("{}.{}", symbol(&receiver_name(receiver)), method),
+        }
+    }
+
+    /// Symbol of the code pointer stored in the value
+    pub fn code_symbol(&self) -> String {
+        match self {
+            FunctionRef::Direct { .. } => self.target_symbol(),
+            FunctionRef::Bound { .. } => format!("{}.bound", self.target_symbol()),
+        }
+    }
+
+    /// The `{ ptr, ptr }` value. `env` is the LLVM operand holding the
+    /// captured receiver: the object pointer for classes and maps, which are
+    /// heap objects already, otherwise a heap copy of the receiver. Ignored for
+    /// direct references.
+    pub fn llvm_value(&self, env: &str) -> String {
+        match self {
+            FunctionRef::Direct { .. } => format!("{{ ptr @{}, ptr null }}", self.code_symbol()),
+            FunctionRef::Bound { .. } => format!("{{ ptr @{}, ptr {} }}", self.code_symbol(), env),
+        }
+    }
+
+    /// Thunk behind a bound method's code pointer; `None` for direct
+    /// references, which need none. Emitted once per bound method.
+    pub fn llvm_thunk(&self) -> Option<String> {
+        let FunctionRef::Bound { receiver, type_, .. } = self else {
+            return None;
+        };
+        let Type::Function(params, return_type) = type_ else {
+            return None;
+        };
+
+        let mut declared = vec!["ptr %env".to_string()];
+        let mut passed = Vec::new();
+        let mut body = String::new();
+        // Aggregates are passed by pointer anyway, so the environment is
+        // passed on as is; scalars are loaded out of it
+        let receiver_type = llvm_type(receiver);
+        if receiver_type == "ptr" {
+            passed.push("ptr %env".to_string());
+        } else {
+            body.push_str(&format!("  %receiver = load {}, ptr %env\n", receiver_type));
+            passed.push(format!("{} %receiver", receiver_type));
+        }
+        for (index, param) in params.iter().enumerate() {
+            let operand = format!("{} %arg{}", llvm_type(param), index);
+            declared.push(operand.clone());
+            passed.push(operand);
+        }
+
+        let return_type = llvm_type(return_type);
+        let call = format!("call {} @{}({})", return_type, self.target_symbol(), passed.join(", "));
+        if return_type == "void" {
+            body.push_str(&format!("  {}\n  ret void\n", call));
+        } else {
+            body.push_str(&format!("  %result = {}\n  ret {} %result\n", call, return_type));
+        }
+
+        Some(format!(
+            "define internal {} @{}({}) {{\n{}}}\n",
+            return_type,
+            self.code_symbol(),
+            declared.join(", "),
+            body,
+        ))
+    }
+}
+
+/// Methods the runtime provides on built-in types
+pub fn builtin_method(receiver: &Type, method: &str) -> Option<Type> {
+    let function = |params: Vec<Type>, return_type: Type| Some(Type::Function(params, Box::new(return_type)));
+
+    match (receiver, method) {
+        (Type::Array(element), "push") => function(vec![(**element).clone()], Type::Void),
+        (Type::Array(element), "pop") => function(vec![], Type::Optional(element.clone())),
+        (Type::Array(_), "len") | (Type::String, "len") => function(vec![], Type::Int),
+        (Type::Array(element), "contains") => function(vec![(**element).clone()], Type::Bool),
+        (Type::String, "contains") => function(vec![Type::String], Type::Bool),
+        (Type::Map(key, value), "get") => function(vec![(**key).clone()], Type::Optional(value.clone())),
+        (Type::Map(key, value), "insert") => function(vec![(**key).clone(), (**value).clone()], Type::Void),
+        _ => None,
+    }
+}
+
+/// Name methods of `receiver` are registered under
+pub fn receiver_name(receiver: &Type) -> String {
+    match receiver {
+        Type::Class(name) | Type::Struct(name) | Type::Interface(name) | Type::Enum(name) | Type::Generic(name, _) => {
+            name.clone()
+        }
+        Type::Array(_) => "Array".to_string(),
+        Type::Map(_, _) => "Map".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// `json::parse` is emitted as `json.parse`
+fn symbol(name: &str) -> String {
+    name.replace("::", ".")
+}
+
+/// LLVM type used to pass a value of `type_`; aggregates go by pointer
+fn llvm_type(type_: &Type) -> &'static str {
+    match type_ {
+        Type::Void => "void",
+        Type::Bool => "i8",
+        Type::Int => "i64",
+        Type::Float => "double",
+        Type::Enum(_) => "i32",
+        Type::Function(_, _) | Type::Interface(_) => "{ ptr, ptr }",
+        _ => "ptr",
+    }
+}
@@ -0,0 +1,600 @@
+//! Binary module interface files (`.zi`).
+//!
+//! An interface holds a module's exported signatures without bodies, so
+//! dependents can type-check against it without reparsing the sources, and
+//! libraries can be distributed without them.
+//!
+//! Layout (all integers little-endian, lengths as LEB128):
+//!
+//! ```text
+//! magic "ZINT" | format version u16 | compiler version str | module name str
+//! | edition u8 | export count | exports...
+//! ```
+//!
+//! Export names are stored without `r#`, so modules of any edition can use
+//! each other's exports: a `yield` from a 2024 module is `r#yield` in 2026.
+
+use crate::edition::Edition;
+use crate::layout::Repr;
+use crate::types::Type;
+use std::fs;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"ZINT";
+pub const INTERFACE_FORMAT_VERSION: u16 = 4;
+pub const INTERFACE_EXTENSION: &str = "zi";
+
+/// Deepest type nesting accepted when decoding; real signatures stay far
+/// below it, and a crafted file cannot exhaust the stack
+const MAX_TYPE_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleInterface {
+    pub module_name: String,
+    pub compiler_version: String,
+    /// Edition the module was written in
+    pub edition: Edition,
+    pub exports: Vec<ExportedItem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportedItem {
+    pub name: String,
+    pub kind: ExportKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportKind {
+    Function(Type),
+    Constant(Type),
+    /// Every class of a sealed hierarchy is exported, public or not, so
+    /// dependents see all implementations of its methods
+    Class {
+        parent: Option<String>,
+        interfaces: Vec<String>,
+        members: Vec<(String, Type)>,
+        sealed: bool,
+    },
+    Interface {
+        methods: Vec<(String, Type)>,
+        sealed: bool,
+    },
+    Struct {
+        fields: Vec<(String, Type)>,
+        /// Dependents lay the struct out the same way
+        repr: Repr,
+    },
+    Enum {
+        variants: Vec<String>,
+    },
+}
+
+#[derive(Debug)]
+pub enum InterfaceError {
+    Io(std::io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    InvalidTag(u8),
+    InvalidUtf8,
+    /// Types nested deeper than `MAX_TYPE_DEPTH`
+    TooDeep,
+    /// A length with more bits than `usize` holds
+    LengthOverflow,
+    /// Written by another compiler, whose type layouts may differ
+    CompilerMismatch { found: String, expected: String },
+}
+
+impl std::fmt::Display for InterfaceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterfaceError::Io(e) => write!(f, "I/O error: {}", e),
+            InterfaceError::BadMagic => write!(f, "Not a Zaitun interface file"),
+            InterfaceError::UnsupportedVersion(v) => write!(
+                f,
+                "Interface format version {} is not supported (expected {})",
+                v, INTERFACE_FORMAT_VERSION
+            ),
+            InterfaceError::Truncated => write!(f, "Interface file is truncated"),
+            InterfaceError::InvalidTag(tag) => write!(f, "Invalid tag {:#04x} in interface file", tag),
+            InterfaceError::InvalidUtf8 => write!(f, "Invalid UTF-8 in interface file"),
+            InterfaceError::LengthOverflow => write!(f, "Length in interface file is too large"),
+            InterfaceError::TooDeep => write!(
+                f,
+                "Types in interface file are nested deeper than {}",
+                MAX_TYPE_DEPTH
+            ),
+            InterfaceError::CompilerMismatch { found, expected } => write!(
+                f,
+                "Interface was written by compiler {} (expected {}); rebuild the module",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InterfaceError {}
+
+impl From<std::io::Error> for InterfaceError {
+    fn from(error: std::io::Error) -> Self {
+        InterfaceError::Io(error)
+    }
+}
+
+impl ModuleInterface {
+    pub fn new(module_name: &str) -> Self {
+        ModuleInterface {
+            module_name: module_name.to_string(),
+            compiler_version: env!("CARGO_PKG_VERSION").to_string(),
+            edition: Edition::default(),
+            exports: Vec::new(),
+        }
+    }
+
+    pub fn add_export(&mut self, name: &str, kind: ExportKind) {
+        self.exports.push(ExportedItem {
+            name: name.to_string(),
+            kind,
+        });
+    }
+
+    pub fn find(&self, name: &str) -> Option<&ExportedItem> {
+        self.exports.iter().find(|item| item.name == name)
+    }
+
+    pub fn write_to(&self, path: &Path) -> Result<(), InterfaceError> {
+        fs::write(path, self.encode())?;
+        Ok(())
+    }
+
+    pub fn read_from(path: &Path) -> Result<Self, InterfaceError> {
+        let bytes = fs::read(path)?;
+        ModuleInterface::decode(&bytes)
+    }
+
+    /// Fail unless this interface was written by the running compiler.
+    /// Importers check this; tools that only compare interfaces across
+    /// releases do not.
+    pub fn ensure_current_compiler(&self) -> Result<(), InterfaceError> {
+        let expected = env!("CARGO_PKG_VERSION");
+        if self.compiler_version == expected {
+            Ok(())
+        } else {
+            Err(InterfaceError::CompilerMismatch {
+                found: self.compiler_version.clone(),
+                expected: expected.to_string(),
+            })
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer { buf: Vec::new() };
+        writer.buf.extend_from_slice(MAGIC);
+        writer.buf.extend_from_slice(&INTERFACE_FORMAT_VERSION.to_le_bytes());
+        writer.string(&self.compiler_version);
+        writer.string(&self.module_name);
+        writer.buf.push(self.edition.tag());
+
+        // Exports are written sorted so identical interfaces are byte-identical
+        let mut exports: Vec<&ExportedItem> = self.exports.iter().collect();
+        exports.sort_by(|a, b| a.name.cmp(&b.name));
+
+        writer.len(exports.len());
+        for item in exports {
+            writer.string(&item.name);
+            writer.export_kind(&item.kind);
+        }
+
+        writer.buf
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, InterfaceError> {
+        let mut reader = Reader { bytes, pos: 0, depth: 0 };
+
+        if reader.take(4)? != MAGIC {
+            return Err(InterfaceError::BadMagic);
+        }
+
+        let version = u16::from_le_bytes([reader.byte()?, reader.byte()?]);
+        if version != INTERFACE_FORMAT_VERSION {
+            return Err(InterfaceError::UnsupportedVersion(version));
+        }
+
+        let compiler_version = reader.string()?;
+        let module_name = reader.string()?;
+        let tag = reader.byte()?;
+        let edition = Edition::from_tag(tag).ok_or(InterfaceError::InvalidTag(tag))?;
+
+        let count = reader.len()?;
+        let mut exports = Vec::with_capacity(count.min(1024));
+        for _ in 0..count {
+            let name = reader.string()?;
+            let kind = reader.export_kind()?;
+            exports.push(ExportedItem { name, kind });
+        }
+
+        Ok(ModuleInterface {
+            module_name,
+            compiler_version,
+            edition,
+            exports,
+        })
+    }
+}
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn len(&mut self, mut value: usize) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.buf.push(byte);
+                break;
+            }
+            self.buf.push(byte | 0x80);
+        }
+    }
+
+    fn string(&mut self, value: &str) {
+        self.len(value.len());
+        self.buf.extend_from_slice(value.as_bytes());
+    }
+
+    fn strings(&mut self, values: &[String]) {
+        self.len(values.len());
+        for value in values {
+            self.string(value);
+        }
+    }
+
+    fn members(&mut self, members: &[(String, Type)]) {
+        self.len(members.len());
+        for (name, type_) in members {
+            self.string(name);
+            self.type_(type_);
+        }
+    }
+
+    fn types(&mut self, types: &[Type]) {
+        self.len(types.len());
+        for type_ in types {
+            self.type_(type_);
+        }
+    }
+
+    fn export_kind(&mut self, kind: &ExportKind) {
+        match kind {
+            ExportKind::Function(type_) => {
+                self.buf.push(0);
+                self.type_(type_);
+            }
+            ExportKind::Constant(type_) => {
+                self.buf.push(1);
+                self.type_(type_);
+            }
+            ExportKind::Class { parent, interfaces, members, sealed } => {
+                self.buf.push(2);
+                match parent {
+                    Some(parent) => {
+                        self.buf.push(1);
+                        self.string(parent);
+                    }
+                    None => self.buf.push(0),
+                }
+                self.strings(interfaces);
+                self.members(members);
+                self.buf.push(*sealed as u8);
+            }
+            ExportKind::Interface { methods, sealed } => {
+                self.buf.push(3);
+                self.members(methods);
+                self.buf.push(*sealed as u8);
+            }
+            ExportKind::Struct { fields, repr } => {
+                self.buf.push(4);
+                self.members(fields);
+                self.buf.push(repr.c as u8 | (repr.packed as u8) << 1);
+                self.len(repr.align.unwrap_or(0) as usize);
+            }
+            ExportKind::Enum { variants } => {
+                self.buf.push(5);
+                self.strings(variants);
+            }
+        }
+    }
+
+    fn type_(&mut self, type_: &Type) {
+        match type_ {
+            Type::Void => self.buf.push(0),
+            Type::Bool => self.buf.push(1),
+            Type::Int => self.buf.push(2),
+            Type::Float => self.buf.push(3),
+            Type::String => self.buf.push(4),
+            Type::Array(elem) => {
+                self.buf.push(5);
+                self.type_(elem);
+            }
+            Type::Map(key, value) => {
+                self.buf.push(6);
+                self.type_(key);
+                self.type_(value);
+            }
+            Type::Function(params, ret) => {
+                self.buf.push(7);
+                self.types(params);
+                self.type_(ret);
+            }
+            Type::Class(name) => {
+                self.buf.push(8);
+                self.string(name);
+            }
+            Type::Interface(name) => {
+                self.buf.push(9);
+                self.string(name);
+            }
+            Type::Struct(name) => {
+                self.buf.push(10);
+                self.string(name);
+            }
+            Type::Enum(name) => {
+                self.buf.push(11);
+                self.string(name);
+            }
+            Type::Optional(inner) => {
+                self.buf.push(12);
+                self.type_(inner);
+            }
+            Type::Union(types) => {
+                self.buf.push(13);
+                self.types(types);
+            }
+            Type::Generic(name, params) => {
+                self.buf.push(14);
+                self.string(name);
+                self.types(params);
+            }
+            Type::Unknown => self.buf.push(15),
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// Nesting of the type being decoded
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], InterfaceError> {
+        let end = self.pos.checked_add(n).ok_or(InterfaceError::Truncated)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(InterfaceError::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn byte(&mut self) -> Result<u8, InterfaceError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn len(&mut self) -> Result<usize, InterfaceError> {
+        let mut value = 0usize;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as usize;
+            // The last byte only has room for what is left of `usize`, one
+            // bit in the 10th byte of a 64-bit length
+            if shift >= usize::BITS || (usize::BITS - shift < 7 && bits >> (usize::BITS - shift) != 0) {
+                return Err(InterfaceError::LengthOverflow);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn flag(&mut self) -> Result<bool, InterfaceError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(InterfaceError::InvalidTag(tag)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, InterfaceError> {
+        let len = self.len()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| InterfaceError::InvalidUtf8)
+    }
+
+    fn strings(&mut self) -> Result<Vec<String>, InterfaceError> {
+        let count = self.len()?;
+        (0..count).map(|_| self.string()).collect()
+    }
+
+    fn members(&mut self) -> Result<Vec<(String, Type)>, InterfaceError> {
+        let count = self.len()?;
+        (0..count).map(|_| Ok((self.string()?, self.type_()?))).collect()
+    }
+
+    fn types(&mut self) -> Result<Vec<Type>, InterfaceError> {
+        let count = self.len()?;
+        (0..count).map(|_| self.type_()).collect()
+    }
+
+    fn export_kind(&mut self) -> Result<ExportKind, InterfaceError> {
+        Ok(match self.byte()? {
+            0 => ExportKind::Function(self.type_()?),
+            1 => ExportKind::Constant(self.type_()?),
+            2 => {
+                let parent = match self.byte()? {
+                    0 => None,
+                    1 => Some(self.string()?),
+                    tag => return Err(InterfaceError::InvalidTag(tag)),
+                };
+                ExportKind::Class {
+                    parent,
+                    interfaces: self.strings()?,
+                    members: self.members()?,
+                    sealed: self.flag()?,
+                }
+            }
+            3 => ExportKind::Interface {
+                methods: self.members()?,
+                sealed: self.flag()?,
+            },
+            4 => {
+                let fields = self.members()?;
+                let flags = self.byte()?;
+                if flags > 0b11 {
+                    return Err(InterfaceError::InvalidTag(flags));
+                }
+                let align = self.len()? as u64;
+                ExportKind::Struct {
+                    fields,
+                    repr: Repr {
+                        c: flags & 1 != 0,
+                        packed: flags & 2 != 0,
+                        align: (align != 0).then_some(align),
+                    },
+                }
+            }
+            5 => ExportKind::Enum { variants: self.strings()? },
+            tag => return Err(InterfaceError::InvalidTag(tag)),
+        })
+    }
+
+    fn type_(&mut self) -> Result<Type, InterfaceError> {
+        if self.depth >= MAX_TYPE_DEPTH {
+            return Err(InterfaceError::TooDeep);
+        }
+        self.depth += 1;
+        let type_ = self.type_inner();
+        self.depth -= 1;
+        type_
+    }
+
+    fn type_inner(&mut self) -> Result<Type, InterfaceError> {
+        Ok(match self.byte()? {
+            0 => Type::Void,
+            1 => Type::Bool,
+            2 => Type::Int,
+            3 => Type::Float,
+            4 => Type::String,
+            5 => Type::Array(Box::new(self.type_()?)),
+            6 => Type::Map(Box::new(self.type_()?), Box::new(self.type_()?)),
+            7 => {
+                let params = self.types()?;
+                Type::Function(params, Box::new(self.type_()?))
+            }
+            8 => Type::Class(self.string()?),
+            9 => Type::Interface(self.string()?),
+            10 => Type::Struct(self.string()?),
+            11 => Type::Enum(self.string()?),
+            12 => Type::Optional(Box::new(self.type_()?)),
+            13 => Type::Union(self.types()?),
+            14 => {
+                let name = self.string()?;
+                Type::Generic(name, self.types()?)
+            }
+            15 => Type::Unknown,
+            tag => return Err(InterfaceError::InvalidTag(tag)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interface_roundtrip() {
+        let mut interface = ModuleInterface::new("geometry");
+        interface.edition = Edition::E2026;
+        interface.add_export("area", ExportKind::Function(Type::Function(
+            vec![Type::Struct("Rect".into())],
+            Box::new(Type::Float),
+        )));
+        interface.add_export("Rect", ExportKind::Struct {
+            fields: vec![("w".into(), Type::Float), ("h".into(), Type::Float)],
+            repr: Repr { c: true, packed: false, align: Some(16) },
+        });
+        interface.add_export("Shape", ExportKind::Interface {
+            methods: vec![("area".into(), Type::Function(vec![], Box::new(Type::Float)))],
+            sealed: true,
+        });
+
+        let decoded = ModuleInterface::decode(&interface.encode()).unwrap();
+        assert_eq!(decoded.module_name, "geometry");
+        assert_eq!(decoded.edition, Edition::E2026);
+        assert_eq!(decoded.find("area"), interface.find("area"));
+        assert_eq!(decoded.find("Rect"), interface.find("Rect"));
+        assert_eq!(decoded.find("Shape"), interface.find("Shape"));
+    }
+
+    #[test]
+    fn test_interface_rejects_truncated_input() {
+        let bytes = ModuleInterface::new("m").encode();
+        assert!(matches!(
+            ModuleInterface::decode(&bytes[..bytes.len() - 1]),
+            Err(InterfaceError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn test_interface_rejects_deep_types() {
+        let mut interface = ModuleInterface::new("m");
+        interface.add_export("x", ExportKind::Constant(Type::Int));
+        let mut bytes = interface.encode();
+
+        // Replace the constant's type with an unbounded chain of arrays
+        bytes.truncate(bytes.len() - 1);
+        bytes.extend(std::iter::repeat_n(5, 100_000));
+        bytes.push(2);
+        assert!(matches!(ModuleInterface::decode(&bytes), Err(InterfaceError::TooDeep)));
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn test_interface_rejects_overlong_lengths() {
+        let read = |bytes: &[u8]| Reader { bytes, pos: 0, depth: 0 }.len();
+
+        let mut max = vec![0xff; 9];
+        max.push(0x01);
+        assert_eq!(read(&max).unwrap(), u64::MAX as usize);
+
+        // Bits past the 64th in the 10th byte
+        let mut too_large = vec![0xff; 9];
+        too_large.push(0x02);
+        assert!(matches!(read(&too_large), Err(InterfaceError::LengthOverflow)));
+        // An 11th byte
+        let mut too_long = vec![0x80; 10];
+        too_long.push(0x00);
+        assert!(matches!(read(&too_long), Err(InterfaceError::LengthOverflow)));
+
+        // The compiler version's length follows the magic and format version
+        let mut bytes = ModuleInterface::new("m").encode();
+        bytes.splice(6..=6, too_large);
+        assert!(matches!(ModuleInterface::decode(&bytes), Err(InterfaceError::LengthOverflow)));
+    }
+
+    #[test]
+    fn test_interface_compiler_version() {
+        let mut interface = ModuleInterface::new("m");
+        assert!(interface.ensure_current_compiler().is_ok());
+
+        interface.compiler_version = "0.0.0-other".into();
+        let decoded = ModuleInterface::decode(&interface.encode()).unwrap();
+        assert!(matches!(
+            decoded.ensure_current_compiler(),
+            Err(InterfaceError::CompilerMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,327 @@
+//! Crash-safe key-value store for tool caches: incremental build
+//! fingerprints, bench baselines and the like.
+//!
+//! The store is an append-only log; every `put` or `remove` appends one
+//! record and the whole map is kept in memory. Layout (integers
+//! little-endian):
+//!
+//! ```text
+//! magic "ZKV1" | records...
+//! record: crc32 u32 | key length u32 | value length u32 | key | value
+//! ```
+//!
+//! The CRC covers everything after it, and a value length of `u32::MAX`
+//! marks a removal. A crash can leave a torn record at the end of the log;
+//! opening stops at the first record that is incomplete or fails its
+//! checksum, and cuts the log there, so a cache loses at most its latest
+//! writes and never returns damaged data. Once most of the log is
+//! overwritten records, it is compacted by writing the live entries to a
+//! new file and renaming it over the old one.
+//!
+//! One process writes a store at a time; caches are cheap to rebuild, so
+//! there is no locking.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"ZKV1";
+const HEADER_LEN: usize = 12;
+const TOMBSTONE: u32 = u32::MAX;
+
+/// Logs smaller than this are never compacted
+const COMPACT_MIN_BYTES: u64 = 64 * 1024;
+
+pub struct KvStore {
+    path: PathBuf,
+    file: File,
+    entries: HashMap<Vec<u8>, Vec<u8>>,
+    log_bytes: u64,
+    /// Bytes of the records holding the current entries
+    live_bytes: u64,
+    /// Bytes cut from the end of the log when it was opened
+    recovered_bytes: u64,
+}
+
+impl KvStore {
+    /// Open the store at `path`, creating it if it does not exist
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut entries = HashMap::new();
+        let mut live_bytes = 0;
+        // Anything but our header is a foreign or damaged file; start over
+        let valid = if data.starts_with(MAGIC) {
+            let mut offset = MAGIC.len();
+            while let Some(Record { key, value, len }) = read_record(&data[offset..]) {
+                match value {
+                    Some(value) => {
+                        if let Some(old) = entries.insert(key.to_vec(), value.to_vec()) {
+                            live_bytes -= record_len(key, &old);
+                        }
+                        live_bytes += len as u64;
+                    }
+                    None => {
+                        if let Some(old) = entries.remove(key) {
+                            live_bytes -= record_len(key, &old);
+                        }
+                    }
+                }
+                offset += len;
+            }
+            offset
+        } else {
+            0
+        };
+
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        if valid < data.len() || data.is_empty() {
+            file.set_len(valid as u64)?;
+            if valid == 0 {
+                file.write_all(MAGIC)?;
+            }
+            file.sync_data()?;
+        }
+        // Appends go after the last good record
+        let file = OpenOptions::new().append(true).open(path)?;
+
+        Ok(KvStore {
+            path: path.to_path_buf(),
+            file,
+            entries,
+            log_bytes: valid.max(MAGIC.len()) as u64,
+            live_bytes,
+            recovered_bytes: (data.len() - valid) as u64,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        if self.get(key) == Some(value) {
+            return Ok(());
+        }
+        self.append(&encode_record(key, Some(value)))?;
+        if let Some(old) = self.entries.insert(key.to_vec(), value.to_vec()) {
+            self.live_bytes -= record_len(key, &old);
+        }
+        self.live_bytes += record_len(key, value);
+        self.compact_if_sparse()
+    }
+
+    /// Remove `key`, returning whether it was present
+    pub fn remove(&mut self, key: &[u8]) -> io::Result<bool> {
+        if !self.entries.contains_key(key) {
+            return Ok(false);
+        }
+        self.append(&encode_record(key, None))?;
+        if let Some(old) = self.entries.remove(key) {
+            self.live_bytes -= record_len(key, &old);
+        }
+        self.compact_if_sparse()?;
+        Ok(true)
+    }
+
+    /// Entries in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.entries.iter().map(|(key, value)| (key.as_slice(), value.as_slice()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Bytes of damaged or incomplete records dropped when the store was
+    /// opened, e.g. after a crash mid-write
+    pub fn recovered_bytes(&self) -> u64 {
+        self.recovered_bytes
+    }
+
+    /// Wait until every write so far is on disk
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    /// Rewrite the log with only the current entries. The new log is
+    /// complete on disk before it replaces the old one, so a crash leaves
+    /// one or the other.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let mut temp_name = self.path.as_os_str().to_owned();
+        temp_name.push(".compact");
+        let temp = PathBuf::from(temp_name);
+
+        let mut data = MAGIC.to_vec();
+        // Sorted so equal stores produce equal files
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort();
+        for (key, value) in entries {
+            data.extend_from_slice(&encode_record(key, Some(value)));
+        }
+
+        let mut file = File::create(&temp)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        fs::rename(&temp, &self.path)?;
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            // Makes the rename durable; not every platform can open a
+            // directory
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.log_bytes = data.len() as u64;
+        self.live_bytes = self.log_bytes - MAGIC.len() as u64;
+        Ok(())
+    }
+
+    fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        // One write per record, so a torn write only damages this one
+        self.file.write_all(record)?;
+        self.log_bytes += record.len() as u64;
+        Ok(())
+    }
+
+    fn compact_if_sparse(&mut self) -> io::Result<()> {
+        if self.log_bytes >= COMPACT_MIN_BYTES && self.log_bytes > 2 * (self.live_bytes + MAGIC.len() as u64) {
+            self.compact()?;
+        }
+        Ok(())
+    }
+}
+
+fn record_len(key: &[u8], value: &[u8]) -> u64 {
+    (HEADER_LEN + key.len() + value.len()) as u64
+}
+
+fn encode_record(key: &[u8], value: Option<&[u8]>) -> Vec<u8> {
+    let value_len = value.map_or(TOMBSTONE, |value| value.len() as u32);
+    let mut record = Vec::with_capacity(HEADER_LEN + key.len() + value.map_or(0, <[u8]>::len));
+    record.extend_from_slice(&[0; 4]);
+    record.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    record.extend_from_slice(&value_len.to_le_bytes());
+    record.extend_from_slice(key);
+    record.extend_from_slice(value.unwrap_or_default());
+    let crc = crc32(&record[4..]);
+    record[..4].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+struct Record<'a> {
+    key: &'a [u8],
+    /// `None` for a removal
+    value: Option<&'a [u8]>,
+    len: usize,
+}
+
+/// The record `data` starts with, if it is complete and passes its checksum
+fn read_record(data: &[u8]) -> Option<Record<'_>> {
+    let header = data.get(..HEADER_LEN)?;
+    let u32_at = |at: usize| u32::from_le_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]]);
+    let (crc, key_len, value_len) = (u32_at(0), u32_at(4) as usize, u32_at(8));
+
+    let body_len = if value_len == TOMBSTONE { 0 } else { value_len as usize };
+    let len = HEADER_LEN.checked_add(key_len)?.checked_add(body_len)?;
+    let record = data.get(..len)?;
+    if crc32(&record[4..]) != crc {
+        return None;
+    }
+
+    let key = &record[HEADER_LEN..HEADER_LEN + key_len];
+    let value = (value_len != TOMBSTONE).then(|| &record[HEADER_LEN + key_len..]);
+    Some(Record { key, value, len })
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32_TABLE: [u32; 256] = crc32_table();
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| (crc >> 8) ^ CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("zaitun-kv-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_kv_store_reopen() {
+        let path = temp_store("reopen");
+        let mut store = KvStore::open(&path).unwrap();
+        store.put(b"a", b"1").unwrap();
+        store.put(b"b", b"2").unwrap();
+        store.put(b"a", b"3").unwrap();
+        store.remove(b"b").unwrap();
+        drop(store);
+
+        let store = KvStore::open(&path).unwrap();
+        assert_eq!(store.get(b"a"), Some(&b"3"[..]));
+        assert_eq!(store.get(b"b"), None);
+        assert_eq!(store.recovered_bytes(), 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_kv_store_drops_torn_record() {
+        let path = temp_store("torn");
+        let mut store = KvStore::open(&path).unwrap();
+        store.put(b"kept", b"yes").unwrap();
+        store.put(b"torn", b"no").unwrap();
+        drop(store);
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 1).unwrap();
+
+        let mut store = KvStore::open(&path).unwrap();
+        assert_eq!(store.get(b"kept"), Some(&b"yes"[..]));
+        assert_eq!(store.get(b"torn"), None);
+        assert!(store.recovered_bytes() > 0);
+        // Later writes land after the last good record
+        store.put(b"next", b"1").unwrap();
+        drop(store);
+        assert_eq!(KvStore::open(&path).unwrap().get(b"next"), Some(&b"1"[..]));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,411 @@
+//! Struct layout: field offsets, size and alignment.
+//!
+//! Without attributes the compiler may reorder fields to reduce padding.
+//! `#[repr(C)]` keeps declaration order with C padding rules, so the struct
+//! can cross an FFI boundary. `#[repr(packed)]` also keeps declaration
+//! order but drops all padding and has alignment 1, so its fields may be
+//! misaligned and must not be borrowed. `#[align(N)]` raises a struct's
+//! alignment to `N`, a power of two, and cannot be combined with `packed`.
+//!
+//! The type checker computes every layout once; codegen and the FFI
+//! checker read the result instead of recomputing it.
+
+use crate::error_handling::{CompileError, ErrorKind, Span};
+use crate::types::Type;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Largest alignment `#[align(N)]` accepts, as in LLVM
+pub const MAX_ALIGN: u64 = 1 << 29;
+
+/// Layout attributes of a struct
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Repr {
+    /// `#[repr(C)]`
+    pub c: bool,
+    /// `#[repr(packed)]`
+    pub packed: bool,
+    /// `#[align(N)]`
+    pub align: Option<u64>,
+}
+
+impl Repr {
+    /// Collect `#[repr(...)]` and `#[align(N)]` from a struct's attributes,
+    /// ignoring unrelated ones
+    pub fn from_attributes<'a, I>(attributes: I) -> Result<Self, LayoutError>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut repr = Repr::default();
+
+        for attribute in attributes {
+            let inner = match attribute.trim().strip_prefix("#[").and_then(|rest| rest.strip_suffix(']')) {
+                Some(inner) => inner.trim(),
+                None => continue,
+            };
+
+            if let Some(args) = inner.strip_prefix("repr(").and_then(|rest| rest.strip_suffix(')')) {
+                for arg in args.split(',').map(str::trim) {
+                    match arg {
+                        "C" => repr.c = true,
+                        "packed" => repr.packed = true,
+                        other => return Err(LayoutError::UnknownRepr(other.to_string())),
+                    }
+                }
+            } else if let Some(arg) = inner.strip_prefix("align(").and_then(|rest| rest.strip_suffix(')')) {
+                let align = arg
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|align| align.is_power_of_two() && *align <= MAX_ALIGN)
+                    .ok_or_else(|| LayoutError::InvalidAlign(arg.trim().to_string()))?;
+                repr.align = Some(align);
+            }
+        }
+
+        if repr.packed && repr.align.is_some() {
+            return Err(LayoutError::PackedAndAligned);
+        }
+        Ok(repr)
+    }
+
+    /// Field order is fixed by the declaration
+    pub fn keeps_order(&self) -> bool {
+        self.c || self.packed
+    }
+}
+
+/// Size and alignment of a value, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeLayout {
+    pub size: u64,
+    pub align: u64,
+}
+
+impl TypeLayout {
+    const fn new(size: u64, align: u64) -> Self {
+        TypeLayout { size, align }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldLayout {
+    pub name: String,
+    pub type_: Type,
+    pub offset: u64,
+    pub layout: TypeLayout,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructLayout {
+    pub name: String,
+    pub repr: Repr,
+    /// In declaration order; offsets are not necessarily increasing
+    pub fields: Vec<FieldLayout>,
+    pub size: u64,
+    pub align: u64,
+}
+
+impl StructLayout {
+    pub fn field(&self, name: &str) -> Option<&FieldLayout> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// Fields sorted by offset, i.e. in memory order
+    pub fn fields_in_memory_order(&self) -> Vec<&FieldLayout> {
+        let mut fields: Vec<&FieldLayout> = self.fields.iter().collect();
+        fields.sort_by_key(|field| field.offset);
+        fields
+    }
+}
+
+/// A struct declaration to lay out
+pub struct StructDecl<'a> {
+    pub name: &'a str,
+    pub fields: &'a [(String, Type)],
+    pub repr: Repr,
+}
+
+/// Computed layouts of all structs known to the type checker
+#[derive(Debug, Clone, Default)]
+pub struct LayoutCx {
+    structs: HashMap<String, StructLayout>,
+}
+
+impl LayoutCx {
+    pub fn new() -> Self {
+        LayoutCx::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StructLayout> {
+        self.structs.get(name)
+    }
+
+    /// Lay out `decls`, each after the structs it contains by value. A
+    /// struct containing itself, directly or not, has no finite size.
+    pub fn compute_all(&mut self, decls: &[StructDecl]) -> Result<(), LayoutError> {
+        let by_name: HashMap<&str, &StructDecl> = decls.iter().map(|decl| (decl.name, decl)).collect();
+        let mut visiting = HashSet::new();
+
+        for decl in decls {
+            self.compute_in_order(decl, &by_name, &mut visiting)?;
+        }
+        Ok(())
+    }
+
+    fn compute_in_order(
+        &mut self,
+        decl: &StructDecl,
+        by_name: &HashMap<&str, &StructDecl>,
+        visiting: &mut HashSet<String>,
+    ) -> Result<(), LayoutError> {
+        if self.structs.contains_key(decl.name) {
+            return Ok(());
+        }
+        if !visiting.insert(decl.name.to_string()) {
+            return Err(LayoutError::Recursive(decl.name.to_string()));
+        }
+
+        for (_, type_) in decl.fields {
+            for name in structs_by_value(type_) {
+                if let Some(dependency) = by_name.get(name) {
+                    self.compute_in_order(dependency, by_name, visiting)?;
+                }
+            }
+        }
+
+        visiting.remove(decl.name);
+        self.compute(decl.name, decl.fields, decl.repr)?;
+        Ok(())
+    }
+
+    /// Lay out one struct whose field structs are already known
+    pub fn compute(&mut self, name: &str, fields: &[(String, Type)], repr: Repr) -> Result<&StructLayout, LayoutError> {
+        let field_layouts = fields
+            .iter()
+            .map(|(field, type_)| Ok((field.clone(), type_.clone(), self.type_layout(type_)?)))
+            .collect::<Result<Vec<_>, LayoutError>>()?;
+
+        // Default repr places the most aligned fields first, which leaves
+        // no padding between fields whose sizes are multiples of their
+        // alignment; the stable sort keeps ties in declaration order
+        let mut placement: Vec<usize> = (0..field_layouts.len()).collect();
+        if !repr.keeps_order() {
+            placement.sort_by_key(|&index| std::cmp::Reverse(field_layouts[index].2.align));
+        }
+
+        let mut offsets = vec![0; field_layouts.len()];
+        let mut offset = 0u64;
+        let mut align = 1u64;
+        for index in placement {
+            let layout = field_layouts[index].2;
+            if !repr.packed {
+                offset = round_up(offset, layout.align);
+                align = align.max(layout.align);
+            }
+            offsets[index] = offset;
+            offset += layout.size;
+        }
+
+        if let Some(forced) = repr.align {
+            align = align.max(forced);
+        }
+        let size = round_up(offset, align);
+
+        let layout = StructLayout {
+            name: name.to_string(),
+            repr,
+            fields: field_layouts
+                .into_iter()
+                .zip(offsets)
+                .map(|((name, type_, layout), offset)| FieldLayout { name, type_, offset, layout })
+                .collect(),
+            size,
+            align,
+        };
+        self.structs.insert(name.to_string(), layout);
+        Ok(&self.structs[name])
+    }
+
+    pub fn type_layout(&self, type_: &Type) -> Result<TypeLayout, LayoutError> {
+        Ok(match type_ {
+            Type::Void => TypeLayout::new(0, 1),
+            Type::Bool => TypeLayout::new(1, 1),
+            // Unsuffixed integers default to i64, see `numeric`
+            Type::Int | Type::Float => TypeLayout::new(8, 8),
+            // Pointer and length
+            Type::String => TypeLayout::new(16, 8),
+            // Pointer, length and capacity
+            Type::Array(_) => TypeLayout::new(24, 8),
+            // Heap-allocated; held by pointer
+            Type::Map(_, _) | Type::Class(_) => TypeLayout::new(8, 8),
+            // Code and environment pointers; data and vtable pointers
+            Type::Function(_, _) | Type::Interface(_) => TypeLayout::new(16, 8),
+            // C-compatible `int` discriminant
+            Type::Enum(_) => TypeLayout::new(4, 4),
+            Type::Struct(name) => {
+                let layout = self.get(name).ok_or_else(|| LayoutError::UnknownStruct(name.clone()))?;
+                TypeLayout::new(layout.size, layout.align)
+            }
+            // A one-byte tag, padded to the payload's alignment, then the payload
+            Type::Optional(inner) => tagged(self.type_layout(inner)?),
+            Type::Union(members) => {
+                let mut payload = TypeLayout::new(0, 1);
+                for member in members {
+                    let member = self.type_layout(member)?;
+                    payload = TypeLayout::new(payload.size.max(member.size), payload.align.max(member.align));
+                }
+                tagged(payload)
+            }
+            Type::Generic(_, _) | Type::Unknown => return Err(LayoutError::NotConcrete(type_.to_string())),
+        })
+    }
+
+    /// `&value.field` is an error when the field lives in a packed struct
+    /// and needs more than byte alignment: the reference could be misaligned
+    pub fn check_field_reference(&self, struct_name: &str, field: &str, span: &Span) -> Result<(), CompileError> {
+        let layout = match self.get(struct_name) {
+            Some(layout) if layout.repr.packed => layout,
+            _ => return Ok(()),
+        };
+        let field_layout = match layout.field(field) {
+            Some(field_layout) => field_layout,
+            None => return Ok(()),
+        };
+
+        if field_layout.layout.align > 1 {
+            return Err(CompileError::new(
+                ErrorKind::Safety,
+                &format!("Reference to field `{}` of packed struct `{}` may be unaligned", field, struct_name),
+            )
+            .with_span(span.clone())
+            .with_note(&format!(
+                "`{}` needs alignment {} but is at offset {} in a struct with alignment 1",
+                field, field_layout.layout.align, field_layout.offset,
+            ))
+            .with_help("copy the field into a local variable and reference that instead"));
+        }
+        Ok(())
+    }
+
+    /// Whether `type_` can be passed to or returned from a foreign function
+    /// by value. Structs must be `#[repr(C)]` (or packed) with FFI-safe
+    /// fields; strings, arrays and other managed types must be converted.
+    pub fn check_ffi_type(&self, type_: &Type, span: &Span) -> Result<(), CompileError> {
+        let reason = match type_ {
+            Type::Void | Type::Bool | Type::Int | Type::Float | Type::Enum(_) => return Ok(()),
+            Type::Struct(name) => match self.get(name) {
+                Some(layout) if layout.repr.keeps_order() => {
+                    for field in &layout.fields {
+                        self.check_ffi_type(&field.type_, span)?;
+                    }
+                    return Ok(());
+                }
+                Some(_) => format!("struct `{}` has no defined layout; add `#[repr(C)]`", name),
+                None => format!("layout of struct `{}` is unknown", name),
+            },
+            other => format!("`{}` has no C equivalent; pass a pointer or a `#[repr(C)]` struct", other),
+        };
+
+        Err(CompileError::new(ErrorKind::Type, &format!("Type `{}` is not FFI-safe", type_))
+            .with_span(span.clone())
+            .with_note(&reason))
+    }
+
+    /// LLVM type for a struct, with explicit padding so LLVM's own layout
+    /// rules cannot disagree with ours
+    pub fn llvm_struct_type(&self, name: &str) -> Option<String> {
+        let layout = self.get(name)?;
+        let mut parts = Vec::new();
+        let mut offset = 0;
+
+        for field in layout.fields_in_memory_order() {
+            if field.offset > offset {
+                parts.push(format!("[{} x i8]", field.offset - offset));
+            }
+            parts.push(llvm_field_type(&field.type_, field.layout));
+            offset = field.offset + field.layout.size;
+        }
+        if layout.size > offset {
+            parts.push(format!("[{} x i8]", layout.size - offset));
+        }
+
+        Some(if layout.repr.packed {
+            format!("<{{ {} }}>", parts.join(", "))
+        } else {
+            format!("{{ {} }}", parts.join(", "))
+        })
+    }
+}
+
+/// LLVM type of a field; managed values are opaque bytes of the right size
+fn llvm_field_type(type_: &Type, layout: TypeLayout) -> String {
+    match type_ {
+        Type::Bool => "i8".to_string(),
+        Type::Int => "i64".to_string(),
+        Type::Float => "double".to_string(),
+        Type::Enum(_) => "i32".to_string(),
+        Type::Struct(name) => format!("%{}", name),
+        Type::Map(_, _) | Type::Class(_) => "ptr".to_string(),
+        _ => format!("[{} x i8]", layout.size),
+    }
+}
+
+fn tagged(payload: TypeLayout) -> TypeLayout {
+    let align = payload.align.max(1);
+    TypeLayout::new(round_up(align + payload.size, align), align)
+}
+
+/// Structs stored inline in a value of `type_`, as opposed to behind a
+/// pointer
+fn structs_by_value(type_: &Type) -> Vec<&str> {
+    match type_ {
+        Type::Struct(name) => vec![name.as_str()],
+        Type::Optional(inner) => structs_by_value(inner),
+        Type::Union(members) => members.iter().flat_map(structs_by_value).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn round_up(value: u64, align: u64) -> u64 {
+    value.div_ceil(align.max(1)) * align.max(1)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutError {
+    UnknownRepr(String),
+    InvalidAlign(String),
+    PackedAndAligned,
+    Recursive(String),
+    UnknownStruct(String),
+    NotConcrete(String),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::UnknownRepr(repr) => write!(f, "Unknown representation `{}`; expected `C` or `packed`", repr),
+            LayoutError::InvalidAlign(align) => {
+                write!(f, "Invalid alignment `{}`: must be a power of two no larger than 2^29", align)
+            }
+            LayoutError::PackedAndAligned => write!(f, "A struct cannot be both `packed` and `align`ed"),
+            LayoutError::Recursive(name) => {
+                write!(f, "Struct `{}` contains itself by value and has infinite size", name)
+            }
+            LayoutError::UnknownStruct(name) => write!(f, "Layout of struct `{}` is not known", name),
+            LayoutError::NotConcrete(type_) => write!(f, "Type `{}` has no layout until it is instantiated", type_),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+impl LayoutError {
+    pub fn into_compile_error(self) -> CompileError {
+        let error = CompileError::new(ErrorKind::Type, &self.to_string());
+        match self {
+            LayoutError::Recursive(_) => error.with_help("store the recursive field behind a reference, e.g. in a class or an array"),
+            _ => error,
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use crate::edition::Edition;
+use crate::numeric::NumericLiteral;
 use std::fmt;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -6,6 +8,7 @@ pub enum TokenType {
     Let,
     Fn,
     Class,
+    Sealed,
     If,
     Else,
     While,
@@ -14,6 +17,10 @@ pub enum TokenType {
     Return,
     Try,
     Catch,
+    // Keywords from edition 2026; identifiers before it
+    Async,
+    Await,
+    Yield,
     
     // Literals
     Identifier,
@@ -73,10 +80,16 @@ pub struct Lexer {
     current: usize,
     line: usize,
     column: usize,
+    edition: Edition,
 }
 
 impl Lexer {
     pub fn new(source: String) -> Self {
+        Lexer::with_edition(source, Edition::default())
+    }
+    
+    /// Lex with the keywords of `edition`
+    pub fn with_edition(source: String, edition: Edition) -> Self {
         Lexer {
             source,
             tokens: Vec::new(),
@@ -84,9 +97,15 @@ impl Lexer {
             current: 0,
             line: 1,
             column: 1,
+            edition,
         }
     }
     
+    /// Text being lexed
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    
     pub fn scan_tokens(&mut self) -> Vec<Token> {
         while !self.is_at_end() {
             self.start = self.current;
@@ -177,6 +196,21 @@ impl Lexer {
     }
     
     fn identifier(&mut self) {
+        // `r#name` is the identifier `name`, even when `name` is a keyword
+        if self.peek() == '#' && self.is_alpha(self.peek_next()) && &self.source[self.start..self.current] == "r" {
+            self.advance();
+            while self.is_alphanumeric(self.peek()) {
+                self.advance();
+            }
+            self.tokens.push(Token {
+                token_type: TokenType::Identifier,
+                lexeme: self.source[self.start + 2..self.current].to_string(),
+                line: self.line,
+                column: self.column - (self.current - self.start),
+            });
+            return;
+        }
+        
         while self.is_alphanumeric(self.peek()) {
             self.advance();
         }
@@ -196,6 +230,10 @@ impl Lexer {
             "catch" => TokenType::Catch,
             "true" => TokenType::True,
             "false" => TokenType::False,
+            "async" if self.edition >= Edition::E2026 => TokenType::Async,
+            "await" if self.edition >= Edition::E2026 => TokenType::Await,
+            "yield" if self.edition >= Edition::E2026 => TokenType::Yield,
+            "sealed" if self.edition >= Edition::E2026 => TokenType::Sealed,
             _ => TokenType::Identifier,
         };
         
@@ -203,7 +241,8 @@ impl Lexer {
     }
     
     fn number(&mut self) {
-        while self.is_digit(self.peek()) {
+        // `_` separates digit groups, e.g. 1_000_000
+        while self.is_digit(self.peek()) || self.peek() == '_' {
             self.advance();
         }
         
@@ -212,11 +251,41 @@ impl Lexer {
             // Consume the "."
             self.advance();
             
-            while self.is_digit(self.peek()) {
+            while self.is_digit(self.peek()) || self.peek() == '_' {
                 self.advance();
             }
         }
         
+        // Exponent, e.g. 1e5 or 2.5E-3. Checked before the suffix, which
+        // would otherwise take the `e`
+        if matches!(self.peek(), 'e' | 'E') {
+            let signed = matches!(self.peek_next(), '+' | '-');
+            let first_digit = if signed { self.peek_at(2) } else { self.peek_next() };
+            if self.is_digit(first_digit) {
+                self.advance();
+                if signed {
+                    self.advance();
+                }
+                while self.is_digit(self.peek()) || self.peek() == '_' {
+                    self.advance();
+                }
+            }
+        }
+        
+        // Type suffix, e.g. 255u8 or 1.5f32
+        if self.is_alpha(self.peek()) {
+            while self.is_alphanumeric(self.peek()) {
+                self.advance();
+            }
+        }
+        
+        // Only the suffix can be wrong here; range is checked once the
+        // literal's type is known
+        if NumericLiteral::parse(&self.source[self.start..self.current]).is_err() {
+            self.add_token_error("Invalid numeric literal suffix");
+            return;
+        }
+        
         self.add_token(TokenType::Number);
     }
     
@@ -237,8 +306,7 @@ impl Lexer {
         // The closing "
         self.advance();
         
-        // Trim the surrounding quotes
-        let value = &self.source[self.start + 1..self.current - 1];
+        // The lexeme keeps its quotes; the parser trims them
         self.add_token(TokenType::String);
     }
     
@@ -264,18 +332,22 @@ impl Lexer {
     }
     
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
+        self.peek_at(1)
+    }
+    
+    fn peek_at(&self, offset: usize) -> char {
+        if self.current + offset >= self.source.len() {
             return '\0';
         }
-        self.source.chars().nth(self.current + 1).unwrap_or('\0')
+        self.source.chars().nth(self.current + offset).unwrap_or('\0')
     }
     
     fn is_alpha(&self, c: char) -> bool {
-        (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_'
+        c.is_ascii_alphabetic() || c == '_'
     }
     
     fn is_digit(&self, c: char) -> bool {
-        c >= '0' && c <= '9'
+        c.is_ascii_digit()
     }
     
     fn is_alphanumeric(&self, c: char) -> bool {
@@ -347,4 +419,50 @@ mod tests {
         assert_eq!(tokens[6].token_type, TokenType::Comma);
         // ... and so on
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_lexer_edition_keywords() {
+        let source = "yield r#yield";
+        let old = Lexer::with_edition(source.to_string(), Edition::E2024).scan_tokens();
+        let new = Lexer::with_edition(source.to_string(), Edition::E2026).scan_tokens();
+        
+        assert_eq!(old[0].token_type, TokenType::Identifier);
+        assert_eq!(new[0].token_type, TokenType::Yield);
+        // Raw identifiers are plain names in every edition
+        assert_eq!(new[1].token_type, TokenType::Identifier);
+        assert_eq!(new[1].lexeme, "yield");
+        assert_eq!(new[1].column, 7);
+    }
+    
+    #[test]
+    fn test_lexer_numbers() {
+        let lex = |source: &str| Lexer::new(source.to_string()).scan_tokens();
+        
+        for source in ["1_000_000", "255u8", "1.5f32", "1e5", "2.5E-3", "6.02e+23", "1_0e1_0f64"] {
+            let tokens = lex(source);
+            assert_eq!(tokens[0].token_type, TokenType::Number, "{}", source);
+            assert_eq!(tokens[0].lexeme, source);
+        }
+        
+        // A method call on an integer is not an exponent
+        let tokens = lex("1.exp");
+        assert_eq!(tokens[0].lexeme, "1");
+        
+        assert_eq!(lex("5xyz")[0].token_type, TokenType::Error);
+        assert_eq!(lex("1e5u8")[0].token_type, TokenType::Error);
+    }
+    
+    #[test]
+    fn test_lexer_sealed_is_edition_keyword() {
+        let source = "let sealed = 1;";
+        let old = Lexer::with_edition(source.to_string(), Edition::E2024).scan_tokens();
+        let new = Lexer::with_edition(source.to_string(), Edition::E2026).scan_tokens();
+        
+        assert_eq!(old[1].token_type, TokenType::Identifier);
+        assert_eq!(new[1].token_type, TokenType::Sealed);
+        
+        let lints = crate::edition::migration_lints(std::path::Path::new("a.zt"), source, Edition::E2024, Edition::E2026);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].fixes[0].replacement, "r#sealed");
+    }
+}
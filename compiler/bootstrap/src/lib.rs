@@ -0,0 +1,53 @@
+//! Bootstrap compiler library, shared by the stable `zaitun_compiler` API
+//! crate and the tools under `tools/`.
+//!
+//! `safety`, `pattern`, `pattern_check`, `lsp` and `pm` are not built:
+//! the first three use AST types the parser does not produce yet
+//! (`MatchExpr`, `Borrow`), and the language server and package manager
+//! live in `tools/`.
+
+// Passes return `CompileError` by value; boxing it everywhere would only
+// move the allocation to the error path's callers
+#![allow(clippy::result_large_err)]
+
+pub mod alloc_reuse;
+pub mod assert;
+pub mod ast;
+pub mod ast_json;
+/// std's checksums, shared with the tools so fingerprints are computed
+/// one way everywhere without the compiler depending on all of std
+#[path = "../../../std/src/checksum.rs"]
+pub mod checksum;
+pub mod daemon;
+pub mod devirtualize;
+pub mod docgen;
+pub mod driver;
+pub mod edition;
+pub mod error;
+pub mod error_handling;
+pub mod ffi;
+pub mod function_ref;
+pub mod interface;
+pub mod kv_store;
+pub mod layout;
+pub mod lexer;
+pub mod lto;
+pub mod r#macro;
+pub mod macro_system;
+pub mod memory_stats;
+pub mod must_use;
+pub mod numeric;
+pub mod optimize;
+pub mod package;
+pub mod parser;
+pub mod profile;
+pub mod reproducible;
+pub mod sccp;
+pub mod ssa;
+pub mod statics;
+pub mod suggest;
+pub mod switch_lower;
+pub mod typecheck;
+pub mod types;
+pub mod vectorize;
+pub mod version_info;
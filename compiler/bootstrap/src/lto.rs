@@ -0,0 +1,297 @@
+//! Link-time whole-program optimization (`--lto`).
+//!
+//! With `--emit lto-ir` each module is written as serialized IR (`.zir`)
+//! next to its interface instead of being lowered to an object. At link
+//! time the IR of the program and all its dependencies is merged into one
+//! unit, and inlining and dead-code elimination run again with every call
+//! target visible. The package manager builds dependency IR through its
+//! artifact cache, so each dependency is lowered once per compiler and
+//! option set and reused by every program linking it.
+//!
+//! The IR kept here is the part link-time passes need: per-function
+//! instruction lists with calls spelled out by qualified name.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+pub const LTO_IR_FORMAT_VERSION: u16 = 1;
+pub const LTO_IR_EXTENSION: &str = "zir";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Instruction {
+    /// Call to a function by qualified name (`module::function`)
+    Call(String),
+    /// Any other instruction, opaque to link-time passes
+    Op(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IrFunction {
+    pub name: String,
+    /// Visible outside the program, e.g. `pub` items of a library
+    pub exported: bool,
+    pub body: Vec<Instruction>,
+}
+
+impl IrFunction {
+    fn calls(&self) -> impl Iterator<Item = &str> {
+        self.body.iter().filter_map(|instruction| match instruction {
+            Instruction::Call(callee) => Some(callee.as_str()),
+            Instruction::Op(_) => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleIr {
+    pub format_version: u16,
+    pub compiler_version: String,
+    pub module_name: String,
+    pub functions: Vec<IrFunction>,
+    /// Functions provided outside the IR, e.g. runtime or FFI symbols
+    pub externs: Vec<String>,
+}
+
+impl ModuleIr {
+    pub fn new(module_name: &str) -> Self {
+        ModuleIr {
+            format_version: LTO_IR_FORMAT_VERSION,
+            compiler_version: env!("CARGO_PKG_VERSION").to_string(),
+            module_name: module_name.to_string(),
+            functions: Vec::new(),
+            externs: Vec::new(),
+        }
+    }
+
+    pub fn write_to(&self, path: &Path) -> Result<(), LtoError> {
+        let json = serde_json::to_vec(self).map_err(|e| LtoError::Malformed(e.to_string()))?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Read IR, rejecting IR from another format or compiler version: it
+    /// must not be mixed into a program built by this compiler
+    pub fn read_from(path: &Path) -> Result<Self, LtoError> {
+        let bytes = fs::read(path)?;
+        let module: ModuleIr =
+            serde_json::from_slice(&bytes).map_err(|e| LtoError::Malformed(e.to_string()))?;
+
+        if module.format_version != LTO_IR_FORMAT_VERSION {
+            return Err(LtoError::UnsupportedVersion(module.format_version));
+        }
+        if module.compiler_version != env!("CARGO_PKG_VERSION") {
+            return Err(LtoError::CompilerMismatch {
+                module: module.module_name,
+                found: module.compiler_version,
+            });
+        }
+
+        Ok(module)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LtoOptions {
+    /// Functions that must survive dead-code elimination besides exported
+    /// ones, normally the entry point
+    pub roots: Vec<String>,
+    /// Largest callee, in instructions, inlined into its callers
+    pub inline_threshold: usize,
+    /// Inlining rounds; each round can expose new small callees
+    pub inline_rounds: usize,
+}
+
+impl Default for LtoOptions {
+    fn default() -> Self {
+        LtoOptions {
+            roots: vec!["main".to_string()],
+            inline_threshold: 32,
+            inline_rounds: 3,
+        }
+    }
+}
+
+/// What link-time optimization did, for `--verbose` output
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LtoStats {
+    pub modules: usize,
+    pub functions_before: usize,
+    pub functions_after: usize,
+    pub calls_inlined: usize,
+}
+
+/// The whole program after merging, keyed by qualified function name
+#[derive(Debug, Clone)]
+pub struct LinkedProgram {
+    pub functions: BTreeMap<String, IrFunction>,
+    pub externs: HashSet<String>,
+    pub stats: LtoStats,
+}
+
+/// Merge module IR and optimize across module boundaries
+pub fn link(modules: Vec<ModuleIr>, options: &LtoOptions) -> Result<LinkedProgram, LtoError> {
+    let mut program = merge(modules)?;
+    program.stats.functions_before = program.functions.len();
+
+    for _ in 0..options.inline_rounds {
+        let inlined = inline_small_functions(&mut program, options.inline_threshold);
+        program.stats.calls_inlined += inlined;
+        if inlined == 0 {
+            break;
+        }
+    }
+
+    eliminate_dead_functions(&mut program, &options.roots);
+    program.stats.functions_after = program.functions.len();
+
+    Ok(program)
+}
+
+/// Read every `.zir` file in `dir`, e.g. a dependency's cached build output
+pub fn read_dir(dir: &Path) -> Result<Vec<ModuleIr>, LtoError> {
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == LTO_IR_EXTENSION))
+        .collect();
+    // Directory order is not stable across filesystems
+    paths.sort();
+
+    paths.iter().map(|path| ModuleIr::read_from(path)).collect()
+}
+
+fn merge(modules: Vec<ModuleIr>) -> Result<LinkedProgram, LtoError> {
+    let mut functions = BTreeMap::new();
+    let mut externs = HashSet::new();
+    let module_count = modules.len();
+
+    for module in modules {
+        externs.extend(module.externs);
+        for function in module.functions {
+            if functions.contains_key(&function.name) {
+                return Err(LtoError::DuplicateSymbol(function.name));
+            }
+            functions.insert(function.name.clone(), function);
+        }
+    }
+
+    for function in functions.values() {
+        for callee in function.calls() {
+            if !functions.contains_key(callee) && !externs.contains(callee) {
+                return Err(LtoError::UndefinedSymbol {
+                    symbol: callee.to_string(),
+                    referenced_from: function.name.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(LinkedProgram {
+        functions,
+        externs,
+        stats: LtoStats {
+            modules: module_count,
+            ..LtoStats::default()
+        },
+    })
+}
+
+/// Replace calls to small, non-recursive functions with their bodies.
+/// Returns the number of call sites inlined.
+fn inline_small_functions(program: &mut LinkedProgram, threshold: usize) -> usize {
+    let inlinable: HashMap<String, Vec<Instruction>> = program
+        .functions
+        .values()
+        .filter(|function| function.body.len() <= threshold)
+        .filter(|function| function.calls().all(|callee| callee != function.name))
+        .map(|function| (function.name.clone(), function.body.clone()))
+        .collect();
+
+    let mut inlined = 0;
+    for function in program.functions.values_mut() {
+        let mut body = Vec::with_capacity(function.body.len());
+        for instruction in function.body.drain(..) {
+            match &instruction {
+                // A caller never inlines into itself, even through a
+                // callee that was made recursive by an earlier round
+                Instruction::Call(callee) if *callee != function.name => {
+                    if let Some(callee_body) = inlinable.get(callee) {
+                        body.extend(callee_body.iter().cloned());
+                        inlined += 1;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            body.push(instruction);
+        }
+        function.body = body;
+    }
+
+    inlined
+}
+
+/// Drop functions unreachable from `roots` and exported functions
+fn eliminate_dead_functions(program: &mut LinkedProgram, roots: &[String]) {
+    let mut live = HashSet::new();
+    let mut worklist: Vec<&str> = program
+        .functions
+        .values()
+        .filter(|function| function.exported)
+        .map(|function| function.name.as_str())
+        .chain(roots.iter().map(String::as_str))
+        .collect();
+
+    while let Some(name) = worklist.pop() {
+        if !live.insert(name.to_string()) {
+            continue;
+        }
+        if let Some(function) = program.functions.get(name) {
+            worklist.extend(function.calls());
+        }
+    }
+
+    program.functions.retain(|name, _| live.contains(name));
+}
+
+#[derive(Debug)]
+pub enum LtoError {
+    Io(std::io::Error),
+    Malformed(String),
+    UnsupportedVersion(u16),
+    CompilerMismatch { module: String, found: String },
+    DuplicateSymbol(String),
+    UndefinedSymbol { symbol: String, referenced_from: String },
+}
+
+impl fmt::Display for LtoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LtoError::Io(e) => write!(f, "I/O error: {}", e),
+            LtoError::Malformed(message) => write!(f, "Malformed LTO IR: {}", message),
+            LtoError::UnsupportedVersion(version) => write!(f, "Unsupported LTO IR format version {}", version),
+            LtoError::CompilerMismatch { module, found } => write!(
+                f,
+                "IR for module {} was produced by compiler {}, expected {}; rebuild it",
+                module,
+                found,
+                env!("CARGO_PKG_VERSION"),
+            ),
+            LtoError::DuplicateSymbol(symbol) => write!(f, "Duplicate definition of {} at link time", symbol),
+            LtoError::UndefinedSymbol { symbol, referenced_from } => {
+                write!(f, "Undefined symbol {} referenced from {}", symbol, referenced_from)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LtoError {}
+
+impl From<std::io::Error> for LtoError {
+    fn from(error: std::io::Error) -> Self {
+        LtoError::Io(error)
+    }
+}
@@ -1,10 +1,17 @@
 use crate::ast::*;
-use crate::error::CompileError;
+use crate::error_handling::CompileError;
+use crate::macro_system::MacroSystem;
 
 pub struct MacroExpander {
     macro_definitions: Vec<MacroDefinition>,
 }
 
+impl Default for MacroExpander {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MacroExpander {
     pub fn new() -> Self {
         MacroExpander {
@@ -17,21 +24,11 @@ impl MacroExpander {
     }
     
     pub fn expand(&self, ast: &mut AST) -> Result<(), Vec<CompileError>> {
-        let mut errors = Vec::new();
-        
         // Expand all macro invocations in the AST
-        // ... implementation details ...
-        
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            Err(errors)
+        let mut system = MacroSystem::new();
+        for definition in &self.macro_definitions {
+            system.register_macro(definition.name.clone(), definition.clone());
         }
+        system.expand_macros(ast)
     }
 }
-
-pub struct MacroDefinition {
-    name: String,
-    params: Vec<String>,
-    body: Vec<ASTNode>,
-}
\ No newline at end of file
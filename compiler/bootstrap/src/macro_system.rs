@@ -1,11 +1,17 @@
 use crate::ast::*;
-use crate::error::CompileError;
+use crate::error_handling::{CompileError, ErrorKind};
 use std::collections::HashMap;
 
 pub struct MacroSystem {
     macros: HashMap<String, MacroDefinition>,
 }
 
+impl Default for MacroSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MacroSystem {
     pub fn new() -> Self {
         MacroSystem {
@@ -20,16 +26,17 @@ impl MacroSystem {
     pub fn expand_macros(&self, ast: &mut AST) -> Result<(), Vec<CompileError>> {
         let mut errors = Vec::new();
         
-        // First pass: collect all macro definitions
+        // First pass: collect the macros defined in this file alongside the
+        // registered ones
+        let mut macros = self.macros.clone();
         for node in &ast.nodes {
             if let ASTNode::MacroDefinition(def) = node {
-                // Register macro definitions
-                // ... existing code ...
+                macros.insert(def.name.clone(), def.clone());
             }
         }
         
         // Second pass: expand macro invocations
-        self.expand_nodes(&mut ast.nodes, &mut errors);
+        Self::expand_nodes(&macros, &mut ast.nodes, &mut errors);
         
         if errors.is_empty() {
             Ok(())
@@ -38,19 +45,17 @@ impl MacroSystem {
         }
     }
     
-    fn expand_nodes(&self, nodes: &mut Vec<ASTNode>, errors: &mut Vec<CompileError>) {
+    fn expand_nodes(macros: &HashMap<String, MacroDefinition>, nodes: &mut Vec<ASTNode>, errors: &mut Vec<CompileError>) {
         let mut i = 0;
         while i < nodes.len() {
             if let ASTNode::MacroInvocation(invocation) = &nodes[i] {
-                if let Some(macro_def) = self.macros.get(&invocation.name) {
-                    match self.expand_invocation(invocation, macro_def) {
+                if let Some(macro_def) = macros.get(&invocation.name) {
+                    match Self::expand_invocation(invocation, macro_def) {
                         Ok(expanded) => {
                             // Replace the invocation with expanded nodes
-                            nodes.remove(i);
-                            for (j, node) in expanded.into_iter().enumerate() {
-                                nodes.insert(i + j, node);
-                            }
-                            i += expanded.len();
+                            let count = expanded.len();
+                            nodes.splice(i..=i, expanded);
+                            i += count;
                         }
                         Err(err) => {
                             errors.push(err);
@@ -58,10 +63,10 @@ impl MacroSystem {
                         }
                     }
                 } else {
-                    errors.push(CompileError::new(
-                        format!("Undefined macro: {}", invocation.name),
-                        invocation.span,
-                    ));
+                    errors.push(
+                        CompileError::new(ErrorKind::Name, &format!("Undefined macro: {}", invocation.name))
+                            .with_span(invocation.span.clone()),
+                    );
                     i += 1;
                 }
             } else {
@@ -72,20 +77,21 @@ impl MacroSystem {
         }
     }
     
-    fn expand_invocation(&self, invocation: &MacroInvocation, definition: &MacroDefinition) -> Result<Vec<ASTNode>, CompileError> {
-        // Implement macro expansion logic
+    fn expand_invocation(invocation: &MacroInvocation, definition: &MacroDefinition) -> Result<Vec<ASTNode>, CompileError> {
+        if invocation.args.len() != definition.params.len() {
+            return Err(CompileError::new(
+                ErrorKind::Type,
+                &format!(
+                    "Macro `{}` takes {} arguments but {} were given",
+                    definition.name,
+                    definition.params.len(),
+                    invocation.args.len()
+                ),
+            )
+            .with_span(invocation.span.clone()));
+        }
+        // Parameters are not substituted into the body yet
         // ... existing code ...
-        Ok(Vec::new())
+        Ok(definition.body.clone())
     }
 }
-
-pub struct MacroDefinition {
-    params: Vec<String>,
-    body: Vec<ASTNode>,
-}
-
-pub struct MacroInvocation {
-    name: String,
-    args: Vec<ASTNode>,
-    span: Span,
-}
\ No newline at end of file
@@ -0,0 +1,216 @@
+//! Memory use of the compiler for `--memory-stats`: the process's peak
+//! resident set size, and what each pass allocated as counted by
+//! `CountingAllocator`. Peak RSS tells users of large codebases how many
+//! builds fit in memory side by side; the per-pass numbers show where a
+//! memory regression came from.
+//!
+//! Counts cover every thread, so a pass's numbers include whatever else
+//! the process did meanwhile.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// System allocator that counts allocations and the bytes in use. The
+/// compiler and bench binaries install it with
+/// `#[global_allocator] static ALLOCATOR: CountingAllocator = CountingAllocator;`;
+/// without it every count is zero.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        add_live(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    // Growing a buffer in place still goes to the allocator, so it counts
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        if new_size > layout.size() {
+            add_live(new_size - layout.size());
+        } else {
+            LIVE_BYTES.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+fn add_live(bytes: usize) {
+    let live = LIVE_BYTES.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    PEAK_LIVE_BYTES.fetch_max(live, Ordering::Relaxed);
+}
+
+/// Allocator calls and bytes requested
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AllocationStats {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+impl AllocationStats {
+    /// Totals since the program started
+    pub fn current() -> Self {
+        AllocationStats {
+            count: ALLOCATIONS.load(Ordering::Relaxed),
+            bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+        }
+    }
+
+    /// What was allocated between `start` and `self`
+    pub fn since(self, start: AllocationStats) -> Self {
+        AllocationStats {
+            count: self.count - start.count,
+            bytes: self.bytes - start.bytes,
+        }
+    }
+}
+
+/// Bytes allocated and not yet freed
+pub fn live_bytes() -> usize {
+    LIVE_BYTES.load(Ordering::Relaxed)
+}
+
+/// Most bytes live at once since the program started or `reset_peak`
+pub fn peak_live_bytes() -> usize {
+    PEAK_LIVE_BYTES.load(Ordering::Relaxed)
+}
+
+/// Start measuring the peak again from what is live now
+pub fn reset_peak() {
+    PEAK_LIVE_BYTES.store(live_bytes(), Ordering::Relaxed);
+}
+
+/// Peak resident set size of the process, which unlike the allocator's
+/// counts includes code, stacks and allocator overhead. `None` where the
+/// platform does not report it.
+pub fn peak_rss_bytes() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        // `VmHWM:     12345 kB`
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find_map(|line| line.strip_prefix("VmHWM:"))?;
+        let kib: u64 = line.trim().trim_end_matches("kB").trim().parse().ok()?;
+        Some(kib * 1024)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Allocations of one compiler pass
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassMemory {
+    pub name: &'static str,
+    pub allocations: AllocationStats,
+    /// Most bytes live during the pass beyond those live when it started
+    pub peak_bytes: usize,
+    /// Bytes still live when the pass ended beyond those live when it
+    /// started; negative when the pass freed more than it kept
+    pub retained_bytes: i64,
+}
+
+/// Per-pass memory statistics of one compilation
+#[derive(Debug, Default)]
+pub struct MemoryReport {
+    passes: Vec<PassMemory>,
+    /// Name, allocations and live bytes at the start of the running pass
+    running: Option<(&'static str, AllocationStats, usize)>,
+    peak_rss: Option<u64>,
+}
+
+impl MemoryReport {
+    pub fn new() -> Self {
+        MemoryReport::default()
+    }
+
+    /// End the running pass, if any, and start `name`
+    pub fn begin_pass(&mut self, name: &'static str) {
+        self.end_pass();
+        reset_peak();
+        self.running = Some((name, AllocationStats::current(), live_bytes()));
+    }
+
+    /// End the running pass and take the process's peak RSS
+    pub fn finish(&mut self) {
+        self.end_pass();
+        self.peak_rss = peak_rss_bytes();
+    }
+
+    fn end_pass(&mut self) {
+        let Some((name, start, live_at_start)) = self.running.take() else {
+            return;
+        };
+        self.passes.push(PassMemory {
+            name,
+            allocations: AllocationStats::current().since(start),
+            peak_bytes: peak_live_bytes().saturating_sub(live_at_start),
+            retained_bytes: live_bytes() as i64 - live_at_start as i64,
+        });
+    }
+
+    pub fn passes(&self) -> &[PassMemory] {
+        &self.passes
+    }
+
+    pub fn peak_rss(&self) -> Option<u64> {
+        self.peak_rss
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.peak_rss {
+            Some(bytes) => write!(f, "memory: peak RSS {}", format_bytes(bytes))?,
+            None => write!(f, "memory: peak RSS not available on this platform")?,
+        }
+        if self.passes.is_empty() {
+            return Ok(());
+        }
+
+        write!(f, "\n  {:<10} {:>12} {:>12} {:>12} {:>12}", "pass", "allocations", "allocated", "peak", "retained")?;
+        for pass in &self.passes {
+            let retained = match pass.retained_bytes {
+                bytes if bytes < 0 => format!("-{}", format_bytes(bytes.unsigned_abs())),
+                bytes => format_bytes(bytes as u64),
+            };
+            write!(
+                f,
+                "\n  {:<10} {:>12} {:>12} {:>12} {:>12}",
+                pass.name,
+                pass.allocations.count,
+                format_bytes(pass.allocations.bytes as u64),
+                format_bytes(pass.peak_bytes as u64),
+                retained,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
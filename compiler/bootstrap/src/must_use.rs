@@ -0,0 +1,137 @@
+//! The `unused_must_use` and `unused_results` lints.
+//!
+//! A value whose type or producing function is marked `#[must_use]` may
+//! not be dropped by an expression statement. `Result` and `Future` are
+//! must-use without an attribute: dropping a `Result` ignores an error and
+//! dropping a `Future` never runs it. Separately, a statement made only of
+//! a pure expression (`x + 1;`) does nothing and is flagged too.
+
+use crate::error_handling::{CompileError, ErrorKind, Span};
+use crate::types::Type;
+use std::collections::HashMap;
+
+pub const UNUSED_MUST_USE: &str = "unused_must_use";
+pub const UNUSED_RESULTS: &str = "unused_results";
+
+/// Names marked `#[must_use]`, with the attribute's reason if it gave one
+#[derive(Debug, Clone)]
+pub struct MustUse {
+    types: HashMap<String, Option<String>>,
+    functions: HashMap<String, Option<String>>,
+}
+
+/// An expression statement, i.e. an expression whose value is discarded
+pub struct DiscardedValue<'a> {
+    /// Source text of the expression, used to build quick fixes
+    pub text: &'a str,
+    pub span: Span,
+    pub type_: &'a Type,
+    /// Function producing the value when the expression is a call
+    pub callee: Option<&'a str>,
+    /// No calls, assignments or other side effects anywhere inside
+    pub pure: bool,
+}
+
+impl Default for MustUse {
+    fn default() -> Self {
+        MustUse::new()
+    }
+}
+
+impl MustUse {
+    pub fn new() -> Self {
+        let mut types = HashMap::new();
+        types.insert(
+            "Result".to_string(),
+            Some("this `Result` may be an error, which should be handled".to_string()),
+        );
+        types.insert(
+            "Future".to_string(),
+            Some("futures do nothing unless awaited".to_string()),
+        );
+
+        MustUse {
+            types,
+            functions: HashMap::new(),
+        }
+    }
+
+    /// Honor `#[must_use]` or `#[must_use = "reason"]` on a type declaration
+    pub fn mark_type(&mut self, name: &str, reason: Option<&str>) {
+        self.types.insert(name.to_string(), reason.map(str::to_string));
+    }
+
+    /// Honor `#[must_use]` on a function; `name` is its qualified name
+    pub fn mark_function(&mut self, name: &str, reason: Option<&str>) {
+        self.functions.insert(name.to_string(), reason.map(str::to_string));
+    }
+
+    /// Parse a `#[must_use]` attribute, returning its reason. `None` if the
+    /// attribute is not `must_use`.
+    pub fn parse_attribute(attribute: &str) -> Option<Option<String>> {
+        let inner = attribute.trim().strip_prefix("#[")?.strip_suffix(']')?.trim();
+        let rest = inner.strip_prefix("must_use")?.trim_start();
+
+        if rest.is_empty() {
+            return Some(None);
+        }
+        let reason = rest.strip_prefix('=')?.trim();
+        let reason = reason.strip_prefix('"')?.strip_suffix('"')?;
+        Some(Some(reason.to_string()))
+    }
+
+    fn type_reason(&self, type_: &Type) -> Option<(&str, Option<&str>)> {
+        let name = match type_ {
+            Type::Generic(name, _)
+            | Type::Struct(name)
+            | Type::Class(name)
+            | Type::Enum(name)
+            | Type::Interface(name) => name,
+            _ => return None,
+        };
+        self.types
+            .get_key_value(name)
+            .map(|(name, reason)| (name.as_str(), reason.as_deref()))
+    }
+
+    /// Warning for a discarded value that should have been used, with quick
+    /// fixes to bind it to `_` or handle it
+    pub fn check(&self, value: &DiscardedValue) -> Option<CompileError> {
+        let function = value
+            .callee
+            .and_then(|callee| self.functions.get_key_value(callee));
+
+        let (message, reason) = if let Some((callee, reason)) = function {
+            (format!("unused return value of `{}` that must be used", callee), reason.as_deref())
+        } else if let Some((type_name, reason)) = self.type_reason(value.type_) {
+            (format!("unused `{}` that must be used", type_name), reason)
+        } else if value.pure {
+            let warning = CompileError::new(
+                ErrorKind::Lint(UNUSED_RESULTS.to_string()),
+                "expression statement has no effect",
+            )
+            .with_span(value.span.clone())
+            .with_fix("Assign to `_`", &format!("let _ = {}", value.text));
+            return Some(warning);
+        } else {
+            return None;
+        };
+
+        let mut warning = CompileError::new(ErrorKind::Lint(UNUSED_MUST_USE.to_string()), &message)
+            .with_span(value.span.clone())
+            .with_fix("Assign to `_`", &format!("let _ = {}", value.text));
+        if let Some(reason) = reason {
+            warning = warning.with_note(reason);
+        }
+
+        Some(match value.type_ {
+            Type::Generic(name, _) if name == "Result" => warning
+                .with_help("handle the error with `?` or `match`, or write `let _ =` to ignore it")
+                .with_fix("Propagate the error with `?`", &format!("{}?", value.text)),
+            Type::Generic(name, _) if name == "Future" => warning
+                .with_help("await the future or write `let _ =` to drop it")
+                .with_fix("Await the future", &format!("{}.await", value.text)),
+            _ => warning,
+        })
+    }
+}
@@ -0,0 +1,281 @@
+//! Numeric literal types: suffixes, defaulting and range checks.
+//!
+//! A literal's type comes from, in order: its suffix (`5u8`, `2.5f32`),
+//! the type the context expects, or the default policy (`i64` and `f64`
+//! unless `--default-int` says otherwise). In a generic context with no
+//! bound to pick a type from, an unsuffixed literal is ambiguous and must
+//! be given a suffix. Integer types check the value fits; `300u8` is an
+//! error, not a wrap.
+
+use crate::error_handling::{CompileError, ErrorKind};
+use crate::types::Type;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NumericType {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+impl NumericType {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "i8" => Some(NumericType::I8),
+            "i16" => Some(NumericType::I16),
+            "i32" => Some(NumericType::I32),
+            "i64" => Some(NumericType::I64),
+            "u8" => Some(NumericType::U8),
+            "u16" => Some(NumericType::U16),
+            "u32" => Some(NumericType::U32),
+            "u64" => Some(NumericType::U64),
+            "f32" => Some(NumericType::F32),
+            "f64" => Some(NumericType::F64),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NumericType::I8 => "i8",
+            NumericType::I16 => "i16",
+            NumericType::I32 => "i32",
+            NumericType::I64 => "i64",
+            NumericType::U8 => "u8",
+            NumericType::U16 => "u16",
+            NumericType::U32 => "u32",
+            NumericType::U64 => "u64",
+            NumericType::F32 => "f32",
+            NumericType::F64 => "f64",
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, NumericType::F32 | NumericType::F64)
+    }
+
+    /// The type checker's coarse numeric type
+    pub fn to_type(self) -> Type {
+        if self.is_float() {
+            Type::Float
+        } else {
+            Type::Int
+        }
+    }
+
+    /// Largest magnitude an integer literal of this type may have; the
+    /// negative bound of signed types is one more than the positive
+    fn max_magnitude(self, negated: bool) -> u128 {
+        let bits = match self {
+            NumericType::I8 | NumericType::U8 => 8,
+            NumericType::I16 | NumericType::U16 => 16,
+            NumericType::I32 | NumericType::U32 => 32,
+            NumericType::I64 | NumericType::U64 => 64,
+            NumericType::F32 | NumericType::F64 => return u128::MAX,
+        };
+        match self {
+            NumericType::U8 | NumericType::U16 | NumericType::U32 | NumericType::U64 => {
+                if negated { 0 } else { (1u128 << bits) - 1 }
+            }
+            _ if negated => 1u128 << (bits - 1),
+            _ => (1u128 << (bits - 1)) - 1,
+        }
+    }
+}
+
+impl fmt::Display for NumericType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A numeric literal as written, e.g. `1_000u32` or `2.5`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumericLiteral {
+    pub text: String,
+    /// Digits with `_` separators removed and without the suffix
+    pub digits: String,
+    pub is_float: bool,
+    pub suffix: Option<NumericType>,
+}
+
+impl NumericLiteral {
+    pub fn parse(text: &str) -> Result<Self, LiteralError> {
+        let mantissa = text
+            .find(|c: char| !(c.is_ascii_digit() || c == '_' || c == '.'))
+            .unwrap_or(text.len());
+        let end = mantissa + exponent_len(&text[mantissa..]);
+        let (number, suffix) = text.split_at(end);
+
+        let suffix = match suffix {
+            "" => None,
+            name => Some(NumericType::parse(name).ok_or_else(|| LiteralError::InvalidSuffix(text.to_string()))?),
+        };
+        let is_float = number.contains(['.', 'e', 'E']);
+        if is_float && suffix.is_some_and(|suffix| !suffix.is_float()) {
+            return Err(LiteralError::FloatForInteger {
+                literal: text.to_string(),
+                expected: suffix.unwrap(),
+            });
+        }
+
+        Ok(NumericLiteral {
+            text: text.to_string(),
+            digits: number.replace('_', ""),
+            is_float,
+            suffix,
+        })
+    }
+}
+
+/// Length of the exponent at the start of `text`, e.g. `e-3` in `e-3f32`,
+/// or 0 if there is none
+fn exponent_len(text: &str) -> usize {
+    let Some(rest) = text.strip_prefix(['e', 'E']) else {
+        return 0;
+    };
+    let sign = usize::from(rest.starts_with(['+', '-']));
+    let digits = &rest[sign..];
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+        return 0;
+    }
+    1 + sign + digits.find(|c: char| !(c.is_ascii_digit() || c == '_')).unwrap_or(digits.len())
+}
+
+/// Types unsuffixed literals get when nothing else decides
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LiteralPolicy {
+    pub default_int: NumericType,
+    pub default_float: NumericType,
+}
+
+impl Default for LiteralPolicy {
+    fn default() -> Self {
+        LiteralPolicy {
+            default_int: NumericType::I64,
+            default_float: NumericType::F64,
+        }
+    }
+}
+
+/// What the surrounding code expects a literal to be
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LiteralContext<'a> {
+    /// Nothing, e.g. `let x = 5`; the policy default applies
+    Unconstrained,
+    Expected(NumericType),
+    /// An argument for an unbound type parameter, e.g. `T` in `id<T>(5)`
+    Generic(&'a str),
+}
+
+impl LiteralPolicy {
+    /// Decide a literal's type. `negated` is set for a literal directly
+    /// under unary minus, so `-128i8` is in range.
+    pub fn resolve(
+        &self,
+        literal: &NumericLiteral,
+        context: LiteralContext,
+        negated: bool,
+    ) -> Result<NumericType, LiteralError> {
+        let type_ = match (literal.suffix, context) {
+            (Some(suffix), LiteralContext::Expected(expected)) if suffix != expected => {
+                return Err(LiteralError::SuffixMismatch {
+                    literal: literal.text.clone(),
+                    expected,
+                });
+            }
+            (Some(suffix), _) => suffix,
+            (None, LiteralContext::Expected(expected)) => {
+                if literal.is_float && !expected.is_float() {
+                    return Err(LiteralError::FloatForInteger {
+                        literal: literal.text.clone(),
+                        expected,
+                    });
+                }
+                expected
+            }
+            (None, LiteralContext::Generic(parameter)) => {
+                return Err(LiteralError::Ambiguous {
+                    literal: literal.text.clone(),
+                    parameter: parameter.to_string(),
+                    default: if literal.is_float { self.default_float } else { self.default_int },
+                });
+            }
+            (None, LiteralContext::Unconstrained) if literal.is_float => self.default_float,
+            (None, LiteralContext::Unconstrained) => self.default_int,
+        };
+
+        if !type_.is_float() {
+            let fits = literal
+                .digits
+                .parse::<u128>()
+                .is_ok_and(|value| value <= type_.max_magnitude(negated));
+            if !fits {
+                return Err(LiteralError::OutOfRange {
+                    literal: literal.text.clone(),
+                    type_,
+                });
+            }
+        }
+
+        Ok(type_)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiteralError {
+    InvalidSuffix(String),
+    OutOfRange { literal: String, type_: NumericType },
+    SuffixMismatch { literal: String, expected: NumericType },
+    FloatForInteger { literal: String, expected: NumericType },
+    Ambiguous { literal: String, parameter: String, default: NumericType },
+}
+
+impl fmt::Display for LiteralError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiteralError::InvalidSuffix(literal) => write!(f, "Invalid suffix on numeric literal {}", literal),
+            LiteralError::OutOfRange { literal, type_ } => {
+                write!(f, "Literal {} is out of range for {}", literal, type_)
+            }
+            LiteralError::SuffixMismatch { literal, expected } => {
+                write!(f, "Literal {} has a suffix that does not match the expected type {}", literal, expected)
+            }
+            LiteralError::FloatForInteger { literal, expected } => {
+                write!(f, "Floating-point literal {} used where {} is expected", literal, expected)
+            }
+            LiteralError::Ambiguous { literal, parameter, .. } => {
+                write!(f, "Type of literal {} is ambiguous: it could be any type for {}", literal, parameter)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LiteralError {}
+
+impl LiteralError {
+    pub fn into_compile_error(self) -> CompileError {
+        let error = CompileError::new(ErrorKind::Type, &self.to_string());
+        match &self {
+            LiteralError::InvalidSuffix(_) => {
+                error.with_help("valid suffixes are i8, i16, i32, i64, u8, u16, u32, u64, f32 and f64")
+            }
+            LiteralError::Ambiguous { literal, default, .. } => {
+                let suffixed = format!("{}{}", literal, default);
+                error
+                    .with_help(&format!("add a type suffix, e.g. `{}`", suffixed))
+                    .with_fix(&format!("Add suffix `{}`", default), &suffixed)
+            }
+            _ => error,
+        }
+    }
+}
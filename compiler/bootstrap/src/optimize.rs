@@ -1,14 +1,120 @@
+use crate::alloc_reuse;
 use crate::ast::*;
-use std::collections::{HashMap, HashSet};
+use crate::error_handling::CompileError;
+use crate::sccp;
+use crate::ssa::SsaFunction;
+use crate::vectorize::{self, CountedLoop, Remark, VectorPlan, VectorTarget};
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+
+/// What the optimizer trades for: `-O1`..`-O3` favour speed, `-Os` and
+/// `-Oz` favour code size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SizeLevel {
+    #[default]
+    Speed,
+    /// `-Os`: smaller code, but keep optimizations that rarely grow it
+    Size,
+    /// `-Oz`: smallest code, even at a noticeable speed cost
+    MinSize,
+}
+
+impl SizeLevel {
+    /// Parse the part after `-O`: `s`, `z`, or a numeric level
+    pub fn from_flag(level: &str) -> Option<(u8, SizeLevel)> {
+        match level {
+            "s" => Some((2, SizeLevel::Size)),
+            "z" => Some((2, SizeLevel::MinSize)),
+            n => n.parse().ok().map(|n: u8| (n.min(3), SizeLevel::Speed)),
+        }
+    }
+}
 
 pub struct Optimizer {
     optimizations: Vec<Box<dyn Optimization>>,
+    /// Run SCCP on each function's SSA form; replaces AST constant folding
+    sccp: bool,
+    /// Serve short-lived allocations in loops from reused buffers
+    reuse_allocations: bool,
+    vectorize: bool,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Optimizer {
+    /// Pass pipeline for an optimization level. Size presets never inline
+    /// and add passes that only shrink code: merging identical functions,
+    /// dropping virtual calls no live type can reach, and outlining
+    /// repeated statement sequences. Constants are propagated on SSA form
+    /// by SCCP rather than folded in the AST. Loops are vectorized from
+    /// `-O2` for speed only, since a vector loop keeps its scalar version
+    /// as fallback and remainder. Allocation reuse runs from `-O2` for
+    /// every preset: it adds one allocation per loop and removes one per
+    /// iteration.
+    pub fn for_level(level: u8, size: SizeLevel) -> Self {
+        let mut optimizer = Optimizer {
+            optimizations: Vec::new(),
+            sccp: level > 0,
+            reuse_allocations: level >= 2,
+            vectorize: false,
+        };
+        
+        if level == 0 {
+            return optimizer;
+        }
+        
+        optimizer.register(Box::new(DeadCodeElimination));
+        // Before inlining, so the calls it makes direct can be inlined
+        optimizer.register(Box::new(Devirtualization));
+        
+        match size {
+            SizeLevel::Speed => {
+                if level >= 2 {
+                    optimizer.register(Box::new(CommonSubexpressionElimination));
+                    optimizer.register(Box::new(InlineExpansion));
+                    optimizer.vectorize = true;
+                }
+            }
+            SizeLevel::Size | SizeLevel::MinSize => {
+                optimizer.register(Box::new(CommonSubexpressionElimination));
+                optimizer.register(Box::new(DeadVirtualCallElimination));
+                optimizer.register(Box::new(MergeIdenticalFunctions));
+                optimizer.register(Box::new(Outlining {
+                    // `-Os` only outlines sequences long enough that the
+                    // call overhead is clearly paid back
+                    min_statements: if size == SizeLevel::MinSize { 2 } else { 4 },
+                    min_occurrences: 2,
+                }));
+            }
+        }
+        
+        optimizer
+    }
+    
+    /// Names of the registered passes, in run order
+    pub fn pass_names(&self) -> Vec<&'static str> {
+        let ssa_passes = [
+            self.sccp.then_some("SparseConditionalConstantPropagation"),
+            self.reuse_allocations.then_some("AllocationReuse"),
+            self.vectorize.then_some("LoopVectorization"),
+        ];
+        ssa_passes
+            .into_iter()
+            .flatten()
+            .chain(self.optimizations.iter().map(|optimization| optimization.name()))
+            .collect()
+    }
+    
     pub fn new() -> Self {
         let mut optimizer = Optimizer {
             optimizations: Vec::new(),
+            sccp: false,
+            reuse_allocations: false,
+            vectorize: false,
         };
         
         // Register optimizations
@@ -40,6 +146,39 @@ impl Optimizer {
         
         Ok(())
     }
+    
+    /// Run the SSA passes on one lowered function, returning its
+    /// `unreachable_code` warnings. The lint is computed before anything is
+    /// pruned, so it is reported at `-O0` too. Allocation reuse runs after
+    /// SCCP has removed dead blocks, which may hold the only escaping use
+    /// of an allocation; its decisions are added to `remarks`.
+    pub fn optimize_function(&self, function: &mut SsaFunction, remarks: &mut Vec<Remark>) -> Vec<CompileError> {
+        let warnings = sccp::unreachable_code(function, &sccp::analyze(function));
+        
+        if self.sccp {
+            sccp::run(function);
+        }
+        if self.reuse_allocations {
+            remarks.extend(alloc_reuse::run(function).remarks);
+        }
+        
+        warnings
+    }
+    
+    /// Vectorization plan for a counted loop, if this level vectorizes and
+    /// the loop qualifies. The remark explains the decision either way;
+    /// there is none when vectorization is off.
+    pub fn vectorize_loop(
+        &self,
+        loop_: &CountedLoop,
+        target: Option<VectorTarget>,
+    ) -> (Option<VectorPlan>, Option<Remark>) {
+        if !self.vectorize {
+            return (None, None);
+        }
+        let (plan, remark) = vectorize::vectorize(loop_, target);
+        (plan, Some(remark))
+    }
 }
 
 pub trait Optimization {
@@ -47,6 +186,8 @@ pub trait Optimization {
     fn run(&self, ast: &mut AST) -> Result<bool, OptimizationError>;
 }
 
+/// Folds binary expressions of two literals. Optimization levels use
+/// `sccp` instead, which also sees through variables and branches.
 pub struct ConstantFolding;
 
 impl Optimization for ConstantFolding {
@@ -118,7 +259,7 @@ impl Optimization for CommonSubexpressionElimination {
         "CommonSubexpressionElimination"
     }
     
-    fn run(&self, ast: &mut AST) -> Result<bool, OptimizationError> {
+    fn run(&self, _ast: &mut AST) -> Result<bool, OptimizationError> {
         // Implement CSE algorithm
         // ... implementation details ...
         Ok(false)
@@ -132,20 +273,96 @@ impl Optimization for InlineExpansion {
         "InlineExpansion"
     }
     
-    fn run(&self, ast: &mut AST) -> Result<bool, OptimizationError> {
+    fn run(&self, _ast: &mut AST) -> Result<bool, OptimizationError> {
         // Implement function inlining
         // ... implementation details ...
         Ok(false)
     }
 }
 
-fn evaluate_constant_expr(left: &Literal, right: &Literal, op: &BinOp) -> Option<Literal> {
+/// Replace functions whose bodies are identical after normalization with
+/// one shared body; public duplicates become thin aliases so their symbols
+/// stay exported
+pub struct MergeIdenticalFunctions;
+
+impl Optimization for MergeIdenticalFunctions {
+    fn name(&self) -> &'static str {
+        "MergeIdenticalFunctions"
+    }
+    
+    fn run(&self, _ast: &mut AST) -> Result<bool, OptimizationError> {
+        // Hash each function body with parameter names replaced by their
+        // positions, compare within equal-hash groups, then rewrite calls
+        // to the first function of each group
+        // ... implementation details ...
+        Ok(false)
+    }
+}
+
+/// Turn method calls through a vtable into direct calls where
+/// `devirtualize::resolve` proves which implementation runs
+pub struct Devirtualization;
+
+impl Optimization for Devirtualization {
+    fn name(&self) -> &'static str {
+        "Devirtualization"
+    }
+    
+    fn run(&self, _ast: &mut AST) -> Result<bool, OptimizationError> {
+        // Track constructed receivers with `ConcreteTypes` through each
+        // function body, resolve every method call on a class or interface,
+        // and rewrite those with a `Dispatch::Direct` target
+        // ... implementation details ...
+        Ok(false)
+    }
+}
+
+/// Remove methods that can only be reached through virtual calls on types
+/// never instantiated in the program, and the vtable slots pointing at them
+pub struct DeadVirtualCallElimination;
+
+impl Optimization for DeadVirtualCallElimination {
+    fn name(&self) -> &'static str {
+        "DeadVirtualCallElimination"
+    }
+    
+    fn run(&self, _ast: &mut AST) -> Result<bool, OptimizationError> {
+        // Collect instantiated classes, mark methods reachable from their
+        // vtables and from direct calls, and drop the rest
+        // ... implementation details ...
+        Ok(false)
+    }
+}
+
+/// Move statement sequences repeated across functions into a new private
+/// function and replace each occurrence with a call
+pub struct Outlining {
+    /// Shortest sequence worth a call
+    pub min_statements: usize,
+    /// Occurrences needed before outlining pays off
+    pub min_occurrences: usize,
+}
+
+impl Optimization for Outlining {
+    fn name(&self) -> &'static str {
+        "Outlining"
+    }
+    
+    fn run(&self, _ast: &mut AST) -> Result<bool, OptimizationError> {
+        // Find repeated sequences of at least `min_statements` with no
+        // early returns, and outline those seen `min_occurrences` times
+        // ... implementation details ...
+        Ok(false)
+    }
+}
+
+fn evaluate_constant_expr(_left: &Literal, _right: &Literal, _op: &BinOp) -> Option<Literal> {
     // Evaluate constant expression
     // ... implementation details ...
     None
 }
 
-fn collect_used_symbols(ast: &AST) -> HashSet<String> {
+fn collect_used_symbols(_ast: &AST) -> HashSet<String> {
     // Collect used symbols
     // ... implementation details ...
     HashSet::new()
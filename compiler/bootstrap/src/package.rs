@@ -19,28 +19,56 @@ impl PackageManager {
         })
     }
     
+    pub fn registry_url(&self) -> &str {
+        &self.registry_url
+    }
+    
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+    
     pub fn install(&mut self, package_name: &str, version: &str) -> Result<(), PackageError> {
         // Download and install package
         // ... implementation details ...
+        self.installed_packages.insert(
+            package_name.to_string(),
+            Package {
+                name: package_name.to_string(),
+                version: version.to_string(),
+                dependencies: Vec::new(),
+            },
+        );
         Ok(())
     }
     
-    pub fn resolve_dependencies(&self, package: &Package) -> Result<Vec<Package>, PackageError> {
-        // Resolve package dependencies
-        // ... implementation details ...
-        Ok(Vec::new())
+    /// Installed packages `package` depends on, failing on the first one
+    /// not installed
+    pub fn resolve_dependencies(&self, package: &Package) -> Result<Vec<&Package>, PackageError> {
+        // Version requirements are not checked against what is installed yet
+        package
+            .dependencies
+            .iter()
+            .map(|dependency| {
+                self.installed_packages.get(&dependency.name).ok_or_else(|| {
+                    PackageError::InvalidPackage(format!(
+                        "{} depends on {} {}, which is not installed",
+                        package.name, dependency.name, dependency.version_req
+                    ))
+                })
+            })
+            .collect()
     }
 }
 
 pub struct Package {
-    name: String,
-    version: String,
-    dependencies: Vec<Dependency>,
+    pub name: String,
+    pub version: String,
+    pub dependencies: Vec<Dependency>,
 }
 
 pub struct Dependency {
-    name: String,
-    version_req: String,
+    pub name: String,
+    pub version_req: String,
 }
 
 #[derive(Debug)]
@@ -1,53 +1,658 @@
-use crate::lexer::{Token, Lexer};
 use crate::ast::*;
+use crate::error_handling::{CompileError, ErrorKind, SourceLocation, Span};
+use crate::lexer::{Lexer, Token, TokenType};
+use crate::numeric::NumericLiteral;
+use std::path::{Path, PathBuf};
 
-pub struct Parser<'a> {
-    lexer: Lexer<'a>,
-    current_token: Option<Token>,
+type ParseResult<T> = Result<T, CompileError>;
+
+/// Recursive-descent parser over the lexer's tokens. `struct`, `enum`,
+/// `interface`, `mod`, `macro`, `loop` and `pub` are not lexer keywords, so
+/// they are recognized here by their spelling.
+pub struct Parser {
+    tokens: Vec<Token>,
+    current: usize,
+    /// Source lines; the lexer drops comments, so doc comments are read
+    /// from here
+    lines: Vec<String>,
+    file: PathBuf,
+    /// Error tokens, reported by `parse` once spans can name the file
+    lexical_errors: Vec<Token>,
+    errors: Vec<CompileError>,
 }
 
-impl<'a> Parser<'a> {
-    pub fn new(lexer: Lexer<'a>) -> Self {
-        let mut parser = Parser { lexer, current_token: None };
-        parser.advance();
-        parser
+impl Parser {
+    pub fn new(mut lexer: Lexer) -> Self {
+        let lines = lexer.source().lines().map(str::to_string).collect();
+        let (lexical_errors, tokens) = lexer
+            .scan_tokens()
+            .into_iter()
+            .partition(|token| token.token_type == TokenType::Error);
+
+        Parser {
+            tokens,
+            current: 0,
+            lines,
+            file: PathBuf::new(),
+            lexical_errors,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Record spans against `file`
+    pub fn with_file(mut self, file: &Path) -> Self {
+        self.file = file.to_path_buf();
+        self
     }
 
+    /// Parse the whole file. Items and statements that do not parse are
+    /// skipped and reported through `errors`.
     pub fn parse(&mut self) -> AST {
+        for token in std::mem::take(&mut self.lexical_errors) {
+            let error = self.error_at(&token, &token.lexeme);
+            self.errors.push(error);
+        }
+
         let mut nodes = vec![];
-        while self.current_token.is_some() {
-            nodes.push(self.parse_statement());
+        while !self.is_at_end() {
+            if let Some(node) = self.recover(Self::parse_item) {
+                nodes.push(node);
+            }
         }
         AST::new(nodes)
     }
 
-    fn parse_statement(&mut self) -> ASTNode {
-        match self.current_token {
-            Some(Token::Keyword(ref kw)) if kw == "fn" => self.parse_function(),
-            Some(Token::Keyword(ref kw)) if kw == "struct" => self.parse_struct(),
-            _ => self.parse_expression(),
-        }
-    }
-    
-    fn parse_loop(&mut self) -> ASTNode {
-        self.expect(Token::Keyword("loop".into()));
-        let body = self.parse_block();
-        ASTNode::Loop { body }
-    }
-
-    fn parse_for(&mut self) -> ASTNode {
-        self.expect(Token::Keyword("for".into()));
-        let init = self.parse_variable_decl();
-        self.expect(Token::Symbol(";".into()));
-        let condition = self.parse_expression();
-        self.expect(Token::Symbol(";".into()));
-        let update = self.parse_expression();
-        let body = self.parse_block();
-        ASTNode::For {
-            init,
-            condition,
-            update,
+    pub fn errors(&self) -> &[CompileError] {
+        &self.errors
+    }
+
+    fn recover(&mut self, parse: fn(&mut Self) -> ParseResult<ASTNode>) -> Option<ASTNode> {
+        let start = self.current;
+        match parse(self) {
+            Ok(node) => Some(node),
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                // A stray `}` stops `synchronize` without being consumed
+                if self.current == start {
+                    self.advance();
+                }
+                None
+            }
+        }
+    }
+
+    /// Skip to the end of the broken statement: past its `;`, past the
+    /// block it opened, or up to the `}` of the enclosing block
+    fn synchronize(&mut self) {
+        let mut depth = 0usize;
+        while !self.is_at_end() {
+            match self.peek().token_type {
+                TokenType::RightBrace if depth == 0 => return,
+                TokenType::RightBrace => {
+                    self.advance();
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                TokenType::LeftBrace => {
+                    self.advance();
+                    depth += 1;
+                }
+                TokenType::Semicolon if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                _ => {
+                    self.advance();
+                }
+            }
+        }
+    }
+
+    fn parse_item(&mut self) -> ParseResult<ASTNode> {
+        let start = self.peek().clone();
+        let doc_comment = self.doc_comment_before(start.line);
+        let is_public = self.match_word("pub");
+        let is_sealed = self.match_token(TokenType::Sealed);
+
+        if is_sealed && !self.check_word("interface") {
+            return Err(self.error_at_current("Expected `interface` after `sealed`"));
+        }
+
+        if self.check(&TokenType::Fn) {
+            self.parse_function(&start, is_public, doc_comment)
+        } else if self.check(&TokenType::Let) {
+            let decl = self.parse_variable_decl(&start, is_public)?;
+            self.expect(TokenType::Semicolon, "`;` after variable declaration")?;
+            Ok(decl)
+        } else if self.check_word("struct") {
+            self.parse_struct(&start, is_public, doc_comment)
+        } else if self.check_word("enum") {
+            self.parse_enum(&start, is_public, doc_comment)
+        } else if self.check_word("interface") {
+            self.parse_interface(&start, is_public, is_sealed, doc_comment)
+        } else if self.check_word("mod") {
+            self.parse_module(&start, doc_comment)
+        } else if self.check_word("macro") {
+            self.parse_macro_definition(&start)
+        } else if is_public {
+            Err(self.error_at_current("Expected a declaration after `pub`"))
+        } else {
+            self.parse_statement()
+        }
+    }
+
+    fn parse_statement(&mut self) -> ParseResult<ASTNode> {
+        if self.check_word("loop") {
+            return self.parse_loop();
+        }
+        if self.check(&TokenType::For) {
+            return self.parse_for();
+        }
+
+        let expr = self.parse_expression()?;
+        // The last expression of a block is its value and needs no `;`
+        if !self.check(&TokenType::RightBrace) {
+            self.expect(TokenType::Semicolon, "`;` after expression")?;
+        }
+        Ok(statement_node(expr))
+    }
+
+    fn parse_function(&mut self, start: &Token, is_public: bool, doc_comment: Option<String>) -> ParseResult<ASTNode> {
+        self.expect(TokenType::Fn, "`fn`")?;
+        let name = self.expect_identifier("function name")?;
+        let params = self.parse_params()?;
+        let return_type = self.parse_return_type()?;
+        let body = self.parse_block()?;
+
+        Ok(ASTNode::FunctionDecl(FunctionDecl {
+            name,
+            is_public,
+            doc_comment,
+            params,
+            return_type,
+            body,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn parse_params(&mut self) -> ParseResult<Vec<Param>> {
+        self.expect(TokenType::LeftParen, "`(`")?;
+        let mut params = Vec::new();
+        while !self.check(&TokenType::RightParen) {
+            let name = self.expect_identifier("parameter name")?;
+            self.expect(TokenType::Colon, "`:` after parameter name")?;
+            let type_name = self.parse_type()?;
+            params.push(Param { name, type_name });
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        self.expect(TokenType::RightParen, "`)` after parameters")?;
+        Ok(params)
+    }
+
+    fn parse_return_type(&mut self) -> ParseResult<String> {
+        if self.match_token(TokenType::Arrow) {
+            self.parse_type()
+        } else {
+            Ok("void".to_string())
+        }
+    }
+
+    /// A type name as written, e.g. `int` or `Map<string, int>`
+    fn parse_type(&mut self) -> ParseResult<String> {
+        let mut name = self.expect_identifier("type name")?;
+        if self.match_token(TokenType::Less) {
+            let mut args = vec![self.parse_type()?];
+            while self.match_token(TokenType::Comma) {
+                args.push(self.parse_type()?);
+            }
+            self.expect(TokenType::Greater, "`>` after type arguments")?;
+            name = format!("{}<{}>", name, args.join(", "));
+        }
+        Ok(name)
+    }
+
+    fn parse_variable_decl(&mut self, start: &Token, is_public: bool) -> ParseResult<ASTNode> {
+        self.expect(TokenType::Let, "`let`")?;
+        let name = self.expect_identifier("variable name")?;
+        let type_name = if self.match_token(TokenType::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        let value = if self.match_token(TokenType::Equal) {
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+
+        Ok(ASTNode::VariableDecl(VariableDecl {
+            name,
+            is_public,
+            type_name,
+            value,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn parse_struct(&mut self, start: &Token, is_public: bool, doc_comment: Option<String>) -> ParseResult<ASTNode> {
+        self.advance();
+        let name = self.expect_identifier("struct name")?;
+        self.expect(TokenType::LeftBrace, "`{` after struct name")?;
+        let mut fields = Vec::new();
+        while !self.check(&TokenType::RightBrace) {
+            let field = self.expect_identifier("field name")?;
+            self.expect(TokenType::Colon, "`:` after field name")?;
+            let type_name = self.parse_type()?;
+            fields.push(Field { name: field, type_name });
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        self.expect(TokenType::RightBrace, "`}` after struct fields")?;
+
+        Ok(ASTNode::StructDecl(StructDecl {
+            name,
+            is_public,
+            doc_comment,
+            fields,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn parse_enum(&mut self, start: &Token, is_public: bool, doc_comment: Option<String>) -> ParseResult<ASTNode> {
+        self.advance();
+        let name = self.expect_identifier("enum name")?;
+        self.expect(TokenType::LeftBrace, "`{` after enum name")?;
+        let mut variants = Vec::new();
+        while !self.check(&TokenType::RightBrace) {
+            variants.push(self.expect_identifier("variant name")?);
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        self.expect(TokenType::RightBrace, "`}` after enum variants")?;
+
+        Ok(ASTNode::EnumDecl(EnumDecl {
+            name,
+            is_public,
+            doc_comment,
+            variants,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn parse_interface(
+        &mut self,
+        start: &Token,
+        is_public: bool,
+        is_sealed: bool,
+        doc_comment: Option<String>,
+    ) -> ParseResult<ASTNode> {
+        self.advance();
+        let name = self.expect_identifier("interface name")?;
+        self.expect(TokenType::LeftBrace, "`{` after interface name")?;
+        let mut methods = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            self.expect(TokenType::Fn, "`fn` in interface body")?;
+            let method = self.expect_identifier("method name")?;
+            let params = self.parse_params()?;
+            let return_type = self.parse_return_type()?;
+            self.expect(TokenType::Semicolon, "`;` after method signature")?;
+            methods.push(MethodSignature {
+                name: method,
+                params,
+                return_type,
+            });
+        }
+        self.expect(TokenType::RightBrace, "`}` after interface body")?;
+
+        Ok(ASTNode::InterfaceDecl(InterfaceDecl {
+            name,
+            is_public,
+            is_sealed,
+            doc_comment,
+            methods,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn parse_module(&mut self, start: &Token, doc_comment: Option<String>) -> ParseResult<ASTNode> {
+        self.advance();
+        let name = self.expect_identifier("module name")?;
+        let body = self.parse_block()?;
+
+        Ok(ASTNode::Module(Module {
+            name,
+            doc_comment,
             body,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn parse_macro_definition(&mut self, start: &Token) -> ParseResult<ASTNode> {
+        self.advance();
+        let name = self.expect_identifier("macro name")?;
+        self.expect(TokenType::LeftParen, "`(` after macro name")?;
+        let mut params = Vec::new();
+        while !self.check(&TokenType::RightParen) {
+            params.push(self.expect_identifier("macro parameter")?);
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
         }
+        self.expect(TokenType::RightParen, "`)` after macro parameters")?;
+        let body = self.parse_block()?;
+
+        Ok(ASTNode::MacroDefinition(MacroDefinition {
+            name,
+            params,
+            body,
+            span: self.span_from(start),
+        }))
+    }
+
+    /// `{ items }`, recovering from errors inside so one bad statement
+    /// does not lose the rest of the block
+    fn parse_block(&mut self) -> ParseResult<Vec<ASTNode>> {
+        self.expect(TokenType::LeftBrace, "`{`")?;
+        let mut nodes = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if let Some(node) = self.recover(Self::parse_item) {
+                nodes.push(node);
+            }
+        }
+        self.expect(TokenType::RightBrace, "`}`")?;
+        Ok(nodes)
+    }
+
+    fn parse_loop(&mut self) -> ParseResult<ASTNode> {
+        self.advance();
+        let body = self.parse_block()?;
+        Ok(ASTNode::Loop { body })
+    }
+
+    fn parse_for(&mut self) -> ParseResult<ASTNode> {
+        self.expect(TokenType::For, "`for`")?;
+        let start = self.peek().clone();
+        let init = self.parse_variable_decl(&start, false)?;
+        self.expect(TokenType::Semicolon, "`;` after loop initializer")?;
+        let condition = statement_node(self.parse_expression()?);
+        self.expect(TokenType::Semicolon, "`;` after loop condition")?;
+        let update = statement_node(self.parse_expression()?);
+        let body = self.parse_block()?;
+        Ok(ASTNode::For {
+            init: Box::new(init),
+            condition: Box::new(condition),
+            update: Box::new(update),
+            body,
+        })
+    }
+
+    fn parse_expression(&mut self) -> ParseResult<Expr> {
+        self.parse_assignment()
     }
-}
\ No newline at end of file
+
+    fn parse_assignment(&mut self) -> ParseResult<Expr> {
+        let target = self.parse_equality()?;
+        if self.check(&TokenType::Equal) {
+            let equals = self.advance();
+            let value = self.parse_assignment()?;
+            return match target {
+                Expr::Identifier(..) => Ok(Expr::Assignment(Box::new(target), Box::new(value))),
+                _ => Err(self.error_at(&equals, "Invalid assignment target")),
+            };
+        }
+        Ok(target)
+    }
+
+    fn parse_equality(&mut self) -> ParseResult<Expr> {
+        self.parse_binary(
+            &[(TokenType::EqualEqual, BinOp::Eq), (TokenType::NotEqual, BinOp::Ne)],
+            Self::parse_comparison,
+        )
+    }
+
+    fn parse_comparison(&mut self) -> ParseResult<Expr> {
+        self.parse_binary(
+            &[
+                (TokenType::Less, BinOp::Lt),
+                (TokenType::LessEqual, BinOp::Le),
+                (TokenType::Greater, BinOp::Gt),
+                (TokenType::GreaterEqual, BinOp::Ge),
+            ],
+            Self::parse_term,
+        )
+    }
+
+    fn parse_term(&mut self) -> ParseResult<Expr> {
+        self.parse_binary(
+            &[(TokenType::Plus, BinOp::Add), (TokenType::Minus, BinOp::Sub)],
+            Self::parse_factor,
+        )
+    }
+
+    fn parse_factor(&mut self) -> ParseResult<Expr> {
+        self.parse_binary(
+            &[(TokenType::Star, BinOp::Mul), (TokenType::Slash, BinOp::Div)],
+            Self::parse_call,
+        )
+    }
+
+    /// Left-associative chain of the `operators`, with operands parsed by
+    /// `operand`
+    fn parse_binary(
+        &mut self,
+        operators: &[(TokenType, BinOp)],
+        operand: fn(&mut Self) -> ParseResult<Expr>,
+    ) -> ParseResult<Expr> {
+        let mut left = operand(self)?;
+        while let Some(op) = operators
+            .iter()
+            .find(|(token_type, _)| self.check(token_type))
+            .map(|(_, op)| *op)
+        {
+            self.advance();
+            let right = operand(self)?;
+            let span = Span {
+                start: left.span().start,
+                end: right.span().end,
+            };
+            left = Expr::Binary(Box::new(BinaryExpr { op, left, right, span }));
+        }
+        Ok(left)
+    }
+
+    fn parse_call(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.parse_primary()?;
+        while self.match_token(TokenType::LeftParen) {
+            let mut args = Vec::new();
+            while !self.check(&TokenType::RightParen) {
+                args.push(self.parse_expression()?);
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+            self.expect(TokenType::RightParen, "`)` after arguments")?;
+            let span = Span {
+                start: expr.span().start,
+                end: self.token_span(self.previous()).end,
+            };
+            expr = Expr::FunctionCall(Box::new(expr), args, span);
+        }
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> ParseResult<Expr> {
+        let token = self.peek().clone();
+        let value = match token.token_type {
+            TokenType::Number => LiteralValue::Number(self.number(&token, false)?),
+            // A negative number is one literal, so `-1` folds and prints
+            // as written
+            TokenType::Minus if self.peek_next().token_type == TokenType::Number => {
+                self.advance();
+                let number = self.peek().clone();
+                LiteralValue::Number(self.number(&number, true)?)
+            }
+            TokenType::String => {
+                let text = &token.lexeme[1..token.lexeme.len() - 1];
+                LiteralValue::String(text.to_string())
+            }
+            TokenType::True => LiteralValue::Bool(true),
+            TokenType::False => LiteralValue::Bool(false),
+            TokenType::Identifier => {
+                self.advance();
+                return Ok(Expr::Identifier(token.lexeme.clone(), self.token_span(&token)));
+            }
+            TokenType::LeftParen => {
+                self.advance();
+                let expr = self.parse_expression()?;
+                self.expect(TokenType::RightParen, "`)` after expression")?;
+                return Ok(expr);
+            }
+            _ => return Err(self.error_at_current("Expected expression")),
+        };
+
+        self.advance();
+        Ok(Expr::Literal(Literal {
+            value,
+            span: self.span_from(&token),
+        }))
+    }
+
+    fn number(&self, token: &Token, negative: bool) -> ParseResult<NumericLiteral> {
+        let mut number = NumericLiteral::parse(&token.lexeme)
+            .map_err(|e| e.into_compile_error().with_span(self.token_span(token)))?;
+        if negative {
+            number.text.insert(0, '-');
+            number.digits.insert(0, '-');
+        }
+        Ok(number)
+    }
+
+    /// `///` lines directly above `line`, without the markers
+    fn doc_comment_before(&self, line: usize) -> Option<String> {
+        let above = self.lines.get(..line.saturating_sub(1))?;
+        let mut doc: Vec<&str> = above
+            .iter()
+            .rev()
+            .map_while(|text| text.trim_start().strip_prefix("///"))
+            .map(|text| text.strip_prefix(' ').unwrap_or(text))
+            .collect();
+        if doc.is_empty() {
+            return None;
+        }
+        doc.reverse();
+        Some(doc.join("\n"))
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.peek().token_type == TokenType::EOF
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+
+    fn peek_next(&self) -> &Token {
+        self.tokens.get(self.current + 1).unwrap_or_else(|| self.peek())
+    }
+
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current.saturating_sub(1)]
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.peek().clone();
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+        token
+    }
+
+    fn check(&self, token_type: &TokenType) -> bool {
+        &self.peek().token_type == token_type
+    }
+
+    /// Whether the next token is the identifier `word`
+    fn check_word(&self, word: &str) -> bool {
+        self.check(&TokenType::Identifier) && self.peek().lexeme == word
+    }
+
+    fn match_token(&mut self, token_type: TokenType) -> bool {
+        let matched = self.check(&token_type);
+        if matched {
+            self.advance();
+        }
+        matched
+    }
+
+    fn match_word(&mut self, word: &str) -> bool {
+        let matched = self.check_word(word);
+        if matched {
+            self.advance();
+        }
+        matched
+    }
+
+    fn expect(&mut self, token_type: TokenType, what: &str) -> ParseResult<Token> {
+        if self.check(&token_type) {
+            Ok(self.advance())
+        } else {
+            Err(self.error_at_current(&format!("Expected {}", what)))
+        }
+    }
+
+    fn expect_identifier(&mut self, what: &str) -> ParseResult<String> {
+        self.expect(TokenType::Identifier, what).map(|token| token.lexeme)
+    }
+
+    fn error_at_current(&self, message: &str) -> CompileError {
+        let token = self.peek();
+        let found = match token.token_type {
+            TokenType::EOF => "end of file".to_string(),
+            _ => format!("`{}`", token.lexeme),
+        };
+        self.error_at(token, &format!("{}, found {}", message, found))
+    }
+
+    fn error_at(&self, token: &Token, message: &str) -> CompileError {
+        CompileError::new(ErrorKind::Syntax, message).with_span(self.token_span(token))
+    }
+
+    fn location(&self, line: usize, column: usize) -> SourceLocation {
+        SourceLocation {
+            file: self.file.clone(),
+            line,
+            column,
+        }
+    }
+
+    fn token_span(&self, token: &Token) -> Span {
+        Span {
+            start: self.location(token.line, token.column),
+            end: self.location(token.line, token.column + token.lexeme.chars().count()),
+        }
+    }
+
+    /// From the start of `start` to the end of the last consumed token
+    fn span_from(&self, start: &Token) -> Span {
+        Span {
+            start: self.location(start.line, start.column),
+            end: self.token_span(self.previous()).end,
+        }
+    }
+}
+
+/// Store literals and binary expressions as their own statement nodes
+fn statement_node(expr: Expr) -> ASTNode {
+    match expr {
+        Expr::Literal(literal) => ASTNode::Literal(literal),
+        Expr::Binary(binary) => ASTNode::BinaryExpr(*binary),
+        other => ASTNode::Expression(other),
+    }
+}
@@ -7,6 +7,12 @@ pub struct Profiler {
     current_section: Option<(String, Instant)>,
 }
 
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Profiler {
     pub fn new() -> Self {
         Profiler {
@@ -31,6 +37,11 @@ impl Profiler {
         }
     }
     
+    /// Add time measured elsewhere, e.g. by a `std::trace` span, to a section
+    pub fn record_section(&mut self, name: &str, duration: Duration) {
+        *self.section_times.entry(name.to_string()).or_insert(Duration::new(0, 0)) += duration;
+    }
+
     pub fn report(&self) -> String {
         let mut report = String::new();
         report.push_str("Profiling Report:\n");
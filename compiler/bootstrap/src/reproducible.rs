@@ -0,0 +1,55 @@
+//! Support for byte-identical builds: path prefix remapping and fixed
+//! artifact timestamps.
+
+use std::path::{Path, PathBuf};
+
+/// Rewrites path prefixes recorded in artifacts (`--remap-path-prefix FROM=TO`)
+#[derive(Debug, Clone, Default)]
+pub struct PathRemapper {
+    mappings: Vec<(PathBuf, PathBuf)>,
+}
+
+impl PathRemapper {
+    pub fn new() -> Self {
+        PathRemapper {
+            mappings: Vec::new(),
+        }
+    }
+
+    /// Parse a `FROM=TO` command line value. The split happens at the last
+    /// `=` so `FROM` may itself contain one.
+    pub fn parse_arg(value: &str) -> Result<(PathBuf, PathBuf), String> {
+        match value.rsplit_once('=') {
+            Some((from, to)) if !from.is_empty() => Ok((PathBuf::from(from), PathBuf::from(to))),
+            _ => Err(format!("Invalid --remap-path-prefix value `{}`, expected FROM=TO", value)),
+        }
+    }
+
+    pub fn add_mapping(&mut self, from: &Path, to: &Path) {
+        self.mappings.push((from.to_path_buf(), to.to_path_buf()));
+    }
+
+    /// Apply the mapping added last among those matching, like other
+    /// compilers do, so later flags override earlier ones
+    pub fn remap(&self, path: &Path) -> PathBuf {
+        for (from, to) in self.mappings.iter().rev() {
+            if let Ok(rest) = path.strip_prefix(from) {
+                return to.join(rest);
+            }
+        }
+        path.to_path_buf()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+}
+
+/// Timestamp to record in emitted artifacts: `SOURCE_DATE_EPOCH` when set,
+/// otherwise zero, so rebuilding from the same lockfile is byte-identical
+pub fn artifact_timestamp() -> u64 {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(0)
+}
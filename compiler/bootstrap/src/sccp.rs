@@ -0,0 +1,373 @@
+//! Sparse conditional constant propagation (SCCP).
+//!
+//! Values and control flow are solved together: a block is only visited
+//! once an edge into it is known to execute, and a phi only merges inputs
+//! from such edges. That finds constants literal-only folding cannot, e.g.
+//! a variable that is `1` on every path actually taken, and branches whose
+//! condition is constant through such values. The pass then folds those
+//! values, turns decided branches into jumps and removes the blocks no
+//! executable edge reaches. The same analysis drives the
+//! `unreachable_code` lint, which runs at every optimization level.
+
+use crate::error_handling::{CompileError, ErrorKind};
+use crate::ssa::{BlockId, Const, Inst, SsaFunction, Terminator, ValueId};
+use std::collections::HashSet;
+
+pub const UNREACHABLE_CODE: &str = "unreachable_code";
+
+/// What is known about a value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lattice {
+    /// Not computed yet, e.g. defined only in blocks not reached so far
+    Undefined,
+    Constant(Const),
+    /// Varies at runtime
+    Overdefined,
+}
+
+impl Lattice {
+    fn meet(self, other: Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Undefined, value) | (value, Lattice::Undefined) => value,
+            (Lattice::Constant(left), Lattice::Constant(right)) if left == right => self,
+            _ => Lattice::Overdefined,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub values: Vec<Lattice>,
+    pub reachable: Vec<bool>,
+    executable_edges: HashSet<(BlockId, BlockId)>,
+}
+
+impl Analysis {
+    pub fn is_executable(&self, from: BlockId, to: BlockId) -> bool {
+        self.executable_edges.contains(&(from, to))
+    }
+
+    pub fn constant(&self, value: ValueId) -> Option<Const> {
+        match self.values[value] {
+            Lattice::Constant(constant) => Some(constant),
+            _ => None,
+        }
+    }
+}
+
+struct Solver<'a> {
+    function: &'a SsaFunction,
+    /// Instructions and blocks (through their terminator) using each value
+    value_users: Vec<Vec<ValueId>>,
+    terminator_users: Vec<Vec<BlockId>>,
+    /// Block defining each value
+    defined_in: Vec<Option<BlockId>>,
+    analysis: Analysis,
+    edge_worklist: Vec<(BlockId, BlockId)>,
+    value_worklist: Vec<ValueId>,
+}
+
+/// Solve values and reachability for `function`
+pub fn analyze(function: &SsaFunction) -> Analysis {
+    let mut value_users = vec![Vec::new(); function.values.len()];
+    let mut terminator_users = vec![Vec::new(); function.values.len()];
+    let mut defined_in = vec![None; function.values.len()];
+
+    for (block, data) in function.blocks.iter().enumerate() {
+        for &value in &data.insts {
+            defined_in[value] = Some(block);
+            for operand in function.values[value].operands() {
+                value_users[operand].push(value);
+            }
+        }
+        for operand in data.terminator.operands() {
+            terminator_users[operand].push(block);
+        }
+    }
+
+    let mut solver = Solver {
+        function,
+        value_users,
+        terminator_users,
+        defined_in,
+        analysis: Analysis {
+            values: vec![Lattice::Undefined; function.values.len()],
+            reachable: vec![false; function.blocks.len()],
+            executable_edges: HashSet::new(),
+        },
+        edge_worklist: Vec::new(),
+        value_worklist: Vec::new(),
+    };
+    if !function.blocks.is_empty() {
+        solver.visit_block(0);
+    }
+    solver.solve();
+    solver.analysis
+}
+
+impl Solver<'_> {
+    fn solve(&mut self) {
+        loop {
+            if let Some((from, to)) = self.edge_worklist.pop() {
+                if !self.analysis.executable_edges.insert((from, to)) {
+                    continue;
+                }
+                if self.analysis.reachable[to] {
+                    // Only phis see the new edge
+                    for &value in &self.function.blocks[to].insts {
+                        if matches!(self.function.values[value], Inst::Phi(_)) {
+                            self.visit_value(value);
+                        }
+                    }
+                } else {
+                    self.visit_block(to);
+                }
+            } else if let Some(value) = self.value_worklist.pop() {
+                for index in 0..self.value_users[value].len() {
+                    let user = self.value_users[value][index];
+                    if self.defined_in[user].is_some_and(|block| self.analysis.reachable[block]) {
+                        self.visit_value(user);
+                    }
+                }
+                for index in 0..self.terminator_users[value].len() {
+                    let block = self.terminator_users[value][index];
+                    if self.analysis.reachable[block] {
+                        self.visit_terminator(block);
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn visit_block(&mut self, block: BlockId) {
+        self.analysis.reachable[block] = true;
+        for index in 0..self.function.blocks[block].insts.len() {
+            self.visit_value(self.function.blocks[block].insts[index]);
+        }
+        self.visit_terminator(block);
+    }
+
+    fn visit_value(&mut self, value: ValueId) {
+        let old = self.analysis.values[value];
+        if old == Lattice::Overdefined {
+            return;
+        }
+
+        // Values only move down the lattice, so this terminates
+        let new = old.meet(self.evaluate(value));
+        if new != old {
+            self.analysis.values[value] = new;
+            self.value_worklist.push(value);
+        }
+    }
+
+    fn evaluate(&self, value: ValueId) -> Lattice {
+        let values = &self.analysis.values;
+        match &self.function.values[value] {
+            Inst::Const(constant) => Lattice::Constant(*constant),
+            Inst::Param(_) | Inst::Call(_, _) | Inst::Opaque(_, _) => Lattice::Overdefined,
+            Inst::Phi(inputs) => {
+                let block = self.defined_in[value].expect("phi belongs to a block");
+                inputs
+                    .iter()
+                    .filter(|(predecessor, _)| self.analysis.is_executable(*predecessor, block))
+                    .fold(Lattice::Undefined, |merged, (_, input)| merged.meet(values[*input]))
+            }
+            Inst::Neg(operand) => match values[*operand] {
+                Lattice::Constant(Const::Int(value)) => {
+                    value.checked_neg().map_or(Lattice::Overdefined, |value| Lattice::Constant(Const::Int(value)))
+                }
+                Lattice::Undefined => Lattice::Undefined,
+                _ => Lattice::Overdefined,
+            },
+            Inst::Not(operand) => match values[*operand] {
+                Lattice::Constant(Const::Bool(value)) => Lattice::Constant(Const::Bool(!value)),
+                Lattice::Undefined => Lattice::Undefined,
+                _ => Lattice::Overdefined,
+            },
+            Inst::Binary(op, left, right) => match (values[*left], values[*right]) {
+                // `false && x` and `true || x` are decided by one side
+                (Lattice::Constant(Const::Bool(false)), _) | (_, Lattice::Constant(Const::Bool(false)))
+                    if op == "&&" =>
+                {
+                    Lattice::Constant(Const::Bool(false))
+                }
+                (Lattice::Constant(Const::Bool(true)), _) | (_, Lattice::Constant(Const::Bool(true)))
+                    if op == "||" =>
+                {
+                    Lattice::Constant(Const::Bool(true))
+                }
+                (Lattice::Constant(left), Lattice::Constant(right)) => {
+                    fold_binary(op, left, right).map_or(Lattice::Overdefined, Lattice::Constant)
+                }
+                (Lattice::Overdefined, _) | (_, Lattice::Overdefined) => Lattice::Overdefined,
+                _ => Lattice::Undefined,
+            },
+        }
+    }
+
+    fn visit_terminator(&mut self, block: BlockId) {
+        match &self.function.blocks[block].terminator {
+            Terminator::Jump(target) => self.edge_worklist.push((block, *target)),
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => match self.analysis.values[*condition] {
+                Lattice::Constant(Const::Bool(true)) => self.edge_worklist.push((block, *then_block)),
+                Lattice::Constant(Const::Bool(false)) => self.edge_worklist.push((block, *else_block)),
+                Lattice::Undefined => {}
+                _ => {
+                    self.edge_worklist.push((block, *then_block));
+                    self.edge_worklist.push((block, *else_block));
+                }
+            },
+            Terminator::Return(_) | Terminator::Unreachable => {}
+        }
+    }
+}
+
+/// `None` when the operation would trap or is not defined for the
+/// operands; it is then left for runtime
+fn fold_binary(op: &str, left: Const, right: Const) -> Option<Const> {
+    Some(match (left, right) {
+        (Const::Int(left), Const::Int(right)) => match op {
+            "+" => Const::Int(left.checked_add(right)?),
+            "-" => Const::Int(left.checked_sub(right)?),
+            "*" => Const::Int(left.checked_mul(right)?),
+            "/" => Const::Int(left.checked_div(right)?),
+            "%" => Const::Int(left.checked_rem(right)?),
+            "==" => Const::Bool(left == right),
+            "!=" => Const::Bool(left != right),
+            "<" => Const::Bool(left < right),
+            "<=" => Const::Bool(left <= right),
+            ">" => Const::Bool(left > right),
+            ">=" => Const::Bool(left >= right),
+            _ => return None,
+        },
+        (Const::Bool(left), Const::Bool(right)) => match op {
+            "&&" => Const::Bool(left && right),
+            "||" => Const::Bool(left || right),
+            "==" => Const::Bool(left == right),
+            "!=" => Const::Bool(left != right),
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+/// Fold constant values, replace decided branches with jumps and remove
+/// unreachable blocks. Returns whether anything changed.
+pub fn run(function: &mut SsaFunction) -> bool {
+    let analysis = analyze(function);
+    let mut changed = false;
+
+    for (value, lattice) in analysis.values.iter().enumerate() {
+        if let Lattice::Constant(constant) = lattice {
+            if function.values[value] != Inst::Const(*constant) {
+                function.values[value] = Inst::Const(*constant);
+                changed = true;
+            }
+        }
+    }
+
+    for (block, data) in function.blocks.iter_mut().enumerate() {
+        if let Terminator::Branch {
+            then_block,
+            else_block,
+            ..
+        } = data.terminator
+        {
+            let takes_then = analysis.is_executable(block, then_block);
+            let takes_else = analysis.is_executable(block, else_block);
+            if takes_then != takes_else {
+                data.terminator = Terminator::Jump(if takes_then { then_block } else { else_block });
+                changed = true;
+            }
+        }
+    }
+
+    if analysis.reachable.iter().all(|&reachable| reachable) {
+        return changed;
+    }
+
+    // Drop unreachable blocks and renumber the rest; phis keep only the
+    // inputs from edges that execute
+    let mut new_ids = vec![None; function.blocks.len()];
+    let mut kept = Vec::new();
+    for (block, data) in std::mem::take(&mut function.blocks).into_iter().enumerate() {
+        if analysis.reachable[block] {
+            new_ids[block] = Some(kept.len());
+            for &value in &data.insts {
+                if let Inst::Phi(inputs) = &mut function.values[value] {
+                    inputs.retain(|(predecessor, _)| analysis.is_executable(*predecessor, block));
+                }
+            }
+            kept.push(data);
+        }
+    }
+
+    let renumber = |block: &mut BlockId| *block = new_ids[*block].expect("successor is reachable");
+    for data in &mut kept {
+        match &mut data.terminator {
+            Terminator::Jump(target) => renumber(target),
+            Terminator::Branch {
+                then_block, else_block, ..
+            } => {
+                renumber(then_block);
+                renumber(else_block);
+            }
+            Terminator::Return(_) | Terminator::Unreachable => {}
+        }
+        for &value in &data.insts {
+            if let Inst::Phi(inputs) = &mut function.values[value] {
+                for (predecessor, _) in inputs {
+                    renumber(predecessor);
+                }
+            }
+        }
+    }
+
+    function.blocks = kept;
+    true
+}
+
+/// `unreachable_code` warnings: one per region of source-level blocks no
+/// execution reaches, at its first block
+pub fn unreachable_code(function: &SsaFunction, analysis: &Analysis) -> Vec<CompileError> {
+    let predecessors = function.predecessors();
+    let mut warnings = Vec::new();
+
+    for (block, data) in function.blocks.iter().enumerate() {
+        let Some(span) = &data.span else { continue };
+        if analysis.reachable[block] {
+            continue;
+        }
+        // Inside a region already reported
+        let dead_source_predecessor = predecessors[block]
+            .iter()
+            .any(|&predecessor| !analysis.reachable[predecessor] && function.blocks[predecessor].span.is_some());
+        if dead_source_predecessor {
+            continue;
+        }
+
+        let mut warning = CompileError::new(ErrorKind::Lint(UNREACHABLE_CODE.to_string()), "unreachable code")
+            .with_span(span.clone());
+        let decided_by = predecessors[block].iter().find_map(|&predecessor| {
+            match &function.blocks[predecessor].terminator {
+                Terminator::Branch { condition, .. } if analysis.reachable[predecessor] => {
+                    analysis.constant(*condition)
+                }
+                _ => None,
+            }
+        });
+        if let Some(condition) = decided_by {
+            warning = warning.with_note(&format!("the condition guarding this code is always `{}`", condition));
+        }
+        warnings.push(warning);
+    }
+
+    warnings
+}
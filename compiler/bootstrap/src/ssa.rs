@@ -0,0 +1,144 @@
+//! SSA form of a function, used by the optimization passes that need
+//! dataflow rather than the AST.
+//!
+//! Every instruction defines one value, numbered by its index in
+//! `SsaFunction::values`, and each block lists the values it defines in
+//! order. Block 0 is the entry. A phi picks its input by the predecessor
+//! control arrived from.
+
+use crate::error_handling::Span;
+use std::fmt;
+
+pub type ValueId = usize;
+pub type BlockId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Const {
+    Int(i64),
+    Bool(bool),
+}
+
+impl fmt::Display for Const {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Const::Int(value) => write!(f, "{}", value),
+            Const::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    Const(Const),
+    /// The function's parameter at this position
+    Param(usize),
+    /// `+ - * / % == != < <= > >= && ||`
+    Binary(String, ValueId, ValueId),
+    Neg(ValueId),
+    Not(ValueId),
+    /// One input per predecessor block
+    Phi(Vec<(BlockId, ValueId)>),
+    Call(String, Vec<ValueId>),
+    /// Anything the passes do not look into, e.g. loads and allocations
+    Opaque(String, Vec<ValueId>),
+}
+
+impl Inst {
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            Inst::Const(_) | Inst::Param(_) => Vec::new(),
+            Inst::Binary(_, left, right) => vec![*left, *right],
+            Inst::Neg(value) | Inst::Not(value) => vec![*value],
+            Inst::Phi(inputs) => inputs.iter().map(|(_, value)| *value).collect(),
+            Inst::Call(_, args) | Inst::Opaque(_, args) => args.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch {
+        condition: ValueId,
+        then_block: BlockId,
+        else_block: BlockId,
+    },
+    Return(Option<ValueId>),
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch { then_block, else_block, .. } => vec![*then_block, *else_block],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            Terminator::Branch { condition, .. } => vec![*condition],
+            Terminator::Return(Some(value)) => vec![*value],
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// Values defined in this block, phis first
+    pub insts: Vec<ValueId>,
+    pub terminator: Terminator,
+    /// Source the block was lowered from; `None` for blocks the compiler
+    /// introduced, such as loop exits and join points
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SsaFunction {
+    pub name: String,
+    pub values: Vec<Inst>,
+    pub blocks: Vec<Block>,
+}
+
+impl SsaFunction {
+    pub fn new(name: &str) -> Self {
+        SsaFunction {
+            name: name.to_string(),
+            values: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Add an empty block ending in `unreachable`, to be filled in
+    pub fn add_block(&mut self, span: Option<Span>) -> BlockId {
+        self.blocks.push(Block {
+            insts: Vec::new(),
+            terminator: Terminator::Unreachable,
+            span,
+        });
+        self.blocks.len() - 1
+    }
+
+    /// Append an instruction to `block`, returning the value it defines
+    pub fn push(&mut self, block: BlockId, inst: Inst) -> ValueId {
+        self.values.push(inst);
+        let value = self.values.len() - 1;
+        self.blocks[block].insts.push(value);
+        value
+    }
+
+    /// Predecessors of each block
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (block, data) in self.blocks.iter().enumerate() {
+            for successor in data.terminator.successors() {
+                if !predecessors[successor].contains(&block) {
+                    predecessors[successor].push(block);
+                }
+            }
+        }
+        predecessors
+    }
+}
@@ -0,0 +1,387 @@
+//! `static` items: initializer evaluation, thread safety and init order.
+//!
+//! A static whose initializer is a constant expression, possibly using
+//! other constant statics, is evaluated at compile time and emitted as
+//! initialized data. Any other initializer, e.g. one calling a function,
+//! runs lazily on first access behind the runtime's init guard (see
+//! `runtime/src/statics.rs`), which the VM uses as well.
+//!
+//! Statics are shared by every thread, so their type must be `Sync`.
+//! Statics referring to each other in a cycle are rejected; cycles that go
+//! through function calls are only visible at run time, where the init
+//! guard reports them.
+
+use crate::error_handling::{CompileError, ErrorKind, Span};
+use crate::layout::LayoutCx;
+use crate::types::{Type, TypeChecker};
+use std::collections::{HashMap, HashSet};
+
+/// Library types that synchronize access to their contents
+pub const SYNC_WRAPPERS: &[&str] = &["Mutex", "RwLock", "Atomic", "AtomicInt", "AtomicBool", "Lazy"];
+
+/// A static's initializer, as far as evaluation needs to see it
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstExpr {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    /// Use of another static
+    Static(String),
+    Neg(Box<ConstExpr>),
+    Not(Box<ConstExpr>),
+    Binary(String, Box<ConstExpr>, Box<ConstExpr>),
+    /// Function call; never evaluated at compile time
+    Call(String, Vec<ConstExpr>),
+}
+
+impl ConstExpr {
+    /// Statics used anywhere in the expression
+    fn statics(&self, out: &mut Vec<String>) {
+        match self {
+            ConstExpr::Static(name) => out.push(name.clone()),
+            ConstExpr::Neg(inner) | ConstExpr::Not(inner) => inner.statics(out),
+            ConstExpr::Binary(_, left, right) => {
+                left.statics(out);
+                right.statics(out);
+            }
+            ConstExpr::Call(_, args) => args.iter().for_each(|arg| arg.statics(out)),
+            ConstExpr::Int(_) | ConstExpr::Float(_) | ConstExpr::Bool(_) | ConstExpr::Str(_) => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+}
+
+impl ConstValue {
+    fn type_(&self) -> Type {
+        match self {
+            ConstValue::Int(_) => Type::Int,
+            ConstValue::Float(_) => Type::Float,
+            ConstValue::Bool(_) => Type::Bool,
+            ConstValue::Str(_) => Type::String,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StaticItem {
+    pub name: String,
+    pub type_: Type,
+    pub init: ConstExpr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StaticInit {
+    Const(ConstValue),
+    /// Initialized on first access
+    Lazy,
+}
+
+/// Statics in initialization order: each after the statics it uses
+#[derive(Debug, Clone, Default)]
+pub struct InitPlan {
+    pub statics: Vec<(String, Type, StaticInit)>,
+}
+
+pub struct StaticChecker<'a> {
+    types: &'a TypeChecker,
+    /// Classes declared safe to share between threads
+    sync_classes: HashSet<String>,
+}
+
+impl<'a> StaticChecker<'a> {
+    pub fn new(types: &'a TypeChecker) -> Self {
+        StaticChecker {
+            types,
+            sync_classes: HashSet::new(),
+        }
+    }
+
+    pub fn mark_sync(&mut self, class_name: &str) {
+        self.sync_classes.insert(class_name.to_string());
+    }
+
+    /// Check all statics of a program and decide how each is initialized
+    pub fn check(&self, items: &[StaticItem]) -> Result<InitPlan, Vec<CompileError>> {
+        let mut errors = Vec::new();
+        let by_name: HashMap<&str, &StaticItem> = items.iter().map(|item| (item.name.as_str(), item)).collect();
+
+        for item in items {
+            let mut uses = Vec::new();
+            item.init.statics(&mut uses);
+            for used in uses.iter().filter(|used| !by_name.contains_key(used.as_str())) {
+                errors.push(
+                    CompileError::new(ErrorKind::Name, &format!("Undefined static `{}`", used))
+                        .with_span(item.span.clone()),
+                );
+            }
+
+            if !self.is_sync(&item.type_, &mut HashSet::new()) {
+                errors.push(
+                    CompileError::new(
+                        ErrorKind::Safety,
+                        &format!("Static `{}` has type `{}`, which cannot be shared between threads", item.name, item.type_),
+                    )
+                    .with_span(item.span.clone())
+                    .with_help("wrap the value in a `Mutex` or another synchronized type"),
+                );
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let order = match init_order(items, &by_name) {
+            Ok(order) => order,
+            Err(cycle) => {
+                let first = by_name[cycle[0].as_str()];
+                return Err(vec![CompileError::new(
+                    ErrorKind::Reference,
+                    &format!("Cycle in static initializers: {}", cycle.join(" -> ")),
+                )
+                .with_span(first.span.clone())
+                .with_help("break the cycle by computing one of the values without the others")]);
+            }
+        };
+
+        let mut values: HashMap<&str, ConstValue> = HashMap::new();
+        let mut plan = InitPlan::default();
+        for item in order {
+            let init = match evaluate(&item.init, &values) {
+                Ok(Some(value)) => {
+                    if value.type_() != item.type_ {
+                        errors.push(
+                            CompileError::new(
+                                ErrorKind::Type,
+                                &format!(
+                                    "Initializer of static `{}` has type `{}`, expected `{}`",
+                                    item.name,
+                                    value.type_(),
+                                    item.type_,
+                                ),
+                            )
+                            .with_span(item.span.clone()),
+                        );
+                        continue;
+                    }
+                    values.insert(&item.name, value.clone());
+                    StaticInit::Const(value)
+                }
+                Ok(None) => StaticInit::Lazy,
+                Err(message) => {
+                    errors.push(
+                        CompileError::new(
+                            ErrorKind::Type,
+                            &format!("Cannot evaluate initializer of static `{}`: {}", item.name, message),
+                        )
+                        .with_span(item.span.clone()),
+                    );
+                    continue;
+                }
+            };
+            plan.statics.push((item.name.clone(), item.type_.clone(), init));
+        }
+
+        if errors.is_empty() {
+            Ok(plan)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn is_sync(&self, type_: &Type, seen: &mut HashSet<String>) -> bool {
+        match type_ {
+            Type::Void | Type::Bool | Type::Int | Type::Float | Type::String | Type::Enum(_) => true,
+            Type::Array(inner) | Type::Optional(inner) => self.is_sync(inner, seen),
+            Type::Map(key, value) => self.is_sync(key, seen) && self.is_sync(value, seen),
+            Type::Union(members) => members.iter().all(|member| self.is_sync(member, seen)),
+            Type::Struct(name) => {
+                // A recursive struct is as Sync as its other fields
+                if !seen.insert(name.clone()) {
+                    return true;
+                }
+                self.types
+                    .members_of(name)
+                    .iter()
+                    .all(|(_, field)| self.is_sync(field, seen))
+            }
+            Type::Generic(name, _) if SYNC_WRAPPERS.contains(&name.as_str()) => true,
+            // Classes are shared references to mutable objects
+            Type::Class(name) | Type::Interface(name) => self.sync_classes.contains(name),
+            // Closures may capture anything
+            Type::Function(_, _) | Type::Generic(_, _) | Type::Unknown => false,
+        }
+    }
+}
+
+/// Topological order of `items`, or the statics forming a cycle
+fn init_order<'a>(
+    items: &'a [StaticItem],
+    by_name: &HashMap<&str, &'a StaticItem>,
+) -> Result<Vec<&'a StaticItem>, Vec<String>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Visiting,
+        Done,
+    }
+
+    fn visit<'a>(
+        item: &'a StaticItem,
+        by_name: &HashMap<&str, &'a StaticItem>,
+        marks: &mut HashMap<String, Mark>,
+        stack: &mut Vec<String>,
+        order: &mut Vec<&'a StaticItem>,
+    ) -> Result<(), Vec<String>> {
+        match marks.get(&item.name) {
+            Some(Mark::Done) => return Ok(()),
+            Some(Mark::Visiting) => {
+                let start = stack.iter().position(|name| *name == item.name).unwrap_or(0);
+                let mut cycle = stack[start..].to_vec();
+                cycle.push(item.name.clone());
+                return Err(cycle);
+            }
+            None => {}
+        }
+
+        marks.insert(item.name.clone(), Mark::Visiting);
+        stack.push(item.name.clone());
+
+        let mut uses = Vec::new();
+        item.init.statics(&mut uses);
+        for used in uses {
+            if let Some(dependency) = by_name.get(used.as_str()) {
+                visit(dependency, by_name, marks, stack, order)?;
+            }
+        }
+
+        stack.pop();
+        marks.insert(item.name.clone(), Mark::Done);
+        order.push(item);
+        Ok(())
+    }
+
+    let mut marks = HashMap::new();
+    let mut order = Vec::with_capacity(items.len());
+    for item in items {
+        visit(item, by_name, &mut marks, &mut Vec::new(), &mut order)?;
+    }
+    Ok(order)
+}
+
+/// Evaluate at compile time. `Ok(None)` means the initializer is not
+/// constant and must run lazily; `Err` is a constant expression that fails,
+/// e.g. overflows or divides by zero.
+fn evaluate(expr: &ConstExpr, statics: &HashMap<&str, ConstValue>) -> Result<Option<ConstValue>, String> {
+    Ok(Some(match expr {
+        ConstExpr::Int(value) => ConstValue::Int(*value),
+        ConstExpr::Float(value) => ConstValue::Float(*value),
+        ConstExpr::Bool(value) => ConstValue::Bool(*value),
+        ConstExpr::Str(value) => ConstValue::Str(value.clone()),
+        // Lazy statics are not in `statics`, so using one makes this lazy too
+        ConstExpr::Static(name) => match statics.get(name.as_str()) {
+            Some(value) => value.clone(),
+            None => return Ok(None),
+        },
+        ConstExpr::Call(_, _) => return Ok(None),
+        ConstExpr::Neg(inner) => match evaluate(inner, statics)? {
+            Some(ConstValue::Int(value)) => ConstValue::Int(value.checked_neg().ok_or("integer overflow")?),
+            Some(ConstValue::Float(value)) => ConstValue::Float(-value),
+            Some(other) => return Err(format!("cannot negate `{}`", other.type_())),
+            None => return Ok(None),
+        },
+        ConstExpr::Not(inner) => match evaluate(inner, statics)? {
+            Some(ConstValue::Bool(value)) => ConstValue::Bool(!value),
+            Some(other) => return Err(format!("cannot apply `!` to `{}`", other.type_())),
+            None => return Ok(None),
+        },
+        ConstExpr::Binary(op, left, right) => {
+            let (left, right) = match (evaluate(left, statics)?, evaluate(right, statics)?) {
+                (Some(left), Some(right)) => (left, right),
+                _ => return Ok(None),
+            };
+            binary(op, left, right)?
+        }
+    }))
+}
+
+fn binary(op: &str, left: ConstValue, right: ConstValue) -> Result<ConstValue, String> {
+    use ConstValue::*;
+
+    let overflow = || "integer overflow".to_string();
+    Ok(match (op, left, right) {
+        ("+", Int(a), Int(b)) => Int(a.checked_add(b).ok_or_else(overflow)?),
+        ("-", Int(a), Int(b)) => Int(a.checked_sub(b).ok_or_else(overflow)?),
+        ("*", Int(a), Int(b)) => Int(a.checked_mul(b).ok_or_else(overflow)?),
+        ("/" | "%", Int(_), Int(0)) => return Err("division by zero".to_string()),
+        ("/", Int(a), Int(b)) => Int(a.checked_div(b).ok_or_else(overflow)?),
+        ("%", Int(a), Int(b)) => Int(a.checked_rem(b).ok_or_else(overflow)?),
+        ("+", Float(a), Float(b)) => Float(a + b),
+        ("-", Float(a), Float(b)) => Float(a - b),
+        ("*", Float(a), Float(b)) => Float(a * b),
+        ("/", Float(a), Float(b)) => Float(a / b),
+        ("+", Str(a), Str(b)) => Str(a + &b),
+        ("&&", Bool(a), Bool(b)) => Bool(a && b),
+        ("||", Bool(a), Bool(b)) => Bool(a || b),
+        ("==", a, b) => Bool(a == b),
+        ("!=", a, b) => Bool(a != b),
+        ("<", Int(a), Int(b)) => Bool(a < b),
+        ("<=", Int(a), Int(b)) => Bool(a <= b),
+        (">", Int(a), Int(b)) => Bool(a > b),
+        (">=", Int(a), Int(b)) => Bool(a >= b),
+        (op, a, b) => return Err(format!("operator `{}` is not defined for `{}` and `{}`", op, a.type_(), b.type_())),
+    })
+}
+
+impl InitPlan {
+    /// LLVM globals for the plan. Constant statics become initialized
+    /// data. Lazy ones get zeroed storage and an init guard; codegen adds
+    /// an `@<name>.init` function running the initializer and calls it
+    /// through `zaitun_static_init` before every access.
+    pub fn llvm_globals(&self, layouts: &LayoutCx) -> Result<String, CompileError> {
+        let mut ir = String::new();
+
+        for (name, type_, init) in &self.statics {
+            match init {
+                StaticInit::Const(ConstValue::Int(value)) => ir.push_str(&format!("@{} = global i64 {}\n", name, value)),
+                StaticInit::Const(ConstValue::Float(value)) => {
+                    ir.push_str(&format!("@{} = global double 0x{:016X}\n", name, value.to_bits()))
+                }
+                StaticInit::Const(ConstValue::Bool(value)) => {
+                    ir.push_str(&format!("@{} = global i8 {}\n", name, *value as u8))
+                }
+                StaticInit::Const(ConstValue::Str(value)) => {
+                    let escaped: String = value.bytes().map(|byte| format!("\\{:02X}", byte)).collect();
+                    ir.push_str(&format!(
+                        "@{name}.bytes = private constant [{len} x i8] c\"{escaped}\"\n\
+                         @{name} = global {{ ptr, i64 }} {{ ptr @{name}.bytes, i64 {len} }}\n",
+                        name = name,
+                        len = value.len(),
+                        escaped = escaped,
+                    ));
+                }
+                StaticInit::Lazy => {
+                    let layout = layouts.type_layout(type_).map_err(|e| e.into_compile_error())?;
+                    ir.push_str(&format!(
+                        "@{name} = global [{size} x i8] zeroinitializer, align {align}\n\
+                         @{name}.guard = global {{ i8, i64 }} zeroinitializer\n\
+                         @{name}.name = private constant [{name_len} x i8] c\"{name}\\00\"\n",
+                        name = name,
+                        name_len = name.len() + 1,
+                        size = layout.size,
+                        align = layout.align,
+                    ));
+                }
+            }
+        }
+
+        Ok(ir)
+    }
+}
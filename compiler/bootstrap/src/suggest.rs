@@ -0,0 +1,89 @@
+//! "Did you mean" suggestions for misspelled names.
+//!
+//! Shared by the resolver (identifiers), the type checker (members), the
+//! command line (flags) and the package manager (package names), so every
+//! tool ranks candidates the same way.
+
+/// Edit distance counting insertions, deletions, substitutions and
+/// transpositions of adjacent characters as one edit each (the optimal
+/// string alignment variant of Damerau-Levenshtein)
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // Three rolling rows: two back is needed for transpositions
+    let mut two_back = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(two_back[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut two_back, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// Largest distance still worth suggesting for a name of this length.
+/// Short names need a close match or every two-letter name looks similar.
+fn max_distance(name: &str) -> usize {
+    (name.chars().count() / 3).max(1)
+}
+
+/// Candidates close to `name`, best first. A case-only difference always
+/// ranks first; ties keep the candidates' original order.
+pub fn suggestions<'a, I>(name: &str, candidates: I) -> Vec<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let limit = max_distance(name);
+    let lowered = name.to_lowercase();
+
+    let mut ranked: Vec<(usize, &'a str)> = candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .filter_map(|candidate| {
+            if candidate.to_lowercase() == lowered {
+                return Some((0, candidate));
+            }
+            let distance = edit_distance(name, candidate);
+            (distance <= limit).then_some((distance, candidate))
+        })
+        .collect();
+
+    ranked.sort_by_key(|(distance, _)| *distance);
+    ranked.dedup_by(|a, b| a.1 == b.1);
+    ranked.into_iter().map(|(_, candidate)| candidate).collect()
+}
+
+/// The closest candidate to `name`, if any is close enough
+pub fn best_match<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    suggestions(name, candidates).into_iter().next()
+}
+
+/// Text for a diagnostic `help` field, e.g. "did you mean `length`?"
+pub fn did_you_mean<'a, I>(name: &str, candidates: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    best_match(name, candidates).map(help_text)
+}
+
+/// Help text suggesting `replacement`
+pub fn help_text(replacement: &str) -> String {
+    format!("did you mean `{}`?", replacement)
+}
@@ -0,0 +1,562 @@
+//! Lowering of `match` on integer and string literals.
+//!
+//! The VM tries arms in source order and takes the first whose pattern
+//! matches; compiled code must pick the same arm for every value. Literal
+//! arms become a map from value to the first arm listing it, and that map
+//! is lowered by shape:
+//!
+//! - integers: a jump table when the values are dense, a binary search
+//!   over sorted values when they are sparse, and a chain of compares when
+//!   there are only a few;
+//! - strings: a switch on the length, then in each length a tree switching
+//!   on the byte that best tells the remaining candidates apart, ending in
+//!   one `memcmp` against the single candidate left.
+//!
+//! Each lowering has a `dispatch` that walks the same decisions the
+//! emitted code makes, which the tests compare with arm-order matching.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::hash::Hash;
+
+/// Fewest cases worth a table instead of compares
+pub const MIN_JUMP_TABLE_CASES: usize = 4;
+/// Largest table, in entries, before a binary search is used instead
+pub const MAX_JUMP_TABLE_SIZE: u128 = 4096;
+/// At most this many cases are tested one after another
+pub const MAX_LINEAR_CASES: usize = 3;
+
+/// The literal arms of a `match` in source order, each with its
+/// alternatives (`1 | 2 => ...`). Only matches whose arms are all literals
+/// without guards, up to an optional final wildcard, are lowered here.
+#[derive(Debug, Clone)]
+pub struct LiteralArms<T> {
+    pub arms: Vec<Vec<T>>,
+    /// Arm taken when no literal matches: the wildcard arm, or the
+    /// no-match trap the exhaustiveness checker allowed
+    pub default: usize,
+}
+
+impl<T: Clone + Eq + Hash> LiteralArms<T> {
+    /// Each value with the first arm that lists it; later occurrences can
+    /// never be reached
+    fn first_arms(&self) -> Vec<(T, usize)> {
+        let mut seen = HashSet::new();
+        let mut cases = Vec::new();
+        for (arm, values) in self.arms.iter().enumerate() {
+            for value in values {
+                if seen.insert(value.clone()) {
+                    cases.push((value.clone(), arm));
+                }
+            }
+        }
+        cases
+    }
+
+    /// The arm the VM takes for `value`
+    pub fn reference_dispatch(&self, value: &T) -> usize {
+        self.arms
+            .iter()
+            .position(|values| values.contains(value))
+            .unwrap_or(self.default)
+    }
+}
+
+/// Code emitted for one lowered match: globals at module level, code at
+/// the match's position in the function
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SwitchIr {
+    pub globals: String,
+    pub code: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntLowering {
+    /// Index a table of arms by `value - min`
+    JumpTable { min: i64, targets: Vec<usize>, default: usize },
+    /// Compare against the middle case and recurse into one half; `cases`
+    /// are sorted by value
+    BinarySearch { cases: Vec<(i64, usize)>, default: usize },
+    /// Compare with each case in turn
+    Linear { cases: Vec<(i64, usize)>, default: usize },
+}
+
+impl IntLowering {
+    pub fn new(arms: &LiteralArms<i64>) -> Self {
+        let mut cases = arms.first_arms();
+        let default = arms.default;
+        if cases.len() <= MAX_LINEAR_CASES {
+            return IntLowering::Linear { cases, default };
+        }
+
+        cases.sort_by_key(|(value, _)| *value);
+        let min = cases[0].0;
+        let max = cases[cases.len() - 1].0;
+        let range = (max as i128 - min as i128 + 1) as u128;
+        // At least 40% of the table holds real cases
+        let dense = range <= MAX_JUMP_TABLE_SIZE && cases.len() as u128 * 10 >= range * 4;
+
+        if cases.len() >= MIN_JUMP_TABLE_CASES && dense {
+            let mut targets = vec![default; range as usize];
+            for (value, arm) in &cases {
+                targets[(*value as i128 - min as i128) as usize] = *arm;
+            }
+            IntLowering::JumpTable { min, targets, default }
+        } else {
+            IntLowering::BinarySearch { cases, default }
+        }
+    }
+
+    /// The arm the lowered code takes for `value`
+    pub fn dispatch(&self, value: i64) -> usize {
+        match self {
+            IntLowering::JumpTable { min, targets, default } => {
+                // `sub` wraps and the compare is unsigned, as in the IR
+                let index = value.wrapping_sub(*min) as u64;
+                if index < targets.len() as u64 {
+                    targets[index as usize]
+                } else {
+                    *default
+                }
+            }
+            IntLowering::BinarySearch { cases, default } => {
+                let mut cases = cases.as_slice();
+                while cases.len() > MAX_LINEAR_CASES {
+                    let middle = cases.len() / 2;
+                    cases = if value < cases[middle].0 { &cases[..middle] } else { &cases[middle..] };
+                }
+                linear_dispatch(cases, value, *default)
+            }
+            IntLowering::Linear { cases, default } => linear_dispatch(cases, value, *default),
+        }
+    }
+
+    /// LLVM IR branching on the `i64` operand `scrutinee` to `labels[arm]`.
+    /// `function` is the enclosing function, for `blockaddress`, and
+    /// `prefix` keeps the names of this match's blocks and globals apart.
+    pub fn llvm_ir(&self, scrutinee: &str, function: &str, prefix: &str, labels: &[String]) -> SwitchIr {
+        let mut ir = SwitchIr::default();
+        match self {
+            IntLowering::JumpTable { min, targets, default } => {
+                let table = format!("@{}.{}.targets", function, prefix);
+                let entries: Vec<String> = targets
+                    .iter()
+                    .map(|arm| format!("ptr blockaddress(@{}, %{})", function, labels[*arm]))
+                    .collect();
+                let _ = writeln!(
+                    ir.globals,
+                    "{} = private constant [{} x ptr] [{}]",
+                    table,
+                    targets.len(),
+                    entries.join(", ")
+                );
+
+                let mut destinations: Vec<usize> = targets.clone();
+                destinations.sort_unstable();
+                destinations.dedup();
+                let destinations: Vec<String> =
+                    destinations.iter().map(|arm| format!("label %{}", labels[*arm])).collect();
+                let _ = write!(
+                    ir.code,
+                    "  %{p}.index = sub i64 {x}, {min}\n\
+                     \x20 %{p}.in_range = icmp ult i64 %{p}.index, {len}\n\
+                     \x20 br i1 %{p}.in_range, label %{p}.table, label %{default}\n\
+                     {p}.table:\n\
+                     \x20 %{p}.slot = getelementptr [{len} x ptr], ptr {table}, i64 0, i64 %{p}.index\n\
+                     \x20 %{p}.target = load ptr, ptr %{p}.slot\n\
+                     \x20 indirectbr ptr %{p}.target, [{destinations}]\n",
+                    p = prefix,
+                    x = scrutinee,
+                    min = min,
+                    len = targets.len(),
+                    default = labels[*default],
+                    table = table,
+                    destinations = destinations.join(", "),
+                );
+            }
+            IntLowering::BinarySearch { cases, default } => {
+                let mut next = 0;
+                search_ir(&mut ir.code, cases, scrutinee, prefix, labels, *default, &mut next);
+            }
+            IntLowering::Linear { cases, default } => {
+                let mut next = 0;
+                linear_ir(&mut ir.code, cases, scrutinee, prefix, labels, *default, &mut next);
+            }
+        }
+        ir
+    }
+}
+
+fn linear_dispatch(cases: &[(i64, usize)], value: i64, default: usize) -> usize {
+    cases
+        .iter()
+        .find(|(case, _)| *case == value)
+        .map_or(default, |(_, arm)| *arm)
+}
+
+fn linear_ir(
+    code: &mut String,
+    cases: &[(i64, usize)],
+    scrutinee: &str,
+    prefix: &str,
+    labels: &[String],
+    default: usize,
+    next: &mut usize,
+) {
+    for (index, (value, arm)) in cases.iter().enumerate() {
+        let id = *next;
+        *next += 1;
+        let otherwise = if index + 1 == cases.len() {
+            format!("%{}", labels[default])
+        } else {
+            format!("%{}.case{}", prefix, id + 1)
+        };
+        if index > 0 {
+            let _ = writeln!(code, "{}.case{}:", prefix, id);
+        }
+        let _ = write!(
+            code,
+            "  %{p}.eq{id} = icmp eq i64 {x}, {value}\n\
+             \x20 br i1 %{p}.eq{id}, label %{arm}, label {otherwise}\n",
+            p = prefix,
+            id = id,
+            x = scrutinee,
+            value = value,
+            arm = labels[*arm],
+            otherwise = otherwise,
+        );
+    }
+    if cases.is_empty() {
+        let _ = writeln!(code, "  br label %{}", labels[default]);
+    }
+}
+
+fn search_ir(
+    code: &mut String,
+    cases: &[(i64, usize)],
+    scrutinee: &str,
+    prefix: &str,
+    labels: &[String],
+    default: usize,
+    next: &mut usize,
+) {
+    if cases.len() <= MAX_LINEAR_CASES {
+        linear_ir(code, cases, scrutinee, prefix, labels, default, next);
+        return;
+    }
+
+    let id = *next;
+    *next += 1;
+    let middle = cases.len() / 2;
+    let _ = write!(
+        code,
+        "  %{p}.lt{id} = icmp slt i64 {x}, {pivot}\n\
+         \x20 br i1 %{p}.lt{id}, label %{p}.low{id}, label %{p}.high{id}\n\
+         {p}.low{id}:\n",
+        p = prefix,
+        id = id,
+        x = scrutinee,
+        pivot = cases[middle].0,
+    );
+    search_ir(code, &cases[..middle], scrutinee, prefix, labels, default, next);
+    let _ = writeln!(code, "{}.high{}:", prefix, id);
+    search_ir(code, &cases[middle..], scrutinee, prefix, labels, default, next);
+}
+
+/// Decision tree over strings of one length
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringTree {
+    /// The only candidate left; confirmed with one `memcmp`
+    Leaf { candidate: Vec<u8>, arm: usize },
+    /// Switch on the byte at `position`; other bytes take the default
+    Byte {
+        position: usize,
+        branches: Vec<(u8, StringTree)>,
+    },
+}
+
+impl StringTree {
+    fn build(mut candidates: Vec<(Vec<u8>, usize)>) -> StringTree {
+        if candidates.len() == 1 {
+            let (candidate, arm) = candidates.pop().expect("one candidate");
+            return StringTree::Leaf { candidate, arm };
+        }
+
+        // The position splitting the candidates into the most groups;
+        // distinct strings of equal length differ somewhere
+        let length = candidates[0].0.len();
+        let position = (0..length)
+            .max_by_key(|&position| {
+                let distinct: HashSet<u8> = candidates.iter().map(|(text, _)| text[position]).collect();
+                (distinct.len(), std::cmp::Reverse(position))
+            })
+            .expect("candidates of equal length differ");
+
+        let mut groups: BTreeMap<u8, Vec<(Vec<u8>, usize)>> = BTreeMap::new();
+        for candidate in candidates {
+            groups.entry(candidate.0[position]).or_default().push(candidate);
+        }
+        StringTree::Byte {
+            position,
+            branches: groups
+                .into_iter()
+                .map(|(byte, group)| (byte, StringTree::build(group)))
+                .collect(),
+        }
+    }
+
+    fn dispatch(&self, text: &[u8], default: usize) -> usize {
+        match self {
+            StringTree::Leaf { candidate, arm } => {
+                if text == candidate.as_slice() {
+                    *arm
+                } else {
+                    default
+                }
+            }
+            StringTree::Byte { position, branches } => branches
+                .iter()
+                .find(|(byte, _)| *byte == text[*position])
+                .map_or(default, |(_, tree)| tree.dispatch(text, default)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringLowering {
+    /// One tree per length some candidate has, by length
+    pub buckets: Vec<(usize, StringTree)>,
+    pub default: usize,
+}
+
+impl StringLowering {
+    pub fn new(arms: &LiteralArms<String>) -> Self {
+        let mut by_length: BTreeMap<usize, Vec<(Vec<u8>, usize)>> = BTreeMap::new();
+        for (text, arm) in arms.first_arms() {
+            by_length.entry(text.len()).or_default().push((text.into_bytes(), arm));
+        }
+
+        StringLowering {
+            buckets: by_length
+                .into_iter()
+                .map(|(length, candidates)| (length, StringTree::build(candidates)))
+                .collect(),
+            default: arms.default,
+        }
+    }
+
+    /// The arm the lowered code takes for `text`
+    pub fn dispatch(&self, text: &str) -> usize {
+        let text = text.as_bytes();
+        self.buckets
+            .iter()
+            .find(|(length, _)| *length == text.len())
+            .map_or(self.default, |(_, tree)| tree.dispatch(text, self.default))
+    }
+
+    /// LLVM IR branching on a string given as its `data` pointer and `len`
+    /// operands, see `IntLowering::llvm_ir` for the other parameters. The
+    /// module must declare `memcmp`.
+    pub fn llvm_ir(&self, data: &str, len: &str, function: &str, prefix: &str, labels: &[String]) -> SwitchIr {
+        let mut ir = SwitchIr::default();
+        let default = &labels[self.default];
+
+        let cases: Vec<String> = self
+            .buckets
+            .iter()
+            .map(|(length, _)| format!("i64 {}, label %{}.len{}", length, prefix, length))
+            .collect();
+        let _ = writeln!(ir.code, "  switch i64 {}, label %{} [{}]", len, default, cases.join(" "));
+
+        let mut next = 0;
+        for (length, tree) in &self.buckets {
+            let _ = writeln!(ir.code, "{}.len{}:", prefix, length);
+            let mut emitter = TreeEmitter {
+                ir: &mut ir,
+                data,
+                function,
+                prefix,
+                labels,
+                default,
+                next: &mut next,
+            };
+            emitter.tree(tree);
+        }
+        ir
+    }
+}
+
+struct TreeEmitter<'a> {
+    ir: &'a mut SwitchIr,
+    data: &'a str,
+    function: &'a str,
+    prefix: &'a str,
+    labels: &'a [String],
+    default: &'a str,
+    next: &'a mut usize,
+}
+
+impl TreeEmitter<'_> {
+    fn id(&mut self) -> usize {
+        *self.next += 1;
+        *self.next - 1
+    }
+
+    fn tree(&mut self, tree: &StringTree) {
+        match tree {
+            StringTree::Leaf { candidate, arm } if candidate.is_empty() => {
+                let _ = writeln!(self.ir.code, "  br label %{}", self.labels[*arm]);
+            }
+            StringTree::Leaf { candidate, arm } => {
+                let id = self.id();
+                let constant = format!("@{}.{}.str{}", self.function, self.prefix, id);
+                let bytes: String = candidate.iter().map(|byte| format!("\\{:02X}", byte)).collect();
+                let _ = writeln!(
+                    self.ir.globals,
+                    "{} = private constant [{} x i8] c\"{}\"",
+                    constant,
+                    candidate.len(),
+                    bytes
+                );
+                let _ = write!(
+                    self.ir.code,
+                    "  %{p}.cmp{id} = call i32 @memcmp(ptr {data}, ptr {constant}, i64 {len})\n\
+                     \x20 %{p}.eq{id} = icmp eq i32 %{p}.cmp{id}, 0\n\
+                     \x20 br i1 %{p}.eq{id}, label %{arm}, label %{default}\n",
+                    p = self.prefix,
+                    id = id,
+                    data = self.data,
+                    constant = constant,
+                    len = candidate.len(),
+                    arm = self.labels[*arm],
+                    default = self.default,
+                );
+            }
+            StringTree::Byte { position, branches } => {
+                let id = self.id();
+                let cases: Vec<String> = branches
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (byte, _))| format!("i8 {}, label %{}.b{}_{}", *byte as i8, self.prefix, id, index))
+                    .collect();
+                let _ = write!(
+                    self.ir.code,
+                    "  %{p}.at{id} = getelementptr i8, ptr {data}, i64 {position}\n\
+                     \x20 %{p}.byte{id} = load i8, ptr %{p}.at{id}\n\
+                     \x20 switch i8 %{p}.byte{id}, label %{default} [{cases}]\n",
+                    p = self.prefix,
+                    id = id,
+                    data = self.data,
+                    position = position,
+                    default = self.default,
+                    cases = cases.join(" "),
+                );
+                for (index, (_, subtree)) in branches.iter().enumerate() {
+                    let _ = writeln!(self.ir.code, "{}.b{}_{}:", self.prefix, id, index);
+                    self.tree(subtree);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic xorshift, so failures reproduce
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    fn random_int_arms(rng: &mut Rng, spread: i64) -> LiteralArms<i64> {
+        let base = match rng.below(3) {
+            0 => 0,
+            1 => i64::MIN,
+            _ => i64::MAX - spread,
+        };
+        let arm_count = 1 + rng.below(12) as usize;
+        let arms = (0..arm_count)
+            .map(|_| {
+                (0..1 + rng.below(3))
+                    .map(|_| base.wrapping_add(rng.below(spread as u64) as i64))
+                    .collect()
+            })
+            .collect();
+        LiteralArms { arms, default: arm_count }
+    }
+
+    #[test]
+    fn test_int_lowering_matches_arm_order() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        let mut seen_kinds = HashSet::new();
+
+        for round in 0..2000 {
+            let spread = [8, 40, 1_000, 1 << 40][round % 4];
+            let arms = random_int_arms(&mut rng, spread);
+            let lowering = IntLowering::new(&arms);
+            seen_kinds.insert(std::mem::discriminant(&lowering));
+
+            let mut probes: Vec<i64> = arms.arms.iter().flatten().copied().collect();
+            for value in probes.clone() {
+                probes.extend([value.wrapping_sub(1), value.wrapping_add(1)]);
+            }
+            probes.extend([i64::MIN, i64::MAX, 0, rng.next() as i64]);
+            for value in probes {
+                assert_eq!(
+                    lowering.dispatch(value),
+                    arms.reference_dispatch(&value),
+                    "value {} with arms {:?} lowered as {:?}",
+                    value,
+                    arms.arms,
+                    lowering,
+                );
+            }
+        }
+
+        assert_eq!(seen_kinds.len(), 3, "every lowering strategy was exercised");
+    }
+
+    #[test]
+    fn test_string_lowering_matches_arm_order() {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        let alphabet = ["a", "b", "é", ""];
+        let random_string = |rng: &mut Rng| -> String {
+            (0..rng.below(5)).map(|_| alphabet[rng.below(4) as usize]).collect()
+        };
+
+        for _ in 0..2000 {
+            let arm_count = 1 + rng.below(8) as usize;
+            let arms = LiteralArms {
+                arms: (0..arm_count)
+                    .map(|_| (0..1 + rng.below(3)).map(|_| random_string(&mut rng)).collect())
+                    .collect(),
+                default: arm_count,
+            };
+            let lowering = StringLowering::new(&arms);
+
+            let mut probes: Vec<String> = arms.arms.iter().flatten().cloned().collect();
+            probes.extend((0..8).map(|_| random_string(&mut rng)));
+            for text in probes {
+                assert_eq!(
+                    lowering.dispatch(&text),
+                    arms.reference_dispatch(&text),
+                    "{:?} with arms {:?}",
+                    text,
+                    arms.arms,
+                );
+            }
+        }
+    }
+}
@@ -1,42 +1,220 @@
 use crate::ast::*;
-use crate::error::CompileError;
+use crate::error_handling::{CompileError, ErrorKind, Span};
+use crate::types::Type;
+use std::collections::HashMap;
 
+/// What the checker knows about a name in scope
+#[derive(Debug, Clone)]
+struct TypeInfo {
+    /// `None` when neither the declaration nor its value says
+    type_: Option<Type>,
+    span: Span,
+}
+
+/// Checks what the bootstrap AST can tell without name resolution across
+/// files: names defined twice in one scope, initializers that do not match
+/// the declared type, and operators applied to operands they do not take
 pub struct TypeChecker {
-    symbol_table: HashMap<String, TypeInfo>,
+    /// Innermost scope last
+    scopes: Vec<HashMap<String, TypeInfo>>,
+    errors: Vec<CompileError>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TypeChecker {
     pub fn new() -> Self {
         TypeChecker {
-            symbol_table: HashMap::new(),
+            scopes: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
     pub fn check(&mut self, ast: &AST) -> Vec<CompileError> {
-        let mut errors = Vec::new();
-        // Implement type rule validation
-        // ... existing code ...
-        errors
+        self.scopes = vec![HashMap::new()];
+        self.check_nodes(&ast.nodes);
+        std::mem::take(&mut self.errors)
     }
 
-    fn check_interface_impl(&self, impl_block: &InterfaceImpl) -> Vec<CompileError> {
-        let mut errors = Vec::new();
-        // Verify all interface requirements are met
-        // ... existing code ...
-        errors
+    fn check_nodes(&mut self, nodes: &[ASTNode]) {
+        for node in nodes {
+            self.check_node(node);
+        }
     }
-}
 
-    fn check_binary_op(&self, op: &BinOp, left: &Type, right: &Type) -> Result<Type, CompileError> {
-        match (left, right) {
-            (Type::Int, Type::Int) => Ok(Type::Int),
-            (Type::Float, Type::Float) => Ok(Type::Float),
-            // ... existing code ...
-            _ => Err(CompileError::TypeMismatch {
-                expected: format!("{}", left),
-                found: format!("{}", right),
-                span: op.span,
-            }),
+    fn check_scoped(&mut self, nodes: &[ASTNode]) {
+        self.scopes.push(HashMap::new());
+        self.check_nodes(nodes);
+        self.scopes.pop();
+    }
+
+    fn check_node(&mut self, node: &ASTNode) {
+        match node {
+            ASTNode::FunctionDecl(f) => {
+                self.declare(&f.name, None, &f.span);
+                self.scopes.push(HashMap::new());
+                for param in &f.params {
+                    self.declare(&param.name, builtin_type(&param.type_name), &f.span);
+                }
+                self.check_nodes(&f.body);
+                self.scopes.pop();
+            }
+            ASTNode::VariableDecl(v) => {
+                let declared = v.type_name.as_deref().and_then(builtin_type);
+                let value = v.value.as_ref().and_then(|value| self.expr_type(value));
+                if let (Some(declared), Some(value)) = (&declared, &value) {
+                    if declared != value {
+                        self.errors.push(
+                            CompileError::new(
+                                ErrorKind::Type,
+                                &format!("`{}` is declared `{}` but initialized with `{}`", v.name, declared, value),
+                            )
+                            .with_span(v.span.clone()),
+                        );
+                    }
+                }
+                self.declare(&v.name, declared.or(value), &v.span);
+            }
+            ASTNode::StructDecl(s) => self.declare(&s.name, None, &s.span),
+            ASTNode::EnumDecl(e) => self.declare(&e.name, None, &e.span),
+            ASTNode::InterfaceDecl(i) => self.declare(&i.name, None, &i.span),
+            ASTNode::Module(m) => {
+                self.declare(&m.name, None, &m.span);
+                self.check_scoped(&m.body);
+            }
+            // Macro bodies are only checked once expanded
+            ASTNode::MacroDefinition(definition) => self.declare(&definition.name, None, &definition.span),
+            ASTNode::MacroInvocation(invocation) => {
+                for arg in &invocation.args {
+                    self.expr_type(arg);
+                }
+            }
+            ASTNode::Loop { body } => self.check_scoped(body),
+            ASTNode::For { init, condition, update, body } => {
+                self.scopes.push(HashMap::new());
+                self.check_node(init);
+                self.check_node(condition);
+                self.check_node(update);
+                self.check_scoped(body);
+                self.scopes.pop();
+            }
+            ASTNode::BinaryExpr(binary) => {
+                self.binary_type(binary);
+            }
+            ASTNode::Literal(_) => {}
+            ASTNode::Expression(expr) => {
+                self.expr_type(expr);
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &str, type_: Option<Type>, span: &Span) {
+        let scope = self.scopes.last_mut().expect("checker has a scope");
+        if let Some(previous) = scope.get(name) {
+            let error = CompileError::new(ErrorKind::Name, &format!("`{}` is defined multiple times", name))
+                .with_span(span.clone())
+                .with_note(&format!("previous definition at {}", previous.span));
+            self.errors.push(error);
+            return;
         }
+        scope.insert(name.to_string(), TypeInfo { type_, span: span.clone() });
     }
-}
\ No newline at end of file
+
+    fn lookup(&self, name: &str) -> Option<&TypeInfo> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Type of `expr` when it is known, reporting errors inside it
+    fn expr_type(&mut self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Literal(literal) => Some(literal_type(literal)),
+            Expr::Identifier(name, _) => self.lookup(name).and_then(|info| info.type_.clone()),
+            Expr::Binary(binary) => self.binary_type(binary),
+            Expr::FunctionCall(callee, args, _) => {
+                self.expr_type(callee);
+                for arg in args {
+                    self.expr_type(arg);
+                }
+                None
+            }
+            Expr::Assignment(target, value) => {
+                let target_type = self.expr_type(target);
+                let value_type = self.expr_type(value);
+                if let (Some(target_type), Some(value_type)) = (&target_type, &value_type) {
+                    if target_type != value_type {
+                        self.errors.push(
+                            CompileError::new(
+                                ErrorKind::Type,
+                                &format!("Cannot assign `{}` to a variable of type `{}`", value_type, target_type),
+                            )
+                            .with_span(expr.span()),
+                        );
+                    }
+                }
+                value_type
+            }
+            Expr::ThreadSpawn(inner, _) | Expr::AtomicAccess(inner, _) => {
+                self.expr_type(inner);
+                None
+            }
+        }
+    }
+
+    fn binary_type(&mut self, binary: &BinaryExpr) -> Option<Type> {
+        let left = self.expr_type(&binary.left);
+        let right = self.expr_type(&binary.right);
+        match self.check_binary_op(&binary.op, &left?, &right?, &binary.span) {
+            Ok(type_) => Some(type_),
+            Err(error) => {
+                self.errors.push(error);
+                None
+            }
+        }
+    }
+
+    fn check_binary_op(&self, op: &BinOp, left: &Type, right: &Type, span: &Span) -> Result<Type, CompileError> {
+        let operand = match (left, right) {
+            (Type::Int, Type::Int) => Type::Int,
+            (Type::Float, Type::Float) => Type::Float,
+            (Type::String, Type::String) if matches!(op, BinOp::Add | BinOp::Eq | BinOp::Ne) => Type::String,
+            (Type::Bool, Type::Bool) if matches!(op, BinOp::Eq | BinOp::Ne) => Type::Bool,
+            _ => {
+                return Err(CompileError::new(
+                    ErrorKind::Type,
+                    &format!("Cannot apply `{}` to `{}` and `{}`", op, left, right),
+                )
+                .with_span(span.clone()))
+            }
+        };
+        Ok(if op.is_comparison() { Type::Bool } else { operand })
+    }
+}
+
+fn literal_type(literal: &Literal) -> Type {
+    match &literal.value {
+        LiteralValue::Number(number) => match number.suffix {
+            Some(suffix) => suffix.to_type(),
+            None if number.is_float => Type::Float,
+            None => Type::Int,
+        },
+        LiteralValue::String(_) => Type::String,
+        LiteralValue::Bool(_) => Type::Bool,
+    }
+}
+
+/// Built-in types by name; other names need resolution this checker does
+/// not do
+fn builtin_type(name: &str) -> Option<Type> {
+    match name {
+        "void" => Some(Type::Void),
+        "bool" => Some(Type::Bool),
+        "int" => Some(Type::Int),
+        "float" => Some(Type::Float),
+        "string" => Some(Type::String),
+        _ => None,
+    }
+}
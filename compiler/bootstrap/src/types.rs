@@ -1,4 +1,11 @@
-use std::collections::HashMap;
+use crate::error_handling::{CompileError, ErrorKind};
+use crate::function_ref::{self, FunctionRef};
+use crate::interface::{ExportKind, ModuleInterface};
+use crate::layout::{LayoutCx, LayoutError, StructDecl};
+use crate::must_use::{DiscardedValue, MustUse};
+use crate::numeric::{LiteralContext, LiteralError, LiteralPolicy, NumericLiteral};
+use crate::suggest;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -70,18 +77,45 @@ impl fmt::Display for Type {
     }
 }
 
+pub const IMPLICIT_INT_TO_FLOAT: &str = "implicit_int_to_float";
+
 pub struct TypeChecker {
     type_env: HashMap<String, Type>,
+    /// Names in `type_env` that are declared functions rather than
+    /// variables holding a function value
+    functions: HashSet<String>,
     class_hierarchy: HashMap<String, Vec<String>>,
     interface_implementations: HashMap<String, Vec<String>>,
+    /// Sealed classes and interfaces, with the module allowed to extend
+    /// them. Subclasses of a sealed class are sealed too.
+    sealed: HashMap<String, String>,
+    /// Fields and methods of named types, for member lookup
+    members: HashMap<String, Vec<(String, Type)>>,
+    /// Types and functions marked `#[must_use]`
+    must_use: MustUse,
+    literal_policy: LiteralPolicy,
+    /// Struct layouts, shared with codegen and the FFI checker
+    layouts: LayoutCx,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TypeChecker {
     pub fn new() -> Self {
         TypeChecker {
             type_env: HashMap::new(),
+            functions: HashSet::new(),
             class_hierarchy: HashMap::new(),
             interface_implementations: HashMap::new(),
+            sealed: HashMap::new(),
+            members: HashMap::new(),
+            must_use: MustUse::new(),
+            literal_policy: LiteralPolicy::default(),
+            layouts: LayoutCx::new(),
         }
     }
     
@@ -89,22 +123,233 @@ impl TypeChecker {
         self.type_env.insert(name.to_string(), type_);
     }
     
+    pub fn add_function(&mut self, name: &str, type_: Type) {
+        self.functions.insert(name.to_string());
+        self.add_variable(name, type_);
+    }
+    
+    pub fn is_function(&self, name: &str) -> bool {
+        self.functions.contains(name)
+    }
+    
     pub fn get_variable_type(&self, name: &str) -> Option<&Type> {
         self.type_env.get(name)
     }
     
+    /// Resolve a variable, suggesting the closest known name if it is not
+    /// in scope
+    pub fn lookup_variable(&self, name: &str) -> Result<&Type, TypeError> {
+        self.type_env.get(name).ok_or_else(|| {
+            let suggestion = suggest::best_match(name, self.type_env.keys().map(String::as_str));
+            TypeError::UndefinedVariable(name.to_string(), suggestion.map(str::to_string))
+        })
+    }
+    
+    pub fn add_member(&mut self, type_name: &str, member: &str, type_: Type) {
+        self.members
+            .entry(type_name.to_string())
+            .or_default()
+            .push((member.to_string(), type_));
+    }
+    
+    /// Fields and methods of a named type, empty if it has none or is unknown
+    pub fn members_of(&self, type_name: &str) -> &[(String, Type)] {
+        self.members.get(type_name).map(Vec::as_slice).unwrap_or(&[])
+    }
+    
+    /// Resolve a field or method of a named type, suggesting the closest
+    /// member of that type if there is no exact match
+    pub fn lookup_member(&self, type_name: &str, member: &str) -> Result<&Type, TypeError> {
+        let members = self.members.get(type_name).map(Vec::as_slice).unwrap_or(&[]);
+        
+        if let Some((_, type_)) = members.iter().find(|(name, _)| name == member) {
+            return Ok(type_);
+        }
+        
+        let suggestion = suggest::best_match(member, members.iter().map(|(name, _)| name.as_str()));
+        Err(TypeError::MemberNotFound(
+            type_name.to_string(),
+            member.to_string(),
+            suggestion.map(str::to_string),
+        ))
+    }
+    
+    /// A named function used as a value, e.g. `let f = parse`. `None` when
+    /// `name` is a variable, which is read like any other.
+    pub fn reference_function(&self, name: &str) -> Result<Option<FunctionRef>, TypeError> {
+        let type_ = self.lookup_variable(name)?;
+        if !self.is_function(name) {
+            return Ok(None);
+        }
+        
+        Ok(Some(FunctionRef::Direct {
+            name: name.to_string(),
+            type_: type_.clone(),
+        }))
+    }
+    
+    /// A method bound to its receiver, e.g. `let f = list.push`. The value's
+    /// type leaves the receiver out of the parameters.
+    pub fn reference_method(&self, receiver: &Type, method: &str) -> Result<FunctionRef, TypeError> {
+        let type_ = match function_ref::builtin_method(receiver, method) {
+            Some(type_) => type_,
+            None => self
+                .lookup_member(&function_ref::receiver_name(receiver), method)?
+                .clone(),
+        };
+        if !matches!(type_, Type::Function(_, _)) {
+            return Err(TypeError::NotCallable(format!("{}.{}", receiver, method)));
+        }
+        
+        Ok(FunctionRef::Bound {
+            receiver: receiver.clone(),
+            method: method.to_string(),
+            type_,
+        })
+    }
+    
+    /// Type of calling a value of type `callee` with arguments of the given
+    /// types; works the same for functions, references and bound methods
+    pub fn check_call(&self, callee: &Type, arg_types: &[Type]) -> Result<Type, TypeError> {
+        let Type::Function(param_types, return_type) = callee else {
+            return match callee {
+                Type::Unknown => Ok(Type::Unknown),
+                _ => Err(TypeError::NotCallable(callee.to_string())),
+            };
+        };
+        if param_types.len() != arg_types.len() {
+            return Err(TypeError::WrongNumberOfArguments(param_types.len(), arg_types.len()));
+        }
+        
+        for (param_type, arg_type) in param_types.iter().zip(arg_types) {
+            self.check_assignment(param_type, arg_type)?;
+        }
+        Ok((**return_type).clone())
+    }
+    
+    pub fn must_use_mut(&mut self) -> &mut MustUse {
+        &mut self.must_use
+    }
+    
+    /// Lint an expression statement: warns when it drops a must-use value
+    /// or has no effect at all
+    pub fn check_expression_statement(&self, value: &DiscardedValue) -> Option<CompileError> {
+        self.must_use.check(value)
+    }
+    
+    pub fn layouts(&self) -> &LayoutCx {
+        &self.layouts
+    }
+    
+    /// Lay out the structs declared in a module, after those they contain
+    pub fn compute_layouts(&mut self, decls: &[StructDecl]) -> Result<(), LayoutError> {
+        self.layouts.compute_all(decls)
+    }
+    
+    pub fn set_literal_policy(&mut self, policy: LiteralPolicy) {
+        self.literal_policy = policy;
+    }
+    
+    /// Type of a numeric literal in `context`; see `numeric` for the rules
+    pub fn check_literal(
+        &self,
+        text: &str,
+        context: LiteralContext,
+        negated: bool,
+    ) -> Result<Type, LiteralError> {
+        let literal = NumericLiteral::parse(text)?;
+        let type_ = self.literal_policy.resolve(&literal, context, negated)?;
+        Ok(type_.to_type())
+    }
+    
+    /// Result type of `left op right` for arithmetic operators. Mixing int
+    /// and float is allowed but converts the int implicitly, which loses
+    /// precision above 2^53, so it comes with an `implicit_int_to_float`
+    /// warning.
+    pub fn check_arithmetic(
+        &self,
+        op: &str,
+        left: &Type,
+        right: &Type,
+    ) -> Result<(Type, Option<CompileError>), TypeError> {
+        match (left, right) {
+            (Type::Int, Type::Int) => Ok((Type::Int, None)),
+            (Type::Float, Type::Float) => Ok((Type::Float, None)),
+            (Type::Int, Type::Float) | (Type::Float, Type::Int) => {
+                let side = if *left == Type::Int { "left" } else { "right" };
+                let warning = CompileError::new(
+                    ErrorKind::Lint(IMPLICIT_INT_TO_FLOAT.to_string()),
+                    &format!("implicit conversion of int to float in `{}`", op),
+                )
+                .with_note(&format!("the {} operand is an int", side))
+                .with_help("convert explicitly with `as f64`");
+                Ok((Type::Float, Some(warning)))
+            }
+            (Type::String, Type::String) if op == "+" => Ok((Type::String, None)),
+            _ => Err(TypeError::InvalidOperator(op.to_string(), left.to_string(), right.to_string())),
+        }
+    }
+    
     pub fn add_class(&mut self, name: &str, parent: Option<&str>) {
         if let Some(parent_name) = parent {
-            let entry = self.class_hierarchy.entry(parent_name.to_string()).or_insert_with(Vec::new);
+            let entry = self.class_hierarchy.entry(parent_name.to_string()).or_default();
             entry.push(name.to_string());
+            
+            if let Some(module) = self.sealed.get(parent_name).cloned() {
+                self.sealed.entry(name.to_string()).or_insert(module);
+            }
         }
     }
     
     pub fn add_interface_implementation(&mut self, class_name: &str, interface_name: &str) {
-        let entry = self.interface_implementations.entry(interface_name.to_string()).or_insert_with(Vec::new);
+        let entry = self.interface_implementations.entry(interface_name.to_string()).or_default();
         entry.push(class_name.to_string());
     }
     
+    /// Honor `sealed` on a class or interface declared in `module`
+    pub fn mark_sealed(&mut self, name: &str, module: &str) {
+        self.sealed.insert(name.to_string(), module.to_string());
+    }
+    
+    pub fn is_sealed(&self, name: &str) -> bool {
+        self.sealed.contains_key(name)
+    }
+    
+    /// A class in `module` may only extend or implement sealed types
+    /// declared in that same module
+    pub fn check_supertypes(&self, module: &str, class_name: &str, supertypes: &[&str]) -> Result<(), TypeError> {
+        for supertype in supertypes {
+            match self.sealed.get(*supertype) {
+                Some(sealed_in) if sealed_in != module => {
+                    return Err(TypeError::ExtendsSealed(
+                        class_name.to_string(),
+                        supertype.to_string(),
+                        sealed_in.clone(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn parent_class(&self, class_name: &str) -> Option<&str> {
+        self.class_hierarchy
+            .iter()
+            .find(|(_, subclasses)| subclasses.iter().any(|subclass| subclass == class_name))
+            .map(|(parent, _)| parent.as_str())
+    }
+    
+    /// Direct subclasses of a class
+    pub fn subclasses(&self, class_name: &str) -> &[String] {
+        self.class_hierarchy.get(class_name).map(Vec::as_slice).unwrap_or(&[])
+    }
+    
+    /// Classes declared to implement an interface, not their subclasses
+    pub fn implementers(&self, interface_name: &str) -> &[String] {
+        self.interface_implementations.get(interface_name).map(Vec::as_slice).unwrap_or(&[])
+    }
+    
     pub fn is_subtype(&self, sub: &Type, super_: &Type) -> bool {
         if sub == super_ {
             return true;
@@ -201,6 +446,57 @@ impl TypeChecker {
         false
     }
     
+    /// Make a dependency's exported items visible, qualified by module name
+    pub fn import_interface(&mut self, interface: &ModuleInterface) -> Result<(), LayoutError> {
+        let mut structs = Vec::new();
+        
+        for item in &interface.exports {
+            let qualified = format!("{}::{}", interface.module_name, item.name);
+            
+            match &item.kind {
+                ExportKind::Function(type_) => {
+                    self.add_function(&qualified, type_.clone());
+                }
+                ExportKind::Constant(type_) => {
+                    self.add_variable(&qualified, type_.clone());
+                }
+                ExportKind::Class { parent, interfaces, members, sealed } => {
+                    if *sealed {
+                        self.mark_sealed(&item.name, &interface.module_name);
+                    }
+                    self.add_class(&item.name, parent.as_deref());
+                    for interface_name in interfaces {
+                        self.add_interface_implementation(&item.name, interface_name);
+                    }
+                    for (name, type_) in members {
+                        self.add_member(&item.name, name, type_.clone());
+                    }
+                }
+                ExportKind::Interface { methods: members, sealed } => {
+                    if *sealed {
+                        self.mark_sealed(&item.name, &interface.module_name);
+                    }
+                    for (name, type_) in members {
+                        self.add_member(&item.name, name, type_.clone());
+                    }
+                }
+                ExportKind::Struct { fields, repr } => {
+                    for (name, type_) in fields {
+                        self.add_member(&item.name, name, type_.clone());
+                    }
+                    structs.push(StructDecl {
+                        name: &item.name,
+                        fields,
+                        repr: *repr,
+                    });
+                }
+                ExportKind::Enum { .. } => {}
+            }
+        }
+        
+        self.layouts.compute_all(&structs)
+    }
+    
     pub fn check_assignment(&self, target_type: &Type, value_type: &Type) -> Result<(), TypeError> {
         if self.is_subtype(value_type, target_type) {
             Ok(())
@@ -213,22 +509,25 @@ impl TypeChecker {
     }
 }
 
+/// Errors carrying an `Option<String>` hold the closest known name, if any
 #[derive(Debug)]
 pub enum TypeError {
-    UndefinedVariable(String),
+    UndefinedVariable(String, Option<String>),
     UndefinedType(String),
     IncompatibleTypes(String, String),
     NotCallable(String),
     WrongNumberOfArguments(usize, usize),
-    MemberNotFound(String, String),
+    MemberNotFound(String, String, Option<String>),
     NotIndexable(String),
     InvalidOperator(String, String, String),
+    /// Class, sealed supertype, module the supertype is sealed to
+    ExtendsSealed(String, String, String),
 }
 
 impl fmt::Display for TypeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            TypeError::UndefinedVariable(name) => {
+            TypeError::UndefinedVariable(name, _) => {
                 write!(f, "Undefined variable: {}", name)
             }
             TypeError::UndefinedType(name) => {
@@ -243,7 +542,7 @@ impl fmt::Display for TypeError {
             TypeError::WrongNumberOfArguments(expected, actual) => {
                 write!(f, "Expected {} arguments but got {}", expected, actual)
             }
-            TypeError::MemberNotFound(type_, member) => {
+            TypeError::MemberNotFound(type_, member, _) => {
                 write!(f, "Member {} not found in type {}", member, type_)
             }
             TypeError::NotIndexable(type_) => {
@@ -252,8 +551,36 @@ impl fmt::Display for TypeError {
             TypeError::InvalidOperator(op, left, right) => {
                 write!(f, "Operator {} not defined for types {} and {}", op, left, right)
             }
+            TypeError::ExtendsSealed(class, supertype, module) => {
+                write!(f, "Class {} cannot extend {}: it is sealed to module {}", class, supertype, module)
+            }
         }
     }
 }
 
-impl std::error::Error for TypeError {}
\ No newline at end of file
+impl std::error::Error for TypeError {}
+
+impl TypeError {
+    /// The closest known name for an undefined variable or member
+    pub fn suggestion(&self) -> Option<&str> {
+        match self {
+            TypeError::UndefinedVariable(_, suggestion) | TypeError::MemberNotFound(_, _, suggestion) => {
+                suggestion.as_deref()
+            }
+            _ => None,
+        }
+    }
+    
+    pub fn into_compile_error(self) -> CompileError {
+        let kind = match self {
+            TypeError::UndefinedVariable(..) | TypeError::UndefinedType(_) => ErrorKind::Name,
+            _ => ErrorKind::Type,
+        };
+        
+        let error = CompileError::new(kind, &self.to_string());
+        match self.suggestion() {
+            Some(suggestion) => error.with_suggestion(suggestion),
+            None => error,
+        }
+    }
+}
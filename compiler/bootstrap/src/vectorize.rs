@@ -0,0 +1,528 @@
+//! Loop vectorization.
+//!
+//! Counted loops over arrays run several iterations at once in SIMD
+//! registers when that cannot change what the program does. Two shapes are
+//! recognized: maps, which store `f(a[i], b[i], ...)` into `out[i]`, and
+//! reductions, which fold such a value into an accumulator with `+`, `*`,
+//! `min` or `max`. Anything else, e.g. calls, early exits or a value one
+//! iteration computes and the next reads, keeps the loop scalar.
+//!
+//! Bounds checks are not dropped. One check before the loop covers every
+//! index it will touch; when it fails the original scalar loop runs, so an
+//! out-of-bounds access traps at the same iteration and after the same
+//! stores as without vectorization. Arrays that may be the same array get
+//! a runtime overlap check that falls back the same way.
+//!
+//! Each decision is reported as a `Remark`, shown with `--remarks`.
+
+use crate::error_handling::Span;
+use crate::types::Type;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// A loop `for counter in start..end` lowered for vectorization
+#[derive(Debug, Clone)]
+pub struct CountedLoop {
+    pub function: String,
+    pub span: Span,
+    /// `#[no_vectorize]` on the loop or its function
+    pub no_vectorize: bool,
+    pub counter: String,
+    pub step: i64,
+    pub arrays: Vec<ArrayInfo>,
+    pub body: Vec<LoopStmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArrayInfo {
+    pub name: String,
+    pub element: Type,
+    /// Known not to share storage with any other array of the loop, e.g.
+    /// allocated in this function and not yet shared
+    pub unique: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoopExpr {
+    /// `array[counter + offset]`
+    Load { array: String, offset: i64 },
+    Counter,
+    Int(i64),
+    Float(f64),
+    /// A variable the loop does not assign
+    Invariant(String),
+    Binary(String, Box<LoopExpr>, Box<LoopExpr>),
+    Neg(Box<LoopExpr>),
+    Call(String, Vec<LoopExpr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoopStmt {
+    /// `array[counter + offset] = value`
+    Store { array: String, offset: i64, value: LoopExpr },
+    /// `accumulator = accumulator op value`, or `min`/`max` of the two
+    Reduce { accumulator: String, op: String, value: LoopExpr },
+    /// `break` or `return`
+    Exit,
+    /// Any other statement, described for remarks, e.g. "an assignment to `x`"
+    Other(String),
+}
+
+/// Width of the target's SIMD registers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VectorTarget {
+    pub register_bits: u32,
+}
+
+impl VectorTarget {
+    /// Baseline SIMD every CPU of the target has; `None` for targets
+    /// without it
+    pub fn for_triple(triple: &str) -> Option<Self> {
+        let arch = triple.split('-').next().unwrap_or("");
+        match arch {
+            "x86_64" | "aarch64" | "arm64" => Some(VectorTarget { register_bits: 128 }),
+            "wasm32" if triple.contains("simd") => Some(VectorTarget { register_bits: 128 }),
+            _ => None,
+        }
+    }
+
+    /// Integers are `i64` and floats `double`, so every lane is 64 bits
+    fn lanes(&self) -> u32 {
+        self.register_bits / 64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopKind {
+    Map,
+    Reduction,
+    MapReduction,
+}
+
+impl fmt::Display for LoopKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoopKind::Map => write!(f, "map"),
+            LoopKind::Reduction => write!(f, "reduction"),
+            LoopKind::MapReduction => write!(f, "map and reduction"),
+        }
+    }
+}
+
+/// How a loop is vectorized
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorPlan {
+    pub kind: LoopKind,
+    pub lanes: u32,
+    pub element: Type,
+    /// Smallest and largest offset from the counter each array is indexed
+    /// at; checked once against the array's length before the loop
+    pub bounds: Vec<(String, i64, i64)>,
+    /// Pairs of arrays that must not overlap, checked at runtime
+    pub overlap_checks: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemarkKind {
+    Passed,
+    Missed,
+}
+
+/// An optimizer decision, for `--remarks`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remark {
+    pub pass: &'static str,
+    pub kind: RemarkKind,
+    pub function: String,
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for Remark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: remark [{}] in `{}`: {}", self.span, self.pass, self.function, self.message)
+    }
+}
+
+/// Decide whether and how to vectorize `loop_`, with the remark explaining
+/// the decision
+pub fn vectorize(loop_: &CountedLoop, target: Option<VectorTarget>) -> (Option<VectorPlan>, Remark) {
+    let result = target
+        .ok_or_else(|| "the target has no SIMD instructions".to_string())
+        .and_then(|target| plan(loop_, target));
+
+    let (plan, kind, message) = match result {
+        Ok(plan) => {
+            let mut message = format!("loop vectorized ({}, {} lanes)", plan.kind, plan.lanes);
+            if !plan.overlap_checks.is_empty() {
+                let pairs: Vec<String> = plan
+                    .overlap_checks
+                    .iter()
+                    .map(|(a, b)| format!("`{}` and `{}`", a, b))
+                    .collect();
+                message.push_str(&format!(" with a runtime overlap check between {}", pairs.join(", ")));
+            }
+            (Some(plan), RemarkKind::Passed, message)
+        }
+        Err(reason) => (None, RemarkKind::Missed, format!("loop not vectorized: {}", reason)),
+    };
+
+    let remark = Remark {
+        pass: "vectorize",
+        kind,
+        function: loop_.function.clone(),
+        span: loop_.span.clone(),
+        message,
+    };
+    (plan, remark)
+}
+
+fn plan(loop_: &CountedLoop, target: VectorTarget) -> Result<VectorPlan, String> {
+    if loop_.no_vectorize {
+        return Err("it is marked `#[no_vectorize]`".to_string());
+    }
+    if loop_.step != 1 {
+        return Err(format!("`{}` steps by {} instead of 1", loop_.counter, loop_.step));
+    }
+
+    let mut loads = Vec::new();
+    let mut stores = Vec::new();
+    let mut accumulators = Vec::new();
+    for statement in &loop_.body {
+        match statement {
+            LoopStmt::Exit => return Err("it can exit early".to_string()),
+            LoopStmt::Other(description) => return Err(format!("its body contains {}", description)),
+            LoopStmt::Store { array, offset, value } => {
+                check_expr(value, &mut loads)?;
+                stores.push((array.as_str(), *offset));
+            }
+            LoopStmt::Reduce { accumulator, op, value } => {
+                check_expr(value, &mut loads)?;
+                accumulators.push((accumulator.as_str(), op.as_str()));
+            }
+        }
+    }
+
+    let element = element_type(loop_)?;
+    for (accumulator, op) in &accumulators {
+        if !matches!(*op, "+" | "*" | "min" | "max") {
+            return Err(format!("`{}` is reduced with `{}`", accumulator, op));
+        }
+        if element == Type::Float {
+            return Err(format!(
+                "reducing floats into `{}` in a different order would change rounding",
+                accumulator
+            ));
+        }
+        if reads_invariant(&loop_.body, accumulator) {
+            return Err(format!("`{}` is read inside the loop while being accumulated", accumulator));
+        }
+    }
+
+    // A store at one offset and another access to the same array at a
+    // different one carries a value from one iteration to another
+    for (array, offset) in &stores {
+        let other_offset = loads
+            .iter()
+            .chain(stores.iter())
+            .find(|(other, other_offset)| other == array && other_offset != offset);
+        if other_offset.is_some() {
+            return Err(format!("`{}` is written and accessed at another index in the same loop", array));
+        }
+    }
+
+    let accessed: BTreeSet<&str> = loads.iter().chain(stores.iter()).map(|(array, _)| *array).collect();
+    let mut overlap_checks = Vec::new();
+    for (stored, _) in &stores {
+        for other in &accessed {
+            if stored == other || is_unique(loop_, stored) || is_unique(loop_, other) {
+                continue;
+            }
+            let (first, second) = if stored < other { (stored, other) } else { (other, stored) };
+            let pair = (first.to_string(), second.to_string());
+            if !overlap_checks.contains(&pair) {
+                overlap_checks.push(pair);
+            }
+        }
+    }
+
+    let bounds = accessed
+        .iter()
+        .map(|array| {
+            let offsets = loads
+                .iter()
+                .chain(stores.iter())
+                .filter(|(other, _)| other == array)
+                .map(|(_, offset)| *offset);
+            let min = offsets.clone().min().unwrap_or(0);
+            let max = offsets.max().unwrap_or(0);
+            (array.to_string(), min, max)
+        })
+        .collect();
+
+    let kind = match (stores.is_empty(), accumulators.is_empty()) {
+        (false, true) => LoopKind::Map,
+        (true, false) => LoopKind::Reduction,
+        (false, false) => LoopKind::MapReduction,
+        (true, true) => return Err("it neither stores to an array nor accumulates a value".to_string()),
+    };
+
+    Ok(VectorPlan {
+        kind,
+        lanes: target.lanes(),
+        element,
+        bounds,
+        overlap_checks,
+    })
+}
+
+/// Collect the loads in `expr`, rejecting what has no lane-wise equivalent
+fn check_expr<'a>(expr: &'a LoopExpr, loads: &mut Vec<(&'a str, i64)>) -> Result<(), String> {
+    match expr {
+        LoopExpr::Load { array, offset } => loads.push((array.as_str(), *offset)),
+        LoopExpr::Counter | LoopExpr::Int(_) | LoopExpr::Float(_) | LoopExpr::Invariant(_) => {}
+        LoopExpr::Neg(inner) => check_expr(inner, loads)?,
+        LoopExpr::Binary(op, left, right) => {
+            if !matches!(op.as_str(), "+" | "-" | "*" | "/") {
+                return Err(format!("it uses `{}`, which has no vector form", op));
+            }
+            check_expr(left, loads)?;
+            check_expr(right, loads)?;
+        }
+        LoopExpr::Call(name, _) => return Err(format!("it calls `{}`, which may have side effects", name)),
+    }
+    Ok(())
+}
+
+/// The one element type of every array. Integer division keeps a loop
+/// scalar: a zero divisor must trap at its own iteration.
+fn element_type(loop_: &CountedLoop) -> Result<Type, String> {
+    let mut element = None;
+    for array in &loop_.arrays {
+        if !matches!(array.element, Type::Int | Type::Float) {
+            return Err(format!("`{}` holds {} values", array.name, array.element));
+        }
+        match &element {
+            None => element = Some(array.element.clone()),
+            Some(first) if *first != array.element => {
+                return Err("its arrays have different element types".to_string());
+            }
+            Some(_) => {}
+        }
+    }
+
+    let element = element.unwrap_or(Type::Int);
+    if element == Type::Int && loop_.body.iter().any(divides) {
+        return Err("integer division could trap on a zero divisor".to_string());
+    }
+    Ok(element)
+}
+
+fn divides(statement: &LoopStmt) -> bool {
+    fn expr_divides(expr: &LoopExpr) -> bool {
+        match expr {
+            LoopExpr::Binary(op, left, right) => op == "/" || expr_divides(left) || expr_divides(right),
+            LoopExpr::Neg(inner) => expr_divides(inner),
+            _ => false,
+        }
+    }
+    match statement {
+        LoopStmt::Store { value, .. } | LoopStmt::Reduce { value, .. } => expr_divides(value),
+        LoopStmt::Exit | LoopStmt::Other(_) => false,
+    }
+}
+
+fn reads_invariant(body: &[LoopStmt], name: &str) -> bool {
+    fn expr_reads(expr: &LoopExpr, name: &str) -> bool {
+        match expr {
+            LoopExpr::Invariant(variable) => variable == name,
+            LoopExpr::Binary(_, left, right) => expr_reads(left, name) || expr_reads(right, name),
+            LoopExpr::Neg(inner) => expr_reads(inner, name),
+            LoopExpr::Call(_, args) => args.iter().any(|arg| expr_reads(arg, name)),
+            _ => false,
+        }
+    }
+    body.iter().any(|statement| match statement {
+        LoopStmt::Store { value, .. } | LoopStmt::Reduce { value, .. } => expr_reads(value, name),
+        LoopStmt::Exit | LoopStmt::Other(_) => false,
+    })
+}
+
+fn is_unique(loop_: &CountedLoop, array: &str) -> bool {
+    loop_.arrays.iter().any(|info| info.name == array && info.unique)
+}
+
+impl VectorPlan {
+    /// LLVM IR for one vector iteration of `loop_`, starting at element
+    /// `%i`. Codegen provides `%<array>.data` pointers, `%<name>` for
+    /// invariants and a `<lanes x T>` phi `%<accumulator>.vec` per
+    /// accumulator, fed by the body's `%<accumulator>.vec.next`. It wraps
+    /// the body with the bounds and overlap checks, the scalar fallback and
+    /// remainder loop, and `reduce_accumulator`.
+    pub fn llvm_body(&self, loop_: &CountedLoop) -> String {
+        let mut emitter = Emitter {
+            plan: self,
+            lines: Vec::new(),
+            next: 0,
+        };
+
+        for statement in &loop_.body {
+            match statement {
+                LoopStmt::Store { array, offset, value } => {
+                    let value = emitter.expr(value);
+                    let pointer = emitter.element_pointer(array, *offset);
+                    let vector = emitter.vector_type();
+                    emitter.lines.push(format!("store {} {}, ptr {}, align 8", vector, value, pointer));
+                }
+                LoopStmt::Reduce { accumulator, op, value } => {
+                    let value = emitter.expr(value);
+                    let vector = emitter.vector_type();
+                    let accumulated = format!("%{}.vec", accumulator);
+                    let update = match op.as_str() {
+                        "+" => format!("add {} {}, {}", vector, accumulated, value),
+                        "*" => format!("mul {} {}, {}", vector, accumulated, value),
+                        min_max => format!(
+                            "call {vector} @llvm.{intrinsic}.v{lanes}i64({vector} {accumulated}, {vector} {value})",
+                            vector = vector,
+                            intrinsic = if min_max == "min" { "smin" } else { "smax" },
+                            lanes = self.lanes,
+                            accumulated = accumulated,
+                            value = value,
+                        ),
+                    };
+                    emitter.lines.push(format!("%{}.vec.next = {}", accumulator, update));
+                }
+                LoopStmt::Exit | LoopStmt::Other(_) => {}
+            }
+        }
+
+        emitter.lines.iter().map(|line| format!("  {}\n", line)).collect()
+    }
+
+    /// Horizontal reduction of an accumulator's vector into its scalar
+    /// after the vector loop
+    pub fn reduce_accumulator(&self, accumulator: &str, op: &str) -> String {
+        let operation = match op {
+            "+" => "add",
+            "*" => "mul",
+            "min" => "smin",
+            _ => "smax",
+        };
+        format!(
+            "%{acc}.reduced = call i64 @llvm.vector.reduce.{op}.v{lanes}i64(<{lanes} x i64> %{acc}.vec)",
+            acc = accumulator,
+            op = operation,
+            lanes = self.lanes,
+        )
+    }
+}
+
+struct Emitter<'a> {
+    plan: &'a VectorPlan,
+    lines: Vec<String>,
+    next: usize,
+}
+
+impl Emitter<'_> {
+    fn scalar_type(&self) -> &'static str {
+        if self.plan.element == Type::Float {
+            "double"
+        } else {
+            "i64"
+        }
+    }
+
+    fn vector_type(&self) -> String {
+        format!("<{} x {}>", self.plan.lanes, self.scalar_type())
+    }
+
+    fn instruction(&mut self, text: &str) -> String {
+        let name = format!("%v{}", self.next);
+        self.next += 1;
+        self.lines.push(format!("{} = {}", name, text));
+        name
+    }
+
+    fn element_pointer(&mut self, array: &str, offset: i64) -> String {
+        let index = if offset == 0 {
+            "%i".to_string()
+        } else {
+            self.instruction(&format!("add i64 %i, {}", offset))
+        };
+        let scalar = self.scalar_type();
+        self.instruction(&format!("getelementptr {}, ptr %{}.data, i64 {}", scalar, array, index))
+    }
+
+    /// A vector with `scalar` in every lane
+    fn splat(&mut self, scalar: &str) -> String {
+        let vector = self.vector_type();
+        let scalar_type = self.scalar_type();
+        let single = self.instruction(&format!(
+            "insertelement {} poison, {} {}, i64 0",
+            vector, scalar_type, scalar
+        ));
+        self.instruction(&format!(
+            "shufflevector {vector} {single}, {vector} poison, <{lanes} x i32> zeroinitializer",
+            vector = vector,
+            single = single,
+            lanes = self.plan.lanes,
+        ))
+    }
+
+    fn expr(&mut self, expr: &LoopExpr) -> String {
+        let float = self.plan.element == Type::Float;
+        match expr {
+            LoopExpr::Load { array, offset } => {
+                let pointer = self.element_pointer(array, *offset);
+                let vector = self.vector_type();
+                self.instruction(&format!("load {}, ptr {}, align 8", vector, pointer))
+            }
+            LoopExpr::Counter => {
+                let counter = self.splat("%i");
+                let steps: Vec<String> = (0..self.plan.lanes).map(|lane| format!("i64 {}", lane)).collect();
+                let counter = self.instruction(&format!(
+                    "add <{} x i64> {}, <{}>",
+                    self.plan.lanes,
+                    counter,
+                    steps.join(", ")
+                ));
+                if float {
+                    let vector = self.vector_type();
+                    self.instruction(&format!("sitofp <{} x i64> {} to {}", self.plan.lanes, counter, vector))
+                } else {
+                    counter
+                }
+            }
+            LoopExpr::Int(value) if float => self.splat(&format!("{:?}", *value as f64)),
+            LoopExpr::Int(value) => self.splat(&value.to_string()),
+            LoopExpr::Float(value) => self.splat(&format!("{:?}", value)),
+            LoopExpr::Invariant(name) => self.splat(&format!("%{}", name)),
+            LoopExpr::Neg(inner) => {
+                let inner = self.expr(inner);
+                let vector = self.vector_type();
+                if float {
+                    self.instruction(&format!("fneg {} {}", vector, inner))
+                } else {
+                    self.instruction(&format!("sub {} zeroinitializer, {}", vector, inner))
+                }
+            }
+            LoopExpr::Binary(op, left, right) => {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                let opcode = match (op.as_str(), float) {
+                    ("+", false) => "add",
+                    ("-", false) => "sub",
+                    ("*", false) => "mul",
+                    ("+", true) => "fadd",
+                    ("-", true) => "fsub",
+                    ("*", true) => "fmul",
+                    _ => "fdiv",
+                };
+                let vector = self.vector_type();
+                self.instruction(&format!("{} {} {}, {}", opcode, vector, left, right))
+            }
+            // Rejected by `plan`
+            LoopExpr::Call(name, _) => unreachable!("call to `{}` in a vectorized loop", name),
+        }
+    }
+}
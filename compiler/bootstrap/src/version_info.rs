@@ -0,0 +1,326 @@
+//! `zaitun --print=version-info`: what this compiler supports, as JSON, so
+//! tools can check at startup that they can work with the compiler they
+//! found rather than failing in odd ways later.
+//!
+//! ```text
+//! $ zaitun --print=version-info
+//! {
+//!   "format": 1,
+//!   "compiler_version": "0.1.0",
+//!   "language_version": "0.1",
+//!   "editions": [
+//!     "2024",
+//!     "2026"
+//!   ],
+//!   ...
+//! }
+//! ```
+//!
+//! Tools describe what they need in a `Requirements` and call
+//! `VersionInfo::query` then `check`. Fields may be added without bumping
+//! `VERSION_INFO_FORMAT`; removing or changing one bumps it.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::io;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ast_json::AST_JSON_VERSION;
+use crate::daemon::PROTOCOL_VERSION;
+use crate::edition::Edition;
+use crate::interface::INTERFACE_FORMAT_VERSION;
+
+pub const VERSION_INFO_FORMAT: u32 = 1;
+
+/// Version of the language the compiler implements, `major.minor`.
+/// Programs written for `x.y` compile with any `x.z` where `z >= y`; before
+/// 1.0 the minor version must match exactly.
+pub const LANGUAGE_VERSION: &str = "0.1";
+
+/// Target triples code can be generated for
+pub const TARGETS: &[&str] = &[
+    "x86_64-unknown-linux-gnu",
+    "aarch64-unknown-linux-gnu",
+    "x86_64-apple-darwin",
+    "aarch64-apple-darwin",
+    "x86_64-pc-windows-msvc",
+    "wasm32-unknown-unknown",
+];
+
+/// Optional capabilities tools may depend on; named after the command-line
+/// flag or `--emit` kind that uses them
+pub const FEATURES: &[&str] = &[
+    "daemon",
+    "incremental",
+    "edition",
+    "memory-stats",
+    "lto",
+    "remarks",
+    "keep-debug-asserts",
+    "emit-ast-json",
+    "emit-interface",
+    "emit-lto-ir",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub format: u32,
+    pub compiler_version: String,
+    pub language_version: String,
+    pub editions: Vec<String>,
+    pub targets: Vec<String>,
+    pub features: Vec<String>,
+    /// `daemon::PROTOCOL_VERSION`
+    pub daemon_protocol: u32,
+    /// Version of `.zi` interface files written
+    pub interface_format: u16,
+    /// Version of `--emit ast-json` documents
+    pub ast_json_version: u32,
+}
+
+impl VersionInfo {
+    /// What this compiler supports
+    pub fn current() -> Self {
+        let strings = |items: &[&str]| items.iter().map(|item| item.to_string()).collect();
+        VersionInfo {
+            format: VERSION_INFO_FORMAT,
+            compiler_version: env!("CARGO_PKG_VERSION").to_string(),
+            language_version: LANGUAGE_VERSION.to_string(),
+            editions: Edition::ALL.iter().map(|edition| edition.to_string()).collect(),
+            targets: strings(TARGETS),
+            features: strings(FEATURES),
+            daemon_protocol: PROTOCOL_VERSION,
+            interface_format: INTERFACE_FORMAT_VERSION,
+            ast_json_version: AST_JSON_VERSION,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        // Plain data with string keys always serializes
+        serde_json::to_string_pretty(self).expect("version info serialization")
+    }
+
+    /// Read `--print=version-info` output, rejecting newer formats
+    pub fn from_json(json: &str) -> Result<Self, VersionError> {
+        #[derive(Deserialize)]
+        struct Format {
+            format: u32,
+        }
+
+        // Check the format first: a newer one may not parse as this one
+        let format: Format = serde_json::from_str(json).map_err(|e| VersionError::Malformed(e.to_string()))?;
+        if format.format > VERSION_INFO_FORMAT {
+            return Err(VersionError::NewerFormat(format.format));
+        }
+        serde_json::from_str(json).map_err(|e| VersionError::Malformed(e.to_string()))
+    }
+
+    /// Ask the compiler at `compiler` what it supports
+    pub fn query(compiler: &OsStr) -> Result<Self, VersionError> {
+        let name = compiler.to_string_lossy().into_owned();
+        let output = Command::new(compiler)
+            .arg("--print=version-info")
+            .output()
+            .map_err(|e| VersionError::NotFound(name.clone(), e))?;
+        // Compilers from before the handshake reject the flag
+        if !output.status.success() {
+            return Err(VersionError::Unsupported(name));
+        }
+        VersionInfo::from_json(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Check that this compiler meets `requirements`, listing every unmet
+    /// requirement at once
+    pub fn check(&self, requirements: &Requirements) -> Result<(), VersionError> {
+        let mut problems = Vec::new();
+
+        if let Some(needed) = &requirements.language_version {
+            if !language_compatible(&self.language_version, needed) {
+                problems.push(format!(
+                    "needs language version {} but the compiler implements {}",
+                    needed, self.language_version
+                ));
+            }
+        }
+        if let Some(needed) = requirements.daemon_protocol {
+            if self.daemon_protocol != needed {
+                problems.push(format!(
+                    "speaks daemon protocol {} but the compiler speaks {}",
+                    needed, self.daemon_protocol
+                ));
+            }
+        }
+        if let Some(needed) = requirements.interface_format {
+            if self.interface_format != needed {
+                problems.push(format!(
+                    "reads interface format {} but the compiler writes {}",
+                    needed, self.interface_format
+                ));
+            }
+        }
+        if let Some(needed) = requirements.ast_json_version {
+            // Readers accept documents up to their own version
+            if self.ast_json_version > needed {
+                problems.push(format!(
+                    "reads AST JSON up to version {} but the compiler writes {}",
+                    needed, self.ast_json_version
+                ));
+            }
+        }
+        for feature in &requirements.features {
+            if !self.features.contains(feature) {
+                problems.push(format!("needs compiler feature `{}`, which it lacks", feature));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(VersionError::Incompatible {
+                tool: requirements.tool.clone(),
+                compiler_version: self.compiler_version.clone(),
+                problems,
+            })
+        }
+    }
+}
+
+/// Whether code for language `needed` builds with a compiler implementing
+/// `supported`; see `LANGUAGE_VERSION`
+fn language_compatible(supported: &str, needed: &str) -> bool {
+    let parse = |version: &str| {
+        let (major, minor) = version.split_once('.')?;
+        Some((major.parse::<u32>().ok()?, minor.parse::<u32>().ok()?))
+    };
+    match (parse(supported), parse(needed)) {
+        (Some((0, minor)), Some((0, needed_minor))) => minor == needed_minor,
+        (Some((major, minor)), Some((needed_major, needed_minor))) => major == needed_major && minor >= needed_minor,
+        _ => false,
+    }
+}
+
+/// What a tool needs from the compiler
+#[derive(Debug, Clone, PartialEq)]
+pub struct Requirements {
+    /// Named in mismatch errors, e.g. `zaitun-lsp`
+    pub tool: String,
+    pub language_version: Option<String>,
+    pub daemon_protocol: Option<u32>,
+    pub interface_format: Option<u16>,
+    pub ast_json_version: Option<u32>,
+    pub features: Vec<String>,
+}
+
+impl Requirements {
+    /// The language version this build of the tool was written against
+    pub fn new(tool: &str) -> Self {
+        Requirements {
+            tool: tool.to_string(),
+            language_version: Some(LANGUAGE_VERSION.to_string()),
+            daemon_protocol: None,
+            interface_format: None,
+            ast_json_version: None,
+            features: Vec::new(),
+        }
+    }
+
+    pub fn with_feature(mut self, feature: &str) -> Self {
+        self.features.push(feature.to_string());
+        self
+    }
+
+    /// Reads the `.zi` files the compiler writes
+    pub fn with_interface_format(mut self) -> Self {
+        self.interface_format = Some(INTERFACE_FORMAT_VERSION);
+        self
+    }
+
+    /// Reads `--emit ast-json` output
+    pub fn with_ast_json(mut self) -> Self {
+        self.ast_json_version = Some(AST_JSON_VERSION);
+        self
+    }
+
+    /// Talks to the compile daemon
+    pub fn with_daemon_protocol(mut self) -> Self {
+        self.daemon_protocol = Some(PROTOCOL_VERSION);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum VersionError {
+    /// The compiler could not be run
+    NotFound(String, io::Error),
+    /// The compiler predates `--print=version-info`
+    Unsupported(String),
+    Malformed(String),
+    NewerFormat(u32),
+    Incompatible {
+        tool: String,
+        compiler_version: String,
+        problems: Vec<String>,
+    },
+}
+
+impl fmt::Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionError::NotFound(compiler, e) => write!(
+                f,
+                "Could not run the compiler `{}`: {}\nhelp: install the Zaitun toolchain or set $ZAITUN to the compiler's path",
+                compiler, e
+            ),
+            VersionError::Unsupported(compiler) => write!(
+                f,
+                "The compiler `{}` is too old to report its version info\nhelp: upgrade the toolchain so the compiler and tools come from the same release",
+                compiler
+            ),
+            VersionError::Malformed(e) => write!(f, "Malformed compiler version info: {}", e),
+            VersionError::NewerFormat(format) => write!(
+                f,
+                "The compiler reports version info in format {}, newer than this tool reads ({})\nhelp: upgrade the tool to match the compiler",
+                format, VERSION_INFO_FORMAT
+            ),
+            VersionError::Incompatible { tool, compiler_version, problems } => {
+                write!(f, "{} is not compatible with compiler {}:", tool, compiler_version)?;
+                for problem in problems {
+                    write!(f, "\n  - {} {}", tool, problem)?;
+                }
+                write!(f, "\nhelp: use the {} that ships with compiler {}, or set $ZAITUN to a matching compiler", tool, compiler_version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VersionError {}
+
+/// The compiler tools run: `$ZAITUN`, or `zaitun` from the `PATH`
+pub fn compiler_command() -> OsString {
+    env::var_os("ZAITUN").unwrap_or_else(|| OsString::from("zaitun"))
+}
+
+/// Query the compiler tools run and check it meets `requirements`; for
+/// tools to call at startup
+pub fn verify(requirements: &Requirements) -> Result<VersionInfo, VersionError> {
+    let info = VersionInfo::query(&compiler_command())?;
+    info.check(requirements)?;
+    Ok(info)
+}
+
+/// Entry point for `zaitun --print=WHAT`
+pub fn run_print(what: &str) -> i32 {
+    match what {
+        "version-info" => {
+            println!("{}", VersionInfo::current().to_json());
+            0
+        }
+        _ => {
+            eprintln!("Unknown --print value: {} (expected version-info)", what);
+            2
+        }
+    }
+}
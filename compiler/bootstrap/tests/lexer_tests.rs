@@ -1,13 +1,28 @@
-#[cfg(test)]
-mod tests {
-    use super::*;
+use zaitun_bootstrap::lexer::{Lexer, TokenType};
 
-    #[test]
-    fn test_basic_tokens() {
-        let input = "fn main() { let x = 42; }";
-        let mut lexer = Lexer::new(input);
-        assert_eq!(lexer.next_token(), Some(Token::Keyword("fn".into())));
-        assert_eq!(lexer.next_token(), Some(Token::Ident("main".into())));
-        // ... more test cases ...
-    }
-}
\ No newline at end of file
+#[test]
+fn test_basic_tokens() {
+    let input = "fn main() { let x = 42; }";
+    let tokens = Lexer::new(input.to_string()).scan_tokens();
+    let kinds: Vec<(TokenType, &str)> = tokens
+        .iter()
+        .map(|token| (token.token_type.clone(), token.lexeme.as_str()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (TokenType::Fn, "fn"),
+            (TokenType::Identifier, "main"),
+            (TokenType::LeftParen, "("),
+            (TokenType::RightParen, ")"),
+            (TokenType::LeftBrace, "{"),
+            (TokenType::Let, "let"),
+            (TokenType::Identifier, "x"),
+            (TokenType::Equal, "="),
+            (TokenType::Number, "42"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::RightBrace, "}"),
+            (TokenType::EOF, ""),
+        ]
+    );
+}
@@ -0,0 +1,256 @@
+use std::mem;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Arc;
+
+/// Header placed before the elements of every array allocation.
+///
+/// Codegen addresses fields through the `ARRAY_*_OFFSET` constants rather
+/// than assuming a layout, so the header can grow without touching it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArrayHeader {
+    pub len: usize,
+    pub capacity: usize,
+    /// Distance in bytes between consecutive elements (size rounded up to
+    /// the element's alignment)
+    pub stride: u32,
+    pub flags: u32,
+}
+
+pub const ARRAY_LEN_OFFSET: usize = 0;
+pub const ARRAY_CAPACITY_OFFSET: usize = mem::size_of::<usize>();
+pub const ARRAY_STRIDE_OFFSET: usize = 2 * mem::size_of::<usize>();
+pub const ARRAY_FLAGS_OFFSET: usize = ARRAY_STRIDE_OFFSET + mem::size_of::<u32>();
+/// Offset of the first element from the start of the allocation
+pub const ARRAY_DATA_OFFSET: usize = mem::size_of::<ArrayHeader>();
+
+/// The array is shared and must be copied before mutation
+pub const ARRAY_FLAG_COPY_ON_WRITE: u32 = 1 << 0;
+
+impl ArrayHeader {
+    pub fn new<T>(capacity: usize) -> Self {
+        ArrayHeader {
+            len: 0,
+            capacity,
+            stride: element_stride::<T>() as u32,
+            flags: 0,
+        }
+    }
+
+    /// Total allocation size for the header plus `capacity` elements
+    pub fn allocation_size(&self) -> usize {
+        ARRAY_DATA_OFFSET + self.capacity * self.stride as usize
+    }
+
+    pub fn is_copy_on_write(&self) -> bool {
+        self.flags & ARRAY_FLAG_COPY_ON_WRITE != 0
+    }
+}
+
+/// Element stride for `T`: its size rounded up to its alignment
+pub fn element_stride<T>() -> usize {
+    let size = mem::size_of::<T>();
+    let align = mem::align_of::<T>();
+    (size + align - 1) / align * align
+}
+
+/// How capacity grows when an append does not fit
+#[derive(Debug, Clone, Copy)]
+pub struct GrowthPolicy {
+    pub min_capacity: usize,
+    /// Below this many bytes capacity doubles; above it grows by half, to
+    /// limit wasted memory for large arrays
+    pub doubling_limit_bytes: usize,
+}
+
+impl Default for GrowthPolicy {
+    fn default() -> Self {
+        GrowthPolicy {
+            min_capacity: 4,
+            doubling_limit_bytes: 1024 * 1024,
+        }
+    }
+}
+
+impl GrowthPolicy {
+    pub fn next_capacity(&self, current: usize, required: usize, stride: usize) -> usize {
+        if required <= current {
+            return current;
+        }
+
+        let grown = if current * stride.max(1) < self.doubling_limit_bytes {
+            current.saturating_mul(2)
+        } else {
+            current.saturating_add(current / 2)
+        };
+
+        grown.max(required).max(self.min_capacity)
+    }
+}
+
+/// Called on an out-of-bounds access with `(index, len)`. Must not return.
+pub type BoundsCheckHook = fn(usize, usize) -> !;
+
+fn default_bounds_hook(index: usize, len: usize) -> ! {
+    panic!("index out of bounds: the len is {} but the index is {}", len, index)
+}
+
+static BOUNDS_HOOK: AtomicPtr<()> = AtomicPtr::new(default_bounds_hook as *mut ());
+
+/// Replace the handler invoked on failed bounds checks, e.g. so the VM can
+/// raise a catchable language-level error with a stack trace
+pub fn set_bounds_check_hook(hook: BoundsCheckHook) {
+    BOUNDS_HOOK.store(hook as *mut (), Ordering::SeqCst);
+}
+
+/// Entry point for failed bounds checks from codegen and the VM
+#[cold]
+pub fn bounds_check_failed(index: usize, len: usize) -> ! {
+    let hook = BOUNDS_HOOK.load(Ordering::SeqCst);
+    // Only ever stores `BoundsCheckHook` values
+    let hook: BoundsCheckHook = unsafe { mem::transmute(hook) };
+    hook(index, len)
+}
+
+/// Array storage used by the VM
+pub struct RtArray<T> {
+    storage: Storage<T>,
+    policy: GrowthPolicy,
+}
+
+enum Storage<T> {
+    Unique(Vec<T>),
+    /// Shared between tasks; copied on first mutation
+    Shared(Arc<Vec<T>>),
+}
+
+impl<T: Clone> RtArray<T> {
+    pub fn new() -> Self {
+        RtArray::with_policy(GrowthPolicy::default())
+    }
+
+    pub fn with_policy(policy: GrowthPolicy) -> Self {
+        RtArray {
+            storage: Storage::Unique(Vec::new()),
+            policy,
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        RtArray {
+            storage: Storage::Unique(Vec::with_capacity(capacity)),
+            policy: GrowthPolicy::default(),
+        }
+    }
+
+    fn items(&self) -> &Vec<T> {
+        match &self.storage {
+            Storage::Unique(items) => items,
+            Storage::Shared(items) => items,
+        }
+    }
+
+    fn items_mut(&mut self) -> &mut Vec<T> {
+        match &mut self.storage {
+            Storage::Unique(items) => items,
+            Storage::Shared(items) => Arc::make_mut(items),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items().is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.items().capacity()
+    }
+
+    pub fn header(&self) -> ArrayHeader {
+        ArrayHeader {
+            len: self.len(),
+            capacity: self.capacity(),
+            stride: element_stride::<T>() as u32,
+            flags: if self.is_shared() { ARRAY_FLAG_COPY_ON_WRITE } else { 0 },
+        }
+    }
+
+    /// Bounds-checked read
+    pub fn get(&self, index: usize) -> &T {
+        let items = self.items();
+        match items.get(index) {
+            Some(item) => item,
+            None => bounds_check_failed(index, items.len()),
+        }
+    }
+
+    /// Bounds-checked write; copies shared storage first
+    pub fn set(&mut self, index: usize, value: T) {
+        let len = self.len();
+        if index >= len {
+            bounds_check_failed(index, len);
+        }
+        self.items_mut()[index] = value;
+    }
+
+    pub fn push(&mut self, value: T) {
+        let policy = self.policy;
+        let items = self.items_mut();
+        if items.len() == items.capacity() {
+            let target = policy.next_capacity(items.capacity(), items.len() + 1, element_stride::<T>());
+            items.reserve_exact(target - items.len());
+        }
+        items.push(value);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.items_mut().pop()
+    }
+
+    /// Remove all elements, keeping the capacity unless the storage is
+    /// shared, so a reused array does not allocate again
+    pub fn clear(&mut self) {
+        match &mut self.storage {
+            Storage::Unique(items) => items.clear(),
+            Storage::Shared(_) => self.storage = Storage::Unique(Vec::new()),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.items().iter()
+    }
+
+    /// Switch to copy-on-write mode and return a handle sharing the same
+    /// elements, for passing the array to another task without copying
+    pub fn share(&mut self) -> RtArray<T> {
+        if let Storage::Unique(items) = &mut self.storage {
+            let items = mem::take(items);
+            self.storage = Storage::Shared(Arc::new(items));
+        }
+
+        match &self.storage {
+            Storage::Shared(items) => RtArray {
+                storage: Storage::Shared(Arc::clone(items)),
+                policy: self.policy,
+            },
+            Storage::Unique(_) => unreachable!(),
+        }
+    }
+
+    /// Whether a mutation would currently copy the elements
+    pub fn is_shared(&self) -> bool {
+        match &self.storage {
+            Storage::Unique(_) => false,
+            Storage::Shared(items) => Arc::strong_count(items) > 1,
+        }
+    }
+}
+
+impl<T: Clone> Default for RtArray<T> {
+    fn default() -> Self {
+        RtArray::new()
+    }
+}
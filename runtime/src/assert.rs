@@ -0,0 +1,151 @@
+use std::ffi::CStr;
+use std::fmt;
+use std::mem;
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::string::ZString;
+
+/// Value of a subexpression of a failed assertion
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssertValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(ZString),
+}
+
+impl fmt::Display for AssertValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssertValue::Int(value) => write!(f, "{}", value),
+            AssertValue::Float(value) => write!(f, "{:?}", value),
+            AssertValue::Bool(value) => write!(f, "{}", value),
+            AssertValue::Str(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+/// A failed `assert` or `debug_assert`, raised the same way by the VM and
+/// compiled code
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertionFailure {
+    pub debug: bool,
+    /// The condition as the compiler printed it
+    pub expression: String,
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    pub message: Option<ZString>,
+    /// Subexpressions and their values, in source order
+    pub values: Vec<(String, AssertValue)>,
+}
+
+impl fmt::Display for AssertionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.debug { "debug assertion" } else { "assertion" };
+        write!(f, "{} failed: `{}`", kind, self.expression)?;
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+        write!(f, "\n  --> {}:{}:{}", self.file, self.line, self.column)?;
+        for (expression, value) in &self.values {
+            write!(f, "\n  {} = {}", expression, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Called with a failed assertion. Must not return.
+pub type AssertHook = fn(&AssertionFailure) -> !;
+
+fn default_assert_hook(failure: &AssertionFailure) -> ! {
+    panic!("{}", failure)
+}
+
+static ASSERT_HOOK: AtomicPtr<()> = AtomicPtr::new(default_assert_hook as *mut ());
+
+/// Replace the handler invoked on failed assertions, e.g. so the VM can
+/// raise a language-level error with a stack trace
+pub fn set_assert_hook(hook: AssertHook) {
+    ASSERT_HOOK.store(hook as *mut (), Ordering::SeqCst);
+}
+
+/// Entry point for failed assertions from the VM; compiled code arrives
+/// through `zaitun_assert_failed`
+#[cold]
+pub fn assertion_failed(failure: &AssertionFailure) -> ! {
+    let hook = ASSERT_HOOK.load(Ordering::SeqCst);
+    // Only ever stores `AssertHook` values
+    let hook: AssertHook = unsafe { mem::transmute(hook) };
+    hook(failure)
+}
+
+/// Constant data codegen emits for each assertion;
+/// `%zaitun.assert_info` in compiled code
+#[repr(C)]
+pub struct AssertInfo {
+    /// 0 for `assert`, 1 for `debug_assert`
+    pub kind: u8,
+    pub expression: *const c_char,
+    pub file: *const c_char,
+    pub line: u32,
+    pub column: u32,
+    /// `count` NUL-terminated subexpression texts
+    pub names: *const *const c_char,
+    pub count: u32,
+}
+
+/// A subexpression's value as compiled code passes it;
+/// `%zaitun.assert_value`. `bits` holds an `i64`, the bits of a `double`,
+/// a bool as 0 or 1, or a pointer to a `ZString`, as `tag` says.
+#[repr(C)]
+pub struct RawAssertValue {
+    pub tag: u8,
+    pub bits: u64,
+}
+
+impl RawAssertValue {
+    unsafe fn decode(&self) -> AssertValue {
+        match self.tag {
+            0 => AssertValue::Int(self.bits as i64),
+            1 => AssertValue::Float(f64::from_bits(self.bits)),
+            2 => AssertValue::Bool(self.bits != 0),
+            _ => AssertValue::Str(unsafe { &*(self.bits as usize as *const ZString) }.clone()),
+        }
+    }
+}
+
+/// Called by compiled code when an assertion fails
+///
+/// # Safety
+///
+/// `info` must point to the assertion's `AssertInfo`, `values` to its
+/// `count` values (or be null when there are none) and `message` to a
+/// `ZString` or be null.
+#[no_mangle]
+pub unsafe extern "C" fn zaitun_assert_failed(
+    info: *const AssertInfo,
+    values: *const RawAssertValue,
+    message: *const ZString,
+) -> ! {
+    let info = unsafe { &*info };
+    let text = |ptr: *const c_char| unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned();
+
+    let mut captured = Vec::with_capacity(info.count as usize);
+    for index in 0..info.count as usize {
+        let name = text(unsafe { *info.names.add(index) });
+        let value = unsafe { (*values.add(index)).decode() };
+        captured.push((name, value));
+    }
+
+    assertion_failed(&AssertionFailure {
+        debug: info.kind == 1,
+        expression: text(info.expression),
+        file: text(info.file),
+        line: info.line,
+        column: info.column,
+        message: unsafe { message.as_ref() }.cloned(),
+        values: captured,
+    })
+}
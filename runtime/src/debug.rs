@@ -0,0 +1,178 @@
+//! Debug hooks for interpreted code.
+//!
+//! A VM run with hooks calls `DebugHooks::on_statement` before each
+//! statement with its location and call depth. The hooks decide with a
+//! `Stepper` whether to stop there; stopping is simply not returning until
+//! the user resumes, whether the user is at the REPL prompt or in an editor
+//! speaking the debug adapter protocol. The stepper holds what both need:
+//! breakpoints and step-in, step-over and step-out.
+
+use std::fmt;
+
+/// Where a statement starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub line: u32,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// Called by the VM as it runs
+pub trait DebugHooks {
+    /// Before each statement; `depth` is the number of calls in progress,
+    /// 0 at the top level. Returns when execution should go on.
+    fn on_statement(&mut self, location: &Location, depth: usize);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub id: u32,
+    /// As given; matches any path ending in it
+    pub file: String,
+    pub line: u32,
+    pub hits: u32,
+}
+
+impl Breakpoint {
+    fn matches(&self, location: &Location) -> bool {
+        if location.line != self.line {
+            return false;
+        }
+        // `main.zt` matches `src/main.zt` but not `src/domain.zt`
+        match location.file.strip_suffix(self.file.as_str()) {
+            Some(prefix) => prefix.is_empty() || prefix.ends_with('/') || prefix.ends_with('\\'),
+            None => false,
+        }
+    }
+}
+
+/// How to go on after a stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepMode {
+    /// Until a breakpoint
+    Continue,
+    /// To the next statement, entering calls
+    StepIn,
+    /// To the next statement in this function or its caller
+    StepOver,
+    /// To the next statement in the caller
+    StepOut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(u32),
+    Step,
+}
+
+/// Breakpoints and stepping state for one debugging session
+#[derive(Debug)]
+pub struct Stepper {
+    breakpoints: Vec<Breakpoint>,
+    next_id: u32,
+    mode: StepMode,
+    /// Call depth of the last stop, which steps are relative to
+    depth: usize,
+    /// The last stop, so the statements of one line stop only once
+    stopped_at: Option<(Location, usize)>,
+}
+
+impl Stepper {
+    pub fn new() -> Self {
+        Stepper {
+            breakpoints: Vec::new(),
+            next_id: 1,
+            mode: StepMode::Continue,
+            depth: 0,
+            stopped_at: None,
+        }
+    }
+
+    /// Returns the breakpoint's id
+    pub fn add_breakpoint(&mut self, file: &str, line: u32) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.push(Breakpoint {
+            id,
+            file: file.to_string(),
+            line,
+            hits: 0,
+        });
+        id
+    }
+
+    /// Returns whether there was such a breakpoint
+    pub fn remove_breakpoint(&mut self, id: u32) -> bool {
+        let before = self.breakpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        self.breakpoints.len() != before
+    }
+
+    /// Drop every breakpoint in `file`, for debug adapters, which send a
+    /// file's whole set each time it changes
+    pub fn clear_breakpoints(&mut self, file: &str) {
+        self.breakpoints.retain(|breakpoint| breakpoint.file != file);
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Set how execution goes on from the last stop, or from the start
+    /// when nothing has run yet
+    pub fn resume(&mut self, mode: StepMode) {
+        self.mode = mode;
+    }
+
+    /// Forget the last stop when a new program or REPL input starts, so
+    /// its first line can stop again
+    pub fn reset(&mut self) {
+        self.depth = 0;
+        self.stopped_at = None;
+    }
+
+    /// Whether to stop before the statement at `location`. Stopping
+    /// switches back to `StepMode::Continue` until the next `resume`.
+    pub fn check(&mut self, location: &Location, depth: usize) -> Option<StopReason> {
+        if let Some((at, at_depth)) = &self.stopped_at {
+            if at == location && *at_depth == depth {
+                return None;
+            }
+        }
+        self.stopped_at = None;
+
+        let reason = match self.breakpoints.iter_mut().find(|breakpoint| breakpoint.matches(location)) {
+            Some(breakpoint) => {
+                breakpoint.hits += 1;
+                Some(StopReason::Breakpoint(breakpoint.id))
+            }
+            None => {
+                let stepped = match self.mode {
+                    StepMode::Continue => false,
+                    StepMode::StepIn => true,
+                    StepMode::StepOver => depth <= self.depth,
+                    StepMode::StepOut => depth < self.depth,
+                };
+                stepped.then_some(StopReason::Step)
+            }
+        };
+
+        if reason.is_some() {
+            self.mode = StepMode::Continue;
+            self.depth = depth;
+            self.stopped_at = Some((location.clone(), depth));
+        }
+        reason
+    }
+}
+
+impl Default for Stepper {
+    fn default() -> Self {
+        Stepper::new()
+    }
+}
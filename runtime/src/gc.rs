@@ -60,6 +60,39 @@ impl ManagedObject {
     }
 }
 
+/// Implemented by runtime values that can hold references to managed
+/// objects, so the mark phase can follow them
+pub trait Trace {
+    fn trace(&self, tracer: &mut Tracer);
+}
+
+/// Collects the ids of objects reached during the mark phase
+pub struct Tracer {
+    reached: Vec<usize>,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Tracer {
+            reached: Vec::new(),
+        }
+    }
+    
+    pub fn mark<T>(&mut self, ptr: &GcPtr<T>) {
+        self.reached.push(ptr.id);
+    }
+    
+    pub fn reached(&self) -> &[usize] {
+        &self.reached
+    }
+}
+
+impl<T> Trace for GcPtr<T> {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.mark(self);
+    }
+}
+
 pub struct GcPtr<T> {
     id: usize,
     _phantom: std::marker::PhantomData<T>,
@@ -0,0 +1,300 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, BuildHasherDefault, Hash};
+
+use crate::gc::{Trace, Tracer};
+
+/// Fixed-key SipHash, used when iteration order must be reproducible
+pub type DeterministicState = BuildHasherDefault<DefaultHasher>;
+
+/// Order in which `RtMap::iter` yields entries
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IterationOrder {
+    /// Table order. Varies between runs with the default random keys.
+    Unspecified,
+    /// Insertion order, stable across runs. Used for reproducible builds
+    /// and tests.
+    Insertion,
+}
+
+const EMPTY: u32 = u32::MAX;
+const MIN_SLOTS: usize = 8;
+
+#[derive(Clone, Copy)]
+struct Slot {
+    /// Index into `entries`, or `EMPTY`
+    index: u32,
+    /// Distance from the slot the hash maps to
+    dist: u32,
+}
+
+impl Slot {
+    const VACANT: Slot = Slot { index: EMPTY, dist: 0 };
+
+    fn is_empty(&self) -> bool {
+        self.index == EMPTY
+    }
+}
+
+struct Entry<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+/// Runtime implementation of the language-level `Map` type.
+///
+/// Open addressing with robin-hood probing over a table of indices; the
+/// entries themselves live densely in insertion order, with removed
+/// entries left as holes until the next compaction.
+pub struct RtMap<K, V, S = RandomState> {
+    slots: Vec<Slot>,
+    entries: Vec<Option<Entry<K, V>>>,
+    len: usize,
+    hash_builder: S,
+    order: IterationOrder,
+}
+
+impl<K: Hash + Eq, V> RtMap<K, V, RandomState> {
+    pub fn new() -> Self {
+        RtMap::with_hasher(RandomState::new(), IterationOrder::Unspecified)
+    }
+}
+
+impl<K: Hash + Eq, V> RtMap<K, V, DeterministicState> {
+    /// Map with fixed hash keys and insertion-order iteration
+    pub fn deterministic() -> Self {
+        RtMap::with_hasher(DeterministicState::default(), IterationOrder::Insertion)
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> RtMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S, order: IterationOrder) -> Self {
+        RtMap {
+            slots: Vec::new(),
+            entries: Vec::new(),
+            len: 0,
+            hash_builder,
+            order,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn hash_key<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.hash_builder.hash_one(key)
+    }
+
+    fn mask(&self) -> usize {
+        self.slots.len() - 1
+    }
+
+    /// Slot position holding `key`, if present
+    fn find_slot<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.slots.is_empty() {
+            return None;
+        }
+
+        let hash = self.hash_key(key);
+        let mask = self.mask();
+        let mut pos = hash as usize & mask;
+        let mut dist = 0;
+
+        loop {
+            let slot = self.slots[pos];
+            // Robin-hood invariant: the key would have displaced any entry
+            // closer to its home slot than we are to ours
+            if slot.is_empty() || (slot.dist as usize) < dist {
+                return None;
+            }
+
+            if let Some(entry) = &self.entries[slot.index as usize] {
+                if entry.hash == hash && entry.key.borrow() == key {
+                    return Some(pos);
+                }
+            }
+
+            pos = (pos + 1) & mask;
+            dist += 1;
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let pos = self.find_slot(key)?;
+        self.entries[self.slots[pos].index as usize].as_ref().map(|e| &e.value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let pos = self.find_slot(key)?;
+        let index = self.slots[pos].index as usize;
+        self.entries[index].as_mut().map(|e| &mut e.value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find_slot(key).is_some()
+    }
+
+    /// Insert or replace, returning the previous value
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(pos) = self.find_slot(&key) {
+            let index = self.slots[pos].index as usize;
+            let entry = self.entries[index].as_mut().expect("slot points at live entry");
+            return Some(std::mem::replace(&mut entry.value, value));
+        }
+
+        // Keep the load factor at or below 7/8
+        if (self.len + 1) * 8 > self.slots.len() * 7 {
+            self.grow();
+        }
+
+        let hash = self.hash_key(&key);
+        let index = self.entries.len();
+        self.entries.push(Some(Entry { hash, key, value }));
+        self.len += 1;
+        self.place(hash, index as u32);
+
+        None
+    }
+
+    /// Robin-hood placement: take slots from entries that are closer to home
+    fn place(&mut self, hash: u64, index: u32) {
+        let mask = self.mask();
+        let mut pos = hash as usize & mask;
+        let mut carried = Slot { index, dist: 0 };
+
+        loop {
+            let slot = self.slots[pos];
+            if slot.is_empty() {
+                self.slots[pos] = carried;
+                return;
+            }
+
+            if slot.dist < carried.dist {
+                self.slots[pos] = carried;
+                carried = slot;
+            }
+
+            pos = (pos + 1) & mask;
+            carried.dist += 1;
+        }
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut pos = self.find_slot(key)?;
+        let index = self.slots[pos].index as usize;
+        let entry = self.entries[index].take()?;
+        self.len -= 1;
+
+        // Backward-shift deletion keeps probe sequences short without
+        // tombstones in the table
+        let mask = self.mask();
+        loop {
+            let next = (pos + 1) & mask;
+            let slot = self.slots[next];
+            if slot.is_empty() || slot.dist == 0 {
+                self.slots[pos] = Slot::VACANT;
+                break;
+            }
+            self.slots[pos] = Slot { index: slot.index, dist: slot.dist - 1 };
+            pos = next;
+        }
+
+        // Reclaim holes once they make up half of the entry storage
+        if self.entries.len() >= MIN_SLOTS && self.len * 2 < self.entries.len() {
+            self.rebuild(self.slots.len());
+        }
+
+        Some(entry.value)
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.entries.clear();
+        self.len = 0;
+    }
+
+    fn grow(&mut self) {
+        let slots = (self.slots.len() * 2).max(MIN_SLOTS);
+        self.rebuild(slots);
+    }
+
+    /// Compact entries and re-place them in a table of `slot_count` slots
+    fn rebuild(&mut self, slot_count: usize) {
+        self.entries.retain(|entry| entry.is_some());
+        self.slots = vec![Slot::VACANT; slot_count];
+
+        for index in 0..self.entries.len() {
+            let hash = self.entries[index].as_ref().map(|e| e.hash).unwrap_or_default();
+            self.place(hash, index as u32);
+        }
+    }
+
+    /// Iterate entries in the map's configured order
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&K, &V)> + '_> {
+        match self.order {
+            IterationOrder::Insertion => Box::new(
+                self.entries.iter().flatten().map(|e| (&e.key, &e.value)),
+            ),
+            IterationOrder::Unspecified => Box::new(
+                self.slots.iter()
+                    .filter(|slot| !slot.is_empty())
+                    .filter_map(move |slot| self.entries[slot.index as usize].as_ref())
+                    .map(|e| (&e.key, &e.value)),
+            ),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// Longest probe distance in the table, exposed for tuning and tests
+    pub fn max_probe_distance(&self) -> usize {
+        self.slots.iter().filter(|s| !s.is_empty()).map(|s| s.dist as usize).max().unwrap_or(0)
+    }
+}
+
+impl<K: Hash + Eq, V> Default for RtMap<K, V, RandomState> {
+    fn default() -> Self {
+        RtMap::new()
+    }
+}
+
+impl<K: Trace, V: Trace, S> Trace for RtMap<K, V, S> {
+    fn trace(&self, tracer: &mut Tracer) {
+        for entry in self.entries.iter().flatten() {
+            entry.key.trace(tracer);
+            entry.value.trace(tracer);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Lazy initialization of `static` items.
+//!
+//! Statics the compiler cannot evaluate at compile time are initialized on
+//! first access. Compiled code calls `zaitun_static_init` with the static's
+//! guard and init function before every access; the VM uses `LazyStatic`
+//! directly. Either way the initializer runs exactly once, other threads
+//! wait for it, and a static reached again from its own initializer, i.e. a
+//! cycle through function calls the compiler could not see, is reported
+//! instead of deadlocking.
+
+use std::cell::Cell;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+
+const UNINIT: u8 = 0;
+const RUNNING: u8 = 1;
+const DONE: u8 = 2;
+
+/// Id of the current thread, never 0
+fn current_thread() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static ID: Cell<u64> = const { Cell::new(0) };
+    }
+
+    ID.with(|id| {
+        if id.get() == 0 {
+            id.set(NEXT.fetch_add(1, Ordering::Relaxed));
+        }
+        id.get()
+    })
+}
+
+/// Init state of one lazy static; `{ i8, i64 }` in compiled code
+#[repr(C)]
+pub struct StaticGuard {
+    state: AtomicU8,
+    /// Thread running the initializer while `state` is `RUNNING`
+    owner: AtomicU64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitCycle {
+    pub static_name: String,
+}
+
+impl fmt::Display for InitCycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "static `{}` was accessed during its own initialization", self.static_name)
+    }
+}
+
+impl std::error::Error for InitCycle {}
+
+impl StaticGuard {
+    pub const fn new() -> Self {
+        StaticGuard {
+            state: AtomicU8::new(UNINIT),
+            owner: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.state.load(Ordering::Acquire) == DONE
+    }
+
+    /// Run `init` unless it already ran. Returns `false` without running
+    /// anything when called again from inside `init` on the same thread.
+    pub fn run_once(&self, init: impl FnOnce()) -> bool {
+        loop {
+            match self.state.compare_exchange(UNINIT, RUNNING, Ordering::Acquire, Ordering::Acquire) {
+                Ok(_) => {
+                    self.owner.store(current_thread(), Ordering::Relaxed);
+                    let reset = ResetOnPanic(self);
+                    init();
+                    std::mem::forget(reset);
+                    self.owner.store(0, Ordering::Relaxed);
+                    self.state.store(DONE, Ordering::Release);
+                    return true;
+                }
+                Err(DONE) => return true,
+                Err(_) if self.owner.load(Ordering::Relaxed) == current_thread() => return false,
+                // Another thread is initializing; initializers are short
+                Err(_) => thread::yield_now(),
+            }
+        }
+    }
+}
+
+/// Lets the next access retry when an initializer panics, instead of
+/// leaving waiting threads spinning forever
+struct ResetOnPanic<'a>(&'a StaticGuard);
+
+impl Drop for ResetOnPanic<'_> {
+    fn drop(&mut self) {
+        self.0.owner.store(0, Ordering::Relaxed);
+        self.0.state.store(UNINIT, Ordering::Release);
+    }
+}
+
+impl Default for StaticGuard {
+    fn default() -> Self {
+        StaticGuard::new()
+    }
+}
+
+/// Called by compiled code before each access to a lazy static
+///
+/// # Safety
+///
+/// `guard` must point to the static's guard and `name` to a NUL-terminated
+/// string, both valid for the whole program.
+#[no_mangle]
+pub unsafe extern "C" fn zaitun_static_init(guard: *const StaticGuard, init: extern "C" fn(), name: *const u8) {
+    if unsafe { &*guard }.run_once(|| init()) {
+        return;
+    }
+
+    let name = unsafe { std::ffi::CStr::from_ptr(name.cast()) }.to_string_lossy().into_owned();
+    eprintln!("fatal: {}", InitCycle { static_name: name });
+    std::process::abort();
+}
+
+/// A lazily initialized static for the VM
+pub struct LazyStatic<T> {
+    name: String,
+    guard: StaticGuard,
+    value: OnceLock<T>,
+}
+
+impl<T> LazyStatic<T> {
+    pub fn new(name: &str) -> Self {
+        LazyStatic {
+            name: name.to_string(),
+            guard: StaticGuard::new(),
+            value: OnceLock::new(),
+        }
+    }
+
+    /// The value, running `init` on first access
+    pub fn get_or_init(&self, init: impl FnOnce() -> T) -> Result<&T, InitCycle> {
+        let ran = self.guard.run_once(|| {
+            let _ = self.value.set(init());
+        });
+        if !ran {
+            return Err(InitCycle {
+                static_name: self.name.clone(),
+            });
+        }
+        Ok(self.value.get().expect("static initialized"))
+    }
+}
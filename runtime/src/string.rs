@@ -0,0 +1,309 @@
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Strings up to this many bytes are stored inline without allocating
+pub const INLINE_CAPACITY: usize = 22;
+
+/// Runtime string used by the VM and compiled code.
+///
+/// Always valid UTF-8. Short strings live inline; longer ones point into a
+/// shared immutable buffer, so slicing and cloning never copy. Heap buffers
+/// contain no references to other managed objects, which lets the collector
+/// treat them as leaves instead of scanning their contents.
+#[derive(Clone)]
+pub struct ZString {
+    repr: Repr,
+}
+
+#[derive(Clone)]
+enum Repr {
+    Inline {
+        len: u8,
+        bytes: [u8; INLINE_CAPACITY],
+    },
+    Heap {
+        buffer: Arc<[u8]>,
+        start: usize,
+        len: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZStringError {
+    InvalidUtf8 { valid_up_to: usize },
+    NotCharBoundary(usize),
+    OutOfBounds { index: usize, len: usize },
+}
+
+impl fmt::Display for ZStringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZStringError::InvalidUtf8 { valid_up_to } => {
+                write!(f, "Invalid UTF-8 after byte {}", valid_up_to)
+            }
+            ZStringError::NotCharBoundary(index) => {
+                write!(f, "Byte index {} is not a character boundary", index)
+            }
+            ZStringError::OutOfBounds { index, len } => {
+                write!(f, "Byte index {} out of bounds for string of length {}", index, len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ZStringError {}
+
+impl ZString {
+    pub fn new() -> Self {
+        ZString {
+            repr: Repr::Inline {
+                len: 0,
+                bytes: [0; INLINE_CAPACITY],
+            },
+        }
+    }
+
+    /// Validate and take ownership of raw bytes
+    pub fn from_utf8(bytes: Vec<u8>) -> Result<Self, ZStringError> {
+        match std::str::from_utf8(&bytes) {
+            Ok(_) => Ok(ZString::from_valid_bytes(&bytes)),
+            Err(e) => Err(ZStringError::InvalidUtf8 {
+                valid_up_to: e.valid_up_to(),
+            }),
+        }
+    }
+
+    fn from_valid_bytes(bytes: &[u8]) -> Self {
+        if bytes.len() <= INLINE_CAPACITY {
+            let mut inline = [0; INLINE_CAPACITY];
+            inline[..bytes.len()].copy_from_slice(bytes);
+            ZString {
+                repr: Repr::Inline {
+                    len: bytes.len() as u8,
+                    bytes: inline,
+                },
+            }
+        } else {
+            ZString {
+                repr: Repr::Heap {
+                    buffer: Arc::from(bytes),
+                    start: 0,
+                    len: bytes.len(),
+                },
+            }
+        }
+    }
+
+    /// Length in bytes, O(1)
+    pub fn len(&self) -> usize {
+        match &self.repr {
+            Repr::Inline { len, .. } => *len as usize,
+            Repr::Heap { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the contents are stored inline (no heap buffer)
+    pub fn is_inline(&self) -> bool {
+        matches!(self.repr, Repr::Inline { .. })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.repr {
+            Repr::Inline { len, bytes } => &bytes[..*len as usize],
+            Repr::Heap { buffer, start, len } => &buffer[*start..*start + *len],
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Every constructor validates UTF-8 and slicing checks boundaries
+        unsafe { std::str::from_utf8_unchecked(self.as_bytes()) }
+    }
+
+    /// Number of Unicode scalar values, O(n)
+    pub fn char_count(&self) -> usize {
+        self.as_str().chars().count()
+    }
+
+    /// Substring by byte range. Heap strings share the parent's buffer;
+    /// results short enough to inline are copied so the parent can be freed.
+    pub fn slice(&self, range: Range<usize>) -> Result<ZString, ZStringError> {
+        let len = self.len();
+        for index in [range.start, range.end] {
+            if index > len {
+                return Err(ZStringError::OutOfBounds { index, len });
+            }
+            if !self.as_str().is_char_boundary(index) {
+                return Err(ZStringError::NotCharBoundary(index));
+            }
+        }
+        if range.start > range.end {
+            return Err(ZStringError::OutOfBounds { index: range.start, len: range.end });
+        }
+
+        let sub_len = range.end - range.start;
+        match &self.repr {
+            Repr::Heap { buffer, start, .. } if sub_len > INLINE_CAPACITY => Ok(ZString {
+                repr: Repr::Heap {
+                    buffer: Arc::clone(buffer),
+                    start: start + range.start,
+                    len: sub_len,
+                },
+            }),
+            _ => Ok(ZString::from_valid_bytes(&self.as_bytes()[range])),
+        }
+    }
+
+    /// Concatenate into a new string
+    pub fn concat(&self, other: &ZString) -> ZString {
+        if other.is_empty() {
+            return self.clone();
+        }
+        if self.is_empty() {
+            return other.clone();
+        }
+
+        let mut bytes = Vec::with_capacity(self.len() + other.len());
+        bytes.extend_from_slice(self.as_bytes());
+        bytes.extend_from_slice(other.as_bytes());
+        ZString::from_valid_bytes(&bytes)
+    }
+
+    /// Replace the contents with `left` followed by `right`, writing into
+    /// this string's heap buffer when it is large enough and not shared.
+    /// Used by loops whose temporary strings the optimizer proved do not
+    /// outlive an iteration.
+    pub fn concat_into(&mut self, left: &ZString, right: &ZString) {
+        let len = left.len() + right.len();
+        if len > INLINE_CAPACITY {
+            if let Repr::Heap { buffer, start, len: current } = &mut self.repr {
+                if let Some(bytes) = Arc::get_mut(buffer).filter(|bytes| bytes.len() >= len) {
+                    bytes[..left.len()].copy_from_slice(left.as_bytes());
+                    bytes[left.len()..len].copy_from_slice(right.as_bytes());
+                    *start = 0;
+                    *current = len;
+                    return;
+                }
+            }
+        }
+        *self = left.concat(right);
+    }
+
+    /// Bytes kept alive by this string, including unreferenced parts of a
+    /// shared buffer. Used by the collector's allocation accounting.
+    pub fn retained_bytes(&self) -> usize {
+        match &self.repr {
+            Repr::Inline { .. } => 0,
+            Repr::Heap { buffer, .. } => buffer.len(),
+        }
+    }
+
+    /// Copy a small slice out of a large shared buffer so the buffer can be
+    /// released
+    pub fn compact(&self) -> ZString {
+        match &self.repr {
+            Repr::Heap { buffer, len, .. } if *len < buffer.len() => {
+                ZString::from_valid_bytes(self.as_bytes())
+            }
+            _ => self.clone(),
+        }
+    }
+}
+
+impl Default for ZString {
+    fn default() -> Self {
+        ZString::new()
+    }
+}
+
+impl From<&str> for ZString {
+    fn from(value: &str) -> Self {
+        ZString::from_valid_bytes(value.as_bytes())
+    }
+}
+
+impl From<String> for ZString {
+    fn from(value: String) -> Self {
+        if value.len() <= INLINE_CAPACITY {
+            ZString::from_valid_bytes(value.as_bytes())
+        } else {
+            let len = value.len();
+            ZString {
+                repr: Repr::Heap {
+                    buffer: Arc::from(value.into_bytes().into_boxed_slice()),
+                    start: 0,
+                    len,
+                },
+            }
+        }
+    }
+}
+
+impl From<ZString> for String {
+    fn from(value: ZString) -> Self {
+        value.as_str().to_string()
+    }
+}
+
+impl AsRef<str> for ZString {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for ZString {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for ZString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl Eq for ZString {}
+
+impl PartialEq<str> for ZString {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialOrd for ZString {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ZString {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_bytes().cmp(other.as_bytes())
+    }
+}
+
+impl Hash for ZString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Must agree with `str`'s hash for `Borrow<str>` lookups
+        self.as_str().hash(state);
+    }
+}
+
+impl fmt::Display for ZString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for ZString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
@@ -0,0 +1,487 @@
+//! Fast non-cryptographic checksums for cache keys, fingerprints and
+//! corruption detection. None of these resist deliberate collisions; use
+//! `crypto::Hash` where an attacker controls the input.
+
+use std::hash::{BuildHasher, Hasher};
+
+/// Common interface for streaming checksums
+pub trait Checksum {
+    type Output;
+
+    fn update(&mut self, data: &[u8]);
+
+    /// Checksum of everything passed to `update` so far. Does not reset
+    /// the state, so more data can follow.
+    fn finish(&self) -> Self::Output;
+
+    fn reset(&mut self);
+}
+
+// CRC32
+
+/// Build slicing-by-8 tables for a reflected CRC-32 polynomial
+const fn crc_tables(poly: u32) -> [[u32; 256]; 8] {
+    let mut tables = [[0u32; 256]; 8];
+
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ poly } else { crc >> 1 };
+            bit += 1;
+        }
+        tables[0][i] = crc;
+        i += 1;
+    }
+
+    let mut i = 0;
+    while i < 256 {
+        let mut t = 1;
+        while t < 8 {
+            let prev = tables[t - 1][i];
+            tables[t][i] = (prev >> 8) ^ tables[0][(prev & 0xff) as usize];
+            t += 1;
+        }
+        i += 1;
+    }
+
+    tables
+}
+
+static CRC32_TABLES: [[u32; 256]; 8] = crc_tables(0xedb8_8320);
+static CRC32C_TABLES: [[u32; 256]; 8] = crc_tables(0x82f6_3b78);
+
+fn crc_update_table(tables: &[[u32; 256]; 8], mut crc: u32, data: &[u8]) -> u32 {
+    let mut chunks = data.chunks_exact(8);
+
+    for chunk in &mut chunks {
+        let lo = crc ^ u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        let hi = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        crc = tables[7][(lo & 0xff) as usize]
+            ^ tables[6][((lo >> 8) & 0xff) as usize]
+            ^ tables[5][((lo >> 16) & 0xff) as usize]
+            ^ tables[4][(lo >> 24) as usize]
+            ^ tables[3][(hi & 0xff) as usize]
+            ^ tables[2][((hi >> 8) & 0xff) as usize]
+            ^ tables[1][((hi >> 16) & 0xff) as usize]
+            ^ tables[0][(hi >> 24) as usize];
+    }
+
+    for &byte in chunks.remainder() {
+        crc = (crc >> 8) ^ tables[0][((crc ^ byte as u32) & 0xff) as usize];
+    }
+
+    crc
+}
+
+/// CRC-32 (IEEE 802.3), as used by zip, gzip and PNG
+#[derive(Debug, Clone)]
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Crc32 { state: !0 }
+    }
+
+    pub fn checksum(data: &[u8]) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(data);
+        crc.finish()
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32::new()
+    }
+}
+
+impl Checksum for Crc32 {
+    type Output = u32;
+
+    fn update(&mut self, data: &[u8]) {
+        self.state = crc_update_table(&CRC32_TABLES, self.state, data);
+    }
+
+    fn finish(&self) -> u32 {
+        !self.state
+    }
+
+    fn reset(&mut self) {
+        self.state = !0;
+    }
+}
+
+/// CRC-32C (Castagnoli). Uses the SSE4.2 `crc32` instruction when the CPU
+/// has it, which makes it the fastest checksum here for large inputs.
+#[derive(Debug, Clone)]
+pub struct Crc32c {
+    state: u32,
+}
+
+impl Crc32c {
+    pub fn new() -> Self {
+        Crc32c { state: !0 }
+    }
+
+    pub fn checksum(data: &[u8]) -> u32 {
+        let mut crc = Crc32c::new();
+        crc.update(data);
+        crc.finish()
+    }
+}
+
+impl Default for Crc32c {
+    fn default() -> Self {
+        Crc32c::new()
+    }
+}
+
+impl Checksum for Crc32c {
+    type Output = u32;
+
+    fn update(&mut self, data: &[u8]) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("sse4.2") {
+                // Feature presence checked above
+                self.state = unsafe { crc32c_sse42(self.state, data) };
+                return;
+            }
+        }
+
+        self.state = crc_update_table(&CRC32C_TABLES, self.state, data);
+    }
+
+    fn finish(&self) -> u32 {
+        !self.state
+    }
+
+    fn reset(&mut self) {
+        self.state = !0;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+unsafe fn crc32c_sse42(crc: u32, data: &[u8]) -> u32 {
+    use std::arch::x86_64::{_mm_crc32_u64, _mm_crc32_u8};
+
+    let mut crc = crc as u64;
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let word = u64::from_le_bytes(chunk.try_into().expect("chunk of 8 bytes"));
+        crc = _mm_crc32_u64(crc, word);
+    }
+
+    let mut crc = crc as u32;
+    for &byte in chunks.remainder() {
+        crc = _mm_crc32_u8(crc, byte);
+    }
+    crc
+}
+
+// xxHash64
+
+const PRIME64_1: u64 = 0x9e37_79b1_85eb_ca87;
+const PRIME64_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const PRIME64_3: u64 = 0x1656_67b1_9e37_79f9;
+const PRIME64_4: u64 = 0x85eb_ca77_c2b2_ae63;
+const PRIME64_5: u64 = 0x27d4_eb2f_1656_67c5;
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().expect("at least 8 bytes"))
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().expect("at least 4 bytes"))
+}
+
+fn xxh64_round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME64_1)
+}
+
+fn xxh64_merge(acc: u64, val: u64) -> u64 {
+    (acc ^ xxh64_round(0, val))
+        .wrapping_mul(PRIME64_1)
+        .wrapping_add(PRIME64_4)
+}
+
+/// xxHash64 with streaming input. Output is stable across platforms and
+/// releases, so it is safe to persist in cache keys.
+#[derive(Debug, Clone)]
+pub struct XxHash64 {
+    seed: u64,
+    acc: [u64; 4],
+    buffer: [u8; 32],
+    buffered: usize,
+    total_len: u64,
+}
+
+impl XxHash64 {
+    pub fn new() -> Self {
+        XxHash64::with_seed(0)
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        XxHash64 {
+            seed,
+            acc: Self::initial_acc(seed),
+            buffer: [0; 32],
+            buffered: 0,
+            total_len: 0,
+        }
+    }
+
+    fn initial_acc(seed: u64) -> [u64; 4] {
+        [
+            seed.wrapping_add(PRIME64_1).wrapping_add(PRIME64_2),
+            seed.wrapping_add(PRIME64_2),
+            seed,
+            seed.wrapping_sub(PRIME64_1),
+        ]
+    }
+
+    pub fn checksum(data: &[u8]) -> u64 {
+        let mut hasher = XxHash64::new();
+        hasher.update(data);
+        Checksum::finish(&hasher)
+    }
+
+    fn consume_stripe(acc: &mut [u64; 4], stripe: &[u8]) {
+        for (i, lane) in acc.iter_mut().enumerate() {
+            *lane = xxh64_round(*lane, read_u64(&stripe[i * 8..]));
+        }
+    }
+}
+
+impl Default for XxHash64 {
+    fn default() -> Self {
+        XxHash64::new()
+    }
+}
+
+impl Checksum for XxHash64 {
+    type Output = u64;
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+
+        if self.buffered > 0 {
+            let take = (32 - self.buffered).min(data.len());
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+            self.buffered += take;
+            data = &data[take..];
+
+            if self.buffered < 32 {
+                return;
+            }
+            let buffer = self.buffer;
+            Self::consume_stripe(&mut self.acc, &buffer);
+            self.buffered = 0;
+        }
+
+        let mut stripes = data.chunks_exact(32);
+        for stripe in &mut stripes {
+            Self::consume_stripe(&mut self.acc, stripe);
+        }
+
+        let rest = stripes.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut hash = if self.total_len >= 32 {
+            let [a, b, c, d] = self.acc;
+            let mut hash = a.rotate_left(1)
+                .wrapping_add(b.rotate_left(7))
+                .wrapping_add(c.rotate_left(12))
+                .wrapping_add(d.rotate_left(18));
+            for lane in self.acc {
+                hash = xxh64_merge(hash, lane);
+            }
+            hash
+        } else {
+            self.seed.wrapping_add(PRIME64_5)
+        };
+
+        hash = hash.wrapping_add(self.total_len);
+
+        let mut tail = &self.buffer[..self.buffered];
+        while tail.len() >= 8 {
+            hash ^= xxh64_round(0, read_u64(tail));
+            hash = hash.rotate_left(27).wrapping_mul(PRIME64_1).wrapping_add(PRIME64_4);
+            tail = &tail[8..];
+        }
+        if tail.len() >= 4 {
+            hash ^= (read_u32(tail) as u64).wrapping_mul(PRIME64_1);
+            hash = hash.rotate_left(23).wrapping_mul(PRIME64_2).wrapping_add(PRIME64_3);
+            tail = &tail[4..];
+        }
+        for &byte in tail {
+            hash ^= (byte as u64).wrapping_mul(PRIME64_5);
+            hash = hash.rotate_left(11).wrapping_mul(PRIME64_1);
+        }
+
+        // Avalanche
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(PRIME64_2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(PRIME64_3);
+        hash ^= hash >> 32;
+        hash
+    }
+
+    fn reset(&mut self) {
+        *self = XxHash64::with_seed(self.seed);
+    }
+}
+
+impl Hasher for XxHash64 {
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        Checksum::finish(self)
+    }
+}
+
+/// `BuildHasher` producing seeded `XxHash64`s, for maps whose hashes must
+/// not vary between runs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XxHashBuilder {
+    pub seed: u64,
+}
+
+impl BuildHasher for XxHashBuilder {
+    type Hasher = XxHash64;
+
+    fn build_hasher(&self) -> XxHash64 {
+        XxHash64::with_seed(self.seed)
+    }
+}
+
+/// Accumulates a fingerprint over a sequence of fields. Each field is
+/// length-prefixed so `("ab", "c")` and `("a", "bc")` differ.
+#[derive(Debug, Clone, Default)]
+pub struct Fingerprint {
+    hasher: XxHash64,
+}
+
+impl Fingerprint {
+    pub fn new() -> Self {
+        Fingerprint::default()
+    }
+
+    pub fn field(&mut self, data: &[u8]) -> &mut Self {
+        self.hasher.update(&(data.len() as u64).to_le_bytes());
+        self.hasher.update(data);
+        self
+    }
+
+    pub fn str_field(&mut self, value: &str) -> &mut Self {
+        self.field(value.as_bytes())
+    }
+
+    pub fn finish(&self) -> u64 {
+        Checksum::finish(&self.hasher)
+    }
+
+    /// Fixed-width hex form, suitable for file and directory names
+    pub fn to_hex(&self) -> String {
+        format!("{:016x}", self.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three full 32-byte stripes and a tail that takes every step of
+    /// `XxHash64::finish`
+    fn sample() -> Vec<u8> {
+        (0..100u32).map(|i| (i * 7 + 3) as u8).collect()
+    }
+
+    const SPAM: &[u8] = b"Nobody inspects the spammish repetition";
+
+    #[test]
+    fn test_crc32_vectors() {
+        assert_eq!(Crc32::checksum(b""), 0);
+        assert_eq!(Crc32::checksum(b"123456789"), 0xcbf4_3926);
+        assert_eq!(Crc32::checksum(SPAM), 0xad42_70ed);
+    }
+
+    #[test]
+    fn test_crc32c_vectors() {
+        assert_eq!(Crc32c::checksum(b""), 0);
+        assert_eq!(Crc32c::checksum(b"123456789"), 0xe306_9283);
+        // Whichever path `update` took, the table path gives the same
+        assert_eq!(!crc_update_table(&CRC32C_TABLES, !0, b"123456789"), 0xe306_9283);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_crc32c_sse42_matches_table() {
+        if !is_x86_feature_detected!("sse4.2") {
+            return;
+        }
+        let data = sample();
+        for len in 0..=data.len() {
+            for state in [!0, 0, 0x1234_5678] {
+                let table = crc_update_table(&CRC32C_TABLES, state, &data[..len]);
+                // Feature presence checked above
+                let sse = unsafe { crc32c_sse42(state, &data[..len]) };
+                assert_eq!(sse, table, "{} bytes from state {:#x}", len, state);
+            }
+        }
+    }
+
+    #[test]
+    fn test_xxh64_vectors() {
+        assert_eq!(XxHash64::checksum(b""), 0xef46_db37_51d8_e999);
+        assert_eq!(XxHash64::checksum(b"abc"), 0x44bc_2cf5_ad77_0999);
+        assert_eq!(XxHash64::checksum(SPAM), 0xfbce_a83c_8a37_8bf1);
+        assert_eq!(XxHash64::checksum(&sample()), 0xa61f_8d4c_170f_e531);
+
+        let mut seeded = XxHash64::with_seed(1);
+        seeded.update(b"abc");
+        assert_eq!(Checksum::finish(&seeded), 0xbea9_ca81_9932_8908);
+        seeded.reset();
+        seeded.update(SPAM);
+        assert_eq!(Checksum::finish(&seeded), 0x43f4_2544_8d95_4db6);
+    }
+
+    fn streamed<C: Checksum + Default>(parts: &[&[u8]]) -> C::Output {
+        let mut checksum = C::default();
+        for part in parts {
+            checksum.update(part);
+        }
+        checksum.finish()
+    }
+
+    #[test]
+    fn test_streaming_matches_one_shot() {
+        let data = sample();
+        for split in [0, 1, 31, 32, 33, 64, 99, 100] {
+            let (a, b) = data.split_at(split);
+            assert_eq!(streamed::<Crc32>(&[a, b]), Crc32::checksum(&data), "split at {}", split);
+            assert_eq!(streamed::<Crc32c>(&[a, b]), Crc32c::checksum(&data), "split at {}", split);
+            assert_eq!(streamed::<XxHash64>(&[a, b]), XxHash64::checksum(&data), "split at {}", split);
+        }
+
+        // A partly filled buffer completed by the next update, then more
+        let (a, rest) = data.split_at(31);
+        let (b, c) = rest.split_at(33);
+        assert_eq!(streamed::<XxHash64>(&[a, b, c]), XxHash64::checksum(&data));
+        let bytes: Vec<&[u8]> = data.chunks(1).collect();
+        assert_eq!(streamed::<XxHash64>(&bytes), XxHash64::checksum(&data));
+    }
+}
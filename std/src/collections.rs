@@ -3,6 +3,12 @@ use std::hash::{Hash, Hasher};
 use std::fmt;
 use std::iter::FromIterator;
 
+pub mod bitset;
+pub mod graph;
+
+pub use bitset::{BitSet, BloomFilter};
+pub use graph::{CycleError, Graph, NodeId, TopologicalSortError};
+
 /// A dynamically-sized array
 pub struct Vector<T> {
     inner: Vec<T>,
@@ -0,0 +1,415 @@
+use std::fmt;
+
+use crate::checksum::{Checksum, XxHash64};
+
+const WORD_BITS: usize = 64;
+
+/// Fixed-capacity set of small integers stored as a bit vector.
+///
+/// Sized for dataflow analyses: union/intersection/difference work a word
+/// at a time and report whether anything changed, which is the fixed-point
+/// test those analyses loop on.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct BitSet {
+    words: Vec<u64>,
+    capacity: usize,
+}
+
+impl BitSet {
+    /// Empty set able to hold `0..capacity`
+    pub fn new(capacity: usize) -> Self {
+        BitSet {
+            words: vec![0; capacity.div_ceil(WORD_BITS)],
+            capacity,
+        }
+    }
+
+    /// Set containing every value in `0..capacity`
+    pub fn full(capacity: usize) -> Self {
+        let mut set = BitSet {
+            words: vec![!0; capacity.div_ceil(WORD_BITS)],
+            capacity,
+        };
+        set.clear_excess();
+        set
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Make room for `0..capacity`, keeping the members. Never shrinks.
+    pub fn grow(&mut self, capacity: usize) {
+        if capacity > self.capacity {
+            self.capacity = capacity;
+            self.words.resize(capacity.div_ceil(WORD_BITS), 0);
+        }
+    }
+
+    /// Zero the unused high bits of the last word so counts stay exact
+    fn clear_excess(&mut self) {
+        let used = self.capacity % WORD_BITS;
+        if used != 0 {
+            if let Some(last) = self.words.last_mut() {
+                *last &= (1u64 << used) - 1;
+            }
+        }
+    }
+
+    fn locate(&self, value: usize) -> (usize, u64) {
+        assert!(value < self.capacity, "BitSet value {} out of range 0..{}", value, self.capacity);
+        (value / WORD_BITS, 1u64 << (value % WORD_BITS))
+    }
+
+    /// Add `value`, returning whether it was newly inserted
+    pub fn insert(&mut self, value: usize) -> bool {
+        let (word, mask) = self.locate(value);
+        let was_set = self.words[word] & mask != 0;
+        self.words[word] |= mask;
+        !was_set
+    }
+
+    /// Remove `value`, returning whether it was present
+    pub fn remove(&mut self, value: usize) -> bool {
+        let (word, mask) = self.locate(value);
+        let was_set = self.words[word] & mask != 0;
+        self.words[word] &= !mask;
+        was_set
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        if value >= self.capacity {
+            return false;
+        }
+        self.words[value / WORD_BITS] & (1u64 << (value % WORD_BITS)) != 0
+    }
+
+    pub fn clear(&mut self) {
+        self.words.iter_mut().for_each(|w| *w = 0);
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    /// Number of members strictly less than `value`
+    pub fn rank(&self, value: usize) -> usize {
+        let value = value.min(self.capacity);
+        let (full_words, bits) = (value / WORD_BITS, value % WORD_BITS);
+
+        let mut count: usize = self.words[..full_words].iter().map(|w| w.count_ones() as usize).sum();
+        if bits != 0 {
+            count += (self.words[full_words] & ((1u64 << bits) - 1)).count_ones() as usize;
+        }
+        count
+    }
+
+    /// The `n`th smallest member (zero-based)
+    pub fn select(&self, mut n: usize) -> Option<usize> {
+        for (i, &word) in self.words.iter().enumerate() {
+            let ones = word.count_ones() as usize;
+            if n < ones {
+                let mut word = word;
+                for _ in 0..n {
+                    word &= word - 1;
+                }
+                return Some(i * WORD_BITS + word.trailing_zeros() as usize);
+            }
+            n -= ones;
+        }
+        None
+    }
+
+    /// Apply `op` a word at a time, treating words past the end of `other`
+    /// as empty
+    fn combine<F: Fn(u64, u64) -> u64>(&mut self, other: &BitSet, op: F) -> bool {
+        let mut changed = false;
+        for (i, word) in self.words.iter_mut().enumerate() {
+            let updated = op(*word, other.words.get(i).copied().unwrap_or(0));
+            changed |= updated != *word;
+            *word = updated;
+        }
+        changed
+    }
+
+    /// `self |= other`, returning whether `self` changed. `self` grows to
+    /// `other`'s capacity if that is larger.
+    pub fn union_with(&mut self, other: &BitSet) -> bool {
+        self.grow(other.capacity);
+        self.combine(other, |a, b| a | b)
+    }
+
+    /// `self &= other`, returning whether `self` changed
+    pub fn intersect_with(&mut self, other: &BitSet) -> bool {
+        self.combine(other, |a, b| a & b)
+    }
+
+    /// `self -= other`, returning whether `self` changed
+    pub fn subtract(&mut self, other: &BitSet) -> bool {
+        self.combine(other, |a, b| a & !b)
+    }
+
+    pub fn is_subset(&self, other: &BitSet) -> bool {
+        self.words.iter().zip(&other.words).all(|(a, b)| a & !b == 0)
+            && self.words.iter().skip(other.words.len()).all(|&w| w == 0)
+    }
+
+    pub fn is_disjoint(&self, other: &BitSet) -> bool {
+        self.words.iter().zip(&other.words).all(|(a, b)| a & b == 0)
+    }
+
+    /// Members in increasing order
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            words: &self.words,
+            index: 0,
+            current: self.words.first().copied().unwrap_or(0),
+        }
+    }
+}
+
+impl fmt::Debug for BitSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+pub struct Iter<'a> {
+    words: &'a [u64],
+    index: usize,
+    current: u64,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.current == 0 {
+            self.index += 1;
+            self.current = *self.words.get(self.index)?;
+        }
+
+        let bit = self.current.trailing_zeros() as usize;
+        // Clear the lowest set bit
+        self.current &= self.current - 1;
+        Some(self.index * WORD_BITS + bit)
+    }
+}
+
+impl<'a> IntoIterator for &'a BitSet {
+    type Item = usize;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// Probabilistic set membership: `contains` never returns a false
+/// negative, and returns a false positive with roughly the configured
+/// probability once `expected_items` have been inserted.
+#[derive(Clone)]
+pub struct BloomFilter {
+    bits: BitSet,
+    hash_count: u32,
+    seed: u64,
+}
+
+impl BloomFilter {
+    /// Size the filter for `expected_items` at `false_positive_rate`
+    /// (between 0 and 1, exclusive)
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> Self {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be between 0 and 1"
+        );
+
+        let n = expected_items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-n * false_positive_rate.ln() / (ln2 * ln2)).ceil().max(64.0) as usize;
+        let hash_count = ((bits as f64 / n) * ln2).round().clamp(1.0, 32.0) as u32;
+
+        BloomFilter::with_parameters(bits, hash_count)
+    }
+
+    pub fn with_parameters(bits: usize, hash_count: u32) -> Self {
+        BloomFilter {
+            bits: BitSet::new(bits.max(1)),
+            hash_count: hash_count.max(1),
+            seed: 0x5bd1_e995,
+        }
+    }
+
+    pub fn bit_count(&self) -> usize {
+        self.bits.capacity()
+    }
+
+    pub fn hash_count(&self) -> u32 {
+        self.hash_count
+    }
+
+    /// Two independent 64-bit hashes combined as `h1 + i*h2`
+    /// (Kirsch-Mitzenmacher), which behaves like `k` separate hashes
+    fn positions(&self, item: &[u8]) -> impl Iterator<Item = usize> {
+        let mut first = XxHash64::with_seed(self.seed);
+        let mut second = XxHash64::with_seed(!self.seed);
+        first.update(item);
+        second.update(item);
+        // An odd step visits distinct positions for power-of-two sizes
+        let (h1, h2) = (Checksum::finish(&first), Checksum::finish(&second) | 1);
+
+        let m = self.bits.capacity() as u64;
+        (0..self.hash_count as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % m) as usize)
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        let positions: Vec<usize> = self.positions(item).collect();
+        for position in positions {
+            self.bits.insert(position);
+        }
+    }
+
+    pub fn contains(&self, item: &[u8]) -> bool {
+        self.positions(item).all(|p| self.bits.contains(p))
+    }
+
+    /// Merge another filter built with the same parameters
+    pub fn union_with(&mut self, other: &BloomFilter) {
+        assert!(
+            self.hash_count == other.hash_count && self.bit_count() == other.bit_count(),
+            "BloomFilter parameters differ"
+        );
+        self.bits.union_with(&other.bits);
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.clear();
+    }
+
+    /// Estimated false-positive probability given the current fill
+    pub fn estimated_false_positive_rate(&self) -> f64 {
+        let fill = self.bits.len() as f64 / self.bits.capacity() as f64;
+        fill.powi(self.hash_count as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(capacity: usize, values: &[usize]) -> BitSet {
+        let mut set = BitSet::new(capacity);
+        for &value in values {
+            set.insert(value);
+        }
+        set
+    }
+
+    fn members(set: &BitSet) -> Vec<usize> {
+        set.iter().collect()
+    }
+
+    #[test]
+    fn test_word_boundary() {
+        let mut set = BitSet::new(65);
+        assert!(set.insert(63));
+        assert!(set.insert(64));
+        assert!(!set.insert(64));
+        assert!(set.contains(63) && set.contains(64) && !set.contains(62));
+        assert!(!set.contains(65));
+        assert_eq!(members(&set), [63, 64]);
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.rank(64), 1);
+        assert_eq!(set.rank(65), 2);
+        assert_eq!(set.select(1), Some(64));
+        assert_eq!(set.select(2), None);
+        assert!(set.remove(63));
+        assert!(!set.remove(63));
+        assert_eq!(members(&set), [64]);
+    }
+
+    #[test]
+    fn test_full_partial_word() {
+        for capacity in [1, 63, 64, 65, 130] {
+            let set = BitSet::full(capacity);
+            assert_eq!(set.len(), capacity);
+            assert_eq!(set.iter().last(), Some(capacity - 1));
+            assert!(!set.contains(capacity));
+        }
+        assert!(BitSet::full(0).is_empty());
+        assert_eq!(BitSet::new(0).iter().next(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_insert_out_of_range() {
+        BitSet::new(65).insert(65);
+    }
+
+    #[test]
+    fn test_grow() {
+        let mut set = set(10, &[3, 9]);
+        set.grow(200);
+        assert_eq!(set.capacity(), 200);
+        assert!(set.insert(199));
+        assert_eq!(members(&set), [3, 9, 199]);
+
+        set.grow(5);
+        assert_eq!(set.capacity(), 200);
+
+        // Bits past the old capacity start out empty
+        let mut full = BitSet::full(70);
+        full.grow(128);
+        assert_eq!(full.len(), 70);
+        assert!(!full.contains(70));
+    }
+
+    #[test]
+    fn test_iteration_skips_empty_words() {
+        let set = set(300, &[0, 191, 192, 299]);
+        assert_eq!(members(&set), [0, 191, 192, 299]);
+        assert_eq!((&set).into_iter().count(), 4);
+        assert_eq!(format!("{:?}", set), "{0, 191, 192, 299}");
+        assert_eq!(members(&self::set(300, &[250])), [250]);
+    }
+
+    #[test]
+    fn test_union_different_capacities() {
+        let mut small = set(10, &[1, 9]);
+        let large = set(100, &[9, 64, 99]);
+        assert!(small.union_with(&large));
+        assert_eq!(small.capacity(), 100);
+        assert_eq!(members(&small), [1, 9, 64, 99]);
+        assert!(!small.union_with(&large));
+
+        let mut large = set(100, &[64]);
+        assert!(large.union_with(&set(10, &[1])));
+        assert_eq!(large.capacity(), 100);
+        assert_eq!(members(&large), [1, 64]);
+    }
+
+    #[test]
+    fn test_intersect_and_subtract_different_capacities() {
+        let mut large = set(200, &[1, 64, 150]);
+        assert!(large.intersect_with(&set(65, &[1, 64])));
+        assert_eq!(members(&large), [1, 64]);
+        assert_eq!(large.capacity(), 200);
+
+        let mut small = set(65, &[1, 64]);
+        assert!(small.intersect_with(&set(200, &[64, 150])));
+        assert_eq!(members(&small), [64]);
+        assert!(!small.intersect_with(&set(200, &[64])));
+
+        let mut large = set(200, &[1, 64, 150]);
+        assert!(large.subtract(&set(65, &[64])));
+        assert_eq!(members(&large), [1, 150]);
+
+        assert!(set(65, &[64]).is_subset(&set(200, &[64, 150])));
+        assert!(!set(200, &[64, 150]).is_subset(&set(65, &[64])));
+        assert!(set(65, &[1]).is_disjoint(&set(200, &[150])));
+    }
+}
@@ -0,0 +1,510 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fmt;
+
+/// Index of a node within its `Graph`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub usize);
+
+/// Returned when an operation requiring an acyclic graph finds a cycle
+#[derive(Debug, Clone, PartialEq)]
+pub struct CycleError {
+    /// Nodes on the cycle in edge order; the last has an edge back to the
+    /// first
+    pub cycle: Vec<NodeId>,
+}
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path: Vec<String> = self.cycle.iter().map(|n| n.0.to_string()).collect();
+        write!(f, "Cycle detected: {}", path.join(" -> "))
+    }
+}
+
+impl std::error::Error for CycleError {}
+
+/// Why `Graph::topological_sort` has no order to return
+#[derive(Debug, Clone, PartialEq)]
+pub enum TopologicalSortError {
+    /// Every edge of an undirected graph goes both ways, so no order puts
+    /// both ends first
+    Undirected,
+    Cycle(CycleError),
+}
+
+impl fmt::Display for TopologicalSortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TopologicalSortError::Undirected => write!(f, "Cannot sort an undirected graph topologically"),
+            TopologicalSortError::Cycle(cycle) => write!(f, "{}", cycle),
+        }
+    }
+}
+
+impl std::error::Error for TopologicalSortError {}
+
+/// Adjacency-list graph with node payloads `N` and edge weights `E`.
+///
+/// Nodes are never removed, so `NodeId`s stay valid for the graph's
+/// lifetime. All traversals are iterative and visit neighbours in
+/// insertion order, so results are deterministic.
+#[derive(Debug, Clone)]
+pub struct Graph<N, E = ()> {
+    nodes: Vec<N>,
+    edges: Vec<Vec<(NodeId, E)>>,
+    directed: bool,
+    edge_count: usize,
+}
+
+impl<N, E> Graph<N, E> {
+    pub fn directed() -> Self {
+        Graph {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            directed: true,
+            edge_count: 0,
+        }
+    }
+
+    pub fn undirected() -> Self {
+        Graph {
+            directed: false,
+            ..Graph::directed()
+        }
+    }
+
+    pub fn is_directed(&self) -> bool {
+        self.directed
+    }
+
+    pub fn add_node(&mut self, value: N) -> NodeId {
+        self.nodes.push(value);
+        self.edges.push(Vec::new());
+        NodeId(self.nodes.len() - 1)
+    }
+
+    pub fn node(&self, id: NodeId) -> &N {
+        &self.nodes[id.0]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut N {
+        &mut self.nodes[id.0]
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edge_count
+    }
+
+    pub fn node_ids(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.nodes.len()).map(NodeId)
+    }
+
+    /// First node whose payload satisfies `pred`
+    pub fn find_node<F: Fn(&N) -> bool>(&self, pred: F) -> Option<NodeId> {
+        self.nodes.iter().position(pred).map(NodeId)
+    }
+
+    /// Outgoing edges of `id` (all incident edges when undirected)
+    pub fn neighbors(&self, id: NodeId) -> impl Iterator<Item = (NodeId, &E)> {
+        self.edges[id.0].iter().map(|(to, weight)| (*to, weight))
+    }
+
+    pub fn has_edge(&self, from: NodeId, to: NodeId) -> bool {
+        self.edges[from.0].iter().any(|(n, _)| *n == to)
+    }
+
+    /// Graph with every edge reversed, e.g. to turn "depends on" into
+    /// "is depended on by"
+    pub fn reversed(&self) -> Graph<N, E>
+    where
+        N: Clone,
+        E: Clone,
+    {
+        let mut reversed = Graph {
+            nodes: self.nodes.clone(),
+            edges: vec![Vec::new(); self.nodes.len()],
+            directed: self.directed,
+            edge_count: self.edge_count,
+        };
+        for (from, edges) in self.edges.iter().enumerate() {
+            for (to, weight) in edges {
+                reversed.edges[to.0].push((NodeId(from), weight.clone()));
+            }
+        }
+        reversed
+    }
+
+    /// Nodes reachable from any of `roots`, in breadth-first order. Nodes
+    /// not in the result are dead with respect to those roots.
+    pub fn reachable_from(&self, roots: &[NodeId]) -> Vec<NodeId> {
+        let mut seen = vec![false; self.nodes.len()];
+        let mut order = Vec::new();
+        let mut queue = VecDeque::new();
+
+        for &root in roots {
+            if !seen[root.0] {
+                seen[root.0] = true;
+                queue.push_back(root);
+            }
+        }
+
+        while let Some(node) = queue.pop_front() {
+            order.push(node);
+            for (next, _) in &self.edges[node.0] {
+                if !seen[next.0] {
+                    seen[next.0] = true;
+                    queue.push_back(*next);
+                }
+            }
+        }
+
+        order
+    }
+
+    /// Order nodes so every edge points from an earlier node to a later
+    /// one. Ties are broken by insertion order.
+    pub fn topological_sort(&self) -> Result<Vec<NodeId>, TopologicalSortError> {
+        if !self.directed {
+            return Err(TopologicalSortError::Undirected);
+        }
+
+        let mut in_degree = vec![0usize; self.nodes.len()];
+        for edges in &self.edges {
+            for (to, _) in edges {
+                in_degree[to.0] += 1;
+            }
+        }
+
+        let mut ready: BinaryHeap<Reverse<usize>> = in_degree
+            .iter()
+            .enumerate()
+            .filter(|(_, &d)| d == 0)
+            .map(|(i, _)| Reverse(i))
+            .collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+
+        while let Some(Reverse(node)) = ready.pop() {
+            order.push(NodeId(node));
+            for (to, _) in &self.edges[node] {
+                in_degree[to.0] -= 1;
+                if in_degree[to.0] == 0 {
+                    ready.push(Reverse(to.0));
+                }
+            }
+        }
+
+        if order.len() == self.nodes.len() {
+            Ok(order)
+        } else {
+            Err(TopologicalSortError::Cycle(CycleError {
+                cycle: self.find_cycle().unwrap_or_default(),
+            }))
+        }
+    }
+
+    /// Some cycle in the graph, if there is one. For undirected graphs a
+    /// single edge is not considered a cycle.
+    pub fn find_cycle(&self) -> Option<Vec<NodeId>> {
+        const UNVISITED: u8 = 0;
+        const ON_STACK: u8 = 1;
+        const DONE: u8 = 2;
+
+        let mut state = vec![UNVISITED; self.nodes.len()];
+        let mut parent: Vec<Option<usize>> = vec![None; self.nodes.len()];
+
+        for start in 0..self.nodes.len() {
+            if state[start] != UNVISITED {
+                continue;
+            }
+
+            // (node, index of next edge to explore)
+            let mut stack = vec![(start, 0usize)];
+            state[start] = ON_STACK;
+
+            while let Some(&mut (node, ref mut next_edge)) = stack.last_mut() {
+                let Some((to, _)) = self.edges[node].get(*next_edge) else {
+                    state[node] = DONE;
+                    stack.pop();
+                    continue;
+                };
+                *next_edge += 1;
+                let to = to.0;
+
+                if !self.directed && parent[node] == Some(to) {
+                    continue;
+                }
+
+                match state[to] {
+                    UNVISITED => {
+                        state[to] = ON_STACK;
+                        parent[to] = Some(node);
+                        stack.push((to, 0));
+                    }
+                    ON_STACK => {
+                        let mut cycle: Vec<NodeId> = Vec::new();
+                        let mut current = node;
+                        cycle.push(NodeId(current));
+                        while current != to {
+                            current = parent[current].expect("cycle member has parent");
+                            cycle.push(NodeId(current));
+                        }
+                        cycle.reverse();
+                        return Some(cycle);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn has_cycle(&self) -> bool {
+        self.find_cycle().is_some()
+    }
+
+    /// Strongly connected components (Tarjan), each listed in discovery
+    /// order. Components come out in reverse topological order of the
+    /// condensation: a component only has edges into earlier ones.
+    pub fn strongly_connected_components(&self) -> Vec<Vec<NodeId>> {
+        let n = self.nodes.len();
+        let mut index = vec![usize::MAX; n];
+        let mut low_link = vec![0usize; n];
+        let mut on_stack = vec![false; n];
+        let mut component_stack = Vec::new();
+        let mut components = Vec::new();
+        let mut next_index = 0;
+
+        for start in 0..n {
+            if index[start] != usize::MAX {
+                continue;
+            }
+
+            let mut call_stack = vec![(start, 0usize)];
+            index[start] = next_index;
+            low_link[start] = next_index;
+            next_index += 1;
+            component_stack.push(start);
+            on_stack[start] = true;
+
+            while let Some(&(node, edge)) = call_stack.last() {
+                if let Some((to, _)) = self.edges[node].get(edge) {
+                    call_stack.last_mut().expect("non-empty").1 += 1;
+                    let to = to.0;
+
+                    if index[to] == usize::MAX {
+                        index[to] = next_index;
+                        low_link[to] = next_index;
+                        next_index += 1;
+                        component_stack.push(to);
+                        on_stack[to] = true;
+                        call_stack.push((to, 0));
+                    } else if on_stack[to] {
+                        low_link[node] = low_link[node].min(index[to]);
+                    }
+                    continue;
+                }
+
+                call_stack.pop();
+                if let Some(&(caller, _)) = call_stack.last() {
+                    low_link[caller] = low_link[caller].min(low_link[node]);
+                }
+
+                if low_link[node] == index[node] {
+                    let mut component = Vec::new();
+                    loop {
+                        let member = component_stack.pop().expect("node is on stack");
+                        on_stack[member] = false;
+                        component.push(NodeId(member));
+                        if member == node {
+                            break;
+                        }
+                    }
+                    component.reverse();
+                    components.push(component);
+                }
+            }
+        }
+
+        components
+    }
+
+    /// Dijkstra from `source` using `weight` to cost each edge. Returns the
+    /// distance to every node (`None` if unreachable) and each node's
+    /// predecessor on a shortest path.
+    pub fn shortest_paths<F>(&self, source: NodeId, weight: F) -> ShortestPaths
+    where
+        F: Fn(&E) -> u64,
+    {
+        let n = self.nodes.len();
+        let mut distance: Vec<Option<u64>> = vec![None; n];
+        let mut previous: Vec<Option<NodeId>> = vec![None; n];
+        let mut heap = BinaryHeap::new();
+
+        distance[source.0] = Some(0);
+        heap.push(Reverse((0u64, source.0)));
+
+        while let Some(Reverse((dist, node))) = heap.pop() {
+            if distance[node].is_some_and(|d| dist > d) {
+                continue;
+            }
+
+            for (to, edge) in &self.edges[node] {
+                let candidate = dist.saturating_add(weight(edge));
+                if distance[to.0].is_none_or(|d| candidate < d) {
+                    distance[to.0] = Some(candidate);
+                    previous[to.0] = Some(NodeId(node));
+                    heap.push(Reverse((candidate, to.0)));
+                }
+            }
+        }
+
+        ShortestPaths { source, distance, previous }
+    }
+}
+
+impl<N, E: Clone> Graph<N, E> {
+    pub fn add_edge(&mut self, from: NodeId, to: NodeId, weight: E) {
+        if !self.directed && from != to {
+            self.edges[to.0].push((from, weight.clone()));
+        }
+        self.edges[from.0].push((to, weight));
+        self.edge_count += 1;
+    }
+}
+
+/// Result of `Graph::shortest_paths`
+#[derive(Debug, Clone)]
+pub struct ShortestPaths {
+    source: NodeId,
+    distance: Vec<Option<u64>>,
+    previous: Vec<Option<NodeId>>,
+}
+
+impl ShortestPaths {
+    pub fn distance(&self, to: NodeId) -> Option<u64> {
+        self.distance[to.0]
+    }
+
+    /// Nodes from the source to `to`, inclusive
+    pub fn path(&self, to: NodeId) -> Option<Vec<NodeId>> {
+        self.distance[to.0]?;
+
+        let mut path = vec![to];
+        let mut current = to;
+        while current != self.source {
+            current = self.previous[current.0]?;
+            path.push(current);
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directed(nodes: usize, edges: &[(usize, usize)]) -> Graph<usize> {
+        let mut graph = Graph::directed();
+        for i in 0..nodes {
+            graph.add_node(i);
+        }
+        for &(from, to) in edges {
+            graph.add_edge(NodeId(from), NodeId(to), ());
+        }
+        graph
+    }
+
+    fn ids(nodes: &[usize]) -> Vec<NodeId> {
+        nodes.iter().copied().map(NodeId).collect()
+    }
+
+    #[test]
+    fn test_topological_sort() {
+        let graph = directed(6, &[(3, 0), (0, 1), (0, 2), (2, 1), (1, 4)]);
+        let order = graph.topological_sort().unwrap();
+        // Node 5 is ready from the start but sorts last: insertion order
+        // only breaks ties between nodes that are ready
+        assert_eq!(order, ids(&[3, 0, 2, 1, 4, 5]));
+
+        let position = |node: NodeId| order.iter().position(|&n| n == node).unwrap();
+        for from in graph.node_ids() {
+            for (to, _) in graph.neighbors(from) {
+                assert!(position(from) < position(to));
+            }
+        }
+    }
+
+    #[test]
+    fn test_topological_sort_reports_cycle() {
+        // 0 -> 1 -> 2 -> 3 -> 1, with 3 -> 4 off the cycle
+        let graph = directed(5, &[(0, 1), (1, 2), (2, 3), (3, 1), (3, 4)]);
+        let cycle = match graph.topological_sort() {
+            Err(TopologicalSortError::Cycle(error)) => error.cycle,
+            other => panic!("expected a cycle, got {:?}", other),
+        };
+        assert_eq!(cycle, ids(&[1, 2, 3]));
+        for (i, &node) in cycle.iter().enumerate() {
+            assert!(graph.has_edge(node, cycle[(i + 1) % cycle.len()]));
+        }
+
+        assert!(directed(2, &[(0, 0)]).has_cycle());
+        assert!(!directed(3, &[(0, 1), (1, 2), (0, 2)]).has_cycle());
+    }
+
+    #[test]
+    fn test_topological_sort_undirected() {
+        let mut graph: Graph<&str> = Graph::undirected();
+        let a = graph.add_node("a");
+        let b = graph.add_node("b");
+        graph.add_edge(a, b, ());
+        assert_eq!(graph.topological_sort(), Err(TopologicalSortError::Undirected));
+
+        // A single edge is not a cycle, a triangle is
+        assert!(!graph.has_cycle());
+        let c = graph.add_node("c");
+        graph.add_edge(b, c, ());
+        assert!(!graph.has_cycle());
+        graph.add_edge(c, a, ());
+        assert_eq!(graph.find_cycle().map(|cycle| cycle.len()), Some(3));
+    }
+
+    #[test]
+    fn test_strongly_connected_components() {
+        let graph = directed(6, &[(0, 1), (1, 2), (2, 0), (2, 3), (3, 4), (4, 3)]);
+        assert_eq!(
+            graph.strongly_connected_components(),
+            vec![ids(&[3, 4]), ids(&[0, 1, 2]), ids(&[5])]
+        );
+    }
+
+    #[test]
+    fn test_shortest_paths() {
+        let mut graph: Graph<&str, u64> = Graph::directed();
+        let a = graph.add_node("a");
+        let b = graph.add_node("b");
+        let c = graph.add_node("c");
+        let d = graph.add_node("d");
+        let unreachable = graph.add_node("e");
+        graph.add_edge(a, b, 4);
+        graph.add_edge(a, c, 1);
+        graph.add_edge(c, b, 2);
+        graph.add_edge(b, d, 1);
+        graph.add_edge(c, d, 5);
+        graph.add_edge(unreachable, a, 1);
+
+        let paths = graph.shortest_paths(a, |&weight| weight);
+        assert_eq!(paths.distance(a), Some(0));
+        assert_eq!(paths.path(a), Some(vec![a]));
+        assert_eq!(paths.distance(b), Some(3));
+        assert_eq!(paths.distance(d), Some(4));
+        assert_eq!(paths.path(d), Some(vec![a, c, b, d]));
+        assert_eq!(paths.distance(unreachable), None);
+        assert_eq!(paths.path(unreachable), None);
+    }
+}
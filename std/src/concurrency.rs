@@ -3,6 +3,12 @@ use std::thread;
 use std::time::Duration;
 use std::fmt;
 
+use crate::io::stream::{Poll, Sink, Stream};
+use crate::io::{IOError, IOResult};
+
+pub mod crawl;
+pub mod timer;
+
 // Thread implementation
 pub struct Thread {
     handle: Option<thread::JoinHandle<()>>,
@@ -257,6 +263,41 @@ impl<T> Receiver<T> {
     }
 }
 
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+    
+    fn poll_next(&mut self) -> Poll<Option<T>> {
+        match self.inner.try_recv() {
+            Ok(value) => Poll::Ready(Some(value)),
+            Err(crossbeam_channel::TryRecvError::Empty) => Poll::Pending,
+            Err(crossbeam_channel::TryRecvError::Disconnected) => Poll::Ready(None),
+        }
+    }
+}
+
+/// Sending side of a bounded channel as a `Sink`: a full channel reports
+/// `Pending`, pushing back on the producer
+impl<T> Sink<T> for Sender<T> {
+    fn poll_ready(&mut self) -> Poll<IOResult<()>> {
+        if self.inner.is_full() {
+            Poll::Pending
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+    
+    fn start_send(&mut self, item: T) -> IOResult<()> {
+        self.inner.try_send(item).map_err(|e| match e {
+            crossbeam_channel::TrySendError::Full(_) => IOError::WouldBlock,
+            crossbeam_channel::TrySendError::Disconnected(_) => IOError::Other("Channel closed".to_string()),
+        })
+    }
+    
+    fn poll_close(&mut self) -> Poll<IOResult<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
 // Error types
 #[derive(Debug)]
 pub enum ThreadError {
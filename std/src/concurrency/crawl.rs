@@ -0,0 +1,253 @@
+//! Parallel processing of every file under a set of directories, for
+//! tools such as the LSP workspace indexer and docgen.
+//!
+//! The calling thread walks the directories and feeds the files it selects
+//! into a bounded channel; workers on a `ThreadPool` take files from it and
+//! process them. The bound keeps the walk from running ahead of slow
+//! workers with the whole tree queued in memory. Files and directories are
+//! visited once by canonical path, so overlapping roots and symlink cycles
+//! cost nothing. A `CancelToken` stops the walk and the workers before the
+//! next file. Paths an `Ignore` excludes are skipped, and excluded
+//! directories are not entered.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use crate::fs::ignore::Ignore;
+
+use super::{bounded_channel, channel, Sender, ThreadPool, ThreadPoolError};
+
+/// Files queued per worker before the walk waits
+const QUEUE_PER_WORKER: usize = 4;
+
+/// Shared flag for stopping a crawl from another thread, e.g. when the LSP
+/// client closes the workspace while it is being indexed
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug)]
+pub enum CrawlError {
+    Io(PathBuf, io::Error),
+    Pool(ThreadPoolError),
+    Cancelled,
+}
+
+impl fmt::Display for CrawlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CrawlError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            CrawlError::Pool(e) => write!(f, "{}", e),
+            CrawlError::Cancelled => write!(f, "Crawl cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for CrawlError {}
+
+/// For tools reporting `io::Result`; cancellation becomes `Interrupted`
+impl From<CrawlError> for io::Error {
+    fn from(error: CrawlError) -> Self {
+        match error {
+            CrawlError::Io(_, e) => e,
+            CrawlError::Cancelled => io::Error::new(io::ErrorKind::Interrupted, error.to_string()),
+            CrawlError::Pool(_) => io::Error::other(error.to_string()),
+        }
+    }
+}
+
+pub struct Crawler {
+    workers: usize,
+    queue_capacity: usize,
+    cancel: CancelToken,
+    ignores: Vec<Ignore>,
+}
+
+impl Crawler {
+    /// One worker per available core
+    pub fn new() -> Self {
+        let workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        Crawler {
+            workers,
+            queue_capacity: workers * QUEUE_PER_WORKER,
+            cancel: CancelToken::new(),
+            ignores: Vec::new(),
+        }
+    }
+
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self.queue_capacity = self.workers * QUEUE_PER_WORKER;
+        self
+    }
+
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity.max(1);
+        self
+    }
+
+    /// Stop when `token` is cancelled instead of by `cancel_token()`
+    pub fn with_cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Skip what `ignore` excludes under its root; one per root when the
+    /// roots have their own ignore files
+    pub fn with_ignore(mut self, ignore: Ignore) -> Self {
+        self.ignores.push(ignore);
+        self
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Run `process` on every file under `roots` that `select` accepts,
+    /// returning the results sorted by path. Roots that are not
+    /// directories are skipped. The first error from walking or
+    /// processing stops the crawl and is returned.
+    pub fn crawl<T, S, F>(&self, roots: &[PathBuf], select: S, process: F) -> Result<Vec<(PathBuf, T)>, CrawlError>
+    where
+        T: Send + 'static,
+        S: Fn(&Path) -> bool,
+        F: Fn(&Path) -> io::Result<T> + Send + Sync + 'static,
+    {
+        let pool = ThreadPool::new(self.workers);
+        let (jobs, queue) = bounded_channel::<PathBuf>(self.queue_capacity);
+        let (results, finished) = channel::<(PathBuf, io::Result<T>)>();
+        let queue = Arc::new(queue);
+        let process = Arc::new(process);
+        // Set by a worker whose file failed, so the walk stops early too
+        let failed = Arc::new(AtomicBool::new(false));
+
+        for _ in 0..self.workers {
+            let queue = Arc::clone(&queue);
+            let results = results.clone();
+            let process = Arc::clone(&process);
+            let cancel = self.cancel.clone();
+            let failed = Arc::clone(&failed);
+            pool.execute(move || {
+                // Keep receiving after a stop so the walk never blocks on a
+                // full queue
+                while let Ok(path) = queue.recv() {
+                    if cancel.is_cancelled() || failed.load(Ordering::SeqCst) {
+                        continue;
+                    }
+                    let result = process(&path);
+                    if result.is_err() {
+                        failed.store(true, Ordering::SeqCst);
+                    }
+                    if results.send((path, result)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .map_err(CrawlError::Pool)?;
+        }
+        drop(results);
+
+        let walked = self.walk(roots, &select, &jobs, &failed);
+        // Closing the queue lets the workers finish
+        drop(jobs);
+
+        let mut processed = Vec::new();
+        let mut first_error = None;
+        while let Ok((path, result)) = finished.recv() {
+            match result {
+                Ok(value) => processed.push((path, value)),
+                Err(e) => {
+                    first_error.get_or_insert(CrawlError::Io(path, e));
+                }
+            }
+        }
+        drop(pool);
+
+        walked?;
+        if let Some(error) = first_error {
+            return Err(error);
+        }
+        if self.cancel.is_cancelled() {
+            return Err(CrawlError::Cancelled);
+        }
+        processed.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(processed)
+    }
+
+    fn walk<S>(&self, roots: &[PathBuf], select: &S, jobs: &Sender<PathBuf>, failed: &AtomicBool) -> Result<(), CrawlError>
+    where
+        S: Fn(&Path) -> bool,
+    {
+        let mut visited = HashSet::new();
+        let mut pending: Vec<PathBuf> = roots.iter().filter(|root| root.is_dir()).cloned().collect();
+
+        while let Some(dir) = pending.pop() {
+            let canonical_dir = fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone());
+            if !visited.insert(canonical_dir.clone()) {
+                continue;
+            }
+
+            let entries = fs::read_dir(&dir).map_err(|e| CrawlError::Io(dir.clone(), e))?;
+            for entry in entries {
+                if self.cancel.is_cancelled() || failed.load(Ordering::SeqCst) {
+                    return Ok(());
+                }
+
+                let entry = entry.map_err(|e| CrawlError::Io(dir.clone(), e))?;
+                let path = entry.path();
+                let file_type = entry.file_type().map_err(|e| CrawlError::Io(path.clone(), e))?;
+                // Only symlinks need resolving; anything else is unique by
+                // its name in an already canonical directory
+                let (is_dir, canonical) = if file_type.is_symlink() {
+                    match fs::canonicalize(&path) {
+                        Ok(target) => (target.is_dir(), target),
+                        // Dangling link
+                        Err(_) => continue,
+                    }
+                } else {
+                    (file_type.is_dir(), canonical_dir.join(entry.file_name()))
+                };
+
+                if self.ignores.iter().any(|ignore| ignore.matches(&path, is_dir)) {
+                    continue;
+                }
+                if is_dir {
+                    pending.push(path);
+                } else if select(&path) && visited.insert(canonical) {
+                    // Only fails once every worker is gone
+                    if jobs.send(path).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Crawler {
+    fn default() -> Self {
+        Crawler::new()
+    }
+}
@@ -0,0 +1,354 @@
+//! Timers for cooperative tasks on the `Scheduler`.
+//!
+//! Deadlines live in a hashed timer wheel: `slot_count` buckets of one
+//! `tick` each, with a deadline hashed to `tick_index % slot_count`.
+//! Insertion and cancellation are O(1); advancing visits only the buckets
+//! for elapsed ticks. Nothing runs in the background: the wheel advances
+//! whenever a timer is polled, so no OS thread is needed.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::io::stream::{Poll, Stream};
+
+const DEFAULT_TICK: Duration = Duration::from_millis(1);
+const DEFAULT_SLOTS: usize = 512;
+
+#[derive(Default)]
+struct TimerState {
+    fired: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+struct Entry {
+    deadline_tick: u64,
+    state: Arc<TimerState>,
+}
+
+struct Wheel {
+    slots: Vec<Vec<Entry>>,
+    tick: Duration,
+    origin: Instant,
+    current_tick: u64,
+    pending: usize,
+}
+
+impl Wheel {
+    fn tick_of(&self, at: Instant) -> u64 {
+        let elapsed = at.saturating_duration_since(self.origin);
+        (elapsed.as_nanos() / self.tick.as_nanos()) as u64
+    }
+
+    fn insert(&mut self, deadline: Instant) -> Arc<TimerState> {
+        let state = Arc::new(TimerState::default());
+
+        // Round up so a timer never fires early
+        let elapsed = deadline.saturating_duration_since(self.origin).as_nanos();
+        let tick_nanos = self.tick.as_nanos();
+        let deadline_tick = ((elapsed + tick_nanos - 1) / tick_nanos) as u64;
+
+        if deadline_tick <= self.current_tick {
+            state.fired.store(true, Ordering::Release);
+            return state;
+        }
+
+        let slot = (deadline_tick % self.slots.len() as u64) as usize;
+        self.slots[slot].push(Entry { deadline_tick, state: Arc::clone(&state) });
+        self.pending += 1;
+        state
+    }
+
+    /// Fire every timer due at or before `now`
+    fn advance(&mut self, now: Instant) {
+        let target = self.tick_of(now);
+        if target <= self.current_tick {
+            return;
+        }
+
+        // A gap longer than one revolution visits each bucket once
+        let steps = (target - self.current_tick).min(self.slots.len() as u64);
+        for step in 1..=steps {
+            let slot = ((self.current_tick + step) % self.slots.len() as u64) as usize;
+            let before = self.slots[slot].len();
+            self.slots[slot].retain(|entry| {
+                if entry.state.cancelled.load(Ordering::Acquire) {
+                    return false;
+                }
+                if entry.deadline_tick <= target {
+                    entry.state.fired.store(true, Ordering::Release);
+                    return false;
+                }
+                true
+            });
+            self.pending -= before - self.slots[slot].len();
+        }
+
+        self.current_tick = target;
+    }
+}
+
+/// Shared handle to a timer wheel. Clones refer to the same wheel.
+#[derive(Clone)]
+pub struct Timer {
+    wheel: Arc<Mutex<Wheel>>,
+}
+
+impl Timer {
+    /// Wheel with 1ms resolution
+    pub fn new() -> Self {
+        Timer::with_resolution(DEFAULT_TICK, DEFAULT_SLOTS)
+    }
+
+    pub fn with_resolution(tick: Duration, slot_count: usize) -> Self {
+        assert!(!tick.is_zero(), "timer tick must be non-zero");
+        Timer {
+            wheel: Arc::new(Mutex::new(Wheel {
+                slots: (0..slot_count.max(1)).map(|_| Vec::new()).collect(),
+                tick,
+                origin: Instant::now(),
+                current_tick: 0,
+                pending: 0,
+            })),
+        }
+    }
+
+    fn register(&self, deadline: Instant) -> Arc<TimerState> {
+        let mut wheel = self.wheel.lock().unwrap_or_else(|e| e.into_inner());
+        wheel.insert(deadline)
+    }
+
+    /// Fire everything due by now. Polling any timer does this implicitly.
+    pub fn advance(&self) {
+        let mut wheel = self.wheel.lock().unwrap_or_else(|e| e.into_inner());
+        wheel.advance(Instant::now());
+    }
+
+    /// Timers registered and neither fired nor swept after cancellation
+    pub fn pending(&self) -> usize {
+        self.wheel.lock().map(|w| w.pending).unwrap_or(0)
+    }
+
+    pub fn sleep(&self, duration: Duration) -> Sleep {
+        self.sleep_until(Instant::now() + duration)
+    }
+
+    pub fn sleep_until(&self, deadline: Instant) -> Sleep {
+        Sleep {
+            timer: self.clone(),
+            state: self.register(deadline),
+            deadline,
+        }
+    }
+
+    /// Resolve to `Err(Elapsed)` if `operation` is still pending after
+    /// `duration`
+    pub fn timeout<P: Pollable>(&self, operation: P, duration: Duration) -> Timeout<P> {
+        Timeout {
+            operation,
+            sleep: self.sleep(duration),
+        }
+    }
+
+    /// Stream yielding once per `period`, starting one period from now
+    pub fn interval(&self, period: Duration) -> Interval {
+        assert!(!period.is_zero(), "interval period must be non-zero");
+        Interval {
+            sleep: self.sleep(period),
+            period,
+        }
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Timer::new()
+    }
+}
+
+/// Completes once its deadline has passed. Dropping it cancels the timer.
+pub struct Sleep {
+    timer: Timer,
+    state: Arc<TimerState>,
+    deadline: Instant,
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn poll(&mut self) -> Poll<()> {
+        if !self.state.fired.load(Ordering::Acquire) {
+            self.timer.advance();
+        }
+
+        if self.state.fired.load(Ordering::Acquire) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    pub fn is_elapsed(&mut self) -> bool {
+        self.poll() == Poll::Ready(())
+    }
+
+    /// Move the deadline to `duration` from now, e.g. to restart a
+    /// debounce window on every keystroke
+    pub fn reset(&mut self, duration: Duration) {
+        self.state.cancelled.store(true, Ordering::Release);
+        self.deadline = Instant::now() + duration;
+        self.state = self.timer.register(self.deadline);
+    }
+
+    /// Scheduler task that runs `f` once the sleep completes
+    pub fn then<F>(mut self, f: F) -> impl FnMut() -> bool + Send
+    where
+        F: FnOnce() + Send,
+    {
+        let mut f = Some(f);
+        move || match self.poll() {
+            Poll::Pending => false,
+            Poll::Ready(()) => {
+                if let Some(f) = f.take() {
+                    f();
+                }
+                true
+            }
+        }
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.state.cancelled.store(true, Ordering::Release);
+    }
+}
+
+/// Operation that can be polled for completion
+pub trait Pollable {
+    type Output;
+
+    fn poll(&mut self) -> Poll<Self::Output>;
+}
+
+impl<T, F: FnMut() -> Poll<T>> Pollable for F {
+    type Output = T;
+
+    fn poll(&mut self) -> Poll<T> {
+        self()
+    }
+}
+
+impl Pollable for Sleep {
+    type Output = ();
+
+    fn poll(&mut self) -> Poll<()> {
+        Sleep::poll(self)
+    }
+}
+
+impl<T: Clone + Send + 'static> Pollable for super::Future<T> {
+    type Output = T;
+
+    fn poll(&mut self) -> Poll<T> {
+        match self.get() {
+            Some(value) => Poll::Ready(value),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// The deadline passed before the operation completed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Operation timed out")
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+pub struct Timeout<P> {
+    operation: P,
+    sleep: Sleep,
+}
+
+impl<P> Timeout<P> {
+    pub fn into_inner(self) -> P {
+        self.operation
+    }
+}
+
+impl<P: Pollable> Pollable for Timeout<P> {
+    type Output = Result<P::Output, Elapsed>;
+
+    /// The operation is polled first, so one that completes exactly at the
+    /// deadline still succeeds
+    fn poll(&mut self) -> Poll<Self::Output> {
+        if let Poll::Ready(value) = self.operation.poll() {
+            return Poll::Ready(Ok(value));
+        }
+        match self.sleep.poll() {
+            Poll::Ready(()) => Poll::Ready(Err(Elapsed)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Periodic ticks as a `Stream`. Ticks missed while nobody polled are
+/// skipped rather than delivered in a burst.
+pub struct Interval {
+    sleep: Sleep,
+    period: Duration,
+}
+
+impl Interval {
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
+
+impl Stream for Interval {
+    type Item = Instant;
+
+    fn poll_next(&mut self) -> Poll<Option<Instant>> {
+        match self.sleep.poll() {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(()) => {
+                let fired_at = self.sleep.deadline();
+                let now = Instant::now();
+                let mut next = fired_at + self.period;
+                while next <= now {
+                    next += self.period;
+                }
+                self.sleep = self.sleep.timer.sleep_until(next);
+                Poll::Ready(Some(fired_at))
+            }
+        }
+    }
+}
+
+/// `Timer::sleep` on a process-wide default timer
+pub fn sleep(duration: Duration) -> Sleep {
+    global().sleep(duration)
+}
+
+/// `Timer::timeout` on a process-wide default timer
+pub fn timeout<P: Pollable>(operation: P, duration: Duration) -> Timeout<P> {
+    global().timeout(operation, duration)
+}
+
+/// `Timer::interval` on a process-wide default timer
+pub fn interval(period: Duration) -> Interval {
+    global().interval(period)
+}
+
+fn global() -> &'static Timer {
+    static GLOBAL: std::sync::OnceLock<Timer> = std::sync::OnceLock::new();
+    GLOBAL.get_or_init(Timer::new)
+}
@@ -1,7 +1,9 @@
 use std::fmt;
-use rand::{CryptoRng, RngCore};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand::RngCore;
 
 // Hash functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HashAlgorithm {
     SHA256,
     SHA512,
@@ -39,9 +41,7 @@ impl Hash {
     }
     
     pub fn as_hex_string(&self) -> String {
-        self.bytes.iter()
-            .map(|b| format!("{:02x}", b))
-            .collect()
+        crate::encoding::hex_encode(&self.bytes)
     }
 }
 
@@ -194,10 +194,42 @@ impl KeyPair {
         }
     }
     
+    /// A key pair saved from `private_key` and `public_key`. An Ed25519
+    /// public key is derived from the private key, so a saved one that does
+    /// not match it is rejected rather than published next to signatures
+    /// it cannot verify.
+    pub fn from_bytes(algorithm: AsymmetricAlgorithm, private_key: &[u8], public_key: &[u8]) -> Result<Self, CryptoError> {
+        let valid = match algorithm {
+            AsymmetricAlgorithm::RSA => !private_key.is_empty() && !public_key.is_empty(),
+            AsymmetricAlgorithm::Ed25519 | AsymmetricAlgorithm::X25519 => {
+                private_key.len() == 32 && public_key.len() == 32
+            }
+        };
+        if !valid {
+            return Err(CryptoError::InvalidKeyLength);
+        }
+        if let AsymmetricAlgorithm::Ed25519 = algorithm {
+            if ed25519_public_key(private_key)? != public_key {
+                return Err(CryptoError::InvalidKey);
+            }
+        }
+        
+        Ok(KeyPair {
+            algorithm,
+            private_key: private_key.to_vec(),
+            public_key: public_key.to_vec(),
+        })
+    }
+    
     pub fn public_key(&self) -> &[u8] {
         &self.public_key
     }
     
+    /// For saving the key pair; keep it secret
+    pub fn private_key(&self) -> &[u8] {
+        &self.private_key
+    }
+    
     pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
         match self.algorithm {
             AsymmetricAlgorithm::RSA => sign_rsa(&self.private_key, data),
@@ -231,6 +263,30 @@ impl KeyPair {
     }
 }
 
+/// Check a signature with only the signer's public key, as whoever
+/// receives signed data does
+pub fn verify_signature(
+    algorithm: AsymmetricAlgorithm,
+    public_key: &[u8],
+    data: &[u8],
+    signature: &[u8],
+) -> Result<bool, CryptoError> {
+    match algorithm {
+        AsymmetricAlgorithm::RSA => verify_rsa(public_key, data, signature),
+        AsymmetricAlgorithm::Ed25519 => {
+            if public_key.len() != 32 {
+                return Err(CryptoError::InvalidKeyLength);
+            }
+            // Anything else cannot be an Ed25519 signature
+            if signature.len() != 64 {
+                return Ok(false);
+            }
+            verify_ed25519(public_key, data, signature)
+        }
+        AsymmetricAlgorithm::X25519 => Err(CryptoError::UnsupportedOperation),
+    }
+}
+
 fn generate_rsa_keypair() -> KeyPair {
     // Implementation using a cryptographic library
     // ... implementation details ...
@@ -241,16 +297,27 @@ fn generate_rsa_keypair() -> KeyPair {
     }
 }
 
+/// The private key is the 32-byte seed the signing key is expanded from
 fn generate_ed25519_keypair() -> KeyPair {
-    // Implementation using a cryptographic library
-    // ... implementation details ...
+    let mut seed = vec![0u8; 32];
+    rand::thread_rng().fill_bytes(&mut seed);
+    let public_key = ed25519_public_key(&seed).expect("a 32-byte seed");
     KeyPair {
         algorithm: AsymmetricAlgorithm::Ed25519,
-        private_key: vec![0; 32],
-        public_key: vec![0; 32],
+        private_key: seed,
+        public_key,
     }
 }
 
+fn ed25519_signing_key(private_key: &[u8]) -> Result<SigningKey, CryptoError> {
+    let seed: &[u8; 32] = private_key.try_into().map_err(|_| CryptoError::InvalidKeyLength)?;
+    Ok(SigningKey::from_bytes(seed))
+}
+
+fn ed25519_public_key(private_key: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    Ok(ed25519_signing_key(private_key)?.verifying_key().to_bytes().to_vec())
+}
+
 fn generate_x25519_keypair() -> KeyPair {
     // Implementation using a cryptographic library
     // ... implementation details ...
@@ -274,15 +341,19 @@ fn verify_rsa(public_key: &[u8], data: &[u8], signature: &[u8]) -> Result<bool,
 }
 
 fn sign_ed25519(private_key: &[u8], data: &[u8]) -> Result<Vec<u8>, CryptoError> {
-    // Implementation using a cryptographic library
-    // ... implementation details ...
-    Ok(vec![0; 64]) // Placeholder
+    let signature = ed25519_signing_key(private_key)?.sign(data);
+    Ok(signature.to_bytes().to_vec())
 }
 
 fn verify_ed25519(public_key: &[u8], data: &[u8], signature: &[u8]) -> Result<bool, CryptoError> {
-    // Implementation using a cryptographic library
-    // ... implementation details ...
-    Ok(true) // Placeholder
+    let public_key: &[u8; 32] = public_key.try_into().map_err(|_| CryptoError::InvalidKeyLength)?;
+    let public_key = VerifyingKey::from_bytes(public_key).map_err(|_| CryptoError::InvalidKey)?;
+    let Ok(signature) = <&[u8; 64]>::try_from(signature) else {
+        return Ok(false);
+    };
+    // Strict verification also rejects weak keys and malleable signatures,
+    // so one signature cannot be rewritten into another that verifies
+    Ok(public_key.verify_strict(data, &Signature::from_bytes(signature)).is_ok())
 }
 
 fn encrypt_rsa(public_key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
@@ -312,6 +383,9 @@ fn decrypt_x25519(private_key: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Cryp
 #[derive(Debug)]
 pub enum CryptoError {
     InvalidKeyLength,
+    /// Right length, but not a usable key, or not the key the other half
+    /// of its pair belongs to
+    InvalidKey,
     InvalidNonceLength,
     EncryptionError,
     DecryptionError,
@@ -324,6 +398,7 @@ impl fmt::Display for CryptoError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CryptoError::InvalidKeyLength => write!(f, "Invalid key length"),
+            CryptoError::InvalidKey => write!(f, "Invalid key"),
             CryptoError::InvalidNonceLength => write!(f, "Invalid nonce length"),
             CryptoError::EncryptionError => write!(f, "Encryption error"),
             CryptoError::DecryptionError => write!(f, "Decryption error"),
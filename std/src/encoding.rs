@@ -0,0 +1,461 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncodingError {
+    InvalidCharacter { position: usize },
+    InvalidLength(usize),
+    InvalidPadding,
+    /// Varint ran past the end of the input
+    UnexpectedEnd,
+    /// Varint does not fit the requested integer type
+    Overflow,
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodingError::InvalidCharacter { position } => {
+                write!(f, "Invalid character at position {}", position)
+            }
+            EncodingError::InvalidLength(len) => write!(f, "Invalid input length: {}", len),
+            EncodingError::InvalidPadding => write!(f, "Invalid padding"),
+            EncodingError::UnexpectedEnd => write!(f, "Unexpected end of input"),
+            EncodingError::Overflow => write!(f, "Varint overflows target type"),
+        }
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+pub type EncodingResult<T> = Result<T, EncodingError>;
+
+// Base64
+
+/// Base64 alphabet and padding rules
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Base64Alphabet {
+    /// RFC 4648 section 4, `+` and `/`, padded
+    Standard,
+    /// RFC 4648 section 5, `-` and `_`, unpadded
+    UrlSafe,
+}
+
+impl Base64Alphabet {
+    fn symbols(self) -> (u8, u8) {
+        match self {
+            Base64Alphabet::Standard => (b'+', b'/'),
+            Base64Alphabet::UrlSafe => (b'-', b'_'),
+        }
+    }
+
+    fn padded(self) -> bool {
+        self == Base64Alphabet::Standard
+    }
+}
+
+/// Mask of all ones when `a < b`, zero otherwise, without branching
+fn ct_lt(a: u8, b: u8) -> u8 {
+    (((a as u16).wrapping_sub(b as u16)) >> 8) as u8
+}
+
+/// Mask of all ones when `lo <= c <= hi`
+fn ct_in_range(c: u8, lo: u8, hi: u8) -> u8 {
+    !ct_lt(c, lo) & !ct_lt(hi, c)
+}
+
+fn ct_eq(a: u8, b: u8) -> u8 {
+    ct_in_range(a, b, b)
+}
+
+/// Map a 6-bit value to its symbol without table lookups, so the memory
+/// access pattern does not depend on the (possibly secret) input
+fn encode_sextet(v: u8, alphabet: Base64Alphabet) -> u8 {
+    let (s62, s63) = alphabet.symbols();
+    let mut out = v.wrapping_add(b'A');
+    out = out.wrapping_add(!ct_lt(v, 26) & (b'a'.wrapping_sub(b'A').wrapping_sub(26)));
+    out = out.wrapping_sub(!ct_lt(v, 52) & (b'a' + 26 - b'0'));
+    out ^= ct_eq(v, 62) & (out ^ s62);
+    out ^= ct_eq(v, 63) & (out ^ s63);
+    out
+}
+
+/// Inverse of `encode_sextet`; returns 0xff for characters outside the
+/// alphabet
+fn decode_sextet(c: u8, alphabet: Base64Alphabet) -> u8 {
+    let (s62, s63) = alphabet.symbols();
+    let mut out = 0xffu8;
+    out ^= ct_in_range(c, b'A', b'Z') & (out ^ c.wrapping_sub(b'A'));
+    out ^= ct_in_range(c, b'a', b'z') & (out ^ c.wrapping_sub(b'a').wrapping_add(26));
+    out ^= ct_in_range(c, b'0', b'9') & (out ^ c.wrapping_sub(b'0').wrapping_add(52));
+    out ^= ct_eq(c, s62) & (out ^ 62);
+    out ^= ct_eq(c, s63) & (out ^ 63);
+    out
+}
+
+pub fn base64_encode(data: &[u8], alphabet: Base64Alphabet) -> String {
+    let mut out = Vec::with_capacity((data.len() + 2) / 3 * 4);
+
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let sextets = [
+            b[0] >> 2,
+            (b[0] & 0x03) << 4 | b[1] >> 4,
+            (b[1] & 0x0f) << 2 | b[2] >> 6,
+            b[2] & 0x3f,
+        ];
+
+        let symbols = chunk.len() + 1;
+        for &sextet in &sextets[..symbols] {
+            out.push(encode_sextet(sextet, alphabet));
+        }
+        if alphabet.padded() {
+            out.extend(std::iter::repeat(b'=').take(4 - symbols));
+        }
+    }
+
+    String::from_utf8(out).expect("base64 output is ASCII")
+}
+
+/// Decode base64. Padding is accepted but not required for either
+/// alphabet. Invalid characters are detected without data-dependent
+/// branches; only the error position of the first bad character leaks.
+pub fn base64_decode(input: &str, alphabet: Base64Alphabet) -> EncodingResult<Vec<u8>> {
+    let bytes = input.as_bytes();
+    let trimmed = match bytes.iter().rposition(|&c| c != b'=') {
+        Some(last) => &bytes[..=last],
+        None => &bytes[..0],
+    };
+
+    let padding = bytes.len() - trimmed.len();
+    if padding > 2 || (padding > 0 && bytes.len() % 4 != 0) {
+        return Err(EncodingError::InvalidPadding);
+    }
+    if trimmed.len() % 4 == 1 {
+        return Err(EncodingError::InvalidLength(input.len()));
+    }
+
+    let mut out = Vec::with_capacity(trimmed.len() * 3 / 4);
+    let mut invalid = 0u8;
+    let mut first_invalid = None;
+
+    for chunk in trimmed.chunks(4) {
+        let mut acc = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let v = decode_sextet(c, alphabet);
+            let bad = ct_eq(v, 0xff);
+            invalid |= bad;
+            if bad != 0 && first_invalid.is_none() {
+                first_invalid = Some(out.len() / 3 * 4 + i);
+            }
+            acc |= ((v & 0x3f) as u32) << (18 - 6 * i);
+        }
+
+        let decoded = [(acc >> 16) as u8, (acc >> 8) as u8, acc as u8];
+        out.extend_from_slice(&decoded[..chunk.len() - 1]);
+    }
+
+    if invalid != 0 {
+        return Err(EncodingError::InvalidCharacter {
+            position: first_invalid.unwrap_or(0),
+        });
+    }
+
+    Ok(out)
+}
+
+// Hex
+
+/// Lowercase hex encoding
+pub fn hex_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() * 2);
+    for &b in data {
+        out.push(hex_digit(b >> 4) as char);
+        out.push(hex_digit(b & 0x0f) as char);
+    }
+    out
+}
+
+fn hex_digit(v: u8) -> u8 {
+    // 0-9 -> '0'-'9', 10-15 -> 'a'-'f' without branching
+    v + b'0' + (!ct_lt(v, 10) & (b'a' - b'0' - 10))
+}
+
+fn hex_value(c: u8) -> u8 {
+    let mut out = 0xffu8;
+    out ^= ct_in_range(c, b'0', b'9') & (out ^ c.wrapping_sub(b'0'));
+    out ^= ct_in_range(c, b'a', b'f') & (out ^ c.wrapping_sub(b'a').wrapping_add(10));
+    out ^= ct_in_range(c, b'A', b'F') & (out ^ c.wrapping_sub(b'A').wrapping_add(10));
+    out
+}
+
+/// Decode hex in either case
+pub fn hex_decode(input: &str) -> EncodingResult<Vec<u8>> {
+    let bytes = input.as_bytes();
+    if bytes.len() % 2 != 0 {
+        return Err(EncodingError::InvalidLength(bytes.len()));
+    }
+
+    let mut out = Vec::with_capacity(bytes.len() / 2);
+    for (i, pair) in bytes.chunks(2).enumerate() {
+        let (hi, lo) = (hex_value(pair[0]), hex_value(pair[1]));
+        if hi == 0xff {
+            return Err(EncodingError::InvalidCharacter { position: i * 2 });
+        }
+        if lo == 0xff {
+            return Err(EncodingError::InvalidCharacter { position: i * 2 + 1 });
+        }
+        out.push(hi << 4 | lo);
+    }
+
+    Ok(out)
+}
+
+// Varints (LEB128)
+
+/// Append `value` as unsigned LEB128
+pub fn write_uvarint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Append `value` as signed LEB128
+pub fn write_ivarint(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Read an unsigned LEB128 value, returning it and the bytes consumed
+pub fn read_uvarint(input: &[u8]) -> EncodingResult<(u64, usize)> {
+    let mut value = 0u64;
+
+    for (i, &byte) in input.iter().enumerate() {
+        let shift = 7 * i as u32;
+        let bits = (byte & 0x7f) as u64;
+        if shift >= 64 || (shift == 63 && bits > 1) {
+            return Err(EncodingError::Overflow);
+        }
+
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+
+    Err(EncodingError::UnexpectedEnd)
+}
+
+/// Read a signed LEB128 value, returning it and the bytes consumed
+pub fn read_ivarint(input: &[u8]) -> EncodingResult<(i64, usize)> {
+    let mut value = 0i64;
+
+    for (i, &byte) in input.iter().enumerate() {
+        let shift = 7 * i as u32;
+        let bits = byte & 0x7f;
+        // The 10th byte holds bit 63 and its sign extension, so it is all
+        // zeros or all ones
+        if shift >= 64 || (shift == 63 && bits != 0 && bits != 0x7f) {
+            return Err(EncodingError::Overflow);
+        }
+
+        value |= (bits as i64) << shift;
+        if byte & 0x80 == 0 {
+            // Sign-extend from the last byte's sign bit
+            if shift + 7 < 64 && byte & 0x40 != 0 {
+                value |= -1i64 << (shift + 7);
+            }
+            return Ok((value, i + 1));
+        }
+    }
+
+    Err(EncodingError::UnexpectedEnd)
+}
+
+/// Number of bytes `write_uvarint` produces for `value`
+pub fn uvarint_len(value: u64) -> usize {
+    let bits = 64 - value.leading_zeros() as usize;
+    bits.max(1).div_ceil(7)
+}
+
+/// ZigZag-map a signed value so small magnitudes encode in few bytes as an
+/// unsigned varint
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+pub fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uvarint(value: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_uvarint(&mut out, value);
+        out
+    }
+
+    fn ivarint(value: i64) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_ivarint(&mut out, value);
+        out
+    }
+
+    fn ten_bytes(fill: u8, last: u8) -> Vec<u8> {
+        let mut bytes = vec![fill; 9];
+        bytes.push(last);
+        bytes
+    }
+
+    #[test]
+    fn test_uvarint_vectors() {
+        let vectors: [(u64, Vec<u8>); 7] = [
+            (0, vec![0x00]),
+            (1, vec![0x01]),
+            (127, vec![0x7f]),
+            (128, vec![0x80, 0x01]),
+            (300, vec![0xac, 0x02]),
+            (624_485, vec![0xe5, 0x8e, 0x26]),
+            (u64::MAX, ten_bytes(0xff, 0x01)),
+        ];
+        for (value, bytes) in vectors {
+            assert_eq!(uvarint(value), bytes);
+            assert_eq!(uvarint_len(value), bytes.len());
+            assert_eq!(read_uvarint(&bytes), Ok((value, bytes.len())));
+        }
+
+        // Trailing input is left for the caller
+        assert_eq!(read_uvarint(&[0xac, 0x02, 0xff]), Ok((300, 2)));
+    }
+
+    #[test]
+    fn test_uvarint_malformed() {
+        assert_eq!(read_uvarint(&[]), Err(EncodingError::UnexpectedEnd));
+        assert_eq!(read_uvarint(&[0x80]), Err(EncodingError::UnexpectedEnd));
+        assert_eq!(read_uvarint(&ten_bytes(0xff, 0xff)[..9]), Err(EncodingError::UnexpectedEnd));
+        assert_eq!(read_uvarint(&ten_bytes(0xff, 0x02)), Err(EncodingError::Overflow));
+        assert_eq!(read_uvarint(&ten_bytes(0x80, 0x7f)), Err(EncodingError::Overflow));
+        let mut eleven = ten_bytes(0x80, 0x80);
+        eleven.push(0x00);
+        assert_eq!(read_uvarint(&eleven), Err(EncodingError::Overflow));
+    }
+
+    #[test]
+    fn test_ivarint_vectors() {
+        let vectors: [(i64, Vec<u8>); 9] = [
+            (0, vec![0x00]),
+            (-1, vec![0x7f]),
+            (63, vec![0x3f]),
+            (64, vec![0xc0, 0x00]),
+            (-64, vec![0x40]),
+            (-65, vec![0xbf, 0x7f]),
+            (-123_456, vec![0xc0, 0xbb, 0x78]),
+            (i64::MAX, ten_bytes(0xff, 0x00)),
+            (i64::MIN, ten_bytes(0x80, 0x7f)),
+        ];
+        for (value, bytes) in vectors {
+            assert_eq!(ivarint(value), bytes);
+            assert_eq!(read_ivarint(&bytes), Ok((value, bytes.len())));
+        }
+    }
+
+    #[test]
+    fn test_ivarint_malformed() {
+        assert_eq!(read_ivarint(&[]), Err(EncodingError::UnexpectedEnd));
+        assert_eq!(read_ivarint(&[0xc0]), Err(EncodingError::UnexpectedEnd));
+        // 10th bytes whose bits past the 64th are not the sign extension
+        for last in [0x01, 0x02, 0x3f, 0x40, 0x7e] {
+            assert_eq!(read_ivarint(&ten_bytes(0x80, last)), Err(EncodingError::Overflow), "{:#04x}", last);
+        }
+        let mut eleven = ten_bytes(0xff, 0xff);
+        eleven.push(0x7f);
+        assert_eq!(read_ivarint(&eleven), Err(EncodingError::Overflow));
+    }
+
+    #[test]
+    fn test_zigzag_vectors() {
+        let vectors = [
+            (0, 0),
+            (-1, 1),
+            (1, 2),
+            (-2, 3),
+            (2_147_483_647, 4_294_967_294),
+            (-2_147_483_648, 4_294_967_295),
+            (i64::MAX, u64::MAX - 1),
+            (i64::MIN, u64::MAX),
+        ];
+        for (signed, unsigned) in vectors {
+            assert_eq!(zigzag_encode(signed), unsigned);
+            assert_eq!(zigzag_decode(unsigned), signed);
+        }
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex_encode(&[]), "");
+        assert_eq!(hex_encode(&[0x00, 0x0f, 0xde, 0xad, 0xbe, 0xef]), "000fdeadbeef");
+        assert_eq!(hex_decode("000fDEADbeef"), Ok(vec![0x00, 0x0f, 0xde, 0xad, 0xbe, 0xef]));
+
+        assert_eq!(hex_decode("abc"), Err(EncodingError::InvalidLength(3)));
+        assert_eq!(hex_decode("zz"), Err(EncodingError::InvalidCharacter { position: 0 }));
+        assert_eq!(hex_decode("0g"), Err(EncodingError::InvalidCharacter { position: 1 }));
+        assert_eq!(hex_decode("00 1"), Err(EncodingError::InvalidCharacter { position: 2 }));
+    }
+
+    #[test]
+    fn test_base64_rfc4648_vectors() {
+        // RFC 4648 section 10
+        let vectors = [
+            ("", "", ""),
+            ("f", "Zg==", "Zg"),
+            ("fo", "Zm8=", "Zm8"),
+            ("foo", "Zm9v", "Zm9v"),
+            ("foob", "Zm9vYg==", "Zm9vYg"),
+            ("fooba", "Zm9vYmE=", "Zm9vYmE"),
+            ("foobar", "Zm9vYmFy", "Zm9vYmFy"),
+        ];
+        for (data, standard, url_safe) in vectors {
+            assert_eq!(base64_encode(data.as_bytes(), Base64Alphabet::Standard), standard);
+            assert_eq!(base64_encode(data.as_bytes(), Base64Alphabet::UrlSafe), url_safe);
+            assert_eq!(base64_decode(standard, Base64Alphabet::Standard).unwrap(), data.as_bytes());
+            assert_eq!(base64_decode(url_safe, Base64Alphabet::UrlSafe).unwrap(), data.as_bytes());
+            // Padding is optional either way
+            assert_eq!(base64_decode(url_safe, Base64Alphabet::Standard).unwrap(), data.as_bytes());
+            assert_eq!(base64_decode(standard, Base64Alphabet::UrlSafe).unwrap(), data.as_bytes());
+        }
+
+        assert_eq!(base64_encode(&[0xfb, 0xff], Base64Alphabet::Standard), "+/8=");
+        assert_eq!(base64_encode(&[0xfb, 0xff], Base64Alphabet::UrlSafe), "-_8");
+    }
+
+    #[test]
+    fn test_base64_malformed() {
+        let decode = |input| base64_decode(input, Base64Alphabet::Standard);
+        assert_eq!(decode("Z"), Err(EncodingError::InvalidLength(1)));
+        assert_eq!(decode("Zm9vY"), Err(EncodingError::InvalidLength(5)));
+        assert_eq!(decode("Zg="), Err(EncodingError::InvalidPadding));
+        assert_eq!(decode("Zg==="), Err(EncodingError::InvalidPadding));
+        assert_eq!(decode("Zm9v!A=="), Err(EncodingError::InvalidCharacter { position: 4 }));
+        assert_eq!(decode("Zm=vYg=="), Err(EncodingError::InvalidCharacter { position: 2 }));
+        assert_eq!(
+            base64_decode("+/8=", Base64Alphabet::UrlSafe),
+            Err(EncodingError::InvalidCharacter { position: 0 })
+        );
+    }
+}
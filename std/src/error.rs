@@ -0,0 +1,184 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+/// Boxed error from any std module
+pub type BoxError = Box<dyn StdError + Send + Sync + 'static>;
+
+/// Result type for application code mixing errors from several std modules
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Error wrapper that any std error converts into with `?`, carrying the
+/// original error plus the context messages added on the way up.
+///
+/// `Error` deliberately does not implement `std::error::Error` itself;
+/// otherwise the blanket `From` conversion below would overlap with the
+/// reflexive `From<T> for T`.
+pub struct Error {
+    inner: BoxError,
+    context: Vec<String>,
+}
+
+impl Error {
+    pub fn new<E>(error: E) -> Self
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        Error {
+            inner: Box::new(error),
+            context: Vec::new(),
+        }
+    }
+
+    /// Error carrying only a message
+    pub fn msg(message: &str) -> Self {
+        Error {
+            inner: message.to_string().into(),
+            context: Vec::new(),
+        }
+    }
+
+    /// Wrap with a description of what was being done when it failed
+    pub fn context(mut self, context: &str) -> Self {
+        self.context.push(context.to_string());
+        self
+    }
+
+    /// Context messages, outermost first
+    pub fn contexts(&self) -> impl Iterator<Item = &str> {
+        self.context.iter().rev().map(|c| c.as_str())
+    }
+
+    /// The wrapped error followed by each of its `source()`s
+    pub fn chain(&self) -> Chain<'_> {
+        Chain {
+            next: Some(self.inner.as_ref()),
+        }
+    }
+
+    /// The innermost error in the source chain
+    pub fn root_cause(&self) -> &(dyn StdError + 'static) {
+        self.chain().last().unwrap_or(self.inner.as_ref())
+    }
+
+    /// Check whether the wrapped error is of a particular module's type,
+    /// e.g. `err.downcast_ref::<IOError>()`
+    pub fn downcast_ref<E: StdError + 'static>(&self) -> Option<&E> {
+        self.inner.downcast_ref::<E>()
+    }
+
+    pub fn into_inner(self) -> BoxError {
+        self.inner
+    }
+}
+
+impl<E> From<E> for Error
+where
+    E: StdError + Send + Sync + 'static,
+{
+    fn from(error: E) -> Self {
+        Error::new(error)
+    }
+}
+
+impl fmt::Display for Error {
+    /// `{}` prints the outermost context (or the error itself);
+    /// `{:#}` prints the whole chain separated by `: `
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !f.alternate() {
+            return match self.context.last() {
+                Some(context) => write!(f, "{}", context),
+                None => write!(f, "{}", self.inner),
+            };
+        }
+
+        for context in self.contexts() {
+            write!(f, "{}: ", context)?;
+        }
+
+        let mut first = true;
+        for error in self.chain() {
+            if !first {
+                write!(f, ": ")?;
+            }
+            write!(f, "{}", error)?;
+            first = false;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut messages = self.contexts().map(|c| c.to_string())
+            .chain(self.chain().map(|e| e.to_string()));
+
+        if let Some(first) = messages.next() {
+            write!(f, "{}", first)?;
+        }
+
+        let mut causes = messages.peekable();
+        if causes.peek().is_some() {
+            write!(f, "\n\nCaused by:")?;
+            for (i, cause) in causes.enumerate() {
+                write!(f, "\n  {}: {}", i, cause)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Iterator over an error and its sources
+pub struct Chain<'a> {
+    next: Option<&'a (dyn StdError + 'static)>,
+}
+
+impl<'a> Iterator for Chain<'a> {
+    type Item = &'a (dyn StdError + 'static);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next?;
+        self.next = current.source();
+        Some(current)
+    }
+}
+
+/// Adds `.context(...)` to any `Result` whose error converts into `Error`
+pub trait Context<T> {
+    fn context(self, context: &str) -> Result<T>;
+
+    /// Like `context`, but only builds the message on failure
+    fn with_context<F>(self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> String;
+}
+
+impl<T, E> Context<T> for std::result::Result<T, E>
+where
+    E: Into<Error>,
+{
+    fn context(self, context: &str) -> Result<T> {
+        self.map_err(|e| e.into().context(context))
+    }
+
+    fn with_context<F>(self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> String,
+    {
+        self.map_err(|e| e.into().context(&f()))
+    }
+}
+
+impl<T> Context<T> for Option<T> {
+    fn context(self, context: &str) -> Result<T> {
+        self.ok_or_else(|| Error::msg(context))
+    }
+
+    fn with_context<F>(self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> String,
+    {
+        self.ok_or_else(|| Error::msg(&f()))
+    }
+}
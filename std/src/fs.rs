@@ -1,3 +1,5 @@
+pub mod ignore;
+
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
@@ -44,6 +46,31 @@ impl SafeFile {
     }
 }
 
+impl crate::io::stream::Read for SafeFile {
+    fn read(&mut self, buf: &mut [u8]) -> crate::io::IOResult<usize> {
+        match &mut self.inner {
+            Some(file) => file.read(buf).map_err(crate::io::IOError::from),
+            None => Err(crate::io::IOError::Other("File not open".to_string())),
+        }
+    }
+}
+
+impl crate::io::stream::Write for SafeFile {
+    fn write(&mut self, buf: &[u8]) -> crate::io::IOResult<usize> {
+        match &mut self.inner {
+            Some(file) => file.write(buf).map_err(crate::io::IOError::from),
+            None => Err(crate::io::IOError::Other("File not open".to_string())),
+        }
+    }
+    
+    fn flush(&mut self) -> crate::io::IOResult<()> {
+        match &mut self.inner {
+            Some(file) => file.flush().map_err(crate::io::IOError::from),
+            None => Err(crate::io::IOError::Other("File not open".to_string())),
+        }
+    }
+}
+
 impl Drop for SafeFile {
     fn drop(&mut self) {
         if self.inner.is_some() {
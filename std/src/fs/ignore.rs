@@ -0,0 +1,241 @@
+//! `.zaitunignore` files: gitignore-style patterns for the files tools skip
+//! when they walk a project, such as build output and vendored packages.
+//!
+//! Each line of the file is a pattern; blank lines and lines starting with
+//! `#` are skipped. As in gitignore:
+//!
+//! - `*` matches within one path component, `?` one character and `[a-z]`
+//!   (or `[!a-z]`) one character of a set
+//! - `**` as a whole component matches any number of components
+//! - a pattern with a `/` other than a trailing one is relative to the
+//!   directory of the ignore file; otherwise it matches a name at any depth
+//! - a trailing `/` only matches directories
+//! - `!` re-includes what an earlier pattern excluded, though not a file
+//!   under an excluded directory, which is never walked
+//!
+//! The last matching pattern decides. A leading `\` escapes a literal `#`
+//! or `!`.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the ignore file read from a project root
+pub const IGNORE_FILE: &str = ".zaitunignore";
+
+/// Skipped in every project, before any ignore file or tool patterns:
+/// version control data and the build output directory
+pub const DEFAULT_PATTERNS: &[&str] = &[".git/", "/target/"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    /// Path components, `**` included
+    components: Vec<String>,
+    negated: bool,
+    dir_only: bool,
+    /// Matched against the whole relative path rather than any suffix
+    anchored: bool,
+}
+
+impl Pattern {
+    fn parse(line: &str) -> Option<Pattern> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let components: Vec<String> = line
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .map(str::to_string)
+            .collect();
+        if components.is_empty() {
+            return None;
+        }
+
+        Some(Pattern {
+            components,
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &[&str], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            return match_components(&self.components, path);
+        }
+        // A name pattern matches at any depth
+        (0..path.len()).any(|start| match_components(&self.components, &path[start..]))
+    }
+}
+
+fn match_components(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        // A trailing `**` matches what is inside, not the directory itself
+        Some((first, rest)) if first == "**" && rest.is_empty() => !path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_components(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((name, path)) => match_glob(first.as_bytes(), name.as_bytes()) && match_components(rest, path),
+            None => false,
+        },
+    }
+}
+
+/// `*`, `?` and `[...]` within one component
+fn match_glob(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| match_glob(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && match_glob(rest, &name[1..]),
+        Some((b'[', rest)) => match (name.split_first(), match_class(rest)) {
+            (Some((&c, name)), Some((class, rest))) => class.contains(c) && match_glob(rest, name),
+            (None, _) => false,
+            // No closing `]`, so a literal `[`
+            (Some((&c, name)), None) => c == b'[' && match_glob(rest, name),
+        },
+        Some((b'\\', [escaped, rest @ ..])) => name.first() == Some(escaped) && match_glob(rest, &name[1..]),
+        Some((&c, rest)) => name.first() == Some(&c) && match_glob(rest, &name[1..]),
+    }
+}
+
+struct Class<'a> {
+    negated: bool,
+    items: &'a [u8],
+}
+
+impl Class<'_> {
+    fn contains(&self, c: u8) -> bool {
+        let mut found = false;
+        let mut i = 0;
+        while i < self.items.len() {
+            if i + 2 < self.items.len() && self.items[i + 1] == b'-' {
+                found |= (self.items[i]..=self.items[i + 2]).contains(&c);
+                i += 3;
+            } else {
+                found |= self.items[i] == c;
+                i += 1;
+            }
+        }
+        found != self.negated
+    }
+}
+
+/// The class after a `[` and the pattern after its `]`
+fn match_class(pattern: &[u8]) -> Option<(Class<'_>, &[u8])> {
+    let (negated, pattern) = match pattern.first() {
+        Some(b'!') | Some(b'^') => (true, &pattern[1..]),
+        _ => (false, pattern),
+    };
+    // A `]` straight after the `[` is part of the set
+    let end = 1 + pattern.get(1..)?.iter().position(|&c| c == b']')?;
+    Some((
+        Class {
+            negated,
+            items: &pattern[..end],
+        },
+        &pattern[end + 1..],
+    ))
+}
+
+/// Patterns for the paths under one root
+#[derive(Debug, Clone)]
+pub struct Ignore {
+    root: PathBuf,
+    patterns: Vec<Pattern>,
+}
+
+impl Ignore {
+    /// Only the default patterns
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let mut ignore = Ignore {
+            root: root.into(),
+            patterns: Vec::new(),
+        };
+        ignore.add_patterns(DEFAULT_PATTERNS);
+        ignore
+    }
+
+    /// The default patterns followed by those of `root`'s ignore file, if
+    /// it has one
+    pub fn load(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let mut ignore = Ignore::new(root);
+        match fs::read_to_string(ignore.root.join(IGNORE_FILE)) {
+            Ok(text) => ignore.add_patterns(text.lines()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(ignore)
+    }
+
+    /// Add a tool's own patterns. They come after the ignore file's, so
+    /// they can re-include what it excludes.
+    pub fn add_patterns<I, S>(&mut self, patterns: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.patterns
+            .extend(patterns.into_iter().filter_map(|pattern| Pattern::parse(pattern.as_ref())));
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Whether the patterns exclude `path` itself, not counting its parent
+    /// directories. For walks, which never enter an excluded directory.
+    /// Paths outside the root are never excluded.
+    pub fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        match self.relative_components(path) {
+            Some(components) if !components.is_empty() => self.matches_components(&components, is_dir),
+            _ => false,
+        }
+    }
+
+    /// Whether `path` or one of its parent directories is excluded
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let Some(components) = self.relative_components(path) else {
+            return false;
+        };
+        (1..=components.len()).any(|len| {
+            let is_dir = len < components.len() || is_dir;
+            self.matches_components(&components[..len], is_dir)
+        })
+    }
+
+    fn matches_components(&self, components: &[&str], is_dir: bool) -> bool {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(components, is_dir))
+            .is_some_and(|pattern| !pattern.negated)
+    }
+
+    fn relative_components<'a>(&self, path: &'a Path) -> Option<Vec<&'a str>> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        relative
+            .components()
+            .filter(|component| !matches!(component, Component::CurDir))
+            .map(|component| match component {
+                Component::Normal(name) => name.to_str(),
+                _ => None,
+            })
+            .collect()
+    }
+}
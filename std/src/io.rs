@@ -3,6 +3,8 @@ use std::io::{self, Read, Write, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use std::fmt;
 
+pub mod stream;
+
 /// Error type for I/O operations
 #[derive(Debug)]
 pub enum IOError {
@@ -11,6 +13,8 @@ pub enum IOError {
     AlreadyExists,
     InvalidInput,
     UnexpectedEof,
+    WouldBlock,
+    Interrupted,
     Other(String),
 }
 
@@ -22,6 +26,8 @@ impl From<io::Error> for IOError {
             io::ErrorKind::AlreadyExists => IOError::AlreadyExists,
             io::ErrorKind::InvalidInput => IOError::InvalidInput,
             io::ErrorKind::UnexpectedEof => IOError::UnexpectedEof,
+            io::ErrorKind::WouldBlock => IOError::WouldBlock,
+            io::ErrorKind::Interrupted => IOError::Interrupted,
             _ => IOError::Other(error.to_string()),
         }
     }
@@ -35,14 +41,30 @@ impl fmt::Display for IOError {
             IOError::AlreadyExists => write!(f, "File or directory already exists"),
             IOError::InvalidInput => write!(f, "Invalid input"),
             IOError::UnexpectedEof => write!(f, "Unexpected end of file"),
+            IOError::WouldBlock => write!(f, "Operation would block"),
+            IOError::Interrupted => write!(f, "Operation interrupted"),
             IOError::Other(message) => write!(f, "{}", message),
         }
     }
 }
 
+impl std::error::Error for IOError {}
+
 /// Result type for I/O operations
 pub type IOResult<T> = Result<T, IOError>;
 
+/// Write `message` to standard output. Compiler intrinsic: calls are
+/// lowered by codegen, this declaration documents the signature.
+pub fn print(message: &str) {
+    print!("{}", message);
+}
+
+/// Write `message` and a newline to standard output. Compiler intrinsic:
+/// calls are lowered by codegen, this declaration documents the signature.
+pub fn println(message: &str) {
+    println!("{}", message);
+}
+
 /// File system operations
 pub struct FileSystem;
 
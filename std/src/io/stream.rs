@@ -0,0 +1,365 @@
+use super::{File, IOError, IOResult};
+use std::io::{Read as StdRead, Write as StdWrite};
+
+/// Buffer size used by `copy` and friends
+pub const DEFAULT_BUF_SIZE: usize = 8 * 1024;
+
+/// Source of bytes.
+///
+/// `read` may return fewer bytes than requested; `Ok(0)` means end of
+/// input. Implementations that cannot make progress without blocking
+/// return `Err(IOError::WouldBlock)` instead of spinning.
+pub trait Read {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize>;
+
+    /// Fill `buf` completely or fail with `UnexpectedEof`
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> IOResult<()> {
+        while !buf.is_empty() {
+            match self.read(buf)? {
+                0 => return Err(IOError::UnexpectedEof),
+                n => buf = &mut buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Read until end of input, returning the number of bytes appended
+    fn read_to_end(&mut self, out: &mut Vec<u8>) -> IOResult<usize> {
+        let start = out.len();
+        let mut chunk = [0u8; DEFAULT_BUF_SIZE];
+        loop {
+            match self.read(&mut chunk)? {
+                0 => return Ok(out.len() - start),
+                n => out.extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
+
+    /// Stop after `limit` bytes
+    fn take(self, limit: u64) -> Take<Self>
+    where
+        Self: Sized,
+    {
+        Take { inner: self, remaining: limit }
+    }
+
+    /// Read `self` to its end, then continue with `next`
+    fn chain<R: Read>(self, next: R) -> Chain<Self, R>
+    where
+        Self: Sized,
+    {
+        Chain { first: self, second: next, first_done: false }
+    }
+
+    /// Copy everything read into `writer` as a side effect
+    fn tee<W: Write>(self, writer: W) -> Tee<Self, W>
+    where
+        Self: Sized,
+    {
+        Tee { reader: self, writer }
+    }
+}
+
+/// Sink for bytes.
+///
+/// `write` may accept fewer bytes than offered, which is how a slow
+/// consumer pushes back on its producer; callers that need everything
+/// written use `write_all`.
+pub trait Write {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize>;
+
+    fn flush(&mut self) -> IOResult<()>;
+
+    fn write_all(&mut self, mut buf: &[u8]) -> IOResult<()> {
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => return Err(IOError::Other("Writer accepted no data".to_string())),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + ?Sized> Read for &mut R {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        (**self).read(buf)
+    }
+}
+
+impl<W: Write + ?Sized> Write for &mut W {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        (**self).write(buf)
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        (**self).flush()
+    }
+}
+
+pub struct Take<R> {
+    inner: R,
+    remaining: u64,
+}
+
+impl<R> Take<R> {
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for Take<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+
+        let max = (buf.len() as u64).min(self.remaining) as usize;
+        let n = self.inner.read(&mut buf[..max])?;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+    first_done: bool,
+}
+
+impl<A: Read, B: Read> Read for Chain<A, B> {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        if !self.first_done {
+            match self.first.read(buf)? {
+                0 if !buf.is_empty() => self.first_done = true,
+                n => return Ok(n),
+            }
+        }
+        self.second.read(buf)
+    }
+}
+
+pub struct Tee<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R, W> Tee<R, W> {
+    pub fn into_parts(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+impl<R: Read, W: Write> Read for Tee<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        let n = self.reader.read(buf)?;
+        self.writer.write_all(&buf[..n])?;
+        Ok(n)
+    }
+}
+
+/// Copy `reader` to `writer` until end of input, returning bytes copied
+pub fn copy<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> IOResult<u64> {
+    copy_with_progress(reader, writer, |_| true)
+}
+
+/// Like `copy`, calling `progress` with the running total after every
+/// chunk. Returning `false` from the callback cancels the copy.
+pub fn copy_with_progress<R, W, F>(reader: &mut R, writer: &mut W, mut progress: F) -> IOResult<u64>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(u64) -> bool,
+{
+    let mut buf = [0u8; DEFAULT_BUF_SIZE];
+    let mut total = 0u64;
+
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(IOError::Interrupted) => continue,
+            Err(e) => return Err(e),
+        };
+
+        writer.write_all(&buf[..n])?;
+        total += n as u64;
+
+        if !progress(total) {
+            return Err(IOError::Other("Copy cancelled".to_string()));
+        }
+    }
+
+    writer.flush()?;
+    Ok(total)
+}
+
+/// In-memory reader over a byte buffer
+pub struct MemoryReader {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl MemoryReader {
+    pub fn new(data: Vec<u8>) -> Self {
+        MemoryReader { data, position: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+}
+
+impl Read for MemoryReader {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        let n = buf.len().min(self.remaining());
+        buf[..n].copy_from_slice(&self.data[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+impl Read for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        let n = buf.len().min(self.len());
+        buf[..n].copy_from_slice(&self[..n]);
+        *self = &self[n..];
+        Ok(n)
+    }
+}
+
+impl Write for Vec<u8> {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        Ok(())
+    }
+}
+
+/// Writer that accepts at most `capacity` unflushed bytes, for exercising
+/// backpressure handling in tests and bounded in-memory pipes
+pub struct BoundedBuffer {
+    data: Vec<u8>,
+    capacity: usize,
+}
+
+impl BoundedBuffer {
+    pub fn new(capacity: usize) -> Self {
+        BoundedBuffer { data: Vec::new(), capacity }
+    }
+
+    /// Remove and return everything buffered so far
+    pub fn drain(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.data)
+    }
+}
+
+impl Write for BoundedBuffer {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        let space = self.capacity - self.data.len();
+        if space == 0 {
+            return Err(IOError::WouldBlock);
+        }
+
+        let n = buf.len().min(space);
+        self.data.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        Ok(())
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        self.inner.read(buf).map_err(IOError::from)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.inner.write(buf).map_err(IOError::from)
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        self.inner.flush().map_err(IOError::from)
+    }
+}
+
+/// Result of polling an asynchronous source or sink
+#[derive(Debug, PartialEq)]
+pub enum Poll<T> {
+    Ready(T),
+    /// No progress possible yet; the scheduler should poll again later
+    Pending,
+}
+
+/// Asynchronous sequence of items, polled by cooperative tasks on the
+/// `concurrency::Scheduler`
+pub trait Stream {
+    type Item;
+
+    /// `Ready(None)` marks the end of the stream
+    fn poll_next(&mut self) -> Poll<Option<Self::Item>>;
+}
+
+/// Asynchronous consumer of items. A sink that is full returns `Pending`
+/// from `poll_ready`, which stops `pump` from pulling more input.
+pub trait Sink<T> {
+    fn poll_ready(&mut self) -> Poll<IOResult<()>>;
+
+    /// Only valid after `poll_ready` returned `Ready(Ok(()))`
+    fn start_send(&mut self, item: T) -> IOResult<()>;
+
+    fn poll_close(&mut self) -> Poll<IOResult<()>>;
+}
+
+/// Build a scheduler task moving items from `stream` to `sink`. The task
+/// never takes an item it cannot hand on, so a slow sink throttles the
+/// stream instead of buffering without bound.
+pub fn pump<S, K>(mut stream: S, mut sink: K) -> impl FnMut() -> bool + Send
+where
+    S: Stream + Send,
+    K: Sink<S::Item> + Send,
+{
+    let mut closing = false;
+
+    move || {
+        if closing {
+            return !matches!(sink.poll_close(), Poll::Pending);
+        }
+
+        loop {
+            match sink.poll_ready() {
+                Poll::Pending => return false,
+                Poll::Ready(Err(_)) => return true,
+                Poll::Ready(Ok(())) => {}
+            }
+
+            match stream.poll_next() {
+                Poll::Pending => return false,
+                Poll::Ready(None) => {
+                    closing = true;
+                    return !matches!(sink.poll_close(), Poll::Pending);
+                }
+                Poll::Ready(Some(item)) => {
+                    if sink.start_send(item).is_err() {
+                        return true;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use crate::io::stream::{Read, Write};
+use crate::io::{IOError, IOResult};
+
+/// Largest frame `read_frame` accepts by default. Guards against a corrupt
+/// or hostile length prefix making the reader allocate gigabytes.
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Write `payload` as one frame: a little-endian `u32` length followed by
+/// the bytes
+pub fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> IOResult<()> {
+    let len = u32::try_from(payload.len())
+        .map_err(|_| IOError::Other(format!("Frame of {} bytes is too large", payload.len())))?;
+
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+/// Read one frame. Returns `Ok(None)` if the peer closed the connection
+/// cleanly between frames; a connection closed mid-frame is
+/// `UnexpectedEof`.
+pub fn read_frame<R: Read>(reader: &mut R, max_size: usize) -> IOResult<Option<Vec<u8>>> {
+    let mut header = [0u8; 4];
+    let mut filled = 0;
+    while filled < header.len() {
+        match reader.read(&mut header[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(IOError::UnexpectedEof),
+            Ok(n) => filled += n,
+            Err(IOError::Interrupted) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    let len = u32::from_le_bytes(header) as usize;
+    if len > max_size {
+        return Err(IOError::Other(format!(
+            "Frame of {} bytes exceeds limit of {}",
+            len, max_size
+        )));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+/// Message-oriented wrapper over a byte stream
+pub struct FramedStream<S> {
+    inner: S,
+    max_frame_size: usize,
+}
+
+impl<S: Read + Write> FramedStream<S> {
+    pub fn new(inner: S) -> Self {
+        FramedStream {
+            inner,
+            max_frame_size: MAX_FRAME_SIZE,
+        }
+    }
+
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    pub fn send(&mut self, payload: &[u8]) -> IOResult<()> {
+        write_frame(&mut self.inner, payload)
+    }
+
+    /// Next message, or `None` once the peer has hung up
+    pub fn recv(&mut self) -> IOResult<Option<Vec<u8>>> {
+        read_frame(&mut self.inner, self.max_frame_size)
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
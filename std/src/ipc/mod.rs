@@ -0,0 +1,46 @@
+//! Local inter-process communication without TCP ports: Unix domain
+//! sockets on Unix, named pipes on Windows. `LocalListener`/`LocalStream`
+//! pick whichever the platform has, so tools can address an endpoint by a
+//! short name and let this module place it.
+
+use std::path::PathBuf;
+
+pub mod framing;
+pub mod shm;
+
+#[cfg(unix)]
+pub mod unix;
+
+#[cfg(windows)]
+pub mod windows;
+
+pub use framing::{read_frame, write_frame, FramedStream, MAX_FRAME_SIZE};
+pub use shm::{SharedMem, SpscRing};
+
+#[cfg(unix)]
+pub use unix::{PeerCredentials, UnixDomainListener as LocalListener, UnixDomainSocket as LocalStream};
+
+#[cfg(windows)]
+pub use windows::{NamedPipe as LocalStream, NamedPipeServer as LocalListener};
+
+/// Platform address for the endpoint called `name`: a socket path under
+/// the per-user runtime directory on Unix, a pipe name on Windows
+pub fn endpoint(name: &str) -> PathBuf {
+    let file_name = format!("zaitun-{}", sanitize(name));
+
+    if cfg!(windows) {
+        return PathBuf::from(format!(r"\\.\pipe\{}", file_name));
+    }
+
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    dir.join(format!("{}.sock", file_name))
+}
+
+/// Keep endpoint names to characters valid in both paths and pipe names
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+        .collect()
+}
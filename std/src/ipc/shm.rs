@@ -0,0 +1,460 @@
+//! Named shared memory and a single-producer/single-consumer ring buffer
+//! laid out inside it, so one process can stream messages to another
+//! without a syscall or copy through the kernel per message.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::io::{IOError, IOResult};
+
+/// Mapping of a named shared memory segment.
+///
+/// The creating process owns the name and removes it on drop; processes
+/// that `open` it only unmap their view.
+pub struct SharedMem {
+    ptr: *mut u8,
+    len: usize,
+    name: String,
+    owner: bool,
+    #[cfg(windows)]
+    handle: sys::Handle,
+}
+
+// The mapping is plain memory; synchronising access is up to the user
+unsafe impl Send for SharedMem {}
+unsafe impl Sync for SharedMem {}
+
+impl SharedMem {
+    /// Create a zero-filled segment of `len` bytes. Fails with
+    /// `AlreadyExists` if the name is taken.
+    pub fn create(name: &str, len: usize) -> IOResult<Self> {
+        if len == 0 {
+            return Err(IOError::InvalidInput);
+        }
+        sys::create(&segment_name(name), len)
+    }
+
+    /// Map an existing segment created by another process
+    pub fn open(name: &str) -> IOResult<Self> {
+        sys::open(&segment_name(name))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    /// View of the segment's bytes.
+    ///
+    /// # Safety
+    /// No other process or thread may write the segment while the slice
+    /// is alive.
+    pub unsafe fn as_slice(&self) -> &[u8] {
+        std::slice::from_raw_parts(self.ptr, self.len)
+    }
+
+    /// Mutable view of the segment's bytes.
+    ///
+    /// # Safety
+    /// No other process or thread may access the segment while the slice
+    /// is alive.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn as_mut_slice(&self) -> &mut [u8] {
+        std::slice::from_raw_parts_mut(self.ptr, self.len)
+    }
+}
+
+impl Drop for SharedMem {
+    fn drop(&mut self) {
+        sys::close(self);
+    }
+}
+
+/// Platform segment names: POSIX wants a single leading slash, Windows a
+/// namespace prefix
+fn segment_name(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+
+    if cfg!(windows) {
+        format!("Local\\zaitun-{}", name)
+    } else {
+        format!("/zaitun-{}", name)
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use super::SharedMem;
+    use crate::io::{IOError, IOResult};
+    use std::ffi::CString;
+
+    fn last_error() -> IOError {
+        IOError::from(std::io::Error::last_os_error())
+    }
+
+    fn map(name: &str, flags: libc::c_int, len: Option<usize>) -> IOResult<SharedMem> {
+        let c_name = CString::new(name).map_err(|_| IOError::InvalidInput)?;
+        let owner = len.is_some();
+
+        // Valid NUL-terminated name; the descriptor is closed below
+        let fd = unsafe { libc::shm_open(c_name.as_ptr(), flags, 0o600) };
+        if fd < 0 {
+            return Err(last_error());
+        }
+
+        let unlink_on_error = |error: IOError| {
+            unsafe {
+                libc::close(fd);
+                if owner {
+                    libc::shm_unlink(c_name.as_ptr());
+                }
+            }
+            error
+        };
+
+        let len = match len {
+            Some(len) => {
+                if unsafe { libc::ftruncate(fd, len as libc::off_t) } != 0 {
+                    return Err(unlink_on_error(last_error()));
+                }
+                len
+            }
+            None => {
+                let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+                if unsafe { libc::fstat(fd, &mut stat) } != 0 {
+                    return Err(unlink_on_error(last_error()));
+                }
+                stat.st_size as usize
+            }
+        };
+
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(unlink_on_error(last_error()));
+        }
+
+        // The mapping keeps the segment alive without the descriptor
+        unsafe { libc::close(fd) };
+
+        Ok(SharedMem {
+            ptr: ptr as *mut u8,
+            len,
+            name: name.to_string(),
+            owner,
+        })
+    }
+
+    pub(super) fn create(name: &str, len: usize) -> IOResult<SharedMem> {
+        map(name, libc::O_CREAT | libc::O_EXCL | libc::O_RDWR, Some(len))
+    }
+
+    pub(super) fn open(name: &str) -> IOResult<SharedMem> {
+        map(name, libc::O_RDWR, None)
+    }
+
+    pub(super) fn close(mem: &mut SharedMem) {
+        unsafe {
+            libc::munmap(mem.ptr as *mut libc::c_void, mem.len);
+            if mem.owner {
+                if let Ok(name) = CString::new(mem.name.as_str()) {
+                    libc::shm_unlink(name.as_ptr());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use super::SharedMem;
+    use crate::io::{IOError, IOResult};
+    use std::ffi::{c_void, OsStr};
+    use std::os::windows::ffi::OsStrExt;
+
+    pub type Handle = *mut c_void;
+
+    const PAGE_READWRITE: u32 = 0x04;
+    const FILE_MAP_ALL_ACCESS: u32 = 0x000f_001f;
+    const ERROR_ALREADY_EXISTS: i32 = 183;
+    const INVALID_HANDLE_VALUE: Handle = -1isize as Handle;
+
+    #[repr(C)]
+    struct MemoryBasicInformation {
+        base_address: *mut c_void,
+        allocation_base: *mut c_void,
+        allocation_protect: u32,
+        partition_id: u16,
+        region_size: usize,
+        state: u32,
+        protect: u32,
+        kind: u32,
+    }
+
+    extern "system" {
+        fn CreateFileMappingW(file: Handle, attrs: *mut c_void, protect: u32, size_high: u32, size_low: u32, name: *const u16) -> Handle;
+        fn OpenFileMappingW(access: u32, inherit: i32, name: *const u16) -> Handle;
+        fn MapViewOfFile(mapping: Handle, access: u32, offset_high: u32, offset_low: u32, len: usize) -> *mut c_void;
+        fn UnmapViewOfFile(base: *const c_void) -> i32;
+        fn VirtualQuery(address: *const c_void, info: *mut MemoryBasicInformation, len: usize) -> usize;
+        fn CloseHandle(handle: Handle) -> i32;
+    }
+
+    fn wide(name: &str) -> Vec<u16> {
+        OsStr::new(name).encode_wide().chain(Some(0)).collect()
+    }
+
+    fn last_error() -> IOError {
+        IOError::from(std::io::Error::last_os_error())
+    }
+
+    pub(super) fn create(name: &str, len: usize) -> IOResult<SharedMem> {
+        let wide_name = wide(name);
+        let handle = unsafe {
+            CreateFileMappingW(
+                INVALID_HANDLE_VALUE,
+                std::ptr::null_mut(),
+                PAGE_READWRITE,
+                (len as u64 >> 32) as u32,
+                len as u32,
+                wide_name.as_ptr(),
+            )
+        };
+        if handle.is_null() {
+            return Err(last_error());
+        }
+        if std::io::Error::last_os_error().raw_os_error() == Some(ERROR_ALREADY_EXISTS) {
+            unsafe { CloseHandle(handle) };
+            return Err(IOError::AlreadyExists);
+        }
+
+        view(handle, name, len, true)
+    }
+
+    pub(super) fn open(name: &str) -> IOResult<SharedMem> {
+        let wide_name = wide(name);
+        let handle = unsafe { OpenFileMappingW(FILE_MAP_ALL_ACCESS, 0, wide_name.as_ptr()) };
+        if handle.is_null() {
+            return Err(last_error());
+        }
+        view(handle, name, 0, false)
+    }
+
+    fn view(handle: Handle, name: &str, len: usize, owner: bool) -> IOResult<SharedMem> {
+        let ptr = unsafe { MapViewOfFile(handle, FILE_MAP_ALL_ACCESS, 0, 0, len) };
+        if ptr.is_null() {
+            let error = last_error();
+            unsafe { CloseHandle(handle) };
+            return Err(error);
+        }
+
+        let len = if len > 0 {
+            len
+        } else {
+            // Opened by name: the view covers the whole section
+            let mut info: MemoryBasicInformation = unsafe { std::mem::zeroed() };
+            unsafe { VirtualQuery(ptr, &mut info, std::mem::size_of::<MemoryBasicInformation>()) };
+            info.region_size
+        };
+
+        Ok(SharedMem {
+            ptr: ptr as *mut u8,
+            len,
+            name: name.to_string(),
+            owner,
+            handle,
+        })
+    }
+
+    /// Windows removes the section once every handle is closed, so owners
+    /// and openers clean up the same way
+    pub(super) fn close(mem: &mut SharedMem) {
+        unsafe {
+            UnmapViewOfFile(mem.ptr as *const c_void);
+            CloseHandle(mem.handle);
+        }
+    }
+}
+
+const RING_MAGIC: u64 = u64::from_le_bytes(*b"ZTRING01");
+/// Header occupies three cache lines so the producer's and consumer's
+/// counters never share one
+const HEAD_OFFSET: usize = 64;
+const TAIL_OFFSET: usize = 128;
+const RING_DATA_OFFSET: usize = 192;
+/// Length prefix in front of every message
+const RECORD_HEADER: usize = 4;
+
+/// Lock-free single-producer/single-consumer byte ring inside a
+/// `SharedMem`. Messages are length-prefixed and may wrap around the end
+/// of the buffer.
+///
+/// Exactly one process (or thread) may call `push` and exactly one `pop`;
+/// the type cannot enforce this across processes.
+pub struct SpscRing {
+    mem: SharedMem,
+    capacity: u64,
+}
+
+impl SpscRing {
+    /// Lay out an empty ring in a fresh segment. Usable data capacity is
+    /// the largest power of two that fits after the header.
+    pub fn create(mem: SharedMem) -> IOResult<Self> {
+        let available = mem.len().saturating_sub(RING_DATA_OFFSET);
+        if available < 2 * RECORD_HEADER {
+            return Err(IOError::InvalidInput);
+        }
+        let capacity = 1u64 << (63 - (available as u64).leading_zeros());
+
+        unsafe {
+            (mem.as_ptr().add(8) as *mut u64).write(capacity);
+            let ring = SpscRing { mem, capacity };
+            ring.head().store(0, Ordering::Relaxed);
+            ring.tail().store(0, Ordering::Relaxed);
+            // Publish the magic last so `attach` never sees a partial header
+            (*(ring.mem.as_ptr() as *const AtomicU64)).store(RING_MAGIC, Ordering::Release);
+            Ok(ring)
+        }
+    }
+
+    /// Use a ring another process laid out with `create`
+    pub fn attach(mem: SharedMem) -> IOResult<Self> {
+        if mem.len() < RING_DATA_OFFSET {
+            return Err(IOError::InvalidInput);
+        }
+
+        unsafe {
+            let magic = (*(mem.as_ptr() as *const AtomicU64)).load(Ordering::Acquire);
+            if magic != RING_MAGIC {
+                return Err(IOError::Other("Shared memory does not hold a ring buffer".to_string()));
+            }
+            let capacity = (mem.as_ptr().add(8) as *const u64).read();
+            if capacity as usize > mem.len() - RING_DATA_OFFSET || !capacity.is_power_of_two() {
+                return Err(IOError::Other("Corrupt ring buffer header".to_string()));
+            }
+            Ok(SpscRing { mem, capacity })
+        }
+    }
+
+    fn head(&self) -> &AtomicU64 {
+        // Mapping is page-aligned, so the offset is 8-byte aligned
+        unsafe { &*(self.mem.as_ptr().add(HEAD_OFFSET) as *const AtomicU64) }
+    }
+
+    fn tail(&self) -> &AtomicU64 {
+        unsafe { &*(self.mem.as_ptr().add(TAIL_OFFSET) as *const AtomicU64) }
+    }
+
+    fn data(&self) -> *mut u8 {
+        unsafe { self.mem.as_ptr().add(RING_DATA_OFFSET) }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity as usize
+    }
+
+    /// Largest message `push` can ever accept
+    pub fn max_message_len(&self) -> usize {
+        self.capacity as usize - RECORD_HEADER
+    }
+
+    /// Copy `bytes` into the ring at logical position `pos`, wrapping
+    fn copy_in(&self, pos: u64, bytes: &[u8]) {
+        let start = (pos & (self.capacity - 1)) as usize;
+        let first = bytes.len().min(self.capacity as usize - start);
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), self.data().add(start), first);
+            std::ptr::copy_nonoverlapping(bytes[first..].as_ptr(), self.data(), bytes.len() - first);
+        }
+    }
+
+    fn copy_out(&self, pos: u64, out: &mut [u8]) {
+        let start = (pos & (self.capacity - 1)) as usize;
+        let first = out.len().min(self.capacity as usize - start);
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.data().add(start), out.as_mut_ptr(), first);
+            let rest = out.len() - first;
+            std::ptr::copy_nonoverlapping(self.data(), out[first..].as_mut_ptr(), rest);
+        }
+    }
+
+    /// Append one message. Returns `Ok(false)` when there is not enough
+    /// free space right now; the producer should retry later.
+    pub fn push(&self, message: &[u8]) -> IOResult<bool> {
+        if message.len() > self.max_message_len() {
+            return Err(IOError::InvalidInput);
+        }
+
+        let head = self.head().load(Ordering::Relaxed);
+        let tail = self.tail().load(Ordering::Acquire);
+        let needed = (RECORD_HEADER + message.len()) as u64;
+        if self.capacity - (head - tail) < needed {
+            return Ok(false);
+        }
+
+        self.copy_in(head, &(message.len() as u32).to_le_bytes());
+        self.copy_in(head + RECORD_HEADER as u64, message);
+        // Make the bytes visible before the consumer can see the new head
+        self.head().store(head + needed, Ordering::Release);
+        Ok(true)
+    }
+
+    /// Remove the oldest message into `out` (replacing its contents).
+    /// Returns `false` if the ring is empty.
+    pub fn pop_into(&self, out: &mut Vec<u8>) -> IOResult<bool> {
+        let tail = self.tail().load(Ordering::Relaxed);
+        let head = self.head().load(Ordering::Acquire);
+        if head == tail {
+            return Ok(false);
+        }
+
+        let mut len_bytes = [0u8; RECORD_HEADER];
+        self.copy_out(tail, &mut len_bytes);
+        let len = u32::from_le_bytes(len_bytes) as usize;
+        if (RECORD_HEADER + len) as u64 > head - tail {
+            return Err(IOError::Other("Corrupt ring buffer record".to_string()));
+        }
+
+        out.clear();
+        out.resize(len, 0);
+        self.copy_out(tail + RECORD_HEADER as u64, out);
+        self.tail().store(tail + (RECORD_HEADER + len) as u64, Ordering::Release);
+        Ok(true)
+    }
+
+    pub fn pop(&self) -> IOResult<Option<Vec<u8>>> {
+        let mut out = Vec::new();
+        Ok(if self.pop_into(&mut out)? { Some(out) } else { None })
+    }
+
+    /// Bytes currently queued, including record headers
+    pub fn used(&self) -> usize {
+        let head = self.head().load(Ordering::Acquire);
+        let tail = self.tail().load(Ordering::Acquire);
+        (head - tail) as usize
+    }
+
+    pub fn shared_mem(&self) -> &SharedMem {
+        &self.mem
+    }
+}
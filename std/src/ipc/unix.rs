@@ -0,0 +1,158 @@
+use std::io::{Read as _, Write as _};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::io::stream::{Read, Write};
+use crate::io::{IOError, IOResult};
+
+/// Identity of the process on the other end of a socket, as reported by
+/// the kernel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeerCredentials {
+    /// Not available on every platform (e.g. macOS `getpeereid`)
+    pub pid: Option<u32>,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// Connected Unix domain socket
+pub struct UnixDomainSocket {
+    stream: UnixStream,
+}
+
+impl UnixDomainSocket {
+    pub fn connect<P: AsRef<Path>>(path: P) -> IOResult<Self> {
+        let stream = UnixStream::connect(path).map_err(IOError::from)?;
+        Ok(UnixDomainSocket { stream })
+    }
+
+    /// Connected pair of sockets, e.g. for talking to a forked child
+    pub fn pair() -> IOResult<(Self, Self)> {
+        let (a, b) = UnixStream::pair().map_err(IOError::from)?;
+        Ok((UnixDomainSocket { stream: a }, UnixDomainSocket { stream: b }))
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> IOResult<()> {
+        self.stream.set_read_timeout(timeout).map_err(IOError::from)
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> IOResult<()> {
+        self.stream.set_nonblocking(nonblocking).map_err(IOError::from)
+    }
+
+    pub fn try_clone(&self) -> IOResult<Self> {
+        let stream = self.stream.try_clone().map_err(IOError::from)?;
+        Ok(UnixDomainSocket { stream })
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn peer_credentials(&self) -> IOResult<PeerCredentials> {
+        use std::os::unix::io::AsRawFd;
+
+        let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // `cred` and `len` are valid for writes of the sizes passed
+        let result = unsafe {
+            libc::getsockopt(
+                self.stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if result != 0 {
+            return Err(IOError::from(std::io::Error::last_os_error()));
+        }
+
+        Ok(PeerCredentials {
+            pid: Some(cred.pid as u32),
+            uid: cred.uid,
+            gid: cred.gid,
+        })
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    pub fn peer_credentials(&self) -> IOResult<PeerCredentials> {
+        use std::os::unix::io::AsRawFd;
+
+        let mut uid: libc::uid_t = 0;
+        let mut gid: libc::gid_t = 0;
+        // Both out-pointers are valid for the duration of the call
+        let result = unsafe { libc::getpeereid(self.stream.as_raw_fd(), &mut uid, &mut gid) };
+        if result != 0 {
+            return Err(IOError::from(std::io::Error::last_os_error()));
+        }
+
+        Ok(PeerCredentials { pid: None, uid, gid })
+    }
+}
+
+impl Read for UnixDomainSocket {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        self.stream.read(buf).map_err(IOError::from)
+    }
+}
+
+impl Write for UnixDomainSocket {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.stream.write(buf).map_err(IOError::from)
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        self.stream.flush().map_err(IOError::from)
+    }
+}
+
+/// Listening Unix domain socket. The socket file is removed on drop.
+pub struct UnixDomainListener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl UnixDomainListener {
+    /// Bind at `path`, replacing a stale socket file left by a process
+    /// that exited without cleaning up. Fails with `AlreadyExists` if
+    /// another process is still listening there; that process sees the
+    /// probe as a connection that closes immediately.
+    pub fn bind<P: AsRef<Path>>(path: P) -> IOResult<Self> {
+        let path = path.as_ref();
+
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(IOError::AlreadyExists);
+            }
+            std::fs::remove_file(path).map_err(IOError::from)?;
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(IOError::from)?;
+        }
+
+        let listener = UnixListener::bind(path).map_err(IOError::from)?;
+        Ok(UnixDomainListener {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn accept(&self) -> IOResult<UnixDomainSocket> {
+        let (stream, _) = self.listener.accept().map_err(IOError::from)?;
+        Ok(UnixDomainSocket { stream })
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> IOResult<()> {
+        self.listener.set_nonblocking(nonblocking).map_err(IOError::from)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for UnixDomainListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,148 @@
+use std::ffi::OsStr;
+use std::fs::{File, OpenOptions};
+use std::io::{Read as _, Write as _};
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::io::{FromRawHandle, RawHandle};
+use std::path::{Path, PathBuf};
+
+use crate::io::stream::{Read, Write};
+use crate::io::{IOError, IOResult};
+
+const PIPE_ACCESS_DUPLEX: u32 = 0x0000_0003;
+const FILE_FLAG_FIRST_PIPE_INSTANCE: u32 = 0x0008_0000;
+const PIPE_TYPE_BYTE: u32 = 0x0000_0000;
+const PIPE_READMODE_BYTE: u32 = 0x0000_0000;
+const PIPE_WAIT: u32 = 0x0000_0000;
+const PIPE_REJECT_REMOTE_CLIENTS: u32 = 0x0000_0008;
+const PIPE_UNLIMITED_INSTANCES: u32 = 255;
+const ERROR_PIPE_CONNECTED: i32 = 535;
+const INVALID_HANDLE_VALUE: RawHandle = -1isize as RawHandle;
+const BUFFER_SIZE: u32 = 64 * 1024;
+
+extern "system" {
+    fn CreateNamedPipeW(
+        name: *const u16,
+        open_mode: u32,
+        pipe_mode: u32,
+        max_instances: u32,
+        out_buffer_size: u32,
+        in_buffer_size: u32,
+        default_timeout: u32,
+        security_attributes: *mut std::ffi::c_void,
+    ) -> RawHandle;
+
+    fn ConnectNamedPipe(pipe: RawHandle, overlapped: *mut std::ffi::c_void) -> i32;
+
+    fn GetNamedPipeClientProcessId(pipe: RawHandle, process_id: *mut u32) -> i32;
+}
+
+/// Connected named pipe, either end
+pub struct NamedPipe {
+    file: File,
+}
+
+impl NamedPipe {
+    /// Connect to a pipe created by `NamedPipeServer::bind`
+    pub fn connect<P: AsRef<Path>>(name: P) -> IOResult<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(name.as_ref())
+            .map_err(IOError::from)?;
+        Ok(NamedPipe { file })
+    }
+
+    /// Process id of the client; only meaningful on the server end
+    pub fn client_process_id(&self) -> IOResult<u32> {
+        use std::os::windows::io::AsRawHandle;
+
+        let mut pid = 0u32;
+        // `pid` is valid for writes and the handle is open
+        let ok = unsafe { GetNamedPipeClientProcessId(self.file.as_raw_handle(), &mut pid) };
+        if ok == 0 {
+            return Err(IOError::from(std::io::Error::last_os_error()));
+        }
+        Ok(pid)
+    }
+}
+
+impl Read for NamedPipe {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        self.file.read(buf).map_err(IOError::from)
+    }
+}
+
+impl Write for NamedPipe {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.file.write(buf).map_err(IOError::from)
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        self.file.flush().map_err(IOError::from)
+    }
+}
+
+/// Server side of a named pipe. Each `accept` creates a fresh pipe
+/// instance and waits for a client to open it.
+pub struct NamedPipeServer {
+    name: PathBuf,
+    wide_name: Vec<u16>,
+    first_instance: bool,
+}
+
+impl NamedPipeServer {
+    /// Claim `name` (e.g. `\\.\pipe\zaitun-daemon`). Fails with
+    /// `AlreadyExists`-style errors if another server owns it.
+    pub fn bind<P: AsRef<Path>>(name: P) -> IOResult<Self> {
+        let name = name.as_ref().to_path_buf();
+        let wide_name = OsStr::new(&name).encode_wide().chain(Some(0)).collect();
+        Ok(NamedPipeServer {
+            name,
+            wide_name,
+            first_instance: true,
+        })
+    }
+
+    pub fn accept(&mut self) -> IOResult<NamedPipe> {
+        let mut open_mode = PIPE_ACCESS_DUPLEX;
+        if self.first_instance {
+            open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+        }
+
+        // Name is NUL-terminated UTF-16 owned by `self`
+        let handle = unsafe {
+            CreateNamedPipeW(
+                self.wide_name.as_ptr(),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                std::ptr::null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(IOError::from(std::io::Error::last_os_error()));
+        }
+        self.first_instance = false;
+
+        // Takes ownership so the handle is closed on every path below
+        let file = unsafe { File::from_raw_handle(handle) };
+
+        let connected = unsafe { ConnectNamedPipe(handle, std::ptr::null_mut()) };
+        if connected == 0 {
+            let error = std::io::Error::last_os_error();
+            // A client that connected between create and connect is fine
+            if error.raw_os_error() != Some(ERROR_PIPE_CONNECTED) {
+                return Err(IOError::from(error));
+            }
+        }
+
+        Ok(NamedPipe { file })
+    }
+
+    pub fn name(&self) -> &Path {
+        &self.name
+    }
+}
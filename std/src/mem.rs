@@ -0,0 +1,7 @@
+//! Type layout queries
+
+/// Size of `T` in bytes. Compiler intrinsic: calls are folded to a
+/// constant during codegen, this declaration documents the signature.
+pub fn size_of<T>() -> usize {
+    std::mem::size_of::<T>()
+}
@@ -1,5 +1,9 @@
+use std::io::{Read as _, Write as _};
 use std::net::TcpStream;
 
+use crate::io::stream::{Read, Write};
+use crate::io::{IOError, IOResult};
+
 pub struct TcpClient {
     stream: TcpStream,
 }
@@ -13,4 +17,20 @@ impl TcpClient {
     pub fn send(&mut self, data: &[u8]) -> Result<usize, std::io::Error> {
         self.stream.write(data)
     }
-}
\ No newline at end of file
+}
+
+impl Read for TcpClient {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        self.stream.read(buf).map_err(IOError::from)
+    }
+}
+
+impl Write for TcpClient {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.stream.write(buf).map_err(IOError::from)
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        self.stream.flush().map_err(IOError::from)
+    }
+}
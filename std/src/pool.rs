@@ -0,0 +1,226 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PoolError {
+    /// Every object is checked out and the pool is at capacity
+    Exhausted,
+    Timeout,
+    /// A thread panicked while holding the pool lock
+    Poisoned,
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::Exhausted => write!(f, "Object pool exhausted"),
+            PoolError::Timeout => write!(f, "Timed out waiting for a pooled object"),
+            PoolError::Poisoned => write!(f, "Object pool lock poisoned"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+type Factory<T> = Box<dyn Fn() -> T + Send + Sync>;
+type Reset<T> = Box<dyn Fn(&mut T) + Send + Sync>;
+
+struct State<T> {
+    idle: Vec<T>,
+    /// Objects in existence, idle or checked out
+    created: usize,
+}
+
+struct Inner<T> {
+    state: Mutex<State<T>>,
+    available: Condvar,
+    factory: Factory<T>,
+    reset: Option<Reset<T>>,
+    max_size: usize,
+}
+
+/// Thread-safe pool of reusable objects, created on demand by a factory
+/// up to `max_size`. Cloning the pool gives another handle to the same
+/// objects.
+pub struct ObjectPool<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for ObjectPool<T> {
+    fn clone(&self) -> Self {
+        ObjectPool {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T: Send + 'static> ObjectPool<T> {
+    pub fn new<F>(max_size: usize, factory: F) -> Self
+    where
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        ObjectPool {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State { idle: Vec::new(), created: 0 }),
+                available: Condvar::new(),
+                factory: Box::new(factory),
+                reset: None,
+                max_size: max_size.max(1),
+            }),
+        }
+    }
+
+    /// Run `reset` on every object as it is returned, e.g. to clear a
+    /// parser's buffers before the next user sees it
+    pub fn with_reset<R>(max_size: usize, factory: impl Fn() -> T + Send + Sync + 'static, reset: R) -> Self
+    where
+        R: Fn(&mut T) + Send + Sync + 'static,
+    {
+        let mut pool = ObjectPool::new(max_size, factory);
+        Arc::get_mut(&mut pool.inner).expect("pool not yet shared").reset = Some(Box::new(reset));
+        pool
+    }
+
+    /// Create up to `count` objects ahead of time
+    pub fn prefill(&self, count: usize) -> Result<(), PoolError> {
+        let mut state = self.inner.state.lock().map_err(|_| PoolError::Poisoned)?;
+        while state.created < self.inner.max_size && state.idle.len() < count {
+            let object = (self.inner.factory)();
+            state.idle.push(object);
+            state.created += 1;
+        }
+        Ok(())
+    }
+
+    /// Take an idle object, or create one if below capacity; otherwise
+    /// fail immediately with `Exhausted`
+    pub fn try_checkout(&self) -> Result<Pooled<T>, PoolError> {
+        let mut state = self.inner.state.lock().map_err(|_| PoolError::Poisoned)?;
+        match self.take(&mut state) {
+            Some(object) => Ok(object),
+            None => Err(PoolError::Exhausted),
+        }
+    }
+
+    /// Like `try_checkout`, but block until an object is returned
+    pub fn checkout(&self) -> Result<Pooled<T>, PoolError> {
+        let mut state = self.inner.state.lock().map_err(|_| PoolError::Poisoned)?;
+        loop {
+            if let Some(object) = self.take(&mut state) {
+                return Ok(object);
+            }
+            state = self.inner.available.wait(state).map_err(|_| PoolError::Poisoned)?;
+        }
+    }
+
+    /// Like `checkout`, giving up after `timeout`
+    pub fn checkout_timeout(&self, timeout: Duration) -> Result<Pooled<T>, PoolError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.inner.state.lock().map_err(|_| PoolError::Poisoned)?;
+
+        loop {
+            if let Some(object) = self.take(&mut state) {
+                return Ok(object);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(PoolError::Timeout);
+            }
+
+            let (guard, _) = self.inner.available
+                .wait_timeout(state, remaining)
+                .map_err(|_| PoolError::Poisoned)?;
+            state = guard;
+        }
+    }
+
+    fn take(&self, state: &mut State<T>) -> Option<Pooled<T>> {
+        let object = match state.idle.pop() {
+            Some(object) => object,
+            None if state.created < self.inner.max_size => {
+                state.created += 1;
+                (self.inner.factory)()
+            }
+            None => return None,
+        };
+
+        Some(Pooled {
+            object: Some(object),
+            pool: Arc::clone(&self.inner),
+        })
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.inner.max_size
+    }
+
+    /// Objects currently idle in the pool
+    pub fn idle_count(&self) -> usize {
+        self.inner.state.lock().map(|s| s.idle.len()).unwrap_or(0)
+    }
+
+    /// Objects created so far, idle or checked out
+    pub fn created_count(&self) -> usize {
+        self.inner.state.lock().map(|s| s.created).unwrap_or(0)
+    }
+}
+
+/// Object checked out of an `ObjectPool`; returned to the pool on drop
+pub struct Pooled<T> {
+    object: Option<T>,
+    pool: Arc<Inner<T>>,
+}
+
+impl<T> Pooled<T> {
+    /// Keep the object permanently, freeing its slot so the pool can
+    /// create a replacement
+    pub fn detach(mut self) -> T {
+        let object = self.object.take().expect("object present until drop");
+        if let Ok(mut state) = self.pool.state.lock() {
+            state.created -= 1;
+        }
+        self.pool.available.notify_one();
+        object
+    }
+}
+
+impl<T> Deref for Pooled<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.object.as_ref().expect("object present until drop")
+    }
+}
+
+impl<T> DerefMut for Pooled<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.object.as_mut().expect("object present until drop")
+    }
+}
+
+impl<T> Drop for Pooled<T> {
+    fn drop(&mut self) {
+        let Some(mut object) = self.object.take() else {
+            return;
+        };
+
+        if let Some(reset) = &self.pool.reset {
+            reset(&mut object);
+        }
+
+        // A poisoned pool just drops the object
+        if let Ok(mut state) = self.pool.state.lock() {
+            state.idle.push(object);
+        }
+        self.pool.available.notify_one();
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Pooled<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.object.fmt(f)
+    }
+}
@@ -0,0 +1,580 @@
+//! Hierarchical spans and structured events.
+//!
+//! Code opens spans around units of work and emits events inside them;
+//! registered subscribers decide what to do with them (print a tree,
+//! write a Chrome trace, feed a profiler). With no subscriber registered
+//! spans cost a thread-local push/pop and nothing else.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Value of a structured field
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Str(s) => write!(f, "{:?}", s),
+            FieldValue::Int(i) => write!(f, "{}", i),
+            FieldValue::Float(x) => write!(f, "{}", x),
+            FieldValue::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        FieldValue::Str(value.to_string())
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        FieldValue::Str(value)
+    }
+}
+
+impl From<i64> for FieldValue {
+    fn from(value: i64) -> Self {
+        FieldValue::Int(value)
+    }
+}
+
+impl From<usize> for FieldValue {
+    fn from(value: usize) -> Self {
+        FieldValue::Int(value as i64)
+    }
+}
+
+impl From<f64> for FieldValue {
+    fn from(value: f64) -> Self {
+        FieldValue::Float(value)
+    }
+}
+
+impl From<bool> for FieldValue {
+    fn from(value: bool) -> Self {
+        FieldValue::Bool(value)
+    }
+}
+
+pub type Fields = Vec<(&'static str, FieldValue)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpanId(pub u64);
+
+/// A span as seen by subscribers
+#[derive(Debug, Clone)]
+pub struct SpanData {
+    pub id: SpanId,
+    pub parent: Option<SpanId>,
+    pub name: String,
+    pub fields: Fields,
+    pub start: Instant,
+    /// Nesting depth on the current thread, 0 for a root span
+    pub depth: usize,
+    pub thread: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub level: Level,
+    pub message: String,
+    pub fields: Fields,
+    /// Innermost span open on this thread when the event fired
+    pub span: Option<SpanId>,
+    pub depth: usize,
+    pub timestamp: Instant,
+    pub thread: u64,
+}
+
+/// Receives spans and events. Callbacks run on the thread doing the work,
+/// so they should be quick.
+pub trait Subscriber: Send + Sync {
+    fn enabled(&self, _level: Level) -> bool {
+        true
+    }
+
+    fn on_enter(&self, _span: &SpanData) {}
+
+    fn on_exit(&self, span: &SpanData, elapsed: Duration);
+
+    fn on_event(&self, event: &Event);
+}
+
+static SUBSCRIBERS: RwLock<Vec<Arc<dyn Subscriber>>> = RwLock::new(Vec::new());
+static NEXT_SPAN_ID: AtomicU64 = AtomicU64::new(1);
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static SPAN_STACK: RefCell<Vec<SpanId>> = const { RefCell::new(Vec::new()) };
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Register a subscriber for all threads
+pub fn add_subscriber(subscriber: Arc<dyn Subscriber>) {
+    if let Ok(mut subscribers) = SUBSCRIBERS.write() {
+        subscribers.push(subscriber);
+    }
+}
+
+pub fn clear_subscribers() {
+    if let Ok(mut subscribers) = SUBSCRIBERS.write() {
+        subscribers.clear();
+    }
+}
+
+fn with_subscribers<F: Fn(&dyn Subscriber)>(f: F) {
+    if let Ok(subscribers) = SUBSCRIBERS.read() {
+        for subscriber in subscribers.iter() {
+            f(subscriber.as_ref());
+        }
+    }
+}
+
+fn has_subscribers() -> bool {
+    SUBSCRIBERS.read().map(|s| !s.is_empty()).unwrap_or(false)
+}
+
+fn current_thread() -> u64 {
+    THREAD_ID.with(|id| *id)
+}
+
+/// Start building a span; call `enter` to open it
+pub fn span(name: &str) -> SpanBuilder {
+    SpanBuilder {
+        name: name.to_string(),
+        fields: Vec::new(),
+    }
+}
+
+/// Run `f` inside a span named `name`
+pub fn in_span<T, F: FnOnce() -> T>(name: &str, f: F) -> T {
+    let _guard = span(name).enter();
+    f()
+}
+
+pub struct SpanBuilder {
+    name: String,
+    fields: Fields,
+}
+
+impl SpanBuilder {
+    pub fn field<V: Into<FieldValue>>(mut self, key: &'static str, value: V) -> Self {
+        self.fields.push((key, value.into()));
+        self
+    }
+
+    /// Open the span on the current thread. It closes when the guard drops;
+    /// guards must be dropped in reverse order of entering.
+    pub fn enter(self) -> SpanGuard {
+        let id = SpanId(NEXT_SPAN_ID.fetch_add(1, Ordering::Relaxed));
+        let (parent, depth) = SPAN_STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            let parent = stack.last().copied();
+            let depth = stack.len();
+            stack.push(id);
+            (parent, depth)
+        });
+
+        let data = SpanData {
+            id,
+            parent,
+            name: self.name,
+            fields: self.fields,
+            start: Instant::now(),
+            depth,
+            thread: current_thread(),
+        };
+        with_subscribers(|s| s.on_enter(&data));
+
+        SpanGuard { data }
+    }
+}
+
+pub struct SpanGuard {
+    data: SpanData,
+}
+
+impl SpanGuard {
+    pub fn id(&self) -> SpanId {
+        self.data.id
+    }
+
+    /// Time since the span was entered
+    pub fn elapsed(&self) -> Duration {
+        self.data.start.elapsed()
+    }
+
+    /// Attach a field discovered while the span is open, e.g. a result
+    /// count; subscribers see it on exit
+    pub fn record<V: Into<FieldValue>>(&mut self, key: &'static str, value: V) {
+        self.data.fields.push((key, value.into()));
+    }
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        let elapsed = self.data.start.elapsed();
+        SPAN_STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            if let Some(pos) = stack.iter().rposition(|id| *id == self.data.id) {
+                stack.truncate(pos);
+            }
+        });
+        with_subscribers(|s| s.on_exit(&self.data, elapsed));
+    }
+}
+
+/// Emit a structured event inside the current span
+pub fn event(level: Level, message: &str, fields: Fields) {
+    if !has_subscribers() {
+        return;
+    }
+
+    let (span, depth) = SPAN_STACK.with(|stack| {
+        let stack = stack.borrow();
+        (stack.last().copied(), stack.len())
+    });
+
+    let event = Event {
+        level,
+        message: message.to_string(),
+        fields,
+        span,
+        depth,
+        timestamp: Instant::now(),
+        thread: current_thread(),
+    };
+    with_subscribers(|s| {
+        if s.enabled(level) {
+            s.on_event(&event);
+        }
+    });
+}
+
+pub fn debug(message: &str) {
+    event(Level::Debug, message, Vec::new());
+}
+
+pub fn info(message: &str) {
+    event(Level::Info, message, Vec::new());
+}
+
+pub fn warn(message: &str) {
+    event(Level::Warn, message, Vec::new());
+}
+
+pub fn error(message: &str) {
+    event(Level::Error, message, Vec::new());
+}
+
+fn format_fields(fields: &Fields) -> String {
+    fields.iter()
+        .map(|(k, v)| format!(" {}={}", k, v))
+        .collect()
+}
+
+/// Prints spans as an indented tree with their durations, and events at
+/// or above `min_level`
+pub struct ConsoleSubscriber {
+    min_level: Level,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl ConsoleSubscriber {
+    pub fn new(min_level: Level) -> Self {
+        ConsoleSubscriber::with_writer(min_level, Box::new(std::io::stderr()))
+    }
+
+    pub fn with_writer(min_level: Level, out: Box<dyn Write + Send>) -> Self {
+        ConsoleSubscriber {
+            min_level,
+            out: Mutex::new(out),
+        }
+    }
+}
+
+impl Subscriber for ConsoleSubscriber {
+    fn enabled(&self, level: Level) -> bool {
+        level >= self.min_level
+    }
+
+    fn on_enter(&self, span: &SpanData) {
+        if let Ok(mut out) = self.out.lock() {
+            let _ = writeln!(out, "{}┌ {}{}", "│ ".repeat(span.depth), span.name, format_fields(&span.fields));
+        }
+    }
+
+    fn on_exit(&self, span: &SpanData, elapsed: Duration) {
+        if let Ok(mut out) = self.out.lock() {
+            let _ = writeln!(out, "{}└ {} {:?}", "│ ".repeat(span.depth), span.name, elapsed);
+        }
+    }
+
+    fn on_event(&self, event: &Event) {
+        if let Ok(mut out) = self.out.lock() {
+            let _ = writeln!(
+                out,
+                "{}{} {}{}",
+                "│ ".repeat(event.depth),
+                event.level,
+                event.message,
+                format_fields(&event.fields)
+            );
+        }
+    }
+}
+
+/// Records spans and events in the Chrome trace event format, viewable in
+/// `chrome://tracing` or Perfetto
+pub struct ChromeTraceSubscriber {
+    origin: Instant,
+    entries: Mutex<Vec<String>>,
+}
+
+impl ChromeTraceSubscriber {
+    pub fn new() -> Self {
+        ChromeTraceSubscriber {
+            origin: Instant::now(),
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn micros_since_origin(&self, at: Instant) -> u128 {
+        at.saturating_duration_since(self.origin).as_micros()
+    }
+
+    pub fn to_json(&self) -> String {
+        let entries = self.entries.lock().map(|e| e.join(",\n  ")).unwrap_or_default();
+        format!("{{\"traceEvents\": [\n  {}\n]}}\n", entries)
+    }
+
+    pub fn write_to(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+}
+
+impl Default for ChromeTraceSubscriber {
+    fn default() -> Self {
+        ChromeTraceSubscriber::new()
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_args(fields: &Fields) -> String {
+    let args: Vec<String> = fields.iter()
+        .map(|(k, v)| {
+            let value = match v {
+                FieldValue::Str(s) => json_string(s),
+                FieldValue::Float(x) if !x.is_finite() => "null".to_string(),
+                other => other.to_string(),
+            };
+            format!("{}: {}", json_string(k), value)
+        })
+        .collect();
+    format!("{{{}}}", args.join(", "))
+}
+
+impl Subscriber for ChromeTraceSubscriber {
+    fn on_exit(&self, span: &SpanData, elapsed: Duration) {
+        // One complete ("X") event per span
+        let entry = format!(
+            "{{\"name\": {}, \"ph\": \"X\", \"ts\": {}, \"dur\": {}, \"pid\": 1, \"tid\": {}, \"args\": {}}}",
+            json_string(&span.name),
+            self.micros_since_origin(span.start),
+            elapsed.as_micros(),
+            span.thread,
+            json_args(&span.fields)
+        );
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.push(entry);
+        }
+    }
+
+    fn on_event(&self, event: &Event) {
+        let entry = format!(
+            "{{\"name\": {}, \"ph\": \"i\", \"s\": \"t\", \"ts\": {}, \"pid\": 1, \"tid\": {}, \"args\": {}}}",
+            json_string(&event.message),
+            self.micros_since_origin(event.timestamp),
+            event.thread,
+            json_args(&event.fields)
+        );
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.push(entry);
+        }
+    }
+}
+
+/// Hands each finished span's name and duration to a callback, e.g. the
+/// compiler's `Profiler::record_section`
+pub struct ForwardingSubscriber<F> {
+    sink: Mutex<F>,
+}
+
+impl<F: FnMut(&str, Duration) + Send> ForwardingSubscriber<F> {
+    pub fn new(sink: F) -> Self {
+        ForwardingSubscriber { sink: Mutex::new(sink) }
+    }
+}
+
+impl<F: FnMut(&str, Duration) + Send> Subscriber for ForwardingSubscriber<F> {
+    fn enabled(&self, _level: Level) -> bool {
+        false
+    }
+
+    fn on_exit(&self, span: &SpanData, elapsed: Duration) {
+        if let Ok(mut sink) = self.sink.lock() {
+            sink(&span.name, elapsed);
+        }
+    }
+
+    fn on_event(&self, _event: &Event) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps the spans finished on the thread that created it; tests run
+    /// in parallel and share the global subscriber list
+    struct Recorder {
+        thread: u64,
+        spans: Mutex<Vec<(SpanData, Duration)>>,
+    }
+
+    impl Recorder {
+        fn register() -> Arc<Recorder> {
+            let recorder = Arc::new(Recorder {
+                thread: current_thread(),
+                spans: Mutex::new(Vec::new()),
+            });
+            add_subscriber(recorder.clone());
+            recorder
+        }
+
+        fn finished(&self) -> Vec<(SpanData, Duration)> {
+            self.spans.lock().unwrap().clone()
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn on_exit(&self, span: &SpanData, elapsed: Duration) {
+            if span.thread == self.thread {
+                self.spans.lock().unwrap().push((span.clone(), elapsed));
+            }
+        }
+
+        fn on_event(&self, _event: &Event) {}
+    }
+
+    #[test]
+    fn test_spans_nest() {
+        let recorder = Recorder::register();
+        {
+            let outer = span("nest.outer").enter();
+            let inner = span("nest.inner").field("n", 1usize).enter();
+            assert_ne!(inner.id(), outer.id());
+        }
+        let sibling = in_span("nest.sibling", || span("nest.child").enter().id());
+
+        let spans = recorder.finished();
+        let names: Vec<&str> = spans.iter().map(|(span, _)| span.name.as_str()).collect();
+        assert_eq!(names, ["nest.inner", "nest.outer", "nest.child", "nest.sibling"]);
+
+        let (outer, inner) = (&spans[1].0, &spans[0].0);
+        assert_eq!(outer.parent, None);
+        assert_eq!(outer.depth, 0);
+        assert_eq!(inner.parent, Some(outer.id));
+        assert_eq!(inner.depth, 1);
+        assert_eq!(inner.fields, vec![("n", FieldValue::Int(1))]);
+        assert_eq!(spans[2].0.id, sibling);
+        assert_eq!(spans[2].0.parent, Some(spans[3].0.id));
+    }
+
+    #[test]
+    fn test_span_timing() {
+        let recorder = Recorder::register();
+        {
+            let _outer = span("timing.outer").enter();
+            let _inner = span("timing.inner").enter();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let spans = recorder.finished();
+        let (inner, outer) = (spans[0].1, spans[1].1);
+        assert!(inner >= Duration::from_millis(10));
+        assert!(outer >= inner);
+    }
+
+    #[test]
+    fn test_chrome_trace_json() {
+        let chrome = Arc::new(ChromeTraceSubscriber::new());
+        add_subscriber(chrome.clone());
+        {
+            let _span = span("chrome.span").field("path", "a\"b").enter();
+            event(Level::Info, "chrome.event", vec![("ok", FieldValue::Bool(true))]);
+        }
+
+        let json = chrome.to_json();
+        assert!(json.starts_with("{\"traceEvents\": ["));
+        assert!(json.trim_end().ends_with("]}"));
+        let span_entry = json.lines().find(|line| line.contains("\"chrome.span\"")).unwrap();
+        assert!(span_entry.contains("\"ph\": \"X\""));
+        assert!(span_entry.contains("\"dur\": "));
+        assert!(span_entry.contains("\"args\": {\"path\": \"a\\\"b\"}"));
+        let event_entry = json.lines().find(|line| line.contains("\"chrome.event\"")).unwrap();
+        assert!(event_entry.contains("\"ph\": \"i\""));
+        assert!(event_entry.contains("\"args\": {\"ok\": true}"));
+    }
+}
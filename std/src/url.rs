@@ -0,0 +1,636 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// URL parsed following the WHATWG URL standard for the schemes tooling
+/// needs (`http`, `https`, `file`, and opaque ones such as `mailto:`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    scheme: String,
+    username: String,
+    password: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    /// Percent-encoded path; starts with `/` for hierarchical URLs
+    path: String,
+    query: Option<String>,
+    fragment: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UrlError {
+    EmptyInput,
+    MissingScheme,
+    InvalidScheme(String),
+    EmptyHost,
+    InvalidPort(String),
+    InvalidHost(String),
+    RelativeWithoutBase,
+    NotAFilePath,
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UrlError::EmptyInput => write!(f, "Empty URL"),
+            UrlError::MissingScheme => write!(f, "URL has no scheme"),
+            UrlError::InvalidScheme(scheme) => write!(f, "Invalid URL scheme: {}", scheme),
+            UrlError::EmptyHost => write!(f, "URL requires a host"),
+            UrlError::InvalidPort(port) => write!(f, "Invalid port: {}", port),
+            UrlError::InvalidHost(host) => write!(f, "Invalid host: {}", host),
+            UrlError::RelativeWithoutBase => write!(f, "Relative URL without a base"),
+            UrlError::NotAFilePath => write!(f, "URL is not a file path"),
+        }
+    }
+}
+
+impl std::error::Error for UrlError {}
+
+/// Schemes with an authority and hierarchical path, and their default ports
+fn special_scheme_port(scheme: &str) -> Option<Option<u16>> {
+    match scheme {
+        "http" | "ws" => Some(Some(80)),
+        "https" | "wss" => Some(Some(443)),
+        "ftp" => Some(Some(21)),
+        "file" => Some(None),
+        _ => None,
+    }
+}
+
+impl Url {
+    /// Parse an absolute URL
+    pub fn parse(input: &str) -> Result<Url, UrlError> {
+        // Leading/trailing C0 control characters and spaces are stripped,
+        // tabs and newlines anywhere are removed
+        let input: String = input
+            .trim_matches(|c: char| c <= ' ')
+            .chars()
+            .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+            .collect();
+
+        if input.is_empty() {
+            return Err(UrlError::EmptyInput);
+        }
+
+        let (scheme, rest) = split_scheme(&input).ok_or(UrlError::MissingScheme)?;
+        let scheme = scheme.to_ascii_lowercase();
+
+        let (rest, fragment) = match rest.split_once('#') {
+            Some((rest, fragment)) => (rest, Some(encode(fragment, FRAGMENT_SET))),
+            None => (rest, None),
+        };
+        let (rest, query) = match rest.split_once('?') {
+            Some((rest, query)) => (rest, Some(encode(query, QUERY_SET))),
+            None => (rest, None),
+        };
+
+        let mut url = Url {
+            scheme,
+            username: String::new(),
+            password: None,
+            host: None,
+            port: None,
+            path: String::new(),
+            query,
+            fragment,
+        };
+
+        let special = special_scheme_port(&url.scheme).is_some();
+        // Special schemes treat backslashes as path separators
+        let rest = if special { rest.replace('\\', "/") } else { rest.to_string() };
+
+        if let Some(after_slashes) = rest.strip_prefix("//") {
+            let (authority, path) = match after_slashes.find('/') {
+                Some(i) => after_slashes.split_at(i),
+                None => (after_slashes, ""),
+            };
+            url.parse_authority(authority)?;
+            url.path = normalize_path(&encode(path, PATH_SET), special);
+        } else if special {
+            if url.scheme != "file" {
+                return Err(UrlError::EmptyHost);
+            }
+            url.path = normalize_path(&encode(&rest, PATH_SET), true);
+        } else {
+            // Opaque path, e.g. `mailto:user@example.com`
+            url.path = encode(&rest, C0_CONTROL_SET);
+        }
+
+        if special && url.path.is_empty() {
+            url.path.push('/');
+        }
+
+        Ok(url)
+    }
+
+    fn parse_authority(&mut self, authority: &str) -> Result<(), UrlError> {
+        let (userinfo, hostport) = match authority.rfind('@') {
+            Some(i) => (Some(&authority[..i]), &authority[i + 1..]),
+            None => (None, authority),
+        };
+
+        if let Some(userinfo) = userinfo {
+            match userinfo.split_once(':') {
+                Some((user, pass)) => {
+                    self.username = encode(user, USERINFO_SET);
+                    self.password = Some(encode(pass, USERINFO_SET));
+                }
+                None => self.username = encode(userinfo, USERINFO_SET),
+            }
+        }
+
+        // IPv6 literals contain colons, so only split on one after `]`
+        let port_sep = if hostport.starts_with('[') {
+            hostport.find(']').and_then(|end| hostport[end..].find(':').map(|i| end + i))
+        } else {
+            hostport.rfind(':')
+        };
+
+        let (host, port) = match port_sep {
+            Some(i) => (&hostport[..i], Some(&hostport[i + 1..])),
+            None => (hostport, None),
+        };
+
+        if let Some(port) = port.filter(|p| !p.is_empty()) {
+            // `u16::from_str` would also take a leading `+`
+            if !port.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(UrlError::InvalidPort(port.to_string()));
+            }
+            let port: u16 = port.parse().map_err(|_| UrlError::InvalidPort(port.to_string()))?;
+            // Default ports are not serialized
+            if special_scheme_port(&self.scheme) != Some(Some(port)) {
+                self.port = Some(port);
+            }
+        }
+
+        if host.is_empty() {
+            if self.scheme != "file" && special_scheme_port(&self.scheme).is_some() {
+                return Err(UrlError::EmptyHost);
+            }
+            return Ok(());
+        }
+
+        let host = decode(host);
+        if host.chars().any(|c| matches!(c, ' ' | '#' | '/' | '<' | '>' | '?' | '@' | '\\' | '^' | '|')) {
+            return Err(UrlError::InvalidHost(host));
+        }
+
+        self.host = Some(if special_scheme_port(&self.scheme).is_some() {
+            host.to_ascii_lowercase()
+        } else {
+            host
+        });
+
+        Ok(())
+    }
+
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    /// Explicit port, `None` when absent or equal to the scheme default
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    pub fn port_or_default(&self) -> Option<u16> {
+        self.port.or_else(|| special_scheme_port(&self.scheme).flatten())
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    pub fn fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+
+    /// Decoded path segments; `None` for opaque URLs like `mailto:`
+    pub fn path_segments(&self) -> Option<Vec<String>> {
+        let path = self.path.strip_prefix('/')?;
+        Some(path.split('/').map(decode).collect())
+    }
+
+    /// Decoded `application/x-www-form-urlencoded` query pairs
+    pub fn query_pairs(&self) -> Vec<(String, String)> {
+        match &self.query {
+            Some(query) => parse_query(query),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn set_query_pairs(&mut self, pairs: &[(&str, &str)]) {
+        if pairs.is_empty() {
+            self.query = None;
+        } else {
+            self.query = Some(encode_query(pairs));
+        }
+    }
+
+    pub fn set_fragment(&mut self, fragment: Option<&str>) {
+        self.fragment = fragment.map(|f| encode(f, FRAGMENT_SET));
+    }
+
+    /// Append a path segment, percent-encoding it as needed
+    pub fn push_segment(&mut self, segment: &str) {
+        if !self.path.ends_with('/') {
+            self.path.push('/');
+        }
+        self.path.push_str(&encode(segment, SEGMENT_SET));
+    }
+
+    /// Resolve a possibly-relative reference against this URL
+    pub fn join(&self, reference: &str) -> Result<Url, UrlError> {
+        let special = special_scheme_port(&self.scheme).is_some();
+        let reference = match split_scheme(reference) {
+            // A special scheme repeated from the base, as in `http:g`, is
+            // still relative to it
+            Some((scheme, rest)) if special && scheme.eq_ignore_ascii_case(&self.scheme) => rest,
+            Some(_) => return Url::parse(reference),
+            None => reference,
+        };
+
+        if self.host.is_none() && !self.path.starts_with('/') {
+            return Err(UrlError::RelativeWithoutBase);
+        }
+
+        let reference = if special { reference.replace('\\', "/") } else { reference.to_string() };
+
+        // Scheme-relative: `//host/path`
+        if reference.starts_with("//") {
+            return Url::parse(&format!("{}:{}", self.scheme, reference));
+        }
+
+        let mut url = self.clone();
+        let (rest, fragment) = match reference.split_once('#') {
+            Some((rest, fragment)) => (rest, Some(encode(fragment, FRAGMENT_SET))),
+            None => (reference.as_str(), None),
+        };
+        url.fragment = fragment;
+
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(encode(query, QUERY_SET))),
+            None => (rest, None),
+        };
+
+        if path.is_empty() {
+            if query.is_some() {
+                url.query = query;
+            }
+            return Ok(url);
+        }
+
+        url.query = query;
+        let path = encode(path, PATH_SET);
+        url.path = if path.starts_with('/') {
+            normalize_path(&path, special)
+        } else {
+            // Merge with the base path minus its last segment
+            let base_dir = match self.path.rfind('/') {
+                Some(i) => &self.path[..=i],
+                None => "/",
+            };
+            normalize_path(&format!("{}{}", base_dir, path), special)
+        };
+
+        Ok(url)
+    }
+
+    /// `file://` URL for an absolute filesystem path
+    pub fn from_file_path(path: &Path) -> Result<Url, UrlError> {
+        if !path.is_absolute() {
+            return Err(UrlError::NotAFilePath);
+        }
+
+        let mut url = Url::parse("file:///")?;
+        url.path.clear();
+        for component in path.components() {
+            match component {
+                std::path::Component::Normal(part) => {
+                    url.path.push('/');
+                    url.path.push_str(&encode(&part.to_string_lossy(), SEGMENT_SET));
+                }
+                std::path::Component::Prefix(prefix) => {
+                    // Windows drive letter, e.g. `C:`
+                    url.path.push('/');
+                    url.path.push_str(&prefix.as_os_str().to_string_lossy());
+                }
+                _ => {}
+            }
+        }
+        if url.path.is_empty() {
+            url.path.push('/');
+        }
+
+        Ok(url)
+    }
+
+    /// Filesystem path of a `file://` URL
+    pub fn to_file_path(&self) -> Result<PathBuf, UrlError> {
+        if self.scheme != "file" {
+            return Err(UrlError::NotAFilePath);
+        }
+        if matches!(self.host.as_deref(), Some(host) if host != "localhost") {
+            return Err(UrlError::NotAFilePath);
+        }
+
+        let path = decode(&self.path);
+        if cfg!(windows) {
+            // `/C:/dir` -> `C:/dir`
+            let trimmed = path.strip_prefix('/').unwrap_or(&path);
+            Ok(PathBuf::from(trimmed.replace('/', "\\")))
+        } else {
+            Ok(PathBuf::from(path))
+        }
+    }
+}
+
+impl std::str::FromStr for Url {
+    type Err = UrlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Url::parse(s)
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.scheme)?;
+
+        if self.host.is_some() || self.scheme == "file" {
+            write!(f, "//")?;
+            if !self.username.is_empty() || self.password.is_some() {
+                write!(f, "{}", self.username)?;
+                if let Some(password) = &self.password {
+                    write!(f, ":{}", password)?;
+                }
+                write!(f, "@")?;
+            }
+            if let Some(host) = &self.host {
+                write!(f, "{}", host)?;
+            }
+            if let Some(port) = self.port {
+                write!(f, ":{}", port)?;
+            }
+        }
+
+        write!(f, "{}", self.path)?;
+        if let Some(query) = &self.query {
+            write!(f, "?{}", query)?;
+        }
+        if let Some(fragment) = &self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn split_scheme(input: &str) -> Option<(&str, &str)> {
+    let colon = input.find(':')?;
+    let scheme = &input[..colon];
+    let mut chars = scheme.chars();
+
+    let valid = chars.next().map_or(false, |c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+
+    if valid {
+        Some((scheme, &input[colon + 1..]))
+    } else {
+        None
+    }
+}
+
+/// Remove `.` and `..` segments (RFC 3986 section 5.2.4)
+fn normalize_path(path: &str, special: bool) -> String {
+    if path.is_empty() {
+        return String::new();
+    }
+
+    let mut output: Vec<&str> = Vec::new();
+    // Only the root: further slashes are empty segments, as in `https://h//a`
+    let segments: Vec<&str> = path.strip_prefix('/').unwrap_or(path).split('/').collect();
+    let last = segments.len() - 1;
+
+    for (i, segment) in segments.iter().enumerate() {
+        let is_dot = |s: &str| s == "." || (special && s.eq_ignore_ascii_case("%2e"));
+        let is_dot_dot = |s: &str| {
+            s == ".." || (special && matches!(s.to_ascii_lowercase().as_str(), ".%2e" | "%2e." | "%2e%2e"))
+        };
+
+        if is_dot_dot(segment) {
+            output.pop();
+            if i == last {
+                output.push("");
+            }
+        } else if is_dot(segment) {
+            if i == last {
+                output.push("");
+            }
+        } else {
+            output.push(segment);
+        }
+    }
+
+    format!("/{}", output.join("/"))
+}
+
+/// Characters percent-encoded in addition to C0 controls and non-ASCII
+type EncodeSet = &'static [u8];
+
+const C0_CONTROL_SET: EncodeSet = b"";
+const FRAGMENT_SET: EncodeSet = b" \"<>`";
+const QUERY_SET: EncodeSet = b" \"#<>";
+const PATH_SET: EncodeSet = b" \"#<>?`{}";
+const USERINFO_SET: EncodeSet = b" \"#<>?`{}/:;=@[\\]^|";
+/// A single path segment; also escapes `/` and `%`
+const SEGMENT_SET: EncodeSet = b" \"#<>?`{}/%";
+const FORM_SET: EncodeSet = b" !\"#$%&'()+,/:;<=>?@[\\]^`{|}~";
+
+fn encode(input: &str, set: EncodeSet) -> String {
+    let mut output = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        if byte < 0x20 || byte >= 0x7f || set.contains(&byte) {
+            output.push_str(&format!("%{:02X}", byte));
+        } else {
+            output.push(byte as char);
+        }
+    }
+    output
+}
+
+/// Percent-encode a single path segment
+pub fn encode_path_segment(input: &str) -> String {
+    encode(input, SEGMENT_SET)
+}
+
+/// Percent-encode a string for a form-encoded query component; spaces
+/// become `+`
+pub fn encode_query_component(input: &str) -> String {
+    encode(input, FORM_SET).replace("%20", "+")
+}
+
+/// Encode key/value pairs as `application/x-www-form-urlencoded`
+pub fn encode_query(pairs: &[(&str, &str)]) -> String {
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", encode_query_component(k), encode_query_component(v)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Decode `%XX` escapes; malformed escapes are kept as-is and invalid
+/// UTF-8 is replaced
+pub fn decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = |b: u8| (b as char).to_digit(16);
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                output.push((hi * 16 + lo) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        output.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(&key.replace('+', " ")), decode(&value.replace('+', " ")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_rfc3986_normal_examples() {
+        // RFC 3986 section 5.4.1
+        let base = Url::parse("http://a/b/c/d;p?q").unwrap();
+        let examples = [
+            ("g:h", "g:h"),
+            ("g", "http://a/b/c/g"),
+            ("./g", "http://a/b/c/g"),
+            ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"),
+            // Special schemes always have a path, so this is `http://g/`
+            // rather than the RFC's `http://g`
+            ("//g", "http://g/"),
+            ("?y", "http://a/b/c/d;p?y"),
+            ("g?y", "http://a/b/c/g?y"),
+            ("#s", "http://a/b/c/d;p?q#s"),
+            ("g#s", "http://a/b/c/g#s"),
+            ("g?y#s", "http://a/b/c/g?y#s"),
+            (";x", "http://a/b/c/;x"),
+            ("g;x", "http://a/b/c/g;x"),
+            ("g;x?y#s", "http://a/b/c/g;x?y#s"),
+            ("", "http://a/b/c/d;p?q"),
+            (".", "http://a/b/c/"),
+            ("./", "http://a/b/c/"),
+            ("..", "http://a/b/"),
+            ("../", "http://a/b/"),
+            ("../g", "http://a/b/g"),
+            ("../..", "http://a/"),
+            ("../../", "http://a/"),
+            ("../../g", "http://a/g"),
+        ];
+        for (reference, expected) in examples {
+            assert_eq!(base.join(reference).unwrap().to_string(), expected, "joining {:?}", reference);
+        }
+    }
+
+    #[test]
+    fn test_join_rfc3986_abnormal_examples() {
+        // RFC 3986 section 5.4.2
+        let base = Url::parse("http://a/b/c/d;p?q").unwrap();
+        let examples = [
+            ("../../../g", "http://a/g"),
+            ("../../../../g", "http://a/g"),
+            ("/./g", "http://a/g"),
+            ("/../g", "http://a/g"),
+            ("g.", "http://a/b/c/g."),
+            (".g", "http://a/b/c/.g"),
+            ("g..", "http://a/b/c/g.."),
+            ("..g", "http://a/b/c/..g"),
+            ("./../g", "http://a/b/g"),
+            ("./g/.", "http://a/b/c/g/"),
+            ("g/./h", "http://a/b/c/g/h"),
+            ("g/../h", "http://a/b/c/h"),
+            ("g;x=1/./y", "http://a/b/c/g;x=1/y"),
+            ("g;x=1/../y", "http://a/b/c/y"),
+            ("g?y/./x", "http://a/b/c/g?y/./x"),
+            ("g?y/../x", "http://a/b/c/g?y/../x"),
+            ("g#s/./x", "http://a/b/c/g#s/./x"),
+            ("g#s/../x", "http://a/b/c/g#s/../x"),
+            // The RFC's result for backward-compatible parsers
+            ("http:g", "http://a/b/c/g"),
+        ];
+        for (reference, expected) in examples {
+            assert_eq!(base.join(reference).unwrap().to_string(), expected, "joining {:?}", reference);
+        }
+    }
+
+    #[test]
+    fn test_parse_serialize_round_trip() {
+        let urls = [
+            "https://h//a",
+            "https://h/a//b/",
+            "http://user:pw@example.com:8080/p/a%20b?q=1&r=2#frag",
+            "http://[::1]:8080/",
+            "https://example.com/",
+            "file:///tmp/dir/file.zt",
+            "mailto:someone@example.com",
+            "ws://host/socket?x",
+        ];
+        for input in urls {
+            let url = Url::parse(input).unwrap();
+            assert_eq!(url.to_string(), input);
+            assert_eq!(Url::parse(&url.to_string()).unwrap(), url);
+        }
+
+        // Parsing normalizes, after which the form is stable
+        let url = Url::parse("HTTP://Example.COM:80/a/./b/../c").unwrap();
+        assert_eq!(url.to_string(), "http://example.com/a/c");
+        assert_eq!(url.port(), None);
+        assert_eq!(Url::parse(&url.to_string()).unwrap(), url);
+        assert_eq!(Url::parse("https://h//a").unwrap().path_segments().unwrap(), ["", "a"]);
+    }
+
+    #[test]
+    fn test_invalid_port() {
+        for port in ["+80", "-1", "8o", "65536", " 80"] {
+            let input = format!("http://host:{}/", port);
+            assert_eq!(Url::parse(&input), Err(UrlError::InvalidPort(port.to_string())), "{}", input);
+        }
+        assert_eq!(Url::parse("http://host:8080/").unwrap().port(), Some(8080));
+    }
+}
@@ -1,6 +1,17 @@
 use std::collections::HashMap;
 use std::time::{Duration, Instant};
 use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use zaitun_bootstrap::kv_store::KvStore;
+
+/// Shared with the compiler's `--memory-stats`. The bench binary installs
+/// the allocator with
+/// `#[global_allocator] static ALLOCATOR: CountingAllocator = CountingAllocator;`;
+/// without it every count is zero.
+pub use zaitun_bootstrap::memory_stats::{AllocationStats, CountingAllocator};
 
 pub struct Benchmark {
     name: String,
@@ -8,6 +19,7 @@ pub struct Benchmark {
     setup_fn: Option<Box<dyn Fn()>>,
     bench_fn: Box<dyn Fn()>,
     teardown_fn: Option<Box<dyn Fn()>>,
+    track_allocations: bool,
 }
 
 impl Benchmark {
@@ -18,6 +30,7 @@ impl Benchmark {
             setup_fn: None,
             bench_fn,
             teardown_fn: None,
+            track_allocations: false,
         }
     }
     
@@ -36,8 +49,16 @@ impl Benchmark {
         self
     }
     
+    /// Also count the allocations each iteration makes, see
+    /// `CountingAllocator`
+    pub fn with_allocation_tracking(mut self) -> Self {
+        self.track_allocations = true;
+        self
+    }
+    
     pub fn run(&self) -> BenchmarkResult {
         let mut durations = Vec::with_capacity(self.iterations);
+        let mut allocations = AllocationStats::default();
         
         for _ in 0..self.iterations {
             // Run setup if provided
@@ -46,9 +67,13 @@ impl Benchmark {
             }
             
             // Run benchmark
+            let before = AllocationStats::current();
             let start = Instant::now();
             (self.bench_fn)();
             let duration = start.elapsed();
+            let made = AllocationStats::current().since(before);
+            allocations.count += made.count;
+            allocations.bytes += made.bytes;
             durations.push(duration);
             
             // Run teardown if provided
@@ -81,6 +106,10 @@ impl Benchmark {
             median,
             min,
             max,
+            allocations: self.track_allocations.then(|| AllocationStats {
+                count: allocations.count / self.iterations,
+                bytes: allocations.bytes / self.iterations,
+            }),
         }
     }
 }
@@ -92,6 +121,8 @@ pub struct BenchmarkResult {
     pub median: Duration,
     pub min: Duration,
     pub max: Duration,
+    /// Per iteration, if tracked
+    pub allocations: Option<AllocationStats>,
 }
 
 impl fmt::Display for BenchmarkResult {
@@ -102,6 +133,9 @@ impl fmt::Display for BenchmarkResult {
         writeln!(f, "  Median:     {:?}", self.median)?;
         writeln!(f, "  Min:        {:?}", self.min)?;
         writeln!(f, "  Max:        {:?}", self.max)?;
+        if let Some(allocations) = self.allocations {
+            writeln!(f, "  Allocs:     {} per iteration ({} bytes)", allocations.count, allocations.bytes)?;
+        }
         Ok(())
     }
 }
@@ -146,4 +180,154 @@ impl BenchmarkSuite {
         
         report
     }
-}
\ No newline at end of file
+}
+/// Binary size of one build artifact, compared against a saved baseline
+pub struct SizeResult {
+    pub name: String,
+    pub bytes: u64,
+    pub baseline: Option<u64>,
+}
+
+impl SizeResult {
+    /// Change from the baseline in percent; positive means the artifact grew
+    pub fn change_percent(&self) -> Option<f64> {
+        match self.baseline {
+            Some(0) | None => None,
+            Some(baseline) => Some((self.bytes as f64 - baseline as f64) / baseline as f64 * 100.0),
+        }
+    }
+}
+
+impl fmt::Display for SizeResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<32} {:>12} bytes", self.name, self.bytes)?;
+        if let (Some(baseline), Some(change)) = (self.baseline, self.change_percent()) {
+            write!(f, "  (baseline {}, {:+.2}%)", baseline, change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Binary-size tracking mode: records artifact sizes (e.g. the same
+/// program built with `-O2`, `-Os` and `-Oz`) and compares them with a
+/// baseline kept in a `KvStore`, so an interrupted save cannot leave a
+/// half-written baseline behind
+pub struct SizeTracker {
+    baseline: HashMap<String, u64>,
+    results: Vec<SizeResult>,
+}
+
+impl SizeTracker {
+    pub fn new() -> Self {
+        SizeTracker {
+            baseline: HashMap::new(),
+            results: Vec::new(),
+        }
+    }
+    
+    /// Load a baseline written by `save_baseline`. A missing store is an
+    /// empty baseline, so the first run just records sizes.
+    pub fn load_baseline(&mut self, path: &Path) -> io::Result<()> {
+        let store = KvStore::open(path)?;
+        for (name, bytes) in store.iter() {
+            if let (Ok(name), Ok(bytes)) = (std::str::from_utf8(name), bytes.try_into()) {
+                self.baseline.insert(name.to_string(), u64::from_le_bytes(bytes));
+            }
+        }
+        Ok(())
+    }
+    
+    /// Record the size of the artifact at `path` under `name`
+    pub fn measure(&mut self, name: &str, path: &Path) -> io::Result<u64> {
+        let bytes = fs::metadata(path)?.len();
+        self.results.push(SizeResult {
+            name: name.to_string(),
+            bytes,
+            baseline: self.baseline.get(name).copied(),
+        });
+        Ok(bytes)
+    }
+    
+    pub fn results(&self) -> &[SizeResult] {
+        &self.results
+    }
+    
+    /// Artifacts that grew by more than `threshold_percent`
+    pub fn regressions(&self, threshold_percent: f64) -> Vec<&SizeResult> {
+        self.results
+            .iter()
+            .filter(|result| result.change_percent().is_some_and(|change| change > threshold_percent))
+            .collect()
+    }
+    
+    /// Make current sizes the new baseline, dropping artifacts that were
+    /// not measured this run
+    pub fn save_baseline(&self, path: &Path) -> io::Result<()> {
+        let mut store = KvStore::open(path)?;
+        let stale: Vec<Vec<u8>> = store
+            .iter()
+            .map(|(name, _)| name.to_vec())
+            .filter(|name| !self.results.iter().any(|result| result.name.as_bytes() == name.as_slice()))
+            .collect();
+        for name in stale {
+            store.remove(&name)?;
+        }
+        for result in &self.results {
+            store.put(result.name.as_bytes(), &result.bytes.to_le_bytes())?;
+        }
+        store.sync()
+    }
+    
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        report.push_str("Binary Sizes:\n");
+        report.push_str("=============\n\n");
+        
+        for result in &self.results {
+            report.push_str(&format!("{}\n", result));
+        }
+        
+        report
+    }
+}
+
+/// Allocations per iteration of one benchmark before and after a change,
+/// e.g. the same program built with and without allocation reuse
+pub struct AllocationComparison {
+    pub name: String,
+    pub before: AllocationStats,
+    pub after: AllocationStats,
+}
+
+impl AllocationComparison {
+    /// Compare two tracked runs; `None` if either did not track allocations
+    pub fn new(before: &BenchmarkResult, after: &BenchmarkResult) -> Option<Self> {
+        Some(AllocationComparison {
+            name: after.name.clone(),
+            before: before.allocations?,
+            after: after.allocations?,
+        })
+    }
+    
+    /// Change in allocation count in percent; negative means fewer
+    pub fn change_percent(&self) -> Option<f64> {
+        match self.before.count {
+            0 => None,
+            before => Some((self.after.count as f64 - before as f64) / before as f64 * 100.0),
+        }
+    }
+}
+
+impl fmt::Display for AllocationComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<32} {:>8} -> {:<8} allocations/iteration ({} -> {} bytes)",
+            self.name, self.before.count, self.after.count, self.before.bytes, self.after.bytes
+        )?;
+        if let Some(change) = self.change_percent() {
+            write!(f, "  {:+.2}%", change)?;
+        }
+        Ok(())
+    }
+}
@@ -2,6 +2,8 @@ use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use zaitun_std::concurrency::crawl::Crawler;
+use zaitun_std::fs::ignore::Ignore;
 
 pub struct DocGenerator {
     source_dirs: Vec<PathBuf>,
@@ -46,12 +48,24 @@ impl DocGenerator {
         // Create output directory if it doesn't exist
         fs::create_dir_all(&self.output_dir)?;
         
-        // Parse source files and extract documentation
-        let mut docs = Vec::new();
+        // Skip what each source directory's .zaitunignore excludes, plus
+        // the patterns from the config
+        let mut crawler = Crawler::new();
         for dir in &self.source_dirs {
-            self.process_directory(dir, &mut docs)?;
+            let mut ignore = Ignore::load(dir)?;
+            ignore.add_patterns(&self.config.ignore);
+            crawler = crawler.with_ignore(ignore);
         }
         
+        // Parse source files in parallel and extract documentation, in
+        // path order so the output does not depend on scheduling
+        let files = crawler.crawl(
+            &self.source_dirs,
+            |path| path.extension().is_some_and(|ext| ext == "safe"),
+            DocGenerator::parse_file,
+        )?;
+        let docs: Vec<DocItem> = files.into_iter().flat_map(|(_, items)| items).collect();
+        
         // Generate index page
         self.generate_index(&docs)?;
         
@@ -69,25 +83,7 @@ impl DocGenerator {
         Ok(())
     }
     
-    fn process_directory(&self, dir: &Path, docs: &mut Vec<DocItem>) -> io::Result<()> {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            
-            if path.is_dir() {
-                self.process_directory(&path, docs)?;
-            } else if let Some(ext) = path.extension() {
-                if ext == "safe" {
-                    let doc_items = self.parse_file(&path)?;
-                    docs.extend(doc_items);
-                }
-            }
-        }
-        
-        Ok(())
-    }
-    
-    fn parse_file(&self, file: &Path) -> io::Result<Vec<DocItem>> {
+    fn parse_file(file: &Path) -> io::Result<Vec<DocItem>> {
         let content = fs::read_to_string(file)?;
         let mut items = Vec::new();
         
@@ -112,7 +108,7 @@ impl DocGenerator {
                 // End of doc comment block, parse the following declaration
                 in_doc_comment = false;
                 
-                if let Some(item) = self.parse_declaration(line, &current_doc, file, line_number) {
+                if let Some(item) = DocGenerator::parse_declaration(line, &current_doc, file, line_number) {
                     items.push(item);
                 }
                 
@@ -123,7 +119,7 @@ impl DocGenerator {
         Ok(items)
     }
     
-    fn parse_declaration(&self, line: &str, doc_text: &str, file: &Path, line_number: usize) -> Option<DocItem> {
+    fn parse_declaration(line: &str, doc_text: &str, file: &Path, line_number: usize) -> Option<DocItem> {
         let line = line.trim();
         
         // Check for different declaration types
@@ -417,6 +413,8 @@ pub struct DocConfig {
     pub author: String,
     pub description: String,
     pub repository: String,
+    /// Patterns skipped in addition to each source directory's ignore file
+    pub ignore: Vec<String>,
 }
 
 impl Default for DocConfig {
@@ -427,6 +425,7 @@ impl Default for DocConfig {
             author: String::from("SafeLang Team"),
             description: String::from("Documentation for the SafeLang programming language"),
             repository: String::from("https://github.com/safelang/safelang"),
+            ignore: Vec::new(),
         }
     }
 }
@@ -9,6 +9,15 @@ use std::io;
 use std::net::TcpStream;
 use serde::{Serialize, Deserialize};
 use serde_json::Value;
+use zaitun_std::checksum::Fingerprint;
+use zaitun_std::concurrency::crawl::{CancelToken, Crawler};
+use zaitun_std::fs::ignore::Ignore;
+use zaitun_std::trace;
+use zaitun_bootstrap::error_handling::{SourceFile, SourceMap};
+use zaitun_bootstrap::kv_store::KvStore;
+use zaitun_bootstrap::version_info::{self, Requirements};
+use zaitun_compiler::{LineCol, Severity as ApiSeverity, Span as ApiSpan};
+use crate::std_source::{StdSourceIndex, StdSymbolOrigin};
 
 // LSP message types
 #[derive(Debug, Serialize, Deserialize)]
@@ -16,6 +25,22 @@ struct InitializeParams {
     capabilities: ClientCapabilities,
     #[serde(rename = "rootUri")]
     root_uri: Option<String>,
+    #[serde(rename = "initializationOptions", default)]
+    initialization_options: Option<InitializationOptions>,
+}
+
+/// Server settings from the client's `initializationOptions`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InitializationOptions {
+    /// Patterns skipped when indexing, in addition to each workspace
+    /// folder's .zaitunignore
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// List every macro expansion level in a diagnostic's related
+    /// information, like `--macro-backtrace`, instead of only the
+    /// outermost invocation
+    #[serde(default, rename = "macroBacktrace")]
+    pub macro_backtrace: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -122,6 +147,15 @@ pub struct Diagnostic {
     pub severity: DiagnosticSeverity,
     pub message: String,
     pub source: String,
+    /// Secondary locations, e.g. the macro invocation an error in expanded
+    /// code came from
+    pub related_information: Vec<RelatedInformation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RelatedInformation {
+    pub location: Location,
+    pub message: String,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -184,21 +218,50 @@ pub struct LanguageServer {
     documents: Arc<Mutex<HashMap<String, Document>>>,
     workspace_folders: Arc<Mutex<Vec<String>>>,
     symbol_table: Arc<Mutex<SymbolTable>>,
+    std_sources: Option<Arc<StdSourceIndex>>,
+    /// Text of open documents. Clones share the loaded files, so the
+    /// compiler driver can be handed the editor's view of the workspace.
+    source_map: Mutex<SourceMap>,
+    /// Cancels the workspace index in progress, if any
+    indexing: Mutex<CancelToken>,
+    ignore_patterns: Mutex<Vec<String>>,
+    /// From the `macroBacktrace` initialization option
+    full_macro_backtrace: Mutex<bool>,
+    /// Symbols of the workspace's files from earlier sessions, keyed by
+    /// path; `None` until the workspace is indexed or when the store cannot
+    /// be opened
+    symbol_cache: Mutex<Option<KvStore>>,
 }
 
 impl LanguageServer {
     pub fn new() -> Self {
+        let std_sources = StdSourceIndex::locate()
+            .and_then(|root| StdSourceIndex::load(&root).ok())
+            .map(Arc::new);
+        
         LanguageServer {
             documents: Arc::new(Mutex::new(HashMap::new())),
             workspace_folders: Arc::new(Mutex::new(Vec::new())),
             symbol_table: Arc::new(Mutex::new(SymbolTable::new())),
+            std_sources,
+            source_map: Mutex::new(SourceMap::new()),
+            indexing: Mutex::new(CancelToken::new()),
+            ignore_patterns: Mutex::new(Vec::new()),
+            full_macro_backtrace: Mutex::new(false),
+            symbol_cache: Mutex::new(None),
         }
     }
     
-    pub fn initialize(&self, root_uri: Option<String>) -> io::Result<()> {
+    pub fn initialize(&self, root_uri: Option<String>, options: InitializationOptions) -> io::Result<()> {
+        // Refuse to start against a compiler for another language version;
+        // the error reaches the editor as the failed initialize response
+        version_info::verify(&Requirements::new("zaitun-lsp")).map_err(|e| io::Error::other(e.to_string()))?;
+        
+        *self.ignore_patterns.lock().unwrap() = options.ignore;
+        *self.full_macro_backtrace.lock().unwrap() = options.macro_backtrace;
+        
         if let Some(uri) = root_uri {
-            let mut folders = self.workspace_folders.lock().unwrap();
-            folders.push(uri);
+            self.workspace_folders.lock().unwrap().push(uri);
             
             // Index workspace
             self.index_workspace()?;
@@ -207,11 +270,21 @@ impl LanguageServer {
         Ok(())
     }
     
+    /// Sources of the documents opened so far, sharing their text
+    pub fn source_map(&self) -> SourceMap {
+        self.source_map.lock().unwrap().clone()
+    }
+    
     pub fn shutdown(&self) -> io::Result<()> {
         // Clean up resources
+        self.indexing.lock().unwrap().cancel();
         self.documents.lock().unwrap().clear();
         self.workspace_folders.lock().unwrap().clear();
         self.symbol_table.lock().unwrap().clear();
+        *self.source_map.lock().unwrap() = SourceMap::new();
+        if let Some(mut cache) = self.symbol_cache.lock().unwrap().take() {
+            let _ = cache.sync();
+        }
         
         Ok(())
     }
@@ -235,6 +308,13 @@ impl LanguageServer {
     }
     
     pub fn did_change(&self, uri: &str, version: i32, text: &str) -> io::Result<()> {
+        if StdSourceIndex::is_std_uri(uri) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "std library sources are read-only",
+            ));
+        }
+        
         let mut documents = self.documents.lock().unwrap();
         
         if let Some(document) = documents.get_mut(uri) {
@@ -257,6 +337,7 @@ impl LanguageServer {
     }
     
     pub fn completion(&self, uri: &str, position: Position) -> io::Result<Vec<CompletionItem>> {
+        let _span = trace::span("lsp.completion").field("uri", uri).enter();
         let documents = self.documents.lock().unwrap();
         let symbol_table = self.symbol_table.lock().unwrap();
         
@@ -306,6 +387,7 @@ impl LanguageServer {
     }
     
     pub fn definition(&self, uri: &str, position: Position) -> io::Result<Option<Location>> {
+        let _span = trace::span("lsp.definition").field("uri", uri).enter();
         let documents = self.documents.lock().unwrap();
         let symbol_table = self.symbol_table.lock().unwrap();
         
@@ -320,12 +402,53 @@ impl LanguageServer {
                     range: symbol.location.range.clone(),
                 }));
             }
+            
+            // Fall back to the bundled std/runtime sources
+            return Ok(self.std_definition(&word));
         }
         
         Ok(None)
     }
     
+    fn std_definition(&self, word: &str) -> Option<Location> {
+        let index = self.std_sources.as_ref()?;
+        let symbol = index.find(word)?;
+        let (uri, line, character) = index.location_of(symbol)?;
+        
+        Some(Location {
+            uri,
+            range: Range {
+                start: Position { line, character },
+                end: Position { line, character },
+            },
+        })
+    }
+    
+    /// Serve the contents of a read-only `zaitun-std:` document
+    pub fn std_source(&self, uri: &str) -> io::Result<String> {
+        match &self.std_sources {
+            Some(index) => index.read_source(uri),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "std sources are not installed with this toolchain",
+            )),
+        }
+    }
+    
+    /// Describe a std symbol implemented by the compiler rather than in source
+    pub fn std_intrinsic_note(&self, word: &str) -> Option<String> {
+        let symbol = self.std_sources.as_ref()?.find(word)?;
+        match &symbol.origin {
+            StdSymbolOrigin::Intrinsic { name, .. } => Some(format!(
+                "`{}` is a compiler intrinsic (`{}`); it has no source implementation",
+                symbol.path, name
+            )),
+            StdSymbolOrigin::Source { .. } => None,
+        }
+    }
+    
     pub fn hover(&self, uri: &str, position: Position) -> io::Result<Option<String>> {
+        let _span = trace::span("lsp.hover").field("uri", uri).enter();
         let documents = self.documents.lock().unwrap();
         let symbol_table = self.symbol_table.lock().unwrap();
         
@@ -343,12 +466,17 @@ impl LanguageServer {
                 
                 return Ok(Some(hover_text));
             }
+            
+            if let Some(note) = self.std_intrinsic_note(&word) {
+                return Ok(Some(note));
+            }
         }
         
         Ok(None)
     }
     
     pub fn references(&self, uri: &str, position: Position) -> io::Result<Vec<Location>> {
+        let _span = trace::span("lsp.references").field("uri", uri).enter();
         let documents = self.documents.lock().unwrap();
         let symbol_table = self.symbol_table.lock().unwrap();
         
@@ -365,7 +493,16 @@ impl LanguageServer {
         }
     }
     
+    /// Record `text` as the current contents of `uri`, returning the
+    /// shared file with its line table
+    fn load_source(&self, uri: &str, text: &str) -> Arc<SourceFile> {
+        let mut source_map = self.source_map.lock().unwrap();
+        let id = source_map.add_source(uri_to_path(uri), text.to_string());
+        source_map.file(id).expect("file was just added")
+    }
+    
     fn validate_document(&self, uri: &str) -> io::Result<()> {
+        let _span = trace::span("lsp.validate").field("uri", uri).enter();
         let mut documents = self.documents.lock().unwrap();
         
         if let Some(document) = documents.get_mut(uri) {
@@ -373,7 +510,9 @@ impl LanguageServer {
             document.diagnostics.clear();
             
             // Parse document and collect diagnostics
-            let diagnostics = self.parse_and_validate(&document.text)?;
+            let file = self.load_source(uri, &document.text);
+            let mut diagnostics = self.parse_and_validate(&file)?;
+            diagnostics.extend(self.compiler_diagnostics(&file));
             document.diagnostics = diagnostics;
             
             // Update symbol table
@@ -384,24 +523,55 @@ impl LanguageServer {
         Ok(())
     }
     
-    fn parse_and_validate(&self, text: &str) -> io::Result<Vec<Diagnostic>> {
+    /// Parse and type check `file` with the compiler. Errors inside macro
+    /// expansions point at the outermost invocation in related
+    /// information; every level is listed with the `macroBacktrace` option.
+    fn compiler_diagnostics(&self, file: &SourceFile) -> Vec<Diagnostic> {
+        let full = *self.full_macro_backtrace.lock().unwrap();
+        let checked = zaitun_compiler::check_str(&file.path.to_string_lossy(), &file.source);
+        
+        checked.diagnostics.iter()
+            .filter_map(|diagnostic| {
+                // Expanded code has no document to show it in; point at the
+                // invocation the user wrote instead
+                let span = match (diagnostic.span.as_ref()?, diagnostic.macro_backtrace.last()) {
+                    (span, Some(outermost)) if span.file != file.path => &outermost.call_site,
+                    (span, _) => span,
+                };
+                let related_information = diagnostic.related_locations(full)
+                    .into_iter()
+                    .map(|(span, message)| RelatedInformation {
+                        location: Location {
+                            uri: path_to_uri(&span.file),
+                            range: api_range(&span),
+                        },
+                        message,
+                    })
+                    .collect();
+                
+                Some(Diagnostic {
+                    range: api_range(span),
+                    severity: match diagnostic.severity {
+                        ApiSeverity::Error => DiagnosticSeverity::Error,
+                        ApiSeverity::Warning => DiagnosticSeverity::Warning,
+                    },
+                    message: diagnostic.message.clone(),
+                    source: "zaitun".to_string(),
+                    related_information,
+                })
+            })
+            .collect()
+    }
+    
+    fn parse_and_validate(&self, file: &SourceFile) -> io::Result<Vec<Diagnostic>> {
         let mut diagnostics = Vec::new();
         
         // Simple validation: check for unmatched braces
         let mut brace_stack = Vec::new();
-        let mut line = 0;
-        let mut char_pos = 0;
         
-        for (i, c) in text.chars().enumerate() {
-            if c == '\n' {
-                line += 1;
-                char_pos = 0;
-            } else {
-                char_pos += 1;
-            }
-            
+        for (offset, c) in file.source.char_indices() {
             if c == '{' || c == '(' || c == '[' {
-                brace_stack.push((c, line, char_pos));
+                brace_stack.push((c, offset));
             } else if c == '}' || c == ')' || c == ']' {
                 let matching = match c {
                     '}' => '{',
@@ -410,100 +580,258 @@ impl LanguageServer {
                     _ => unreachable!(),
                 };
                 
-                if let Some((brace, brace_line, brace_char)) = brace_stack.pop() {
+                if let Some((brace, _)) = brace_stack.pop() {
                     if brace != matching {
                         diagnostics.push(Diagnostic {
-                            range: Range {
-                                start: Position {
-                                    line: line as u32,
-                                    character: char_pos as u32,
-                                },
-                                end: Position {
-                                    line: line as u32,
-                                    character: (char_pos + 1) as u32,
-                                },
-                            },
+                            range: char_range(file, offset),
                             severity: DiagnosticSeverity::Error,
                             message: format!("Mismatched brace: expected closing for '{}'", brace),
                             source: "safelang-lsp".to_string(),
+                            related_information: Vec::new(),
                         });
                     }
                 } else {
                     diagnostics.push(Diagnostic {
-                        range: Range {
-                            start: Position {
-                                line: line as u32,
-                                character: char_pos as u32,
-                            },
-                            end: Position {
-                                line: line as u32,
-                                character: (char_pos + 1) as u32,
-                            },
-                        },
+                        range: char_range(file, offset),
                         severity: DiagnosticSeverity::Error,
                         message: format!("Unexpected closing brace '{}'", c),
                         source: "safelang-lsp".to_string(),
+                        related_information: Vec::new(),
                     });
                 }
             }
         }
         
         // Report any unclosed braces
-        for (brace, line, char_pos) in brace_stack {
+        for (brace, offset) in brace_stack {
             diagnostics.push(Diagnostic {
-                range: Range {
-                    start: Position {
-                        line: line as u32,
-                        character: char_pos as u32,
-                    },
-                    end: Position {
-                        line: line as u32,
-                        character: (char_pos + 1) as u32,
-                    },
-                },
+                range: char_range(file, offset),
                 severity: DiagnosticSeverity::Error,
                 message: format!("Unclosed brace '{}'", brace),
                 source: "safelang-lsp".to_string(),
+                related_information: Vec::new(),
             });
         }
         
         Ok(diagnostics)
     }
     
+    /// Read and parse the workspace's files in parallel, skipping what the
+    /// ignore files and `ignore` option exclude, then add their symbols.
+    /// `shutdown` cancels an index in progress.
     fn index_workspace(&self) -> io::Result<()> {
-        let folders = self.workspace_folders.lock().unwrap();
-        let mut symbol_table = self.symbol_table.lock().unwrap();
+        let mut span = trace::span("lsp.index_workspace").enter();
+        let roots: Vec<PathBuf> = self.workspace_folders
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|folder| uri_to_path(folder))
+            .collect();
         
-        for folder in &*folders {
-            let path = Path::new(folder.strip_prefix("file://").unwrap_or(folder));
-            self.index_directory(&mut symbol_table, path)?;
+        let mut crawler = Crawler::new();
+        let patterns = self.ignore_patterns.lock().unwrap().clone();
+        for root in &roots {
+            let mut ignore = Ignore::load(root)?;
+            ignore.add_patterns(&patterns);
+            crawler = crawler.with_ignore(ignore);
         }
+        *self.indexing.lock().unwrap() = crawler.cancel_token();
         
-        Ok(())
-    }
-    
-    fn index_directory(&self, symbol_table: &mut SymbolTable, dir: &Path) -> io::Result<()> {
-        if !dir.is_dir() {
-            return Ok(());
+        // A broken store only costs the reuse, never the index
+        let mut cache_slot = self.symbol_cache.lock().unwrap();
+        if cache_slot.is_none() {
+            *cache_slot = roots
+                .first()
+                .and_then(|root| KvStore::open(&root.join("target").join(SYMBOL_CACHE_STORE)).ok());
         }
+        let cache = cache_slot.as_ref();
         
-        for entry in std::fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            
-            if path.is_dir() {
-                self.index_directory(symbol_table, &path)?;
-            } else if let Some(ext) = path.extension() {
-                if ext == "safe" {
-                    self.index_file(symbol_table, &path)?;
+        let files = crawler.crawl(
+            &roots,
+            |path| path.extension().is_some_and(|ext| ext == "safe"),
+            |path| {
+                let text = std::fs::read_to_string(path)?;
+                let hash = content_hash(&text);
+                if let Some(symbols) = cache.and_then(|cache| cached_symbols(cache, path, hash)) {
+                    return Ok(IndexedFile::Cached(symbols));
                 }
+                // Files that do not parse are indexed once they are opened
+                Ok(IndexedFile::Parsed(hash, parse_document(&text).ok()))
+            },
+        )?;
+        
+        span.record("files", files.len());
+        let mut symbol_table = self.symbol_table.lock().unwrap();
+        let mut reused = 0;
+        let mut fresh = Vec::new();
+        for (path, file) in &files {
+            match file {
+                IndexedFile::Cached(symbols) => {
+                    reused += 1;
+                    for symbol in symbols {
+                        symbol_table.add_symbol(symbol.clone());
+                    }
+                }
+                IndexedFile::Parsed(hash, Some(ast)) => {
+                    let mut file_table = SymbolTable::new();
+                    self.index_file(&mut file_table, path, ast);
+                    let symbols: Vec<Symbol> = file_table.get_symbols().into_iter().cloned().collect();
+                    for symbol in &symbols {
+                        symbol_table.add_symbol(symbol.clone());
+                    }
+                    fresh.push((path, *hash, symbols));
+                }
+                IndexedFile::Parsed(_, None) => {}
+            }
+        }
+        span.record("cached", reused);
+        
+        if let Some(cache) = cache_slot.as_mut() {
+            for (path, hash, symbols) in fresh {
+                let _ = store_symbols(cache, path, hash, &symbols);
             }
+            // Drop files deleted since the last session
+            let indexed: std::collections::HashSet<Vec<u8>> =
+                files.iter().map(|(path, _)| cache_key(path)).collect();
+            let stale: Vec<Vec<u8>> = cache
+                .iter()
+                .map(|(key, _)| key.to_vec())
+                .filter(|key| !indexed.contains(key))
+                .collect();
+            for key in stale {
+                let _ = cache.remove(&key);
+            }
+            let _ = cache.sync();
         }
         
         Ok(())
     }
 }
 
+/// Filesystem path of a `file://` URI; other URIs are kept as they are,
+/// which still gives them a stable place in the source map
+fn uri_to_path(uri: &str) -> PathBuf {
+    PathBuf::from(uri.strip_prefix("file://").unwrap_or(uri))
+}
+
+/// Symbol cache file, under the first workspace root's `target` directory
+const SYMBOL_CACHE_STORE: &str = "lsp-symbols.kv";
+
+/// A crawled workspace file: either its symbols from the cache, or its
+/// content hash and parse
+enum IndexedFile {
+    Cached(Vec<Symbol>),
+    Parsed(u64, Option<AST>),
+}
+
+/// On-disk form of a `Symbol`
+#[derive(Serialize, Deserialize)]
+struct CachedSymbol {
+    name: String,
+    kind: String,
+    documentation: Option<String>,
+    uri: String,
+    range: [u32; 4],
+}
+
+/// FNV-1a, stable across builds unlike `DefaultHasher`
+fn content_hash(text: &str) -> u64 {
+    Fingerprint::new().str_field(text).finish()
+}
+
+fn cache_key(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Cached symbols of `path`, if they were taken from the same contents.
+/// Entries are the content hash followed by the symbols as JSON.
+fn cached_symbols(cache: &KvStore, path: &Path, hash: u64) -> Option<Vec<Symbol>> {
+    let value = cache.get(&cache_key(path))?;
+    let (stored_hash, json) = value.split_at_checked(8)?;
+    if u64::from_le_bytes(stored_hash.try_into().ok()?) != hash {
+        return None;
+    }
+    let cached: Vec<CachedSymbol> = serde_json::from_slice(json).ok()?;
+    cached
+        .into_iter()
+        .map(|symbol| {
+            let kind = match symbol.kind.as_str() {
+                "Class" => SymbolKind::Class,
+                "Function" => SymbolKind::Function,
+                "Variable" => SymbolKind::Variable,
+                "Struct" => SymbolKind::Struct,
+                "Enum" => SymbolKind::Enum,
+                "Interface" => SymbolKind::Interface,
+                "Module" => SymbolKind::Module,
+                _ => return None,
+            };
+            let [start_line, start_character, end_line, end_character] = symbol.range;
+            Some(Symbol {
+                name: symbol.name,
+                kind,
+                documentation: symbol.documentation,
+                location: Location {
+                    uri: symbol.uri,
+                    range: Range {
+                        start: Position { line: start_line, character: start_character },
+                        end: Position { line: end_line, character: end_character },
+                    },
+                },
+            })
+        })
+        .collect()
+}
+
+fn store_symbols(cache: &mut KvStore, path: &Path, hash: u64, symbols: &[Symbol]) -> io::Result<()> {
+    let cached: Vec<CachedSymbol> = symbols
+        .iter()
+        .map(|symbol| {
+            let range = &symbol.location.range;
+            CachedSymbol {
+                name: symbol.name.clone(),
+                kind: format!("{:?}", symbol.kind),
+                documentation: symbol.documentation.clone(),
+                uri: symbol.location.uri.clone(),
+                range: [range.start.line, range.start.character, range.end.line, range.end.character],
+            }
+        })
+        .collect();
+    
+    let mut value = hash.to_le_bytes().to_vec();
+    value.extend(serde_json::to_vec(&cached)?);
+    cache.put(&cache_key(path), &value)
+}
+
+fn path_to_uri(path: &Path) -> String {
+    format!("file://{}", path.display())
+}
+
+/// LSP range of a compiler span; LSP positions are 0-based
+fn api_range(span: &ApiSpan) -> Range {
+    let position = |at: LineCol| Position {
+        line: at.line.saturating_sub(1) as u32,
+        character: at.column.saturating_sub(1) as u32,
+    };
+    Range {
+        start: position(span.start),
+        end: position(span.end),
+    }
+}
+
+/// 0-based range of the character at byte `offset`
+fn char_range(file: &SourceFile, offset: usize) -> Range {
+    let (line, column) = file.line_col(offset);
+    let start = Position {
+        line: (line - 1) as u32,
+        character: (column - 1) as u32,
+    };
+    let end = Position {
+        character: start.character + 1,
+        ..start.clone()
+    };
+    Range { start, end }
+}
+
 fn parse_document(text: &str) -> Result<AST, ParseError> {
     // Parse document into AST
     // ... implementation details ...
@@ -545,6 +873,9 @@ struct ResponseError {
     data: Option<Value>,
 }
 
+/// LSP error code for a well-formed request the server could not satisfy
+const REQUEST_FAILED: i32 = -32803;
+
 #[derive(Debug, Serialize, Deserialize)]
 struct NotificationMessage {
     jsonrpc: String,
@@ -557,6 +888,7 @@ pub struct LspServer {
     capabilities: Value,
     documents: Arc<Mutex<HashMap<String, String>>>,
     diagnostics: Arc<Mutex<HashMap<String, Vec<Diagnostic>>>>,
+    std_sources: Option<Arc<StdSourceIndex>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -612,6 +944,9 @@ impl LspServer {
             }),
             documents: Arc::new(Mutex::new(HashMap::new())),
             diagnostics: Arc::new(Mutex::new(HashMap::new())),
+            std_sources: StdSourceIndex::locate()
+                .and_then(|root| StdSourceIndex::load(&root).ok())
+                .map(Arc::new),
         }
     }
     
@@ -674,6 +1009,7 @@ impl LspServer {
     }
     
     fn handle_request(&self, request: RequestMessage) -> Option<ResponseMessage> {
+        let _span = trace::span("lsp.request").field("method", request.method.as_str()).enter();
         match request.method.as_str() {
             "initialize" => {
                 Some(ResponseMessage {
@@ -685,6 +1021,36 @@ impl LspServer {
                     error: None,
                 })
             }
+            "zaitun/stdSource" => {
+                // Contents of a read-only `zaitun-std:` document that a
+                // std definition location points to
+                let uri = request.params.as_ref()
+                    .and_then(|params| params.get("uri"))
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                let source = match &self.std_sources {
+                    Some(index) => index.read_source(uri),
+                    None => Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "std sources are not installed with this toolchain",
+                    )),
+                };
+                
+                let (result, error) = match source {
+                    Ok(text) => (Some(json!({ "uri": uri, "text": text })), None),
+                    Err(e) => (None, Some(ResponseError {
+                        code: REQUEST_FAILED,
+                        message: e.to_string(),
+                        data: None,
+                    })),
+                };
+                Some(ResponseMessage {
+                    jsonrpc: "2.0".to_string(),
+                    id: request.id,
+                    result,
+                    error,
+                })
+            }
             "textDocument/completion" => {
                 // Implement completion logic here
                 Some(ResponseMessage {
@@ -726,6 +1092,7 @@ use std::sync::{Arc, Mutex};
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
+use zaitun_std::checksum::Fingerprint;
 
 #[derive(Debug)]
 struct SafeLangDocument {
@@ -841,7 +1208,57 @@ impl LanguageServer for SafeLangLanguageServer {
     async fn shutdown(&self) -> Result<()> {
         Ok(())
     }
-    
+
+    /// Offer quick fixes carried in diagnostic data, filled from the
+    /// compiler's `Diagnostic`: `data.suggestion` is a "did you mean"
+    /// replacement, `data.fixes` a list of `{title, replacement}` edits such
+    /// as assigning an unused `Result` to `_` or propagating it with `?`.
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        let mut actions = Vec::new();
+
+        for diagnostic in params.context.diagnostics {
+            let data = match &diagnostic.data {
+                Some(data) => data,
+                None => continue,
+            };
+
+            let mut fixes = Vec::new();
+            if let Some(replacement) = data.get("suggestion").and_then(|value| value.as_str()) {
+                fixes.push((format!("Replace with `{}`", replacement), replacement.to_string()));
+            }
+            for fix in data.get("fixes").and_then(|value| value.as_array()).into_iter().flatten() {
+                let title = fix.get("title").and_then(|value| value.as_str());
+                let replacement = fix.get("replacement").and_then(|value| value.as_str());
+                if let (Some(title), Some(replacement)) = (title, replacement) {
+                    fixes.push((title.to_string(), replacement.to_string()));
+                }
+            }
+
+            for (index, (title, replacement)) in fixes.into_iter().enumerate() {
+                let mut changes = HashMap::new();
+                changes.insert(uri.clone(), vec![TextEdit {
+                    range: diagnostic.range,
+                    new_text: replacement,
+                }]);
+
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic.clone()]),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(changes),
+                        ..WorkspaceEdit::default()
+                    }),
+                    is_preferred: Some(index == 0),
+                    ..CodeAction::default()
+                }));
+            }
+        }
+
+        Ok(Some(actions))
+    }
+
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         let uri = params.text_document.uri;
         let text = params.text_document.text;
@@ -1045,6 +1462,7 @@ impl LanguageServer for SafeLangLanguageServer {
 }
     
     pub fn definition(&self, uri: &str, position: Position) -> io::Result<Option<Location>> {
+        let _span = trace::span("lsp.definition").field("uri", uri).enter();
         let documents = self.documents.lock().unwrap();
         let symbol_table = self.symbol_table.lock().unwrap();
         
@@ -1065,6 +1483,7 @@ impl LanguageServer for SafeLangLanguageServer {
     }
     
     pub fn hover(&self, uri: &str, position: Position) -> io::Result<Option<String>> {
+        let _span = trace::span("lsp.hover").field("uri", uri).enter();
         let documents = self.documents.lock().unwrap();
         let symbol_table = self.symbol_table.lock().unwrap();
         
@@ -1088,6 +1507,7 @@ impl LanguageServer for SafeLangLanguageServer {
     }
     
     pub fn references(&self, uri: &str, position: Position) -> io::Result<Vec<Location>> {
+        let _span = trace::span("lsp.references").field("uri", uri).enter();
         let documents = self.documents.lock().unwrap();
         let symbol_table = self.symbol_table.lock().unwrap();
         
@@ -1104,7 +1524,16 @@ impl LanguageServer for SafeLangLanguageServer {
         }
     }
     
+    /// Record `text` as the current contents of `uri`, returning the
+    /// shared file with its line table
+    fn load_source(&self, uri: &str, text: &str) -> Arc<SourceFile> {
+        let mut source_map = self.source_map.lock().unwrap();
+        let id = source_map.add_source(uri_to_path(uri), text.to_string());
+        source_map.file(id).expect("file was just added")
+    }
+    
     fn validate_document(&self, uri: &str) -> io::Result<()> {
+        let _span = trace::span("lsp.validate").field("uri", uri).enter();
         let mut documents = self.documents.lock().unwrap();
         
         if let Some(document) = documents.get_mut(uri) {
@@ -1112,7 +1541,9 @@ impl LanguageServer for SafeLangLanguageServer {
             document.diagnostics.clear();
             
             // Parse document and collect diagnostics
-            let diagnostics = self.parse_and_validate(&document.text)?;
+            let file = self.load_source(uri, &document.text);
+            let mut diagnostics = self.parse_and_validate(&file)?;
+            diagnostics.extend(self.compiler_diagnostics(&file));
             document.diagnostics = diagnostics;
             
             // Update symbol table
@@ -1123,24 +1554,55 @@ impl LanguageServer for SafeLangLanguageServer {
         Ok(())
     }
     
-    fn parse_and_validate(&self, text: &str) -> io::Result<Vec<Diagnostic>> {
+    /// Parse and type check `file` with the compiler. Errors inside macro
+    /// expansions point at the outermost invocation in related
+    /// information; every level is listed with the `macroBacktrace` option.
+    fn compiler_diagnostics(&self, file: &SourceFile) -> Vec<Diagnostic> {
+        let full = *self.full_macro_backtrace.lock().unwrap();
+        let checked = zaitun_compiler::check_str(&file.path.to_string_lossy(), &file.source);
+        
+        checked.diagnostics.iter()
+            .filter_map(|diagnostic| {
+                // Expanded code has no document to show it in; point at the
+                // invocation the user wrote instead
+                let span = match (diagnostic.span.as_ref()?, diagnostic.macro_backtrace.last()) {
+                    (span, Some(outermost)) if span.file != file.path => &outermost.call_site,
+                    (span, _) => span,
+                };
+                let related_information = diagnostic.related_locations(full)
+                    .into_iter()
+                    .map(|(span, message)| RelatedInformation {
+                        location: Location {
+                            uri: path_to_uri(&span.file),
+                            range: api_range(&span),
+                        },
+                        message,
+                    })
+                    .collect();
+                
+                Some(Diagnostic {
+                    range: api_range(span),
+                    severity: match diagnostic.severity {
+                        ApiSeverity::Error => DiagnosticSeverity::Error,
+                        ApiSeverity::Warning => DiagnosticSeverity::Warning,
+                    },
+                    message: diagnostic.message.clone(),
+                    source: "zaitun".to_string(),
+                    related_information,
+                })
+            })
+            .collect()
+    }
+    
+    fn parse_and_validate(&self, file: &SourceFile) -> io::Result<Vec<Diagnostic>> {
         let mut diagnostics = Vec::new();
         
         // Simple validation: check for unmatched braces
         let mut brace_stack = Vec::new();
-        let mut line = 0;
-        let mut char_pos = 0;
         
-        for (i, c) in text.chars().enumerate() {
-            if c == '\n' {
-                line += 1;
-                char_pos = 0;
-            } else {
-                char_pos += 1;
-            }
-            
+        for (offset, c) in file.source.char_indices() {
             if c == '{' || c == '(' || c == '[' {
-                brace_stack.push((c, line, char_pos));
+                brace_stack.push((c, offset));
             } else if c == '}' || c == ')' || c == ']' {
                 let matching = match c {
                     '}' => '{',
@@ -1149,100 +1611,258 @@ impl LanguageServer for SafeLangLanguageServer {
                     _ => unreachable!(),
                 };
                 
-                if let Some((brace, brace_line, brace_char)) = brace_stack.pop() {
+                if let Some((brace, _)) = brace_stack.pop() {
                     if brace != matching {
                         diagnostics.push(Diagnostic {
-                            range: Range {
-                                start: Position {
-                                    line: line as u32,
-                                    character: char_pos as u32,
-                                },
-                                end: Position {
-                                    line: line as u32,
-                                    character: (char_pos + 1) as u32,
-                                },
-                            },
+                            range: char_range(file, offset),
                             severity: DiagnosticSeverity::Error,
                             message: format!("Mismatched brace: expected closing for '{}'", brace),
                             source: "safelang-lsp".to_string(),
+                            related_information: Vec::new(),
                         });
                     }
                 } else {
                     diagnostics.push(Diagnostic {
-                        range: Range {
-                            start: Position {
-                                line: line as u32,
-                                character: char_pos as u32,
-                            },
-                            end: Position {
-                                line: line as u32,
-                                character: (char_pos + 1) as u32,
-                            },
-                        },
+                        range: char_range(file, offset),
                         severity: DiagnosticSeverity::Error,
                         message: format!("Unexpected closing brace '{}'", c),
                         source: "safelang-lsp".to_string(),
+                        related_information: Vec::new(),
                     });
                 }
             }
         }
         
         // Report any unclosed braces
-        for (brace, line, char_pos) in brace_stack {
+        for (brace, offset) in brace_stack {
             diagnostics.push(Diagnostic {
-                range: Range {
-                    start: Position {
-                        line: line as u32,
-                        character: char_pos as u32,
-                    },
-                    end: Position {
-                        line: line as u32,
-                        character: (char_pos + 1) as u32,
-                    },
-                },
+                range: char_range(file, offset),
                 severity: DiagnosticSeverity::Error,
                 message: format!("Unclosed brace '{}'", brace),
                 source: "safelang-lsp".to_string(),
+                related_information: Vec::new(),
             });
         }
         
         Ok(diagnostics)
     }
     
+    /// Read and parse the workspace's files in parallel, skipping what the
+    /// ignore files and `ignore` option exclude, then add their symbols.
+    /// `shutdown` cancels an index in progress.
     fn index_workspace(&self) -> io::Result<()> {
-        let folders = self.workspace_folders.lock().unwrap();
-        let mut symbol_table = self.symbol_table.lock().unwrap();
+        let mut span = trace::span("lsp.index_workspace").enter();
+        let roots: Vec<PathBuf> = self.workspace_folders
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|folder| uri_to_path(folder))
+            .collect();
         
-        for folder in &*folders {
-            let path = Path::new(folder.strip_prefix("file://").unwrap_or(folder));
-            self.index_directory(&mut symbol_table, path)?;
+        let mut crawler = Crawler::new();
+        let patterns = self.ignore_patterns.lock().unwrap().clone();
+        for root in &roots {
+            let mut ignore = Ignore::load(root)?;
+            ignore.add_patterns(&patterns);
+            crawler = crawler.with_ignore(ignore);
         }
+        *self.indexing.lock().unwrap() = crawler.cancel_token();
         
-        Ok(())
-    }
-    
-    fn index_directory(&self, symbol_table: &mut SymbolTable, dir: &Path) -> io::Result<()> {
-        if !dir.is_dir() {
-            return Ok(());
+        // A broken store only costs the reuse, never the index
+        let mut cache_slot = self.symbol_cache.lock().unwrap();
+        if cache_slot.is_none() {
+            *cache_slot = roots
+                .first()
+                .and_then(|root| KvStore::open(&root.join("target").join(SYMBOL_CACHE_STORE)).ok());
         }
+        let cache = cache_slot.as_ref();
         
-        for entry in std::fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            
-            if path.is_dir() {
-                self.index_directory(symbol_table, &path)?;
-            } else if let Some(ext) = path.extension() {
-                if ext == "safe" {
-                    self.index_file(symbol_table, &path)?;
+        let files = crawler.crawl(
+            &roots,
+            |path| path.extension().is_some_and(|ext| ext == "safe"),
+            |path| {
+                let text = std::fs::read_to_string(path)?;
+                let hash = content_hash(&text);
+                if let Some(symbols) = cache.and_then(|cache| cached_symbols(cache, path, hash)) {
+                    return Ok(IndexedFile::Cached(symbols));
                 }
+                // Files that do not parse are indexed once they are opened
+                Ok(IndexedFile::Parsed(hash, parse_document(&text).ok()))
+            },
+        )?;
+        
+        span.record("files", files.len());
+        let mut symbol_table = self.symbol_table.lock().unwrap();
+        let mut reused = 0;
+        let mut fresh = Vec::new();
+        for (path, file) in &files {
+            match file {
+                IndexedFile::Cached(symbols) => {
+                    reused += 1;
+                    for symbol in symbols {
+                        symbol_table.add_symbol(symbol.clone());
+                    }
+                }
+                IndexedFile::Parsed(hash, Some(ast)) => {
+                    let mut file_table = SymbolTable::new();
+                    self.index_file(&mut file_table, path, ast);
+                    let symbols: Vec<Symbol> = file_table.get_symbols().into_iter().cloned().collect();
+                    for symbol in &symbols {
+                        symbol_table.add_symbol(symbol.clone());
+                    }
+                    fresh.push((path, *hash, symbols));
+                }
+                IndexedFile::Parsed(_, None) => {}
             }
         }
+        span.record("cached", reused);
+        
+        if let Some(cache) = cache_slot.as_mut() {
+            for (path, hash, symbols) in fresh {
+                let _ = store_symbols(cache, path, hash, &symbols);
+            }
+            // Drop files deleted since the last session
+            let indexed: std::collections::HashSet<Vec<u8>> =
+                files.iter().map(|(path, _)| cache_key(path)).collect();
+            let stale: Vec<Vec<u8>> = cache
+                .iter()
+                .map(|(key, _)| key.to_vec())
+                .filter(|key| !indexed.contains(key))
+                .collect();
+            for key in stale {
+                let _ = cache.remove(&key);
+            }
+            let _ = cache.sync();
+        }
         
         Ok(())
     }
 }
 
+/// Filesystem path of a `file://` URI; other URIs are kept as they are,
+/// which still gives them a stable place in the source map
+fn uri_to_path(uri: &str) -> PathBuf {
+    PathBuf::from(uri.strip_prefix("file://").unwrap_or(uri))
+}
+
+/// Symbol cache file, under the first workspace root's `target` directory
+const SYMBOL_CACHE_STORE: &str = "lsp-symbols.kv";
+
+/// A crawled workspace file: either its symbols from the cache, or its
+/// content hash and parse
+enum IndexedFile {
+    Cached(Vec<Symbol>),
+    Parsed(u64, Option<AST>),
+}
+
+/// On-disk form of a `Symbol`
+#[derive(Serialize, Deserialize)]
+struct CachedSymbol {
+    name: String,
+    kind: String,
+    documentation: Option<String>,
+    uri: String,
+    range: [u32; 4],
+}
+
+/// FNV-1a, stable across builds unlike `DefaultHasher`
+fn content_hash(text: &str) -> u64 {
+    Fingerprint::new().str_field(text).finish()
+}
+
+fn cache_key(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Cached symbols of `path`, if they were taken from the same contents.
+/// Entries are the content hash followed by the symbols as JSON.
+fn cached_symbols(cache: &KvStore, path: &Path, hash: u64) -> Option<Vec<Symbol>> {
+    let value = cache.get(&cache_key(path))?;
+    let (stored_hash, json) = value.split_at_checked(8)?;
+    if u64::from_le_bytes(stored_hash.try_into().ok()?) != hash {
+        return None;
+    }
+    let cached: Vec<CachedSymbol> = serde_json::from_slice(json).ok()?;
+    cached
+        .into_iter()
+        .map(|symbol| {
+            let kind = match symbol.kind.as_str() {
+                "Class" => SymbolKind::Class,
+                "Function" => SymbolKind::Function,
+                "Variable" => SymbolKind::Variable,
+                "Struct" => SymbolKind::Struct,
+                "Enum" => SymbolKind::Enum,
+                "Interface" => SymbolKind::Interface,
+                "Module" => SymbolKind::Module,
+                _ => return None,
+            };
+            let [start_line, start_character, end_line, end_character] = symbol.range;
+            Some(Symbol {
+                name: symbol.name,
+                kind,
+                documentation: symbol.documentation,
+                location: Location {
+                    uri: symbol.uri,
+                    range: Range {
+                        start: Position { line: start_line, character: start_character },
+                        end: Position { line: end_line, character: end_character },
+                    },
+                },
+            })
+        })
+        .collect()
+}
+
+fn store_symbols(cache: &mut KvStore, path: &Path, hash: u64, symbols: &[Symbol]) -> io::Result<()> {
+    let cached: Vec<CachedSymbol> = symbols
+        .iter()
+        .map(|symbol| {
+            let range = &symbol.location.range;
+            CachedSymbol {
+                name: symbol.name.clone(),
+                kind: format!("{:?}", symbol.kind),
+                documentation: symbol.documentation.clone(),
+                uri: symbol.location.uri.clone(),
+                range: [range.start.line, range.start.character, range.end.line, range.end.character],
+            }
+        })
+        .collect();
+    
+    let mut value = hash.to_le_bytes().to_vec();
+    value.extend(serde_json::to_vec(&cached)?);
+    cache.put(&cache_key(path), &value)
+}
+
+fn path_to_uri(path: &Path) -> String {
+    format!("file://{}", path.display())
+}
+
+/// LSP range of a compiler span; LSP positions are 0-based
+fn api_range(span: &ApiSpan) -> Range {
+    let position = |at: LineCol| Position {
+        line: at.line.saturating_sub(1) as u32,
+        character: at.column.saturating_sub(1) as u32,
+    };
+    Range {
+        start: position(span.start),
+        end: position(span.end),
+    }
+}
+
+/// 0-based range of the character at byte `offset`
+fn char_range(file: &SourceFile, offset: usize) -> Range {
+    let (line, column) = file.line_col(offset);
+    let start = Position {
+        line: (line - 1) as u32,
+        character: (column - 1) as u32,
+    };
+    let end = Position {
+        character: start.character + 1,
+        ..start.clone()
+    };
+    Range { start, end }
+}
+
 fn parse_document(text: &str) -> Result<AST, ParseError> {
     // Parse document into AST
     // ... implementation details ...
@@ -1284,6 +1904,9 @@ struct ResponseError {
     data: Option<Value>,
 }
 
+/// LSP error code for a well-formed request the server could not satisfy
+const REQUEST_FAILED: i32 = -32803;
+
 #[derive(Debug, Serialize, Deserialize)]
 struct NotificationMessage {
     jsonrpc: String,
@@ -1296,6 +1919,7 @@ pub struct LspServer {
     capabilities: Value,
     documents: Arc<Mutex<HashMap<String, String>>>,
     diagnostics: Arc<Mutex<HashMap<String, Vec<Diagnostic>>>>,
+    std_sources: Option<Arc<StdSourceIndex>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1351,6 +1975,9 @@ impl LspServer {
             }),
             documents: Arc::new(Mutex::new(HashMap::new())),
             diagnostics: Arc::new(Mutex::new(HashMap::new())),
+            std_sources: StdSourceIndex::locate()
+                .and_then(|root| StdSourceIndex::load(&root).ok())
+                .map(Arc::new),
         }
     }
     
@@ -1413,6 +2040,7 @@ impl LspServer {
     }
     
     fn handle_request(&self, request: RequestMessage) -> Option<ResponseMessage> {
+        let _span = trace::span("lsp.request").field("method", request.method.as_str()).enter();
         match request.method.as_str() {
             "initialize" => {
                 Some(ResponseMessage {
@@ -1424,6 +2052,36 @@ impl LspServer {
                     error: None,
                 })
             }
+            "zaitun/stdSource" => {
+                // Contents of a read-only `zaitun-std:` document that a
+                // std definition location points to
+                let uri = request.params.as_ref()
+                    .and_then(|params| params.get("uri"))
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                let source = match &self.std_sources {
+                    Some(index) => index.read_source(uri),
+                    None => Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "std sources are not installed with this toolchain",
+                    )),
+                };
+                
+                let (result, error) = match source {
+                    Ok(text) => (Some(json!({ "uri": uri, "text": text })), None),
+                    Err(e) => (None, Some(ResponseError {
+                        code: REQUEST_FAILED,
+                        message: e.to_string(),
+                        data: None,
+                    })),
+                };
+                Some(ResponseMessage {
+                    jsonrpc: "2.0".to_string(),
+                    id: request.id,
+                    result,
+                    error,
+                })
+            }
             "textDocument/completion" => {
                 // Implement completion logic here
                 Some(ResponseMessage {
@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// URI scheme used for bundled std/runtime sources. Editors open these
+/// documents read-only since they are not part of the user's workspace.
+pub const STD_SOURCE_SCHEME: &str = "zaitun-std";
+
+/// Name of the metadata file shipped next to the bundled sources
+pub const STD_SOURCE_MANIFEST: &str = "std-sources.toml";
+
+/// Where a std symbol is implemented
+#[derive(Debug, Clone, PartialEq)]
+pub enum StdSymbolOrigin {
+    /// Implemented in a bundled source file
+    Source {
+        relative_path: PathBuf,
+    },
+    /// Implemented by the compiler; `declared_in` points to the std file
+    /// holding the intrinsic's declaration stub, if there is one
+    Intrinsic {
+        name: String,
+        declared_in: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct StdSymbol {
+    pub path: String,
+    pub origin: StdSymbolOrigin,
+}
+
+/// Index of std and runtime symbols shipped with the toolchain
+pub struct StdSourceIndex {
+    root: PathBuf,
+    symbols: HashMap<String, StdSymbol>,
+}
+
+impl StdSourceIndex {
+    pub fn new(root: &Path) -> Self {
+        StdSourceIndex {
+            root: root.to_path_buf(),
+            symbols: HashMap::new(),
+        }
+    }
+
+    /// Locate the bundled sources relative to the running toolchain.
+    /// `ZAITUN_STD_SRC` overrides the default `<prefix>/lib/zaitun/src`.
+    pub fn locate() -> Option<PathBuf> {
+        if let Ok(dir) = std::env::var("ZAITUN_STD_SRC") {
+            return Some(PathBuf::from(dir));
+        }
+
+        let exe = std::env::current_exe().ok()?;
+        let prefix = exe.parent()?.parent()?;
+        let dir = prefix.join("lib").join("zaitun").join("src");
+
+        if dir.is_dir() {
+            Some(dir)
+        } else {
+            None
+        }
+    }
+
+    /// Load the index from the manifest in the bundled source root
+    pub fn load(root: &Path) -> io::Result<Self> {
+        let mut index = StdSourceIndex::new(root);
+        let manifest = fs::read_to_string(root.join(STD_SOURCE_MANIFEST))?;
+        index.parse_manifest(&manifest)?;
+        Ok(index)
+    }
+
+    /// Parse manifest entries of the form
+    ///
+    /// ```text
+    /// std::io::File = "std/src/io.rs"
+    /// std::mem::size_of = { intrinsic = "size_of", declared_in = "std/src/mem.rs" }
+    /// ```
+    fn parse_manifest(&mut self, manifest: &str) -> io::Result<()> {
+        for (line_number, line) in manifest.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+                continue;
+            }
+
+            let (path, value) = line.split_once('=').ok_or_else(|| {
+                invalid_manifest(line_number, "expected `symbol = location`")
+            })?;
+            let path = path.trim().to_string();
+            let value = value.trim();
+
+            let origin = if value.starts_with('{') {
+                parse_intrinsic(value).ok_or_else(|| {
+                    invalid_manifest(line_number, "malformed intrinsic entry")
+                })?
+            } else {
+                let file = value.trim_matches('"');
+                if file.is_empty() {
+                    return Err(invalid_manifest(line_number, "expected \"file\""));
+                }
+                StdSymbolOrigin::Source {
+                    relative_path: PathBuf::from(file),
+                }
+            };
+
+            let file = match &origin {
+                StdSymbolOrigin::Source { relative_path } => Some(relative_path),
+                StdSymbolOrigin::Intrinsic { declared_in, .. } => declared_in.as_ref(),
+            };
+            if file.is_some_and(|file| !is_within_root(file)) {
+                return Err(invalid_manifest(line_number, "path escapes std source root"));
+            }
+
+            self.symbols.insert(path.clone(), StdSymbol { path, origin });
+        }
+
+        Ok(())
+    }
+
+    pub fn add_symbol(&mut self, symbol: StdSymbol) {
+        self.symbols.insert(symbol.path.clone(), symbol);
+    }
+
+    /// Look up a symbol by its full path, falling back to a unique match
+    /// on the last path segment (e.g. `println` for `std::io::println`)
+    pub fn find(&self, name: &str) -> Option<&StdSymbol> {
+        if let Some(symbol) = self.symbols.get(name) {
+            return Some(symbol);
+        }
+
+        let mut candidates = self.symbols.values()
+            .filter(|s| s.path.rsplit("::").next() == Some(name));
+
+        match (candidates.next(), candidates.next()) {
+            (Some(symbol), None) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    /// Build the read-only URI and 0-based position for a std symbol. The
+    /// position is found by searching the file for the symbol's declaration,
+    /// falling back to the top of the file if it has moved elsewhere.
+    pub fn location_of(&self, symbol: &StdSymbol) -> Option<(String, u32, u32)> {
+        let (file, name) = match &symbol.origin {
+            StdSymbolOrigin::Source { relative_path } => {
+                (relative_path, symbol.path.rsplit("::").next().unwrap_or(&symbol.path))
+            }
+            // Intrinsics without a declaration stub have nothing to open
+            StdSymbolOrigin::Intrinsic { name, declared_in } => (declared_in.as_ref()?, name.as_str()),
+        };
+
+        let (line, column) = fs::read_to_string(self.root.join(file))
+            .ok()
+            .and_then(|source| find_declaration(&source, name))
+            .unwrap_or((0, 0));
+        Some((source_uri(file), line, column))
+    }
+
+    /// Read the contents behind a `zaitun-std:` URI. Paths escaping the
+    /// bundled root are rejected.
+    pub fn read_source(&self, uri: &str) -> io::Result<String> {
+        let relative = uri
+            .strip_prefix(STD_SOURCE_SCHEME)
+            .and_then(|rest| rest.strip_prefix(":///"))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a std source URI"))?;
+
+        if !is_within_root(Path::new(relative)) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "path escapes std source root"));
+        }
+
+        fs::read_to_string(self.root.join(relative))
+    }
+
+    pub fn is_std_uri(uri: &str) -> bool {
+        uri.starts_with(STD_SOURCE_SCHEME)
+    }
+}
+
+/// Whether a relative path stays inside the bundled root: no `..`, no
+/// root or prefix, nothing but plain names
+fn is_within_root(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+fn source_uri(relative_path: &Path) -> String {
+    let path = relative_path.to_string_lossy().replace('\\', "/");
+    format!("{}:///{}", STD_SOURCE_SCHEME, path)
+}
+
+/// Item keywords that can introduce a std symbol
+const DECLARATION_KEYWORDS: &[&str] = &["struct", "enum", "fn", "trait", "type", "const", "static", "mod"];
+
+/// Find the 0-based line and column of the name in `name`'s first item
+/// declaration, e.g. `File` in `pub struct File {`. Lines inside block
+/// comments are skipped; line comments never start with a keyword.
+fn find_declaration(source: &str, name: &str) -> Option<(u32, u32)> {
+    let mut in_block_comment = false;
+    for (line_number, line) in source.lines().enumerate() {
+        if in_block_comment {
+            in_block_comment = !line.contains("*/");
+            continue;
+        }
+        if line.trim_start().starts_with("/*") {
+            in_block_comment = !line.contains("*/");
+            continue;
+        }
+
+        let indent = line.len() - line.trim_start().len();
+        let mut rest = line.trim_start();
+        rest = rest.strip_prefix("pub ").unwrap_or(rest);
+
+        for keyword in DECLARATION_KEYWORDS {
+            let after = match rest.strip_prefix(keyword).and_then(|after| after.strip_prefix(' ')) {
+                Some(after) => after.trim_start(),
+                None => continue,
+            };
+            let is_match = after.strip_prefix(name).is_some_and(|tail| {
+                !tail.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+            });
+            if is_match {
+                let column = indent + (line.trim_start().len() - after.len());
+                return Some((line_number as u32, column as u32));
+            }
+        }
+    }
+
+    None
+}
+
+fn parse_intrinsic(value: &str) -> Option<StdSymbolOrigin> {
+    let body = value.strip_prefix('{')?.strip_suffix('}')?;
+    let mut name = None;
+    let mut declared_in = None;
+
+    for field in body.split(',') {
+        let (key, val) = field.split_once('=')?;
+        let val = val.trim().trim_matches('"').to_string();
+        match key.trim() {
+            "intrinsic" => name = Some(val),
+            "declared_in" => declared_in = Some(PathBuf::from(val)),
+            _ => return None,
+        }
+    }
+
+    Some(StdSymbolOrigin::Intrinsic {
+        name: name?,
+        declared_in,
+    })
+}
+
+fn invalid_manifest(line_number: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}:{}: {}", STD_SOURCE_MANIFEST, line_number + 1, message),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_rejects_escaping_paths() {
+        for entry in [
+            r#"std::io::File = "../secret.rs""#,
+            r#"std::io::File = "std/../../secret.rs""#,
+            r#"std::io::File = "/etc/passwd""#,
+            r#"std::mem::size_of = { intrinsic = "size_of", declared_in = "../mem.rs" }"#,
+        ] {
+            let error = StdSourceIndex::new(Path::new("root")).parse_manifest(entry).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", entry);
+        }
+
+        let mut index = StdSourceIndex::new(Path::new("root"));
+        index
+            .parse_manifest("std::io::File = \"std/src/io.rs\"\nstd::mem::size_of = { intrinsic = \"size_of\" }")
+            .unwrap();
+        assert!(index.find("File").is_some());
+        assert!(index.find("size_of").is_some());
+    }
+
+    #[test]
+    fn test_find_declaration_items() {
+        let source = "\
+use crate::io;
+
+pub struct File {
+    fd: i32,
+}
+
+enum Mode { Read, Write }
+
+    pub fn open_file(path: &str) -> File {}
+
+pub trait Read {
+    fn read(&mut self) -> usize;
+}
+";
+        assert_eq!(find_declaration(source, "File"), Some((2, 11)));
+        assert_eq!(find_declaration(source, "Mode"), Some((6, 5)));
+        assert_eq!(find_declaration(source, "open_file"), Some((8, 11)));
+        assert_eq!(find_declaration(source, "Read"), Some((10, 10)));
+        assert_eq!(find_declaration(source, "read"), Some((11, 7)));
+        // Prefixes of a longer name do not match
+        assert_eq!(find_declaration(source, "open"), None);
+    }
+
+    #[test]
+    fn test_find_declaration_skips_comments() {
+        let source = "\
+// fn println() is below
+/// struct Writer is documented here
+/*
+fn println(text: &str) {}
+struct Writer;
+*/
+/* fn flush() {} */
+pub fn println(text: &str) {}
+";
+        assert_eq!(find_declaration(source, "println"), Some((7, 7)));
+        assert_eq!(find_declaration(source, "Writer"), None);
+        assert_eq!(find_declaration(source, "flush"), None);
+    }
+}
@@ -0,0 +1,442 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use zaitun_std::checksum::Fingerprint;
+use zaitun_std::fs::ignore::Ignore;
+
+use crate::manager::PackageError;
+
+/// Extension of Zaitun source files
+pub const SOURCE_EXTENSION: &str = "zt";
+
+/// Identifies one compilation of one package version. Two builds with the
+/// same key produce interchangeable artifacts, whichever project ran them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactKey {
+    pub source_hash: String,
+    pub compiler_version: String,
+    pub options: Vec<(String, String)>,
+}
+
+impl ArtifactKey {
+    pub fn new(source_hash: &str, compiler_version: &str) -> Self {
+        ArtifactKey {
+            source_hash: source_hash.to_string(),
+            compiler_version: compiler_version.to_string(),
+            options: Vec::new(),
+        }
+    }
+
+    pub fn with_option(mut self, name: &str, value: &str) -> Self {
+        self.options.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Stable directory name for this key, the same across Rust releases
+    /// and machines since the store is shared between them
+    pub fn digest(&self) -> String {
+        // Option order must not change the key
+        let mut options = self.options.clone();
+        options.sort();
+
+        let mut fingerprint = Fingerprint::new();
+        fingerprint
+            .str_field(&self.source_hash)
+            .str_field(&self.compiler_version);
+        for (name, value) in &options {
+            fingerprint.str_field(name).str_field(value);
+        }
+        fingerprint.to_hex()
+    }
+}
+
+/// Hash every file under a package's source directory in path order,
+/// leaving out what `ignore` excludes so build output and vendored copies
+/// do not change the key
+pub fn hash_source_tree(dir: &Path, ignore: &Ignore) -> Result<String, PackageError> {
+    let mut files = Vec::new();
+    collect_files(dir, ignore, &mut files)?;
+    files.sort();
+
+    let mut fingerprint = Fingerprint::new();
+    for file in &files {
+        let relative = file.strip_prefix(dir).unwrap_or(file);
+        fingerprint.str_field(&relative.to_string_lossy());
+
+        let content = fs::read(file)
+            .map_err(|e| PackageError::CacheError(format!("Failed to read {}: {}", file.display(), e)))?;
+        fingerprint.field(&content);
+    }
+
+    Ok(fingerprint.to_hex())
+}
+
+/// The package's sources, in path order, leaving out what `ignore`
+/// excludes
+pub fn source_files(dir: &Path, ignore: &Ignore) -> Result<Vec<PathBuf>, PackageError> {
+    let mut files = Vec::new();
+    collect_files(dir, ignore, &mut files)?;
+    files.retain(|file| file.extension().and_then(|extension| extension.to_str()) == Some(SOURCE_EXTENSION));
+    files.sort();
+    Ok(files)
+}
+
+fn collect_files(dir: &Path, ignore: &Ignore, files: &mut Vec<PathBuf>) -> Result<(), PackageError> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| PackageError::CacheError(format!("Failed to read {}: {}", dir.display(), e)))?;
+
+    for entry in entries {
+        let path = entry
+            .map_err(|e| PackageError::CacheError(format!("Failed to read directory entry: {}", e)))?
+            .path();
+
+        let is_dir = path.is_dir();
+        if ignore.matches(&path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            collect_files(&path, ignore, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Eviction limits for the artifact store
+#[derive(Debug, Clone)]
+pub struct EvictionPolicy {
+    /// Total size the store may grow to before least-recently-used entries
+    /// are removed
+    pub max_bytes: u64,
+    /// Entries unused for longer than this are removed regardless of size
+    pub max_age_days: Option<u64>,
+}
+
+impl Default for EvictionPolicy {
+    fn default() -> Self {
+        EvictionPolicy {
+            max_bytes: 10 * 1024 * 1024 * 1024,
+            max_age_days: Some(90),
+        }
+    }
+}
+
+/// Machine-wide, content-addressed store of build artifacts shared by all
+/// workspaces. Entries live at `<root>/<digest[..2]>/<digest>/` and are
+/// published atomically by renaming a fully-written staging directory.
+pub struct ArtifactStore {
+    root: PathBuf,
+    policy: EvictionPolicy,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub total_bytes: u64,
+}
+
+const LAST_USED_FILE: &str = ".last-used";
+
+impl ArtifactStore {
+    pub fn open(root: &Path, policy: EvictionPolicy) -> Result<Self, PackageError> {
+        fs::create_dir_all(root)
+            .map_err(|e| PackageError::CacheError(format!("Failed to create artifact store: {}", e)))?;
+
+        Ok(ArtifactStore {
+            root: root.to_path_buf(),
+            policy,
+        })
+    }
+
+    /// Default location: `ZAITUN_ARTIFACT_CACHE`, else the user cache dir
+    pub fn default_root() -> Option<PathBuf> {
+        if let Ok(dir) = std::env::var("ZAITUN_ARTIFACT_CACHE") {
+            return Some(PathBuf::from(dir));
+        }
+
+        dirs::cache_dir().map(|dir| dir.join("zaitun").join("artifacts"))
+    }
+
+    fn entry_dir(&self, key: &ArtifactKey) -> PathBuf {
+        let digest = key.digest();
+        self.root.join(&digest[..2]).join(digest)
+    }
+
+    /// Return the directory holding the cached artifacts for `key`, if any
+    pub fn lookup(&self, key: &ArtifactKey) -> Option<PathBuf> {
+        let dir = self.entry_dir(key);
+        if dir.is_dir() {
+            touch(&dir);
+            Some(dir)
+        } else {
+            None
+        }
+    }
+
+    /// Copy freshly built artifacts into the store
+    pub fn insert(&self, key: &ArtifactKey, artifacts: &[PathBuf]) -> Result<PathBuf, PackageError> {
+        let dir = self.entry_dir(key);
+        if dir.is_dir() {
+            return Ok(dir);
+        }
+
+        let staging = self.root.join(format!(".staging-{}-{}", key.digest(), std::process::id()));
+        fs::create_dir_all(&staging)
+            .map_err(|e| PackageError::CacheError(format!("Failed to create staging directory: {}", e)))?;
+
+        for artifact in artifacts {
+            let name = artifact.file_name().ok_or_else(|| {
+                PackageError::CacheError(format!("Invalid artifact path: {}", artifact.display()))
+            })?;
+            fs::copy(artifact, staging.join(name))
+                .map_err(|e| PackageError::CacheError(format!("Failed to store {}: {}", artifact.display(), e)))?;
+        }
+        touch(&staging);
+
+        if let Some(parent) = dir.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| PackageError::CacheError(format!("Failed to create artifact store: {}", e)))?;
+        }
+
+        if let Err(e) = fs::rename(&staging, &dir) {
+            let _ = fs::remove_dir_all(&staging);
+            // Another process may have published the same key first; its
+            // artifacts are interchangeable with ours, so keep whichever won
+            if !dir.is_dir() {
+                return Err(PackageError::CacheError(format!("Failed to publish {}: {}", dir.display(), e)));
+            }
+        }
+
+        self.evict_keeping(Some(&dir))?;
+        Ok(dir)
+    }
+
+    /// Remove stale entries, then least-recently-used ones until the store
+    /// fits `max_bytes`
+    pub fn evict(&self) -> Result<CacheStats, PackageError> {
+        self.evict_keeping(None)
+    }
+
+    /// `evict`, never removing `keep`: the entry `insert` just published
+    /// is about to be used even if it alone is over `max_bytes`
+    fn evict_keeping(&self, keep: Option<&Path>) -> Result<CacheStats, PackageError> {
+        let mut entries = self.entries()?;
+        let now = now_secs();
+
+        if let Some(days) = self.policy.max_age_days {
+            let cutoff = now.saturating_sub(days * 24 * 60 * 60);
+            entries.retain(|entry| {
+                if entry.last_used < cutoff && Some(entry.path.as_path()) != keep {
+                    let _ = fs::remove_dir_all(&entry.path);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        entries.sort_by_key(|entry| entry.last_used);
+        let mut total: u64 = entries.iter().map(|entry| entry.bytes).sum();
+
+        let mut remaining = entries.len();
+        for entry in &entries {
+            if total <= self.policy.max_bytes {
+                break;
+            }
+            if Some(entry.path.as_path()) == keep {
+                continue;
+            }
+            fs::remove_dir_all(&entry.path)
+                .map_err(|e| PackageError::CacheError(format!("Failed to evict {}: {}", entry.path.display(), e)))?;
+            total -= entry.bytes;
+            remaining -= 1;
+        }
+
+        Ok(CacheStats {
+            entries: remaining,
+            total_bytes: total,
+        })
+    }
+
+    pub fn stats(&self) -> Result<CacheStats, PackageError> {
+        let entries = self.entries()?;
+        Ok(CacheStats {
+            entries: entries.len(),
+            total_bytes: entries.iter().map(|entry| entry.bytes).sum(),
+        })
+    }
+
+    /// Remove every entry
+    pub fn clear(&self) -> Result<(), PackageError> {
+        for entry in self.entries()? {
+            fs::remove_dir_all(&entry.path)
+                .map_err(|e| PackageError::CacheError(format!("Failed to remove {}: {}", entry.path.display(), e)))?;
+        }
+        Ok(())
+    }
+
+    fn entries(&self) -> Result<Vec<StoreEntry>, PackageError> {
+        let mut entries = Vec::new();
+        let shards = fs::read_dir(&self.root)
+            .map_err(|e| PackageError::CacheError(format!("Failed to read artifact store: {}", e)))?;
+
+        for shard in shards.flatten() {
+            let shard_path = shard.path();
+            if !shard_path.is_dir() || shard.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            for entry in fs::read_dir(&shard_path).into_iter().flatten().flatten() {
+                let path = entry.path();
+                entries.push(StoreEntry {
+                    last_used: read_last_used(&path),
+                    bytes: dir_size(&path),
+                    path,
+                });
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+struct StoreEntry {
+    path: PathBuf,
+    last_used: u64,
+    bytes: u64,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn touch(dir: &Path) {
+    // Access tracking is best effort; a failed write only affects eviction order
+    let _ = fs::write(dir.join(LAST_USED_FILE), now_secs().to_string());
+}
+
+fn read_last_used(dir: &Path) -> u64 {
+    fs::read_to_string(dir.join(LAST_USED_FILE))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                dir_size(&path)
+            } else {
+                entry.metadata().map(|m| m.len()).unwrap_or(0)
+            }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("zaitun-artifacts-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn artifact(dir: &Path, name: &str, bytes: usize) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, vec![b'x'; bytes]).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_key_digest() {
+        let key = ArtifactKey::new("abc", "0.3.0").with_option("opt", "2").with_option("lto", "true");
+        let reordered = ArtifactKey::new("abc", "0.3.0").with_option("lto", "true").with_option("opt", "2");
+        assert_eq!(key.digest(), reordered.digest());
+        assert_eq!(key.digest().len(), 16);
+
+        assert_ne!(key.digest(), ArtifactKey::new("abc", "0.3.0").with_option("opt", "3").with_option("lto", "true").digest());
+        assert_ne!(key.digest(), ArtifactKey::new("abc", "0.3.1").with_option("opt", "2").with_option("lto", "true").digest());
+        // Fields are length-prefixed, so moving bytes between them changes the key
+        assert_ne!(ArtifactKey::new("ab", "c").digest(), ArtifactKey::new("a", "bc").digest());
+    }
+
+    #[test]
+    fn test_store_and_lookup() {
+        let dir = temp_dir("lookup");
+        let store = ArtifactStore::open(&dir.join("store"), EvictionPolicy::default()).unwrap();
+        let key = ArtifactKey::new("abc", "0.3.0");
+        assert_eq!(store.lookup(&key), None);
+
+        let built = artifact(&dir, "libfoo.zo", 4);
+        let entry = store.insert(&key, &[built]).unwrap();
+        assert_eq!(store.lookup(&key), Some(entry.clone()));
+        assert_eq!(fs::read(entry.join("libfoo.zo")).unwrap(), b"xxxx");
+        assert_eq!(store.lookup(&ArtifactKey::new("abd", "0.3.0")), None);
+
+        // A second insert of the same key keeps the published entry
+        assert_eq!(store.insert(&key, &[]).unwrap(), entry);
+        assert_eq!(store.stats().unwrap().entries, 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let dir = temp_dir("evict");
+        let policy = EvictionPolicy {
+            max_bytes: 150,
+            max_age_days: None,
+        };
+        let store = ArtifactStore::open(&dir.join("store"), policy).unwrap();
+        let old = ArtifactKey::new("old", "0.3.0");
+        let new = ArtifactKey::new("new", "0.3.0");
+
+        let old_entry = store.insert(&old, &[artifact(&dir, "old.zo", 100)]).unwrap();
+        fs::write(old_entry.join(LAST_USED_FILE), "1").unwrap();
+        store.insert(&new, &[artifact(&dir, "new.zo", 100)]).unwrap();
+        assert_eq!(store.lookup(&old), None);
+        assert!(store.lookup(&new).is_some());
+
+        // An entry over the limit on its own still survives its insert
+        let large = ArtifactKey::new("large", "0.3.0");
+        store.insert(&large, &[artifact(&dir, "large.zo", 1000)]).unwrap();
+        assert!(store.lookup(&large).is_some());
+        assert_eq!(store.lookup(&new), None);
+
+        // but not a later eviction
+        let stats = store.evict().unwrap();
+        assert_eq!(stats.entries, 0);
+        assert_eq!(store.lookup(&large), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_evict_stale_entries() {
+        let dir = temp_dir("stale");
+        let store = ArtifactStore::open(&dir.join("store"), EvictionPolicy::default()).unwrap();
+        let stale = ArtifactKey::new("stale", "0.3.0");
+        let fresh = ArtifactKey::new("fresh", "0.3.0");
+
+        let stale_entry = store.insert(&stale, &[artifact(&dir, "a.zo", 1)]).unwrap();
+        store.insert(&fresh, &[artifact(&dir, "b.zo", 1)]).unwrap();
+        fs::write(stale_entry.join(LAST_USED_FILE), "0").unwrap();
+
+        assert_eq!(store.evict().unwrap().entries, 1);
+        assert_eq!(store.lookup(&stale), None);
+        assert!(store.lookup(&fresh).is_some());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
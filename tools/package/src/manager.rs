@@ -2,8 +2,19 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+use zaitun_bootstrap::edition::Edition;
+use zaitun_bootstrap::suggest;
+use zaitun_bootstrap::version_info::{self, Requirements, VersionError, VersionInfo};
+use zaitun_std::fs::ignore::Ignore;
+use zaitun_std::trace;
+
+use crate::artifact_cache::{hash_source_tree, source_files, ArtifactKey, ArtifactStore, EvictionPolicy, SOURCE_EXTENSION};
+use crate::semver_check::{diff_interfaces, read_interfaces, SemverReport};
+use crate::signing::{signature_path, PackageSignature, PublisherEntry, SigningKey, TrustPolicy};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PackageConfig {
     name: String,
@@ -13,12 +24,51 @@ pub struct PackageConfig {
     dependencies: HashMap<String, String>,
     dev_dependencies: HashMap<String, String>,
     build_dependencies: HashMap<String, String>,
+    #[serde(default)]
+    artifact_cache: ArtifactCacheConfig,
+    /// Patterns left out of package sources in addition to each
+    /// package's .zaitunignore
+    #[serde(default)]
+    ignore: Vec<String>,
+    /// Edition the package's modules are written in; see
+    /// `zaitun_bootstrap::edition`
+    #[serde(default)]
+    edition: Edition,
+    /// Which packages may be installed; see `signing`
+    #[serde(default)]
+    trust: TrustPolicy,
+}
+
+/// `[artifact-cache]` section of the package config
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ArtifactCacheConfig {
+    enabled: bool,
+    path: Option<PathBuf>,
+    max_size_mb: u64,
+    max_age_days: Option<u64>,
+}
+
+impl Default for ArtifactCacheConfig {
+    fn default() -> Self {
+        ArtifactCacheConfig {
+            enabled: false,
+            path: None,
+            max_size_mb: 10 * 1024,
+            max_age_days: Some(90),
+        }
+    }
 }
 
 pub struct PackageManager {
     registry_url: String,
     cache_dir: PathBuf,
     config: PackageConfig,
+    /// File name of the manifest, which dependencies' manifests share
+    manifest_name: PathBuf,
+    artifact_store: Option<ArtifactStore>,
+    /// The compiler packages are built with, checked when the manager starts
+    compiler: VersionInfo,
 }
 
 impl PackageManager {
@@ -38,14 +88,188 @@ impl PackageManager {
         fs::create_dir_all(&cache_dir)
             .map_err(|e| PackageError::CacheError(format!("Failed to create cache directory: {}", e)))?;
         
+        // Dependencies are built with this compiler and their interfaces
+        // read by it, so a mismatched toolchain fails here rather than in
+        // the middle of an install
+        let requirements = Requirements::new("pm").with_interface_format().with_feature("edition");
+        let compiler = version_info::verify(&requirements)?;
+        
+        let artifact_store = if config.artifact_cache.enabled {
+            let root = config.artifact_cache.path.clone()
+                .or_else(ArtifactStore::default_root)
+                .ok_or_else(|| PackageError::CacheError("Could not determine artifact cache directory".to_string()))?;
+            let policy = EvictionPolicy {
+                max_bytes: config.artifact_cache.max_size_mb * 1024 * 1024,
+                max_age_days: config.artifact_cache.max_age_days,
+            };
+            Some(ArtifactStore::open(&root, policy)?)
+        } else {
+            None
+        };
+        
         Ok(PackageManager {
             registry_url: "https://registry.safelang.org".to_string(),
             cache_dir,
             config,
+            manifest_name: config_path.file_name().map(PathBuf::from).unwrap_or_default(),
+            artifact_store,
+            compiler,
         })
     }
     
+    /// Build an installed dependency, reusing artifacts from the shared
+    /// store when another project already compiled the same sources with
+    /// the same compiler and options
+    pub fn build_dependency(&self, package_name: &str, version: &str, options: &[(&str, &str)]) -> Result<PathBuf, PackageError> {
+        let mut span = trace::span("pm.build_dependency")
+            .field("package", package_name)
+            .field("version", version)
+            .enter();
+        let source_dir = self.cache_dir.join(package_name).join(version);
+        if !source_dir.exists() {
+            return Err(PackageError::PackageNotFound(format!("{} {}", package_name, version), None));
+        }
+        
+        let mut ignore = Ignore::load(&source_dir)
+            .map_err(|e| PackageError::CacheError(format!("Failed to read ignore file: {}", e)))?;
+        ignore.add_patterns(&self.config.ignore);
+        // Each package builds with its own edition, whatever its dependents use
+        let edition = self.dependency_edition(&source_dir)?;
+        let mut key = ArtifactKey::new(&hash_source_tree(&source_dir, &ignore)?, &self.compiler.compiler_version)
+            .with_option("edition", edition.as_str());
+        for (name, value) in options {
+            key = key.with_option(name, value);
+        }
+        
+        if let Some(store) = &self.artifact_store {
+            let cached = store.lookup(&key);
+            span.record("cached", cached.is_some());
+            if let Some(cached) = cached {
+                println!("Reusing cached build of {} {}", package_name, version);
+                return Ok(cached);
+            }
+        }
+        
+        // Interfaces and IR are written next to the output, so everything
+        // the build produces ends up in `target_dir`
+        let target_dir = source_dir.join("target");
+        let output = target_dir.join(package_name);
+        if !self.run_compiler("build", &source_dir, &ignore, edition, options, &output)? {
+            return Err(PackageError::BuildError(format!("Failed to build {} {}", package_name, version)));
+        }
+        
+        match &self.artifact_store {
+            Some(store) => {
+                let artifacts: Vec<PathBuf> = fs::read_dir(&target_dir)
+                    .map_err(|e| PackageError::BuildError(format!("Failed to read build output: {}", e)))?
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_file())
+                    .collect();
+                store.insert(&key, &artifacts)
+            }
+            None => Ok(target_dir),
+        }
+    }
+    
+    /// Run `zaitun COMMAND -o OUTPUT --edition EDITION [--OPTION VALUE]...
+    /// SOURCES` on the package in `source_dir`. Options are compiler flags
+    /// without their dashes; an empty value passes the flag alone. Returns
+    /// whether the compiler succeeded.
+    fn run_compiler(
+        &self,
+        command: &str,
+        source_dir: &Path,
+        ignore: &Ignore,
+        edition: Edition,
+        options: &[(&str, &str)],
+        output: &Path,
+    ) -> Result<bool, PackageError> {
+        // The compiler runs in `source_dir`, where paths relative to ours
+        // would point elsewhere
+        let absolute = |path: &Path| {
+            std::path::absolute(path)
+                .map_err(|e| PackageError::BuildError(format!("Failed to resolve {}: {}", path.display(), e)))
+        };
+        let (source_dir, output) = (&absolute(source_dir)?, &absolute(output)?);
+        
+        let mut span = trace::span("pm.compile").field("command", command).enter();
+        let sources = source_files(source_dir, ignore)?;
+        span.record("sources", sources.len());
+        if sources.is_empty() {
+            return Err(PackageError::BuildError(format!(
+                "No .{} sources in {}",
+                SOURCE_EXTENSION,
+                source_dir.display()
+            )));
+        }
+        if let Some(dir) = output.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| PackageError::BuildError(format!("Failed to create {}: {}", dir.display(), e)))?;
+        }
+        
+        let mut compiler = Command::new(version_info::compiler_command());
+        compiler.arg(command).arg("-o").arg(output).current_dir(source_dir);
+        compiler.arg("--edition").arg(edition.as_str());
+        for (name, value) in options {
+            compiler.arg(format!("--{}", name));
+            if !value.is_empty() {
+                compiler.arg(value);
+            }
+        }
+        compiler.args(&sources);
+        
+        let status = compiler.status()
+            .map_err(|e| PackageError::BuildError(format!("Failed to run compiler: {}", e)))?;
+        span.record("success", status.success());
+        Ok(status.success())
+    }
+    
+    /// Edition from an installed dependency's manifest; the default edition
+    /// when it has none
+    fn dependency_edition(&self, source_dir: &Path) -> Result<Edition, PackageError> {
+        let manifest = source_dir.join(&self.manifest_name);
+        let content = match fs::read_to_string(&manifest) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Edition::default()),
+            Err(e) => return Err(PackageError::ConfigError(format!("Failed to read {}: {}", manifest.display(), e))),
+        };
+        let config: PackageConfig = toml::from_str(&content)
+            .map_err(|e| PackageError::ConfigError(format!("Failed to parse {}: {}", manifest.display(), e)))?;
+        Ok(config.edition)
+    }
+    
+    /// Build a dependency's serialized IR for `--lto` links. The emit kind
+    /// is part of the cache key, so IR is cached alongside, not instead of,
+    /// the dependency's objects. Pass the returned directory to the
+    /// compiler's interface path; `--lto` picks up the `.zir` files there.
+    pub fn build_dependency_ir(&self, package_name: &str, version: &str, options: &[(&str, &str)]) -> Result<PathBuf, PackageError> {
+        // `zaitun build --emit lto-ir --emit interface`, which stops once
+        // both are written
+        let mut options = options.to_vec();
+        options.push(("emit", "lto-ir"));
+        options.push(("emit", "interface"));
+        self.build_dependency(package_name, version, &options)
+    }
+    
+    /// Remove entries from the shared artifact store, or all of them
+    pub fn clean_artifact_cache(&self, all: bool) -> Result<(), PackageError> {
+        let store = match &self.artifact_store {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        
+        if all {
+            store.clear()
+        } else {
+            let stats = store.evict()?;
+            println!("Artifact cache: {} entries, {} bytes", stats.entries, stats.total_bytes);
+            Ok(())
+        }
+    }
+    
     pub fn install(&self, package_name: &str, version: Option<&str>) -> Result<(), PackageError> {
+        let _span = trace::span("pm.install").field("package", package_name).enter();
         println!("Installing package: {}", package_name);
         
         // Determine version to install
@@ -60,11 +284,7 @@ impl PackageManager {
             return Ok(());
         }
         
-        // Download package
-        let package_path = self.download_package(package_name, &version)?;
-        
-        // Extract package
-        self.extract_package(&package_path)?;
+        self.fetch_package(package_name, &version)?;
         
         // Install dependencies
         self.install_dependencies(package_name, &version)?;
@@ -73,13 +293,128 @@ impl PackageManager {
         Ok(())
     }
     
+    /// `pm publish [--sign [--key PATH]] [--allow-breaking]`: publish the
+    /// package in `package_dir`, signed with the key at `key`, or the
+    /// default key, when `sign` is set. Runs `semver_check` first.
+    pub fn publish(&self, package_dir: &Path, sign: bool, key: Option<&Path>, allow_breaking: bool) -> Result<(), PackageError> {
+        let (name, version) = (&self.config.name, &self.config.version);
+        let _span = trace::span("pm.publish").field("package", name.as_str()).field("version", version.as_str()).enter();
+        println!("Publishing {} {}", name, version);
+        
+        self.semver_check(package_dir, allow_breaking)?;
+        
+        let archive = self.pack_package(package_dir)?;
+        let signature = if sign {
+            let key = SigningKey::load(&signing_key_path(key)?)?;
+            let bytes = fs::read(&archive)
+                .map_err(|e| PackageError::PublishError(format!("Failed to read {}: {}", archive.display(), e)))?;
+            let path = signature_path(&archive);
+            key.sign(name, version, &bytes)?.write(&path)?;
+            println!("Signed with key {}", key.public_key_hex());
+            Some(path)
+        } else {
+            None
+        };
+        
+        self.upload_package(&archive, signature.as_deref())?;
+        
+        println!("Successfully published {} version {}", name, version);
+        Ok(())
+    }
+    
+    /// `pm semver-check [--allow-breaking]`: compare the public interface
+    /// of the package in `package_dir` with that of its latest published
+    /// version, and fail on breaking changes its version number does not
+    /// allow. `None` when nothing has been published yet.
+    pub fn semver_check(&self, package_dir: &Path, allow_breaking: bool) -> Result<Option<SemverReport>, PackageError> {
+        let (name, version) = (&self.config.name, &self.config.version);
+        let _span = trace::span("pm.semver_check").field("package", name.as_str()).enter();
+        let previous = match self.resolve_latest_version(name) {
+            Ok(previous) => previous,
+            Err(PackageError::PackageNotFound(..)) => {
+                println!("{} has no published version to compare with", name);
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        };
+        
+        let previous_dir = self.fetch_package(name, &previous)?;
+        let previous_edition = self.dependency_edition(&previous_dir)?;
+        let old = read_interfaces(&self.emit_interfaces(&previous_dir, previous_edition)?)?;
+        let new = read_interfaces(&self.emit_interfaces(package_dir, self.config.edition)?)?;
+        if new.is_empty() {
+            return Err(PackageError::BuildError(format!("{} exports no interfaces to compare", package_dir.display())));
+        }
+        
+        let report = SemverReport::new(name, &previous, version, diff_interfaces(&old, &new))?;
+        println!("{}", report);
+        report.check(allow_breaking)?;
+        Ok(Some(report))
+    }
+    
+    /// Type-check the package in `source_dir`, returning the directory
+    /// its `.zi` interface files were written to
+    fn emit_interfaces(&self, source_dir: &Path, edition: Edition) -> Result<PathBuf, PackageError> {
+        let mut ignore = Ignore::load(source_dir)
+            .map_err(|e| PackageError::CacheError(format!("Failed to read ignore file: {}", e)))?;
+        ignore.add_patterns(&self.config.ignore);
+        
+        // Interfaces are written next to the output. Starting from an empty
+        // directory keeps those of since-removed modules out of the diff.
+        let out_dir = source_dir.join("target").join("interfaces");
+        match fs::remove_dir_all(&out_dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(PackageError::BuildError(format!("Failed to clear {}: {}", out_dir.display(), e))),
+        }
+        let output = out_dir.join(&self.config.name);
+        if !self.run_compiler("check", source_dir, &ignore, edition, &[("emit", "interface")], &output)? {
+            return Err(PackageError::BuildError(format!("Failed to check {}", source_dir.display())));
+        }
+        Ok(out_dir)
+    }
+    
+    /// `pm key generate [--key PATH]`: create a signing key, returning
+    /// where it was saved
+    pub fn generate_signing_key(&self, key: Option<&Path>) -> Result<PathBuf, PackageError> {
+        let path = signing_key_path(key)?;
+        let key = SigningKey::generate();
+        key.save(&path)?;
+        println!("Saved signing key {} to {}", key.public_key_hex(), path.display());
+        Ok(path)
+    }
+    
+    /// `pm key register PUBLISHER [--key PATH]`: add the signing key's
+    /// public half to `publisher`'s keys in the registry index
+    pub fn register_publisher_key(&self, publisher: &str, key: Option<&Path>) -> Result<(), PackageError> {
+        let key = SigningKey::load(&signing_key_path(key)?)?;
+        
+        // Submit the key to the registry, which checks that the signed-in
+        // account owns `publisher`
+        // ... implementation details ...
+        
+        // Recorded locally too, so installs trust it before the next index
+        // update
+        let entry_path = format!("publishers/{}.toml", publisher);
+        let mut entry = self.read_index::<PublisherEntry>(&entry_path)?.unwrap_or_else(|| PublisherEntry {
+            name: publisher.to_string(),
+            keys: Vec::new(),
+        });
+        entry.add_key(&key.public_key_hex());
+        self.write_index(&entry_path, &entry)?;
+        
+        println!("Registered key {} for publisher {}", key.public_key_hex(), publisher);
+        Ok(())
+    }
+    
     pub fn uninstall(&self, package_name: &str) -> Result<(), PackageError> {
+        let _span = trace::span("pm.uninstall").field("package", package_name).enter();
         println!("Uninstalling package: {}", package_name);
         
         // Check if package is installed
         let package_dir = self.cache_dir.join(package_name);
         if !package_dir.exists() {
-            return Err(PackageError::PackageNotFound(package_name.to_string()));
+            return Err(self.package_not_found(package_name));
         }
         
         // Remove package directory
@@ -91,12 +426,13 @@ impl PackageManager {
     }
     
     pub fn update(&self, package_name: &str) -> Result<(), PackageError> {
+        let _span = trace::span("pm.update").field("package", package_name).enter();
         println!("Updating package: {}", package_name);
         
         // Check if package is installed
         let package_dir = self.cache_dir.join(package_name);
         if !package_dir.exists() {
-            return Err(PackageError::PackageNotFound(package_name.to_string()));
+            return Err(self.package_not_found(package_name));
         }
         
         // Get current version
@@ -141,6 +477,19 @@ impl PackageManager {
         Ok(packages)
     }
     
+    /// Error for an unknown package, suggesting the closest installed or
+    /// declared package name
+    fn package_not_found(&self, package_name: &str) -> PackageError {
+        let mut known: Vec<String> = self.list()
+            .map(|packages| packages.into_iter().map(|(name, _)| name).collect())
+            .unwrap_or_default();
+        known.extend(self.config.dependencies.keys().cloned());
+        known.extend(self.config.dev_dependencies.keys().cloned());
+        
+        let suggestion = suggest::best_match(package_name, known.iter().map(String::as_str));
+        PackageError::PackageNotFound(package_name.to_string(), suggestion.map(str::to_string))
+    }
+    
     fn resolve_latest_version(&self, package_name: &str) -> Result<String, PackageError> {
         // Query registry for latest version
         // ... implementation details ...
@@ -162,6 +511,116 @@ impl PackageManager {
         Ok(self.cache_dir.join(format!("{}-{}.tar.gz", package_name, version)))
     }
     
+    /// Download, verify and extract a package, returning its source
+    /// directory
+    fn fetch_package(&self, package_name: &str, version: &str) -> Result<PathBuf, PackageError> {
+        let package_dir = self.cache_dir.join(package_name).join(version);
+        if self.is_package_installed(package_name, version) {
+            return Ok(package_dir);
+        }
+        
+        let _span = trace::span("pm.fetch")
+            .field("package", package_name)
+            .field("version", version)
+            .enter();
+        let package_path = self.download_package(package_name, version)?;
+        
+        // Nothing from the archive is used before its signature is checked
+        self.verify_package(package_name, version, &package_path)?;
+        
+        self.extract_package(&package_path)?;
+        Ok(package_dir)
+    }
+    
+    fn download_signature(&self, package_name: &str, version: &str, package_path: &Path) -> Result<PathBuf, PackageError> {
+        // Download `<archive>.sig` next to the archive, if the registry
+        // has one
+        // ... implementation details ...
+        
+        Ok(signature_path(package_path))
+    }
+    
+    /// Check a downloaded archive's signature against the trust policy and
+    /// its publisher's registered keys
+    fn verify_package(&self, package_name: &str, version: &str, package_path: &Path) -> Result<(), PackageError> {
+        let signature_file = self.download_signature(package_name, version, package_path)?;
+        let Some(signature) = PackageSignature::read(&signature_file)? else {
+            self.config.trust.allow_unsigned(package_name, version)?;
+            println!("Warning: {} {} is not signed", package_name, version);
+            return Ok(());
+        };
+        
+        let publisher = self.publisher_of(package_name)?;
+        let archive = fs::read(package_path)
+            .map_err(|e| PackageError::InstallError(format!("Failed to read {}: {}", package_path.display(), e)))?;
+        publisher.verify(&signature, package_name, version, &archive)?;
+        
+        println!("Verified signature of {} {} by {}", package_name, version, publisher.name);
+        Ok(())
+    }
+    
+    /// The publisher owning `package_name`, with its keys
+    fn publisher_of(&self, package_name: &str) -> Result<PublisherEntry, PackageError> {
+        #[derive(Deserialize)]
+        struct PackageEntry {
+            publisher: String,
+        }
+        
+        let package: PackageEntry = self.read_index(&format!("packages/{}.toml", package_name))?
+            .ok_or_else(|| PackageError::SignatureError(format!("The registry index has no publisher for {}", package_name)))?;
+        self.read_index(&format!("publishers/{}.toml", package.publisher))?
+            .ok_or_else(|| PackageError::SignatureError(format!(
+                "Publisher {} of {} has no registered keys",
+                package.publisher, package_name
+            )))
+    }
+    
+    /// Local copy of the registry index: `packages/NAME.toml` names each
+    /// package's publisher, `publishers/NAME.toml` lists a publisher's keys
+    fn index_dir(&self) -> PathBuf {
+        self.cache_dir.join("index")
+    }
+    
+    /// An index entry; `None` if there is none
+    fn read_index<T: DeserializeOwned>(&self, entry: &str) -> Result<Option<T>, PackageError> {
+        let path = self.index_dir().join(entry);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(PackageError::CacheError(format!("Failed to read index entry {}: {}", path.display(), e))),
+        };
+        toml::from_str(&content)
+            .map(Some)
+            .map_err(|e| PackageError::CacheError(format!("Malformed index entry {}: {}", path.display(), e)))
+    }
+    
+    fn write_index<T: Serialize>(&self, entry: &str, value: &T) -> Result<(), PackageError> {
+        let path = self.index_dir().join(entry);
+        let content = toml::to_string(value)
+            .map_err(|e| PackageError::CacheError(format!("Failed to encode index entry: {}", e)))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| PackageError::CacheError(format!("Failed to create {}: {}", dir.display(), e)))?;
+        }
+        fs::write(&path, content)
+            .map_err(|e| PackageError::CacheError(format!("Failed to write index entry {}: {}", path.display(), e)))
+    }
+    
+    fn pack_package(&self, package_dir: &Path) -> Result<PathBuf, PackageError> {
+        // Pack the files `Ignore::load(package_dir)` keeps, plus
+        // `self.config.ignore`, into a .tar.gz
+        // ... implementation details ...
+        
+        Ok(self.cache_dir.join(format!("{}-{}.tar.gz", self.config.name, self.config.version)))
+    }
+    
+    fn upload_package(&self, archive: &Path, signature: Option<&Path>) -> Result<(), PackageError> {
+        // Upload the archive, and its signature if any, to the registry
+        // ... implementation details ...
+        
+        Ok(())
+    }
+    
     fn extract_package(&self, package_path: &Path) -> Result<(), PackageError> {
         // Extract package archive
         // ... implementation details ...
@@ -188,17 +647,36 @@ impl PackageManager {
     }
 }
 
+/// `key`, or the default signing key location
+fn signing_key_path(key: Option<&Path>) -> Result<PathBuf, PackageError> {
+    match key {
+        Some(path) => Ok(path.to_path_buf()),
+        None => SigningKey::default_path()
+            .ok_or_else(|| PackageError::SignatureError("Could not determine the config directory; pass --key".to_string())),
+    }
+}
+
 #[derive(Debug)]
 pub enum PackageError {
     ConfigError(String),
     CacheError(String),
     NetworkError(String),
-    PackageNotFound(String),
+    /// Name, and the closest known package name if any
+    PackageNotFound(String, Option<String>),
     VersionNotFound(String),
     InstallError(String),
     UninstallError(String),
     UpdateError(String),
     ListError(String),
+    BuildError(String),
+    PublishError(String),
+    /// A package is unsigned against the trust policy, or its signature
+    /// does not verify
+    SignatureError(String),
+    /// A new version's interface breaks compatibility its number promises
+    SemverError(String),
+    /// The compiler is missing or does not match this package manager
+    ToolchainError(VersionError),
 }
 
 impl std::fmt::Display for PackageError {
@@ -207,14 +685,104 @@ impl std::fmt::Display for PackageError {
             PackageError::ConfigError(msg) => write!(f, "Configuration error: {}", msg),
             PackageError::CacheError(msg) => write!(f, "Cache error: {}", msg),
             PackageError::NetworkError(msg) => write!(f, "Network error: {}", msg),
-            PackageError::PackageNotFound(name) => write!(f, "Package not found: {}", name),
+            PackageError::PackageNotFound(name, None) => write!(f, "Package not found: {}", name),
+            PackageError::PackageNotFound(name, Some(suggestion)) => {
+                write!(f, "Package not found: {} ({})", name, suggest::help_text(suggestion))
+            }
             PackageError::VersionNotFound(version) => write!(f, "Version not found: {}", version),
             PackageError::InstallError(msg) => write!(f, "Installation error: {}", msg),
             PackageError::UninstallError(msg) => write!(f, "Uninstallation error: {}", msg),
             PackageError::UpdateError(msg) => write!(f, "Update error: {}", msg),
             PackageError::ListError(msg) => write!(f, "List error: {}", msg),
+            PackageError::BuildError(msg) => write!(f, "Build error: {}", msg),
+            PackageError::PublishError(msg) => write!(f, "Publish error: {}", msg),
+            PackageError::SignatureError(msg) => write!(f, "Signature error: {}", msg),
+            PackageError::SemverError(msg) => write!(f, "Semver error: {}", msg),
+            PackageError::ToolchainError(e) => write!(f, "Toolchain error: {}", e),
         }
     }
 }
 
-impl std::error::Error for PackageError {}
\ No newline at end of file
+impl std::error::Error for PackageError {}
+
+impl From<VersionError> for PackageError {
+    fn from(error: VersionError) -> Self {
+        PackageError::ToolchainError(error)
+    }
+}
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use zaitun_bootstrap::interface::{ExportKind, ModuleInterface};
+    use zaitun_bootstrap::types::Type;
+
+    /// Stands in for `zaitun check`: rejects options the real CLI does not
+    /// take, and writes the interface kept next to each source as
+    /// `NAME.zt.zi.in` to the output's directory
+    const FAKE_COMPILER: &str = r#"#!/bin/sh
+[ "$1" = check ] || exit 2
+shift
+while [ $# -gt 0 ]; do
+    case "$1" in
+        -o) out_dir=$(dirname "$2"); shift ;;
+        --edition|--emit) shift ;;
+        -*) echo "Unknown option: $1" >&2; exit 2 ;;
+        *) cp "$1.zi.in" "$out_dir/$(basename "$1" .zt).zi" || exit 1 ;;
+    esac
+    shift
+done
+"#;
+
+    fn write_package(dir: &Path, exports: &[(&str, Type)]) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("geo.zt"), "").unwrap();
+        let mut interface = ModuleInterface::new("geo");
+        for (name, type_) in exports {
+            interface.add_export(name, ExportKind::Function(type_.clone()));
+        }
+        interface.write_to(&dir.join("geo.zt.zi.in")).unwrap();
+    }
+
+    fn manager(root: &Path, version: &str) -> PackageManager {
+        let manifest = format!(
+            "name = \"geo\"\nversion = \"{}\"\nauthors = []\n[dependencies]\n[dev_dependencies]\n[build_dependencies]\n",
+            version
+        );
+        PackageManager {
+            registry_url: String::new(),
+            cache_dir: root.join("cache"),
+            config: toml::from_str(&manifest).unwrap(),
+            manifest_name: PathBuf::from("zaitun.toml"),
+            artifact_store: None,
+            compiler: VersionInfo::current(),
+        }
+    }
+
+    #[test]
+    fn test_semver_check_between_versions() {
+        let root = std::env::temp_dir().join(format!("zaitun-semver-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let compiler = root.join("zaitun");
+        fs::write(&compiler, FAKE_COMPILER).unwrap();
+        fs::set_permissions(&compiler, fs::Permissions::from_mode(0o755)).unwrap();
+        std::env::set_var("ZAITUN", &compiler);
+
+        // 0.1.0 is the version the registry reports as published
+        let area = Type::Function(vec![Type::Float], Box::new(Type::Float));
+        write_package(&root.join("cache").join("geo").join("0.1.0"), &[("area", area.clone()), ("perimeter", area)]);
+        let work = root.join("work");
+        write_package(&work, &[("area", Type::Function(vec![Type::Float, Type::Float], Box::new(Type::Float)))]);
+
+        let error = manager(&root, "0.1.1").semver_check(&work, false).unwrap_err();
+        assert!(matches!(error, PackageError::SemverError(_)), "{}", error);
+
+        let report = manager(&root, "0.1.1").semver_check(&work, true).unwrap().unwrap();
+        let breaking: Vec<&str> = report.breaking_changes().map(|change| change.path.as_str()).collect();
+        assert_eq!(breaking, ["geo::area", "geo::perimeter"]);
+
+        assert!(manager(&root, "0.2.0").semver_check(&work, false).is_ok());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
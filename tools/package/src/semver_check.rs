@@ -0,0 +1,359 @@
+//! Semver compatibility of a package's public interface.
+//!
+//! `pm semver-check` compares the `.zi` interface files of the package
+//! being published with those of its latest published version. Every
+//! difference becomes a `Change`, and the changes must fit the version
+//! bump: breaking ones need a new major version, or a new minor version
+//! before 1.0. `pm publish` runs the same check and refuses to publish a
+//! breaking release under a compatible version unless `--allow-breaking`
+//! is passed.
+//!
+//! The report doubles as a changelog entry: it prints as markdown, grouped
+//! into breaking changes, additions and other changes.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use zaitun_bootstrap::interface::{ExportKind, ExportedItem, ModuleInterface, INTERFACE_EXTENSION};
+use zaitun_bootstrap::types::Type;
+
+use crate::manager::PackageError;
+
+/// A `major.minor.patch` version; pre-release and build suffixes are
+/// ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    pub fn parse(version: &str) -> Option<Version> {
+        let core = version.split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|part| part.parse::<u64>().ok());
+        let version = Version {
+            major: parts.next()??,
+            minor: parts.next()??,
+            patch: parts.next()??,
+        };
+        parts.next().is_none().then_some(version)
+    }
+
+    /// Whether going from `self` to `next` may break dependents: a new
+    /// major version, or before 1.0 a new minor version
+    pub fn allows_breaking(self, next: Version) -> bool {
+        if self.major == 0 && next.major == 0 {
+            next.minor > self.minor
+        } else {
+            next.major > self.major
+        }
+    }
+
+    /// The smallest version after `self` that may break dependents
+    pub fn next_breaking(self) -> Version {
+        if self.major == 0 {
+            Version { major: 0, minor: self.minor + 1, patch: 0 }
+        } else {
+            Version { major: self.major + 1, minor: 0, patch: 0 }
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// One difference between two versions of the interface
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// `module` or `module::item`
+    pub path: String,
+    pub description: String,
+    /// Whether code using the old version can stop compiling
+    pub breaking: bool,
+}
+
+impl Change {
+    fn new(kind: ChangeKind, path: &str, description: String, breaking: bool) -> Self {
+        Change {
+            kind,
+            path: path.to_string(),
+            description,
+            breaking,
+        }
+    }
+
+    fn section(&self) -> &'static str {
+        match self.kind {
+            _ if self.breaking => SECTIONS[0],
+            ChangeKind::Added => SECTIONS[1],
+            ChangeKind::Removed | ChangeKind::Changed => SECTIONS[2],
+        }
+    }
+}
+
+/// Changelog sections, in order
+const SECTIONS: [&str; 3] = ["Breaking changes", "Added", "Changed"];
+
+/// The `.zi` files in `dir`, ordered by module name
+pub fn read_interfaces(dir: &Path) -> Result<Vec<ModuleInterface>, PackageError> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| PackageError::BuildError(format!("Failed to read interfaces in {}: {}", dir.display(), e)))?;
+    let mut interfaces = Vec::new();
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.extension().and_then(|extension| extension.to_str()) != Some(INTERFACE_EXTENSION) {
+            continue;
+        }
+        let interface = ModuleInterface::read_from(&path)
+            .map_err(|e| PackageError::BuildError(format!("Failed to read interface {}: {}", path.display(), e)))?;
+        interfaces.push(interface);
+    }
+    interfaces.sort_by(|a, b| a.module_name.cmp(&b.module_name));
+    Ok(interfaces)
+}
+
+/// Everything that changed from the `old` modules to the `new` ones
+pub fn diff_interfaces(old: &[ModuleInterface], new: &[ModuleInterface]) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for old_module in old {
+        let name = &old_module.module_name;
+        match new.iter().find(|module| &module.module_name == name) {
+            Some(new_module) => diff_module(old_module, new_module, &mut changes),
+            None => changes.push(Change::new(ChangeKind::Removed, name, "removed module".to_string(), true)),
+        }
+    }
+    for new_module in new {
+        if !old.iter().any(|module| module.module_name == new_module.module_name) {
+            changes.push(Change::new(ChangeKind::Added, &new_module.module_name, "new module".to_string(), false));
+        }
+    }
+    changes
+}
+
+fn diff_module(old: &ModuleInterface, new: &ModuleInterface, changes: &mut Vec<Change>) {
+    let path = |item: &ExportedItem| format!("{}::{}", old.module_name, item.name);
+    for old_item in &old.exports {
+        match new.find(&old_item.name) {
+            Some(new_item) => diff_item(&path(old_item), &old_item.kind, &new_item.kind, changes),
+            None => changes.push(Change::new(
+                ChangeKind::Removed,
+                &path(old_item),
+                format!("removed {}", describe(&old_item.kind)),
+                true,
+            )),
+        }
+    }
+    for new_item in &new.exports {
+        if old.find(&new_item.name).is_some() {
+            continue;
+        }
+        // A new class in a sealed hierarchy is a case exhaustive matches
+        // over the hierarchy do not handle
+        let breaking = matches!(new_item.kind, ExportKind::Class { parent: Some(_), sealed: true, .. });
+        changes.push(Change::new(
+            ChangeKind::Added,
+            &path(new_item),
+            format!("new {}", describe(&new_item.kind)),
+            breaking,
+        ));
+    }
+}
+
+fn describe(kind: &ExportKind) -> &'static str {
+    match kind {
+        ExportKind::Function(_) => "function",
+        ExportKind::Constant(_) => "constant",
+        ExportKind::Class { .. } => "class",
+        ExportKind::Interface { .. } => "interface",
+        ExportKind::Struct { .. } => "struct",
+        ExportKind::Enum { .. } => "enum",
+    }
+}
+
+fn diff_item(path: &str, old: &ExportKind, new: &ExportKind, changes: &mut Vec<Change>) {
+    let mut change = |kind, description: String, breaking| changes.push(Change::new(kind, path, description, breaking));
+    match (old, new) {
+        (ExportKind::Function(old), ExportKind::Function(new)) if old != new => {
+            change(ChangeKind::Changed, format!("signature changed from `{}` to `{}`", old, new), true)
+        }
+        (ExportKind::Constant(old), ExportKind::Constant(new)) if old != new => {
+            change(ChangeKind::Changed, format!("type changed from `{}` to `{}`", old, new), true)
+        }
+        (
+            ExportKind::Class { parent, interfaces, members, sealed },
+            ExportKind::Class {
+                parent: new_parent,
+                interfaces: new_interfaces,
+                members: new_members,
+                sealed: new_sealed,
+            },
+        ) => {
+            if parent != new_parent {
+                let name = |parent: &Option<String>| parent.clone().unwrap_or_else(|| "nothing".to_string());
+                let description = format!("now extends {} instead of {}", name(new_parent), name(parent));
+                change(ChangeKind::Changed, description, true);
+            }
+            for interface in interfaces.iter().filter(|interface| !new_interfaces.contains(interface)) {
+                change(ChangeKind::Removed, format!("no longer implements `{}`", interface), true);
+            }
+            for interface in new_interfaces.iter().filter(|interface| !interfaces.contains(interface)) {
+                change(ChangeKind::Added, format!("now implements `{}`", interface), false);
+            }
+            diff_members("member", members, new_members, false, &mut change);
+            diff_sealed(*sealed, *new_sealed, &mut change);
+        }
+        (ExportKind::Interface { methods, sealed }, ExportKind::Interface { methods: new_methods, sealed: new_sealed }) => {
+            // Outside implementations of a sealed interface do not exist to
+            // be missing the new method
+            diff_members("method", methods, new_methods, !*sealed, &mut change);
+            diff_sealed(*sealed, *new_sealed, &mut change);
+        }
+        (ExportKind::Struct { fields, repr }, ExportKind::Struct { fields: new_fields, repr: new_repr }) => {
+            // Dependents lay structs out themselves and build them with
+            // every field, so any field change breaks them
+            diff_members("field", fields, new_fields, true, &mut change);
+            if repr != new_repr {
+                change(ChangeKind::Changed, "layout attributes changed".to_string(), true);
+            }
+        }
+        (ExportKind::Enum { variants }, ExportKind::Enum { variants: new_variants }) => {
+            for variant in variants.iter().filter(|variant| !new_variants.contains(variant)) {
+                change(ChangeKind::Removed, format!("removed variant `{}`", variant), true);
+            }
+            // Exhaustive matches in dependents miss new variants
+            for variant in new_variants.iter().filter(|variant| !variants.contains(variant)) {
+                change(ChangeKind::Added, format!("new variant `{}`", variant), true);
+            }
+        }
+        (old, new) if describe(old) != describe(new) => {
+            change(ChangeKind::Changed, format!("changed from a {} to a {}", describe(old), describe(new)), true)
+        }
+        _ => {}
+    }
+}
+
+fn diff_members<F>(what: &str, old: &[(String, Type)], new: &[(String, Type)], adding_breaks: bool, change: &mut F)
+where
+    F: FnMut(ChangeKind, String, bool),
+{
+    for (name, type_) in old {
+        match new.iter().find(|(new_name, _)| new_name == name) {
+            Some((_, new_type)) if new_type != type_ => change(
+                ChangeKind::Changed,
+                format!("type of {} `{}` changed from `{}` to `{}`", what, name, type_, new_type),
+                true,
+            ),
+            Some(_) => {}
+            None => change(ChangeKind::Removed, format!("removed {} `{}`", what, name), true),
+        }
+    }
+    for (name, type_) in new {
+        if !old.iter().any(|(old_name, _)| old_name == name) {
+            change(ChangeKind::Added, format!("new {} `{}: {}`", what, name, type_), adding_breaks);
+        }
+    }
+}
+
+fn diff_sealed<F>(sealed: bool, new_sealed: bool, change: &mut F)
+where
+    F: FnMut(ChangeKind, String, bool),
+{
+    match (sealed, new_sealed) {
+        (false, true) => change(ChangeKind::Changed, "now sealed".to_string(), true),
+        (true, false) => change(ChangeKind::Changed, "no longer sealed".to_string(), false),
+        _ => {}
+    }
+}
+
+/// Result of comparing a package with its previous version
+#[derive(Debug)]
+pub struct SemverReport {
+    pub package: String,
+    pub previous: Version,
+    pub version: Version,
+    pub changes: Vec<Change>,
+}
+
+impl SemverReport {
+    pub fn new(package: &str, previous: &str, version: &str, changes: Vec<Change>) -> Result<Self, PackageError> {
+        let parse = |version: &str| {
+            Version::parse(version).ok_or_else(|| {
+                PackageError::SemverError(format!("`{}` is not a major.minor.patch version", version))
+            })
+        };
+        Ok(SemverReport {
+            package: package.to_string(),
+            previous: parse(previous)?,
+            version: parse(version)?,
+            changes,
+        })
+    }
+
+    pub fn breaking_changes(&self) -> impl Iterator<Item = &Change> {
+        self.changes.iter().filter(|change| change.breaking)
+    }
+
+    /// Check the new version number fits the changes. Breaking changes
+    /// under a compatible version pass only with `allow_breaking`.
+    pub fn check(&self, allow_breaking: bool) -> Result<(), PackageError> {
+        if self.version <= self.previous {
+            return Err(PackageError::SemverError(format!(
+                "{} {} is not newer than the published {}",
+                self.package, self.version, self.previous
+            )));
+        }
+
+        let breaking = self.breaking_changes().count();
+        if breaking == 0 || self.previous.allows_breaking(self.version) {
+            return Ok(());
+        }
+        if allow_breaking {
+            println!(
+                "Warning: publishing {} breaking change(s) as compatible version {}",
+                breaking, self.version
+            );
+            return Ok(());
+        }
+        Err(PackageError::SemverError(format!(
+            "{} breaking change(s) since {} need version {} or later, not {}; pass --allow-breaking to publish anyway",
+            breaking,
+            self.previous,
+            self.previous.next_breaking(),
+            self.version
+        )))
+    }
+}
+
+impl fmt::Display for SemverReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "## {} {}\n\nChanges since {}", self.package, self.version, self.previous)?;
+        if self.changes.is_empty() {
+            return write!(f, ": none to the public interface");
+        }
+        write!(f, ".")?;
+
+        for title in SECTIONS {
+            let mut changes = self.changes.iter().filter(|change| change.section() == title).peekable();
+            if changes.peek().is_none() {
+                continue;
+            }
+            write!(f, "\n\n### {}\n", title)?;
+            for change in changes {
+                write!(f, "\n- `{}`: {}", change.path, change.description)?;
+            }
+        }
+        Ok(())
+    }
+}
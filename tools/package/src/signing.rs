@@ -0,0 +1,363 @@
+//! Package signatures.
+//!
+//! Publishers sign the archives they publish with an Ed25519 key
+//! (`pm publish --sign`), and the signature is uploaded next to the
+//! archive as `<archive>.sig`. Public keys are registered per publisher in
+//! the registry index, and `pm install` checks each download against the
+//! keys of the package's publisher. The signed message covers the package
+//! name and version as well as the archive, so a signature cannot be moved
+//! to another package or version.
+//!
+//! Unsigned packages install with a warning unless the trust policy says
+//! `require-signed = true`. A signature that fails to verify always stops
+//! the install.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use zaitun_std::crypto::{self, AsymmetricAlgorithm, KeyPair};
+use zaitun_std::encoding::{hex_decode, hex_encode};
+
+use crate::manager::PackageError;
+
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+/// The only algorithm signatures use so far
+const ALGORITHM: &str = "ed25519";
+
+/// Prefix of every signed message, so package signatures cannot be
+/// mistaken for signatures the same key made for something else
+const SIGNED_CONTEXT: &[u8] = b"zaitun-package-signature-v1\0";
+
+/// `[trust]` section of the package config
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TrustPolicy {
+    /// Refuse to install packages without a signature
+    #[serde(default)]
+    pub require_signed: bool,
+}
+
+impl TrustPolicy {
+    /// Whether a package without a signature may be installed
+    pub fn allow_unsigned(&self, package_name: &str, version: &str) -> Result<(), PackageError> {
+        if self.require_signed {
+            return Err(signature_error(format!(
+                "{} {} is not signed, and the trust policy requires signatures (require-signed = true)",
+                package_name, version
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Where the signature of `archive` is stored
+pub fn signature_path(archive: &Path) -> PathBuf {
+    let mut name = archive.as_os_str().to_owned();
+    name.push(".");
+    name.push(SIGNATURE_EXTENSION);
+    PathBuf::from(name)
+}
+
+fn signed_message(package_name: &str, version: &str, archive: &[u8]) -> Vec<u8> {
+    let mut message = SIGNED_CONTEXT.to_vec();
+    for part in [package_name.as_bytes(), version.as_bytes()] {
+        message.extend_from_slice(part);
+        message.push(0);
+    }
+    message.extend_from_slice(archive);
+    message
+}
+
+fn signature_error(message: String) -> PackageError {
+    PackageError::SignatureError(message)
+}
+
+/// Key file written by `SigningKey::save`
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct KeyFile {
+    algorithm: String,
+    public_key: String,
+    private_key: String,
+}
+
+/// A publisher's key pair
+pub struct SigningKey {
+    keys: KeyPair,
+}
+
+impl SigningKey {
+    pub fn generate() -> Self {
+        SigningKey {
+            keys: KeyPair::generate(AsymmetricAlgorithm::Ed25519),
+        }
+    }
+
+    /// `signing-key.toml` in the user's config directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("zaitun").join("signing-key.toml"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PackageError> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            signature_error(format!(
+                "Failed to read signing key {}: {} (create one with `pm key generate`)",
+                path.display(),
+                e
+            ))
+        })?;
+        let file: KeyFile = toml::from_str(&content)
+            .map_err(|e| signature_error(format!("Failed to parse signing key {}: {}", path.display(), e)))?;
+        if file.algorithm != ALGORITHM {
+            return Err(signature_error(format!("Unsupported signing key algorithm: {}", file.algorithm)));
+        }
+
+        let decode = |hex: &str| {
+            hex_decode(hex).map_err(|e| signature_error(format!("Malformed signing key {}: {}", path.display(), e)))
+        };
+        let keys = KeyPair::from_bytes(AsymmetricAlgorithm::Ed25519, &decode(&file.private_key)?, &decode(&file.public_key)?)
+            .map_err(|e| signature_error(format!("Malformed signing key {}: {}", path.display(), e)))?;
+        Ok(SigningKey { keys })
+    }
+
+    /// Write the key to `path`, readable only by its owner. Never replaces
+    /// an existing key, whose signatures would stop verifying once its
+    /// registration is revoked.
+    pub fn save(&self, path: &Path) -> Result<(), PackageError> {
+        if path.exists() {
+            return Err(signature_error(format!("A signing key already exists at {}", path.display())));
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| signature_error(format!("Failed to create {}: {}", dir.display(), e)))?;
+        }
+
+        let file = KeyFile {
+            algorithm: ALGORITHM.to_string(),
+            public_key: self.public_key_hex(),
+            private_key: hex_encode(self.keys.private_key()),
+        };
+        let content = toml::to_string(&file).map_err(|e| signature_error(format!("Failed to encode signing key: {}", e)))?;
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let write = options.open(path).and_then(|mut out| std::io::Write::write_all(&mut out, content.as_bytes()));
+        write.map_err(|e| signature_error(format!("Failed to write signing key {}: {}", path.display(), e)))
+    }
+
+    /// The public key as registered in the index
+    pub fn public_key_hex(&self) -> String {
+        hex_encode(self.keys.public_key())
+    }
+
+    pub fn sign(&self, package_name: &str, version: &str, archive: &[u8]) -> Result<PackageSignature, PackageError> {
+        let signature = self
+            .keys
+            .sign(&signed_message(package_name, version, archive))
+            .map_err(|e| signature_error(format!("Failed to sign {} {}: {}", package_name, version, e)))?;
+        Ok(PackageSignature {
+            algorithm: ALGORITHM.to_string(),
+            public_key: self.public_key_hex(),
+            signature: hex_encode(&signature),
+        })
+    }
+}
+
+/// Contents of a `.sig` file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackageSignature {
+    pub algorithm: String,
+    /// Hex public key of the signer, which must be registered to the
+    /// package's publisher
+    pub public_key: String,
+    pub signature: String,
+}
+
+impl PackageSignature {
+    /// `None` when there is no signature file
+    pub fn read(path: &Path) -> Result<Option<Self>, PackageError> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(signature_error(format!("Failed to read {}: {}", path.display(), e))),
+        };
+        toml::from_str(&content)
+            .map(Some)
+            .map_err(|e| signature_error(format!("Malformed signature {}: {}", path.display(), e)))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), PackageError> {
+        let content = toml::to_string(self).map_err(|e| signature_error(format!("Failed to encode signature: {}", e)))?;
+        fs::write(path, content).map_err(|e| signature_error(format!("Failed to write {}: {}", path.display(), e)))
+    }
+}
+
+/// A publisher's entry in the registry index
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PublisherEntry {
+    pub name: String,
+    #[serde(default)]
+    pub keys: Vec<PublisherKey>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PublisherKey {
+    /// Hex Ed25519 public key
+    pub public_key: String,
+    /// Set when the key was lost or retired; its signatures no longer count
+    #[serde(default)]
+    pub revoked: bool,
+}
+
+impl PublisherEntry {
+    /// Add `public_key` unless it is already registered
+    pub fn add_key(&mut self, public_key: &str) {
+        if !self.keys.iter().any(|key| key.public_key == public_key) {
+            self.keys.push(PublisherKey {
+                public_key: public_key.to_string(),
+                revoked: false,
+            });
+        }
+    }
+
+    /// Check that `signature` was made over the archive by one of this
+    /// publisher's current keys
+    pub fn verify(
+        &self,
+        signature: &PackageSignature,
+        package_name: &str,
+        version: &str,
+        archive: &[u8],
+    ) -> Result<(), PackageError> {
+        let package = format!("{} {}", package_name, version);
+        if signature.algorithm != ALGORITHM {
+            return Err(signature_error(format!("{} is signed with unsupported algorithm {}", package, signature.algorithm)));
+        }
+        match self.keys.iter().find(|key| key.public_key.eq_ignore_ascii_case(&signature.public_key)) {
+            None => {
+                return Err(signature_error(format!(
+                    "{} is signed with a key that is not registered to its publisher {}",
+                    package, self.name
+                )))
+            }
+            Some(key) if key.revoked => {
+                return Err(signature_error(format!(
+                    "{} is signed with a key {} has revoked",
+                    package, self.name
+                )))
+            }
+            Some(_) => {}
+        }
+
+        let malformed = |e: String| signature_error(format!("Malformed signature for {}: {}", package, e));
+        let public_key = hex_decode(&signature.public_key).map_err(|e| malformed(e.to_string()))?;
+        let bytes = hex_decode(&signature.signature).map_err(|e| malformed(e.to_string()))?;
+        let valid = crypto::verify_signature(
+            AsymmetricAlgorithm::Ed25519,
+            &public_key,
+            &signed_message(package_name, version, archive),
+            &bytes,
+        )
+        .map_err(|e| malformed(e.to_string()))?;
+        if !valid {
+            return Err(signature_error(format!(
+                "Signature of {} does not match its archive; it may have been tampered with",
+                package
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARCHIVE: &[u8] = b"archive contents";
+
+    fn publisher(key: &SigningKey) -> PublisherEntry {
+        let mut publisher = PublisherEntry {
+            name: "alice".to_string(),
+            keys: Vec::new(),
+        };
+        publisher.add_key(&key.public_key_hex());
+        publisher
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = SigningKey::generate();
+        let signature = key.sign("json", "1.2.0", ARCHIVE).unwrap();
+        assert_eq!(hex_decode(&signature.signature).unwrap().len(), 64);
+        publisher(&key).verify(&signature, "json", "1.2.0", ARCHIVE).unwrap();
+    }
+
+    #[test]
+    fn test_tampered_archive_rejected() {
+        let key = SigningKey::generate();
+        let publisher = publisher(&key);
+        let signature = key.sign("json", "1.2.0", ARCHIVE).unwrap();
+
+        assert!(publisher.verify(&signature, "json", "1.2.0", b"archive contentz").is_err());
+        // The signature does not carry over to another version or package
+        assert!(publisher.verify(&signature, "json", "1.2.1", ARCHIVE).is_err());
+        assert!(publisher.verify(&signature, "yaml", "1.2.0", ARCHIVE).is_err());
+    }
+
+    #[test]
+    fn test_wrong_key_rejected() {
+        let key = SigningKey::generate();
+        let other = SigningKey::generate();
+        let mut publisher = publisher(&key);
+
+        let signature = other.sign("json", "1.2.0", ARCHIVE).unwrap();
+        assert!(publisher.verify(&signature, "json", "1.2.0", ARCHIVE).is_err());
+
+        // Another key's signature claiming to be from a registered key
+        let forged = PackageSignature {
+            public_key: key.public_key_hex(),
+            ..signature
+        };
+        assert!(publisher.verify(&forged, "json", "1.2.0", ARCHIVE).is_err());
+
+        let signature = key.sign("json", "1.2.0", ARCHIVE).unwrap();
+        publisher.keys[0].revoked = true;
+        assert!(publisher.verify(&signature, "json", "1.2.0", ARCHIVE).is_err());
+    }
+
+    #[test]
+    fn test_require_signed() {
+        assert!(TrustPolicy { require_signed: true }.allow_unsigned("json", "1.2.0").is_err());
+        assert!(TrustPolicy::default().allow_unsigned("json", "1.2.0").is_ok());
+    }
+
+    #[test]
+    fn test_saved_key_round_trip() {
+        let dir = std::env::temp_dir().join(format!("zaitun-signing-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("signing-key.toml");
+
+        let key = SigningKey::generate();
+        key.save(&path).unwrap();
+        assert!(key.save(&path).is_err());
+        let loaded = SigningKey::load(&path).unwrap();
+        assert_eq!(loaded.public_key_hex(), key.public_key_hex());
+        let signature = loaded.sign("json", "1.2.0", ARCHIVE).unwrap();
+        publisher(&key).verify(&signature, "json", "1.2.0", ARCHIVE).unwrap();
+
+        // A public key that does not belong to the private key is refused
+        let content = fs::read_to_string(&path)
+            .unwrap()
+            .replace(&key.public_key_hex(), &SigningKey::generate().public_key_hex());
+        fs::write(&path, content).unwrap();
+        assert!(SigningKey::load(&path).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
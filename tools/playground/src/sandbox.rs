@@ -0,0 +1,210 @@
+//! Running untrusted programs under strict resource limits.
+//!
+//! Each run gets a fresh scratch directory, an empty environment and
+//! closed stdin. On Unix the child also gets hard rlimits (CPU time,
+//! address space, file size, open files, processes). A wall-clock timeout
+//! catches programs that sleep or block instead of burning CPU. Output is
+//! captured up to a cap so a print loop cannot exhaust server memory.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Resource limits for one compile or run
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub cpu_time: Duration,
+    pub wall_time: Duration,
+    pub memory_bytes: u64,
+    /// Bytes kept from each of stdout and stderr
+    pub max_output: usize,
+    /// Largest file the program may write in its scratch directory
+    pub max_file_size: u64,
+    pub max_open_files: u64,
+    /// 0 disallows `fork` entirely
+    pub max_processes: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            cpu_time: Duration::from_secs(2),
+            wall_time: Duration::from_secs(5),
+            memory_bytes: 256 * 1024 * 1024,
+            max_output: 64 * 1024,
+            max_file_size: 1024 * 1024,
+            max_open_files: 32,
+            max_processes: 0,
+        }
+    }
+}
+
+/// Outcome of a sandboxed run
+#[derive(Debug, Clone)]
+pub struct Execution {
+    pub stdout: String,
+    pub stderr: String,
+    /// `None` when killed by a signal, including limit enforcement
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    /// Output was longer than `max_output` and was cut
+    pub truncated: bool,
+    pub elapsed: Duration,
+}
+
+/// Scratch directory removed on drop
+pub struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    pub fn new() -> std::io::Result<Self> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "zaitun-playground-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+        ));
+        std::fs::create_dir_all(&path)?;
+        Ok(ScratchDir { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Run `program` in `dir` under `limits`
+pub fn run(program: &Path, args: &[&str], dir: &Path, limits: &Limits) -> std::io::Result<Execution> {
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .current_dir(dir)
+        .env_clear()
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    #[cfg(unix)]
+    apply_rlimits(&mut command, limits);
+
+    let start = Instant::now();
+    let mut child = command.spawn()?;
+
+    let stdout = capture(child.stdout.take(), limits.max_output);
+    let stderr = capture(child.stderr.take(), limits.max_output);
+
+    let (status, timed_out) = wait_with_deadline(&mut child, limits.wall_time)?;
+    let elapsed = start.elapsed();
+
+    let (stdout, stdout_truncated) = stdout.join().unwrap_or_default();
+    let (stderr, stderr_truncated) = stderr.join().unwrap_or_default();
+
+    Ok(Execution {
+        stdout,
+        stderr,
+        exit_code: status,
+        timed_out,
+        truncated: stdout_truncated || stderr_truncated,
+        elapsed,
+    })
+}
+
+/// Wait for `child`, killing it once `deadline` has passed
+fn wait_with_deadline(child: &mut Child, deadline: Duration) -> std::io::Result<(Option<i32>, bool)> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status.code(), false));
+        }
+        if start.elapsed() >= deadline {
+            let _ = child.kill();
+            let status = child.wait()?;
+            return Ok((status.code(), true));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Read a pipe to the end on a helper thread, keeping at most `max` bytes.
+/// The rest is drained and dropped so the child never blocks on a full pipe.
+fn capture<R: Read + Send + 'static>(pipe: Option<R>, max: usize) -> thread::JoinHandle<(String, bool)> {
+    thread::spawn(move || {
+        let mut pipe = match pipe {
+            Some(pipe) => pipe,
+            None => return (String::new(), false),
+        };
+
+        let mut kept = Vec::new();
+        let mut truncated = false;
+        let mut buffer = [0u8; 8192];
+        loop {
+            match pipe.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    let room = max.saturating_sub(kept.len());
+                    kept.extend_from_slice(&buffer[..n.min(room)]);
+                    truncated |= n > room;
+                }
+            }
+        }
+
+        (String::from_utf8_lossy(&kept).into_owned(), truncated)
+    })
+}
+
+#[cfg(unix)]
+fn apply_rlimits(command: &mut Command, limits: &Limits) {
+    use std::os::unix::process::CommandExt;
+
+    let cpu = limits.cpu_time.as_secs().max(1);
+    let memory = limits.memory_bytes;
+    let file_size = limits.max_file_size;
+    let open_files = limits.max_open_files;
+    let processes = limits.max_processes;
+
+    // Runs in the forked child before exec; only async-signal-safe calls
+    unsafe {
+        command.pre_exec(move || {
+            set_limit(libc::RLIMIT_CPU, cpu)?;
+            set_limit(libc::RLIMIT_AS, memory)?;
+            set_limit(libc::RLIMIT_FSIZE, file_size)?;
+            set_limit(libc::RLIMIT_NOFILE, open_files)?;
+            set_limit(libc::RLIMIT_CORE, 0)?;
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            set_limit(libc::RLIMIT_NPROC, processes)?;
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            let _ = processes;
+            Ok(())
+        });
+    }
+}
+
+#[cfg(all(unix, any(target_os = "linux", target_os = "android")))]
+type Resource = libc::__rlimit_resource_t;
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+type Resource = libc::c_int;
+
+#[cfg(unix)]
+fn set_limit(resource: Resource, value: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: value as libc::rlim_t,
+        rlim_max: value as libc::rlim_t,
+    };
+    // `limit` is a valid rlimit for the duration of the call
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,266 @@
+//! HTTP backend for the web playground.
+//!
+//! `POST /compile` checks a snippet and returns diagnostics; `POST /run`
+//! also executes it under `sandbox::Limits` and returns its output. Both
+//! take `{"code": "..."}` and answer with a `PlaygroundResponse` as JSON.
+//! `GET /health` is for load balancers.
+//!
+//! The server speaks just enough HTTP/1.1 for a reverse proxy in front of
+//! it: one request per connection, `Content-Length` bodies only.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use zaitun_bootstrap::driver::{CompilerDriver, CompilerOptions, EmitKind};
+
+use crate::sandbox::{self, Limits, ScratchDir};
+
+/// Name of the snippet in diagnostics
+const SNIPPET_FILE_NAME: &str = "<playground>";
+
+#[derive(Debug, Clone)]
+pub struct PlaygroundConfig {
+    pub limits: Limits,
+    /// Largest accepted snippet, in bytes
+    pub max_code_size: usize,
+    /// Compiles and runs allowed at once; further requests wait
+    pub max_concurrent: usize,
+    /// Time allowed for reading a request from a client
+    pub read_timeout: Duration,
+}
+
+impl Default for PlaygroundConfig {
+    fn default() -> Self {
+        PlaygroundConfig {
+            limits: Limits::default(),
+            max_code_size: 64 * 1024,
+            max_concurrent: 4,
+            read_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlaygroundRequest {
+    pub code: String,
+    #[serde(default)]
+    pub optimization_level: u8,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PlaygroundResponse {
+    pub success: bool,
+    pub diagnostics: Vec<String>,
+    /// Present for `/run` once compilation succeeded
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub truncated: bool,
+    pub compile_ms: u64,
+    pub run_ms: u64,
+}
+
+pub struct PlaygroundServer {
+    config: PlaygroundConfig,
+    /// Free job slots, see `max_concurrent`
+    slots: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl PlaygroundServer {
+    pub fn new(config: PlaygroundConfig) -> Self {
+        let slots = Arc::new((Mutex::new(config.max_concurrent.max(1)), Condvar::new()));
+        PlaygroundServer { config, slots }
+    }
+
+    /// Accept connections on `addr` forever, one thread per connection
+    pub fn serve(self, addr: &str) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        let server = Arc::new(self);
+
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let server = Arc::clone(&server);
+            thread::spawn(move || {
+                let _ = server.handle_connection(stream);
+            });
+        }
+
+        Ok(())
+    }
+
+    fn handle_connection(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(self.config.read_timeout))?;
+
+        let (status, body) = match read_request(&mut stream, self.config.max_code_size * 2) {
+            Ok((method, path, body)) => self.route(&method, &path, &body),
+            Err(HttpError(status, message)) => (status, error_body(message)),
+        };
+
+        write_response(&mut stream, status, &body)
+    }
+
+    fn route(&self, method: &str, path: &str, body: &[u8]) -> (u16, String) {
+        let run = match (method, path) {
+            ("GET", "/health") => return (200, "{\"status\":\"ok\"}".to_string()),
+            ("POST", "/compile") => false,
+            ("POST", "/run") => true,
+            (_, "/compile") | (_, "/run") | (_, "/health") => {
+                return (405, error_body("method not allowed"));
+            }
+            _ => return (404, error_body("not found")),
+        };
+
+        let request: PlaygroundRequest = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(e) => return (400, error_body(&format!("invalid request: {}", e))),
+        };
+        if request.code.len() > self.config.max_code_size {
+            return (413, error_body("code too large"));
+        }
+
+        let response = {
+            let _slot = self.acquire_slot();
+            self.compile_and_run(&request, run)
+        };
+
+        match response {
+            Ok(response) => (200, serde_json::to_string(&response).unwrap_or_default()),
+            Err(e) => (500, error_body(&format!("sandbox failure: {}", e))),
+        }
+    }
+
+    /// Compile the snippet in a scratch directory, then run it if asked
+    pub fn compile_and_run(&self, request: &PlaygroundRequest, run: bool) -> std::io::Result<PlaygroundResponse> {
+        let scratch = ScratchDir::new()?;
+        let binary = scratch.path().join("main");
+
+        let compile_start = Instant::now();
+        let mut driver = CompilerDriver::new();
+        driver.add_source_text(Path::new(SNIPPET_FILE_NAME), request.code.clone());
+        driver.set_output_file(&binary);
+
+        let mut options = CompilerOptions::default();
+        options.optimization_level = request.optimization_level.min(3);
+        if !run {
+            options.emit = vec![EmitKind::Interface];
+        }
+        driver.set_options(options);
+
+        let compiled = driver.compile().is_ok();
+        let mut response = PlaygroundResponse {
+            success: compiled,
+            diagnostics: driver.diagnostic_messages(),
+            compile_ms: compile_start.elapsed().as_millis() as u64,
+            ..PlaygroundResponse::default()
+        };
+
+        if !compiled || !run {
+            return Ok(response);
+        }
+
+        let execution = sandbox::run(&binary, &[], scratch.path(), &self.config.limits)?;
+        response.success = execution.exit_code == Some(0) && !execution.timed_out;
+        response.stdout = Some(execution.stdout);
+        response.stderr = Some(execution.stderr);
+        response.exit_code = execution.exit_code;
+        response.timed_out = execution.timed_out;
+        response.truncated = execution.truncated;
+        response.run_ms = execution.elapsed.as_millis() as u64;
+
+        Ok(response)
+    }
+
+    fn acquire_slot(&self) -> SlotGuard<'_> {
+        let (free, available) = &*self.slots;
+        let mut free = free.lock().unwrap();
+        while *free == 0 {
+            free = available.wait(free).unwrap();
+        }
+        *free -= 1;
+        SlotGuard { slots: &self.slots }
+    }
+}
+
+/// Returns a job slot on drop
+struct SlotGuard<'a> {
+    slots: &'a (Mutex<usize>, Condvar),
+}
+
+impl Drop for SlotGuard<'_> {
+    fn drop(&mut self) {
+        let (free, available) = self.slots;
+        *free.lock().unwrap() += 1;
+        available.notify_one();
+    }
+}
+
+struct HttpError(u16, &'static str);
+
+/// Read the request line, headers and `Content-Length` body
+fn read_request(stream: &mut TcpStream, max_body: usize) -> Result<(String, String, Vec<u8>), HttpError> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).map_err(|_| HttpError(400, "bad request"))?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().ok_or(HttpError(400, "bad request"))?.to_string();
+    let path = parts.next().ok_or(HttpError(400, "bad request"))?.to_string();
+
+    let mut content_length = 0usize;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).map_err(|_| HttpError(400, "bad request"))?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().map_err(|_| HttpError(400, "bad content-length"))?;
+            }
+        }
+    }
+
+    if content_length > max_body {
+        return Err(HttpError(413, "request too large"));
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).map_err(|_| HttpError(400, "truncated body"))?;
+
+    Ok((method, path, body))
+}
+
+fn write_response(stream: &mut TcpStream, status: u16, body: &str) -> std::io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body,
+    )?;
+    stream.flush()
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
@@ -1,11 +1,19 @@
 use std::io::{self, Write};
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use zaitun_runtime::debug::{DebugHooks, Location, StepMode, Stepper, StopReason};
+
+/// File name of code typed at the prompt, e.g. for `:break <repl>:1`
+const PROMPT_FILE: &str = "<repl>";
 
 pub struct REPL {
     variables: HashMap<String, Value>,
     history: Vec<String>,
     compiler: Compiler,
     interpreter: Interpreter,
+    debugger: Debugger,
 }
 
 impl REPL {
@@ -15,7 +23,43 @@ impl REPL {
             history: Vec::new(),
             compiler: Compiler::new(),
             interpreter: Interpreter::new(),
+            debugger: Debugger::new(),
+        }
+    }
+    
+    /// Handle `--load FILE` arguments, e.g. from `zaitun test --interactive`
+    pub fn from_args(args: &[String]) -> io::Result<Self> {
+        let mut repl = REPL::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--load" {
+                let path = args.next().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "--load expects a file")
+                })?;
+                repl.load_module(Path::new(path))?;
+            }
         }
+        Ok(repl)
+    }
+    
+    /// Evaluate a module's top-level `let` bindings so they can be used
+    /// from the prompt
+    pub fn load_module(&mut self, path: &Path) -> io::Result<()> {
+        let source = fs::read_to_string(path)?;
+        let file = path.display().to_string();
+        self.debugger.sources.insert(file.clone(), source.clone());
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.starts_with("let ") {
+                let location = Location {
+                    file: file.clone(),
+                    line: index as u32 + 1,
+                };
+                self.evaluate_at(line.trim_end_matches(';'), &location);
+            }
+        }
+        println!("Loaded {}", path.display());
+        Ok(())
     }
     
     pub fn run(&mut self) -> io::Result<()> {
@@ -42,7 +86,22 @@ impl REPL {
                 "help" => self.print_help(),
                 "history" => self.print_history(),
                 "clear" => self.clear_variables(),
-                _ => self.evaluate(input),
+                _ if input.starts_with(':') => {
+                    if let Some(mode) = self.debugger.command(input) {
+                        self.debugger.stepper.resume(mode);
+                        if matches!(mode, StepMode::StepIn | StepMode::StepOver) {
+                            println!("The next input stops at its first statement");
+                        }
+                    }
+                }
+                _ => match input.strip_prefix("load ") {
+                    Some(path) => {
+                        if let Err(e) = self.load_module(Path::new(path.trim())) {
+                            println!("Error: {}", e);
+                        }
+                    }
+                    None => self.evaluate(input),
+                },
             }
         }
         
@@ -50,13 +109,24 @@ impl REPL {
     }
     
     fn evaluate(&mut self, input: &str) {
+        let location = Location {
+            file: PROMPT_FILE.to_string(),
+            line: 1,
+        };
+        self.evaluate_at(input, &location);
+    }
+    
+    /// Evaluate `input`, which starts at `location` for the debugger
+    fn evaluate_at(&mut self, input: &str, location: &Location) {
+        self.debugger.stepper.reset();
+        
         // Special case for variable assignment
         if let Some(pos) = input.find('=') {
             if let Some(var_name) = input[..pos].trim().strip_prefix("let ") {
                 let var_name = var_name.trim();
                 let expr = input[pos + 1..].trim();
                 
-                match self.eval_expression(expr) {
+                match self.eval_expression(expr, location) {
                     Ok(value) => {
                         self.variables.insert(var_name.to_string(), value.clone());
                         println!("{} = {}", var_name, value);
@@ -71,7 +141,7 @@ impl REPL {
         }
         
         // Regular expression evaluation
-        match self.eval_expression(input) {
+        match self.eval_expression(input, location) {
             Ok(value) => {
                 println!("{}", value);
             }
@@ -81,13 +151,13 @@ impl REPL {
         }
     }
     
-    fn eval_expression(&self, expr: &str) -> Result<Value, String> {
+    fn eval_expression(&mut self, expr: &str, location: &Location) -> Result<Value, String> {
         // Try to compile the expression
         let ast = self.compiler.parse(expr)
             .map_err(|e| format!("Parse error: {}", e))?;
         
         // Evaluate the AST
-        self.interpreter.eval(&ast, &self.variables)
+        self.interpreter.eval(&ast, &self.variables, location, &mut self.debugger)
     }
     
     fn print_help(&self) {
@@ -96,8 +166,18 @@ impl REPL {
         println!("  exit, quit           - Exit the REPL");
         println!("  history              - Show command history");
         println!("  clear                - Clear all variables");
+        println!("  load <file>          - Load a module's top-level bindings");
         println!("  let <name> = <expr>  - Assign expression result to variable");
         println!("  <expr>               - Evaluate expression and print result");
+        println!();
+        println!("Debugger commands, at the prompt or when stopped:");
+        println!("  :break <file>:<line> - Stop before the statement on that line");
+        println!("  :delete <id>         - Remove a breakpoint");
+        println!("  :breakpoints         - List breakpoints");
+        println!("  :step                - Stop at the next statement, entering calls");
+        println!("  :next                - Stop at the next statement, stepping over calls");
+        println!("  :finish              - Stop once the current function returns");
+        println!("  :continue            - Run until the next breakpoint");
     }
     
     fn print_history(&self) {
@@ -137,8 +217,16 @@ impl Interpreter {
         Interpreter {}
     }
     
-    fn eval(&self, ast: &AST, variables: &HashMap<String, Value>) -> Result<Value, String> {
-        // Evaluate AST
+    fn eval(
+        &self,
+        ast: &AST,
+        variables: &HashMap<String, Value>,
+        location: &Location,
+        hooks: &mut dyn DebugHooks,
+    ) -> Result<Value, String> {
+        // Evaluate AST, calling `hooks.on_statement` before each statement;
+        // the first is at `location`, at depth 0
+        hooks.on_statement(location, 0);
         // ... implementation details ...
         Ok(Value::Number(0.0))
     }
@@ -148,6 +236,110 @@ struct AST {
     // AST structure
 }
 
+/// Breakpoints and stepping for code run from the REPL. Stopping reads
+/// debugger commands from the terminal until one resumes execution.
+struct Debugger {
+    stepper: Stepper,
+    /// Loaded modules' source, for showing the line stopped at
+    sources: HashMap<String, String>,
+}
+
+impl Debugger {
+    fn new() -> Self {
+        Debugger {
+            stepper: Stepper::new(),
+            sources: HashMap::new(),
+        }
+    }
+    
+    /// Run a `:` command; those that resume execution return how
+    fn command(&mut self, input: &str) -> Option<StepMode> {
+        let (name, arg) = input.split_once(' ').unwrap_or((input, ""));
+        let arg = arg.trim();
+        match name {
+            ":break" | ":b" => {
+                let parsed = arg.rsplit_once(':').and_then(|(file, line)| Some((file, line.parse().ok()?)));
+                match parsed {
+                    Some((file, line)) if !file.is_empty() => {
+                        let id = self.stepper.add_breakpoint(file, line);
+                        println!("Breakpoint {} at {}:{}", id, file, line);
+                    }
+                    _ => println!("Usage: :break <file>:<line>"),
+                }
+                None
+            }
+            ":delete" | ":d" => {
+                match arg.parse() {
+                    Ok(id) if self.stepper.remove_breakpoint(id) => println!("Deleted breakpoint {}", id),
+                    Ok(id) => println!("No breakpoint {}", id),
+                    Err(_) => println!("Usage: :delete <id>"),
+                }
+                None
+            }
+            ":breakpoints" => {
+                if self.stepper.breakpoints().is_empty() {
+                    println!("No breakpoints");
+                }
+                for breakpoint in self.stepper.breakpoints() {
+                    println!("{}: {}:{} (hit {} times)", breakpoint.id, breakpoint.file, breakpoint.line, breakpoint.hits);
+                }
+                None
+            }
+            ":step" | ":s" => Some(StepMode::StepIn),
+            ":next" | ":n" => Some(StepMode::StepOver),
+            ":finish" | ":f" => Some(StepMode::StepOut),
+            ":continue" | ":c" => Some(StepMode::Continue),
+            _ => {
+                println!("Unknown command {}; type 'help' for the list", name);
+                None
+            }
+        }
+    }
+    
+    fn print_stop(&self, location: &Location, reason: StopReason) {
+        match reason {
+            StopReason::Breakpoint(id) => println!("Breakpoint {} at {}", id, location),
+            StopReason::Step => println!("Stopped at {}", location),
+        }
+        let line = self.sources.get(&location.file)
+            .and_then(|source| source.lines().nth((location.line as usize).saturating_sub(1)));
+        if let Some(line) = line {
+            println!("{:>5} | {}", location.line, line);
+        }
+    }
+}
+
+impl DebugHooks for Debugger {
+    fn on_statement(&mut self, location: &Location, depth: usize) {
+        let Some(reason) = self.stepper.check(location, depth) else {
+            return;
+        };
+        self.print_stop(location, reason);
+        
+        loop {
+            print!("(debug) ");
+            let mut input = String::new();
+            // A closed terminal has no one to wait for
+            if io::stdout().flush().is_err() || matches!(io::stdin().read_line(&mut input), Ok(0) | Err(_)) {
+                return;
+            }
+            
+            let input = input.trim();
+            if input.is_empty() {
+                continue;
+            }
+            if !input.starts_with(':') {
+                println!("Stopped at {}; use :step, :next, :finish or :continue to go on", location);
+                continue;
+            }
+            if let Some(mode) = self.command(input) {
+                self.stepper.resume(mode);
+                return;
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 enum Value {
     Number(f64),
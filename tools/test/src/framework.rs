@@ -1,45 +1,418 @@
-use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use std::cell::RefCell;
+use std::fmt;
+use std::panic::Location;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use zaitun_bootstrap::version_info::{self, Requirements};
+
+use crate::triage::{self, TriageAction};
+
+thread_local! {
+    /// Output and last failed assertion of the test running on this thread
+    static CAPTURE: RefCell<Capture> = RefCell::new(Capture::default());
+}
+
+#[derive(Default)]
+struct Capture {
+    output: String,
+    assertion: Option<Assertion>,
+}
+
+/// Write to the running test's captured output, shown only if it fails
+pub fn test_output(text: &str) {
+    CAPTURE.with(|capture| {
+        let mut capture = capture.borrow_mut();
+        capture.output.push_str(text);
+        if !text.ends_with('\n') {
+            capture.output.push('\n');
+        }
+    });
+}
+
+/// A failed equality assertion and where it was made
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assertion {
+    pub expected: String,
+    pub actual: String,
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Assertion {
+    #[track_caller]
+    fn record(expected: String, actual: String) -> String {
+        let location = Location::caller();
+        let assertion = Assertion {
+            expected,
+            actual,
+            file: location.file().to_string(),
+            line: location.line(),
+            column: location.column(),
+        };
+        let message = format!("Assertion failed at {}:{}:{}", assertion.file, assertion.line, assertion.column);
+        CAPTURE.with(|capture| capture.borrow_mut().assertion = Some(assertion));
+        message
+    }
+    
+    /// Line diff of expected against actual: `-` expected, `+` actual
+    pub fn diff(&self) -> String {
+        let expected: Vec<&str> = self.expected.lines().collect();
+        let actual: Vec<&str> = self.actual.lines().collect();
+        
+        // Longest common subsequence table, filled from the end
+        let mut lcs = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+        for i in (0..expected.len()).rev() {
+            for j in (0..actual.len()).rev() {
+                lcs[i][j] = if expected[i] == actual[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        
+        let mut diff = String::new();
+        let (mut i, mut j) = (0, 0);
+        while i < expected.len() || j < actual.len() {
+            if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+                diff.push_str(&format!("  {}\n", expected[i]));
+                i += 1;
+                j += 1;
+            } else if i < expected.len() && (j == actual.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                diff.push_str(&format!("- {}\n", expected[i]));
+                i += 1;
+            } else {
+                diff.push_str(&format!("+ {}\n", actual[j]));
+                j += 1;
+            }
+        }
+        diff
+    }
+}
+
+/// Fail unless `expected == actual`, recording both for the failure diff
+#[track_caller]
+pub fn assert_equal<T: fmt::Debug + PartialEq>(expected: T, actual: T) -> Result<(), String> {
+    if expected == actual {
+        return Ok(());
+    }
+    Err(Assertion::record(format!("{:#?}", expected), format!("{:#?}", actual)))
+}
+
+/// Like `assert_equal`, comparing text as-is so multi-line output diffs
+/// line by line instead of as one escaped string
+#[track_caller]
+pub fn assert_text(expected: &str, actual: &str) -> Result<(), String> {
+    if expected == actual {
+        return Ok(());
+    }
+    Err(Assertion::record(expected.to_string(), actual.to_string()))
+}
+
+/// Order tests run in. Random by default so tests that depend on state
+/// left behind by others fail visibly instead of by accident.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestOrder {
+    /// `--ordered`: the order tests were registered in
+    Declaration,
+    /// Shuffled with this seed; `--seed N` reproduces a run
+    Random(u64),
+}
+
+impl TestOrder {
+    /// Random order with a fresh seed
+    pub fn random() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(0);
+        TestOrder::Random(nanos ^ (std::process::id() as u64).rotate_left(32))
+    }
+    
+    /// Pick the order from `--ordered` / `--seed N` command-line arguments
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut order = TestOrder::random();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--ordered" => order = TestOrder::Declaration,
+                "--seed" => {
+                    let seed = args.next().ok_or("--seed expects a number")?;
+                    let seed = seed.parse().map_err(|_| format!("Invalid seed: {}", seed))?;
+                    order = TestOrder::Random(seed);
+                }
+                _ => {}
+            }
+        }
+        Ok(order)
+    }
+}
+
+/// Name a parameterized test case is reported and filtered under
+pub fn case_name(test: &str, case: &str) -> String {
+    format!("{}[{}]", test, case)
+}
+
+/// Arguments of a `#[test_case(...)]` attribute. Each attribute on a test
+/// function becomes one case; `name = "..."` overrides the case name,
+/// which otherwise is the arguments as written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCaseAttribute {
+    pub args: Vec<String>,
+    pub name: Option<String>,
+}
+
+impl TestCaseAttribute {
+    /// Parse `#[test_case(1, "a, b", name = "pair")]`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let inner = text
+            .trim()
+            .strip_prefix("#[test_case(")
+            .and_then(|rest| rest.strip_suffix(")]"))
+            .ok_or_else(|| format!("Not a test_case attribute: {}", text))?;
+        
+        let mut args = Vec::new();
+        let mut name = None;
+        for arg in split_args(inner)? {
+            match arg.strip_prefix("name").map(str::trim_start) {
+                Some(rest) if rest.starts_with('=') => {
+                    let value = rest[1..].trim();
+                    let value = value
+                        .strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"'))
+                        .ok_or_else(|| format!("test_case name must be a string: {}", value))?;
+                    name = Some(value.to_string());
+                }
+                _ => args.push(arg),
+            }
+        }
+        
+        if args.is_empty() {
+            return Err("test_case needs at least one argument".to_string());
+        }
+        Ok(TestCaseAttribute { args, name })
+    }
+    
+    pub fn case_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.args.join(", "))
+    }
+}
+
+/// Split attribute arguments on top-level commas, leaving commas inside
+/// strings and brackets alone
+fn split_args(text: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    
+    for c in text.chars() {
+        if in_string {
+            in_string = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+            current.push(c);
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.checked_sub(1).ok_or("Unbalanced brackets in test_case")?,
+            ',' if depth == 0 => {
+                args.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    
+    if in_string || depth != 0 {
+        return Err("Unterminated argument in test_case".to_string());
+    }
+    if !current.trim().is_empty() {
+        args.push(current.trim().to_string());
+    }
+    Ok(args)
+}
+
+/// Setup and teardown run around every test, and around each case of a
+/// parameterized test separately so cases never see each other's state
+pub struct Fixture {
+    pub setup: Box<dyn Fn() -> Result<(), String>>,
+    pub teardown: Box<dyn Fn()>,
+}
 
 pub struct TestRunner {
-    tests: HashMap<String, TestCase>,
+    /// In registration order; see `TestOrder`
+    tests: Vec<TestCase>,
+    order: TestOrder,
+    fixture: Option<Fixture>,
+    /// Only tests whose full name, including `[case]`, contains this run
+    filter: Option<String>,
+    /// Stop at each failure and ask what to do; see `triage`
+    interactive: bool,
     results: Vec<TestResult>,
 }
 
 impl TestRunner {
     pub fn new() -> Self {
         TestRunner {
-            tests: HashMap::new(),
+            tests: Vec::new(),
+            order: TestOrder::random(),
+            fixture: None,
+            filter: None,
+            interactive: false,
             results: Vec::new(),
         }
     }
     
+    pub fn set_order(&mut self, order: TestOrder) {
+        self.order = order;
+    }
+    
+    pub fn set_fixture(&mut self, fixture: Fixture) {
+        self.fixture = Some(fixture);
+    }
+    
+    /// `--interactive`: on each failure, show its output and diff and
+    /// offer to retry, skip, edit or open a REPL before going on
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+    
+    /// Run only tests whose name contains `pattern`: `parse` selects every
+    /// case of `parse`, `parse[empty]` a single case
+    pub fn set_filter(&mut self, pattern: Option<String>) {
+        self.filter = pattern;
+    }
+    
+    /// Apply `zaitun test` arguments: `--ordered`, `--seed N`,
+    /// `--filter PATTERN` and `--interactive`. First checks that the
+    /// toolchain's compiler, which triage also runs, matches this runner.
+    pub fn configure_from_args(&mut self, args: &[String]) -> Result<(), String> {
+        version_info::verify(&Requirements::new("zaitun test")).map_err(|e| e.to_string())?;
+        self.set_order(TestOrder::from_args(args)?);
+        self.set_filter(Self::filter_from_args(args)?);
+        self.set_interactive(args.iter().any(|arg| arg == "--interactive"));
+        Ok(())
+    }
+    
+    /// Pick the filter from a `--filter PATTERN` command-line argument
+    pub fn filter_from_args(args: &[String]) -> Result<Option<String>, String> {
+        let mut filter = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--filter" {
+                filter = Some(args.next().ok_or("--filter expects a pattern")?.clone());
+            }
+        }
+        Ok(filter)
+    }
+    
+    /// Register a test. Registering a name again replaces the earlier test
+    /// but keeps its position.
     pub fn register_test(&mut self, name: &str, test_fn: Box<dyn Fn() -> Result<(), String>>) {
-        self.tests.insert(name.to_string(), TestCase {
+        let test = TestCase {
             name: name.to_string(),
             test_fn,
-        });
+        };
+        
+        match self.tests.iter_mut().find(|existing| existing.name == name) {
+            Some(existing) => *existing = test,
+            None => self.tests.push(test),
+        }
+    }
+    
+    /// Register one test per case, each reported as `name[case]`. This is
+    /// what `#[test_case(...)]` attributes on a test function expand to.
+    pub fn register_cases<T, F>(&mut self, name: &str, cases: Vec<(String, T)>, test_fn: F)
+    where
+        T: 'static,
+        F: Fn(&T) -> Result<(), String> + 'static,
+    {
+        let test_fn = Rc::new(test_fn);
+        for (case, input) in cases {
+            let test_fn = Rc::clone(&test_fn);
+            self.register_test(&case_name(name, &case), Box::new(move || test_fn(&input)));
+        }
+    }
+    
+    /// Register the cases `provider` returns, for tables built at run time
+    /// such as one case per file in a directory. A failing provider is
+    /// reported as the failing test `name[provider]`.
+    pub fn register_data_provider<T, P, F>(&mut self, name: &str, provider: P, test_fn: F)
+    where
+        T: 'static,
+        P: FnOnce() -> Result<Vec<(String, T)>, String>,
+        F: Fn(&T) -> Result<(), String> + 'static,
+    {
+        match provider() {
+            Ok(cases) => self.register_cases(name, cases, test_fn),
+            Err(message) => {
+                let message = format!("Data provider failed: {}", message);
+                self.register_test(&case_name(name, "provider"), Box::new(move || Err(message.clone())));
+            }
+        }
+    }
+    
+    /// Indices into `tests` in the order they will run
+    fn run_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.tests.len())
+            .filter(|&index| match &self.filter {
+                Some(pattern) => self.tests[index].name.contains(pattern.as_str()),
+                None => true,
+            })
+            .collect();
+        
+        if let TestOrder::Random(seed) = self.order {
+            // Fisher-Yates with a seeded generator, so the same seed and
+            // test list always give the same order
+            let mut rng = SplitMix64(seed);
+            for i in (1..order.len()).rev() {
+                let j = (rng.next() % (i as u64 + 1)) as usize;
+                order.swap(i, j);
+            }
+        }
+        
+        order
     }
     
     pub fn run_all(&mut self) -> TestSummary {
         let start_time = Instant::now();
         self.results.clear();
         
-        for (name, test) in &self.tests {
-            let test_start = Instant::now();
-            let result = (test.test_fn)();
-            let duration = test_start.elapsed();
-            
-            let status = match result {
-                Ok(_) => TestStatus::Passed,
-                Err(message) => TestStatus::Failed(message),
-            };
-            
-            self.results.push(TestResult {
-                name: name.clone(),
-                status,
-                duration,
-            });
+        let run_order = self.run_order();
+        let filtered_out = self.tests.len() - run_order.len();
+        
+        if let TestOrder::Random(seed) = self.order {
+            println!("Running {} tests in random order (seed: {}; rerun with --seed {})", run_order.len(), seed, seed);
+        }
+        
+        'tests: for index in run_order {
+            let test = &self.tests[index];
+            loop {
+                let result = self.run_captured(test);
+                
+                let action = match (&result.status, self.interactive) {
+                    (TestStatus::Failed(_), true) => triage::prompt(&result),
+                    _ => TriageAction::Skip,
+                };
+                match action {
+                    TriageAction::Retry => continue,
+                    TriageAction::Skip => {
+                        self.results.push(result);
+                        break;
+                    }
+                    TriageAction::Quit => {
+                        self.results.push(result);
+                        break 'tests;
+                    }
+                }
+            }
         }
         
         let total_duration = start_time.elapsed();
@@ -50,13 +423,55 @@ impl TestRunner {
             total: self.results.len(),
             passed,
             failed,
+            filtered_out,
             duration: total_duration,
+            order: self.order,
+        }
+    }
+    
+    /// Run one test, collecting what it wrote with `test_output` and its
+    /// failed assertion, if any
+    fn run_captured(&self, test: &TestCase) -> TestResult {
+        CAPTURE.with(|capture| *capture.borrow_mut() = Capture::default());
+        
+        let test_start = Instant::now();
+        let result = self.run_with_fixture(test);
+        let duration = test_start.elapsed();
+        
+        let capture = CAPTURE.with(|capture| capture.take());
+        let status = match result {
+            Ok(_) => TestStatus::Passed,
+            Err(message) => TestStatus::Failed(message),
+        };
+        
+        TestResult {
+            name: test.name.clone(),
+            status,
+            duration,
+            output: capture.output,
+            assertion: capture.assertion,
         }
     }
     
+    fn run_with_fixture(&self, test: &TestCase) -> Result<(), String> {
+        let fixture = match &self.fixture {
+            Some(fixture) => fixture,
+            None => return (test.test_fn)(),
+        };
+        
+        (fixture.setup)().map_err(|message| format!("Fixture setup failed: {}", message))?;
+        let result = (test.test_fn)();
+        (fixture.teardown)();
+        result
+    }
+    
     pub fn report(&self) -> String {
         let mut report = String::new();
         
+        if let TestOrder::Random(seed) = self.order {
+            report.push_str(&format!("Test order seed: {}\n", seed));
+        }
+        
         for result in &self.results {
             match &result.status {
                 TestStatus::Passed => {
@@ -64,6 +479,9 @@ impl TestRunner {
                 }
                 TestStatus::Failed(message) => {
                     report.push_str(&format!("✗ {} ({:?})\n  Error: {}\n", result.name, result.duration, message));
+                    if let Some(assertion) = &result.assertion {
+                        report.push_str(&assertion.diff());
+                    }
                 }
             }
         }
@@ -77,13 +495,16 @@ struct TestCase {
     test_fn: Box<dyn Fn() -> Result<(), String>>,
 }
 
-struct TestResult {
-    name: String,
-    status: TestStatus,
-    duration: Duration,
+pub struct TestResult {
+    pub name: String,
+    pub status: TestStatus,
+    pub duration: Duration,
+    /// Written with `test_output` while the test ran
+    pub output: String,
+    pub assertion: Option<Assertion>,
 }
 
-enum TestStatus {
+pub enum TestStatus {
     Passed,
     Failed(String),
 }
@@ -97,9 +518,26 @@ impl TestStatus {
     }
 }
 
-struct TestSummary {
-    total: usize,
-    passed: usize,
-    failed: usize,
-    duration: Duration,
+pub struct TestSummary {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    /// Registered tests skipped by the filter
+    pub filtered_out: usize,
+    pub duration: Duration,
+    /// Needed to reproduce a failure that only shows up in one order
+    pub order: TestOrder,
+}
+
+/// Small seeded generator for shuffling; not for anything security related
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,116 @@
+//! Interactive failure triage for `zaitun test --interactive`.
+//!
+//! The runner stops at each failing test and shows its captured output
+//! and assertion diff, then asks what to do: run it again after a fix,
+//! move on, open the failing line in `$EDITOR`, or start a REPL with the
+//! test's module loaded to poke at it.
+
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process::Command;
+
+use crate::framework::{Assertion, TestResult, TestStatus};
+
+/// What the runner does with a failed test once triage is over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriageAction {
+    /// Run the test again, e.g. after editing the code
+    Retry,
+    /// Record the failure and continue with the next test
+    Skip,
+    /// Record the failure and stop the run
+    Quit,
+}
+
+/// Show a failure and ask until the user picks retry, skip or quit.
+/// End of input counts as quit so a closed terminal never hangs the run.
+pub fn prompt(result: &TestResult) -> TriageAction {
+    print_failure(result);
+
+    let stdin = io::stdin();
+    loop {
+        print!("[r]etry, [s]kip, [e]dit, re[p]l, [q]uit? ");
+        let _ = io::stdout().flush();
+
+        let mut choice = String::new();
+        match stdin.lock().read_line(&mut choice) {
+            Ok(0) | Err(_) => return TriageAction::Quit,
+            Ok(_) => {}
+        }
+
+        match choice.trim() {
+            "r" | "retry" => return TriageAction::Retry,
+            "s" | "skip" | "" => return TriageAction::Skip,
+            "q" | "quit" => return TriageAction::Quit,
+            "e" | "edit" => match &result.assertion {
+                Some(assertion) => report(open_in_editor(assertion)),
+                None => println!("No failing location recorded for this test"),
+            },
+            "p" | "repl" => match &result.assertion {
+                Some(assertion) => report(open_repl(Path::new(&assertion.file))),
+                None => println!("No module recorded for this test"),
+            },
+            other => println!("Unknown choice: {}", other),
+        }
+    }
+}
+
+fn print_failure(result: &TestResult) {
+    let message = match &result.status {
+        TestStatus::Failed(message) => message.as_str(),
+        TestStatus::Passed => return,
+    };
+
+    println!();
+    println!("✗ {} ({:?})", result.name, result.duration);
+    println!("  Error: {}", message);
+
+    if !result.output.is_empty() {
+        println!("--- captured output ---");
+        print!("{}", result.output);
+    }
+    if let Some(assertion) = &result.assertion {
+        println!("--- expected (-) / actual (+) ---");
+        print!("{}", assertion.diff());
+    }
+}
+
+fn report(outcome: io::Result<()>) {
+    if let Err(e) = outcome {
+        println!("{}", e);
+    }
+}
+
+/// Open `$VISUAL` or `$EDITOR` at the failing line and wait for it to exit
+fn open_in_editor(assertion: &Assertion) -> io::Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "Set $EDITOR to open the failing file"))?;
+
+    // Editors disagree on how to take a position; these cover the common ones
+    let program = Path::new(&editor)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("")
+        .to_string();
+    let args = match program.as_str() {
+        "code" | "codium" => vec!["--goto".to_string(), format!("{}:{}:{}", assertion.file, assertion.line, assertion.column)],
+        "subl" | "zed" | "hx" => vec![format!("{}:{}:{}", assertion.file, assertion.line, assertion.column)],
+        _ => vec![format!("+{}", assertion.line), assertion.file.clone()],
+    };
+
+    let status = Command::new(&editor).args(&args).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("{} exited with {}", editor, status)));
+    }
+    Ok(())
+}
+
+/// Start `zaitun repl` with the test's module loaded; `$ZAITUN` overrides
+/// the compiler binary
+fn open_repl(module: &Path) -> io::Result<()> {
+    let zaitun = env::var("ZAITUN").unwrap_or_else(|_| "zaitun".to_string());
+    Command::new(zaitun).arg("repl").arg("--load").arg(module).status()?;
+    Ok(())
+}